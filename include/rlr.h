@@ -0,0 +1,53 @@
+/*
+ * C interface to the rlr crate, available when the crate is built with the
+ * `ffi` feature. See the documentation of the crate's `ffi` module.
+ */
+#ifndef RLR_H
+#define RLR_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef enum RlrStatus {
+    RLR_OK = 0,
+    RLR_NULL_POINTER = 1,
+    RLR_INVALID_UTF8 = 2,
+    RLR_ERROR = 3,
+} RlrStatus;
+
+typedef struct RlrAgent RlrAgent;
+
+RlrAgent *rlr_agent_new(int32_t priming_threshold, double learning_rate,
+                        double discount_factor);
+
+void rlr_agent_free(RlrAgent *agent);
+
+RlrStatus rlr_agent_recommend(RlrAgent *agent, const char *state_id,
+                              const char *const *action_ids,
+                              size_t action_count, size_t *out_index);
+
+RlrStatus rlr_agent_learn(RlrAgent *agent, const char *previous_state_id,
+                          const char *const *previous_action_ids,
+                          size_t previous_action_count, const char *action_id,
+                          const char *current_state_id,
+                          const char *const *current_action_ids,
+                          size_t current_action_count, double reward);
+
+RlrStatus rlr_agent_snapshot(const RlrAgent *agent, uint8_t **out_data,
+                             size_t *out_len);
+
+RlrAgent *rlr_agent_restore(const uint8_t *data, size_t len);
+
+void rlr_bytes_free(uint8_t *data, size_t len);
+
+const char *rlr_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* RLR_H */
@@ -0,0 +1,70 @@
+//! Derive macros for the `rlr` crate. These are re-exported by `rlr` when its
+//! `derive` feature is enabled, and should be used from there.
+
+#![warn(missing_docs, clippy::all, clippy::pedantic, clippy::nursery)]
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields};
+
+/// Implements `rlr::actions::Actioner` for an enum whose variants have no
+/// fields.
+///
+/// The ID of each action is the name of its variant (as a `&'static str`), so
+/// IDs remain stable as long as variants are not renamed. An `all_variants`
+/// function is also generated, which returns every variant in declaration
+/// order, for use as the possible actions of a state.
+#[proc_macro_derive(Actioner)]
+pub fn derive_actioner(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_actioner(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn expand_actioner(input: &DeriveInput) -> Result<proc_macro2::TokenStream, Error> {
+    let Data::Enum(data) = &input.data else {
+        return Err(Error::new_spanned(
+            &input.ident,
+            "Actioner can only be derived for enums",
+        ));
+    };
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &input.generics,
+            "Actioner cannot be derived for generic enums",
+        ));
+    }
+    if let Some(variant) = data
+        .variants
+        .iter()
+        .find(|v| !matches!(v.fields, Fields::Unit))
+    {
+        return Err(Error::new_spanned(
+            variant,
+            "Actioner can only be derived for enums whose variants have no fields",
+        ));
+    }
+
+    let name = &input.ident;
+    let variants: Vec<_> = data.variants.iter().map(|v| &v.ident).collect();
+    let ids = variants.iter().map(ToString::to_string);
+    Ok(quote! {
+        impl #name {
+            /// Returns every variant of the enum, in declaration order.
+            pub const fn all_variants() -> &'static [Self] {
+                &[#(Self::#variants),*]
+            }
+        }
+
+        impl<'a> ::rlr::actions::Actioner<'a> for #name {
+            type Id = &'static str;
+
+            fn id(&self) -> &'static str {
+                match self {
+                    #(Self::#variants => #ids,)*
+                }
+            }
+        }
+    })
+}
@@ -1,10 +1,42 @@
 //! Actions represent steps that can be taken to transition a model from one
 //! state to another.
 
+use std::fmt::Debug;
+use std::hash::Hash;
+
+/// Derives `Actioner` for an enum whose variants have no fields. Each
+/// variant's ID is its name, and an `all_variants` function is generated
+/// that returns every variant. Requires the `derive` feature.
+#[cfg(feature = "derive")]
+pub use rlr_derive::Actioner;
+
 /// Represents an action that can be applied to the model's current state.
 pub trait Actioner<'a> {
-    /// Returns a string representation of the given action.
+    /// The type used to identify actions. Any type that can be hashed,
+    /// compared, and cloned can be used, such as strings, integers, or tuples.
+    type Id: Hash + Eq + Ord + Clone + Debug;
+
+    /// Returns the ID of the given action.
     /// Implementors shoud take care to ensure this is a consistent hash for a
     /// given state.
-    fn id(&self) -> &'a str;
+    fn id(&self) -> Self::Id;
+}
+
+#[cfg(all(test, feature = "derive"))]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq, Actioner)]
+    enum Direction {
+        Up,
+        Down,
+    }
+
+    #[test]
+    fn derive_actioner() {
+        assert_eq!(&[Direction::Up, Direction::Down], Direction::all_variants());
+        let ids: Vec<&str> = Direction::all_variants().iter().map(Actioner::id).collect();
+        assert_eq!(vec!["Up", "Down"], ids);
+    }
 }
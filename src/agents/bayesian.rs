@@ -19,41 +19,285 @@
 //! observed cumulative reward moreso than the mean of all other actions.
 
 use crate::actions::Actioner;
-use crate::agents::Agenter;
-use crate::internal::datastructures::QMap;
+use crate::agents::frozen::FrozenPolicy;
+use crate::agents::recommender::Recommender;
+use crate::agents::{Agenter, Lifecycle};
+use crate::errors::LearnerError;
+pub use crate::internal::math::NonFinitePolicy;
+use crate::internal::{math, rng};
+use crate::reproducibility::ReproducibilityConfig;
 use crate::states::Stater;
 use crate::stats::ActionStatter;
-use crate::{errors::LearnerError, internal::math};
-use rand::Rng;
-use std::collections::HashMap;
+use crate::stores::{QMap, QStore};
+use rand::{Rng, RngCore};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::cell::Cell;
+#[cfg(any(feature = "bincode", feature = "msgpack"))]
+use std::collections::BTreeMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+use std::hash::Hash;
+#[cfg(any(feature = "bincode", feature = "msgpack"))]
+use std::io;
 use std::marker;
 
+/// The bytes that begin every snapshot written by `Agent::save_to`.
+#[cfg(feature = "bincode")]
+const SNAPSHOT_MAGIC: [u8; 4] = *b"RLRQ";
+
+/// The version of the snapshot format written by `Agent::save_to`.
+///
+/// Version 0 refers to snapshots written before the format was versioned,
+/// which have no header. Whenever the format changes, this number must be
+/// incremented and `Agent::migrate_snapshot` taught how to read the previous
+/// version.
+#[cfg(feature = "bincode")]
+pub const SNAPSHOT_VERSION: u16 = 2;
+
+/// A function that chooses one of `n` tied actions, returning its index.
+/// The function is supplied with the agent's random number generator.
+///
+/// If the returned index is not less than `n`, the agent returns an error
+/// rather than recommending an action.
+pub type TieBreaker<'a> = Box<dyn Fn(usize, &mut dyn RngCore) -> usize + 'a>;
+
+/// A function that returns the learning rate for a state-action pair.
+///
+/// The function is supplied with the number of times the action has been
+/// observed for the state, including the observation being learned from, so
+/// the count is always at least 1.
+pub type LearningRateSchedule<'a> = Box<dyn Fn(u64) -> f64 + 'a>;
+
+/// A function that returns the discount factor to use for a state, or `None`
+/// to use the agent's discount factor.
+pub type DiscountOverride<'a, S> = Box<dyn Fn(&S) -> Option<f64> + 'a>;
+
+/// A function that reshapes the reward for a transition, given the previous
+/// state, the action taken, the current state, and the original reward.
+pub type RewardShaper<'a, S, A> = Box<dyn Fn(&S, &A, &S, f64) -> f64 + 'a>;
+
+/// Describes an update that an agent has made while learning. See
+/// `Agent::on_learn`.
+#[derive(Debug)]
+pub struct LearnEvent<'e, S, A> {
+    /// The state that was transitioned from.
+    pub previous_state: &'e S,
+
+    /// The action that was taken.
+    pub action: &'e A,
+
+    /// The state that was transitioned to.
+    pub current_state: &'e S,
+
+    /// The reward passed to `learn`, before any shaping or exploration bonus.
+    pub reward: f64,
+
+    /// The raw q-value of the state-action pair before the update.
+    pub old_q: f64,
+
+    /// The raw q-value of the state-action pair after the update.
+    pub new_q: f64,
+
+    /// The temporal difference error of the update: how far the discounted
+    /// value of the transition was from the q-value it updated.
+    pub td_error: f64,
+}
+
+/// Describes an action that an agent has recommended. See
+/// `Agent::on_recommend`.
+#[derive(Debug)]
+pub struct RecommendEvent<'e, S, A> {
+    /// The state for which an action was recommended.
+    pub state: &'e S,
+
+    /// The recommended action.
+    pub action: &'e A,
+
+    /// The weighted q-value of the recommended action.
+    pub q_value: f64,
+
+    /// Why the action was recommended.
+    pub kind: DecisionKind,
+}
+
+/// Describes why an agent recommended an action. See
+/// `Agent::recommend_action_detailed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum DecisionKind {
+    /// The action had the highest score of all of the state's actions.
+    Greedy,
+
+    /// Several actions shared the highest score, and the tie breaker chose
+    /// among them.
+    TieBreak,
+
+    /// The action had the highest score, but not the highest q-value, so it
+    /// was chosen to explore rather than to exploit what the agent has
+    /// learned.
+    Exploratory,
+}
+
+impl fmt::Display for DecisionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Greedy => "greedy",
+            Self::TieBreak => "tie-break",
+            Self::Exploratory => "exploratory",
+        };
+        f.write_str(name)
+    }
+}
+
+/// An action that an agent considered while recommending an action. See
+/// `Recommendation`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate<AK> {
+    /// The ID of the action.
+    pub action_id: AK,
+
+    /// The weighted q-value of the action.
+    pub q_value: f64,
+
+    /// The score the action was ranked by: its q-value, less the action's
+    /// cost, plus any exploration bonus.
+    pub score: f64,
+}
+
+/// An action recommended by `Agent::recommend_action_detailed`, along with
+/// the reason it was chosen and the actions it was chosen from.
+#[derive(Debug)]
+pub struct Recommendation<'a, A, AK> {
+    /// The recommended action.
+    pub action: &'a A,
+
+    /// Why the action was recommended.
+    pub kind: DecisionKind,
+
+    /// Every action that was considered, sorted by action ID.
+    pub candidates: Vec<Candidate<AK>>,
+}
+
+/// A function that is called with each `LearnEvent`.
+pub type LearnHook<'a, S, A> = Box<dyn for<'e> FnMut(&LearnEvent<'e, S, A>) + 'a>;
+
+/// A function that is called with each `RecommendEvent`.
+pub type RecommendHook<'a, S, A> = Box<dyn for<'e> FnMut(&RecommendEvent<'e, S, A>) + 'a>;
+
+/// The store used by an `Agent` when no other store is specified: a `QMap`
+/// keyed by the IDs of the agent's states and actions.
+pub type DefaultStore<'a, S, A, AS> = QMap<<S as Stater<'a, A>>::Id, <A as Actioner<'a>>::Id, AS>;
+
+/// Determines how `Agent::merge_from` combines the stats of a state-action
+/// pair that is present in both agents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeStrategy {
+    /// Average the q-values, weighting each by the number of times the action
+    /// was called, and sum the call counts. If neither action has been
+    /// called, the q-values are averaged evenly.
+    WeightedAverage,
+
+    /// Average the q-values evenly, and sum the call counts.
+    Average,
+
+    /// Replace the existing stats with the other agent's stats.
+    Replace,
+
+    /// Keep the existing stats, ignoring the other agent's stats.
+    KeepExisting,
+}
+
+impl MergeStrategy {
+    fn merge<AS: ActionStatter>(self, ours: &AS, theirs: &AS) -> AS {
+        let (our_weight, their_weight) = match self {
+            Self::Replace => return theirs.clone(),
+            Self::KeepExisting => return ours.clone(),
+            Self::WeightedAverage if ours.calls() > 0 || theirs.calls() > 0 => (
+                math::count_to_f64(ours.calls()),
+                math::count_to_f64(theirs.calls()),
+            ),
+            Self::WeightedAverage | Self::Average => (1.0, 1.0),
+        };
+        let combine = |x: f64, y: f64| {
+            math::safe_divide(
+                our_weight.mul_add(x, their_weight * y),
+                our_weight + their_weight,
+            )
+        };
+
+        let mut merged = ours.clone();
+        merged.set_calls(ours.calls().saturating_add(theirs.calls()));
+        merged.set_q_value_raw(combine(ours.q_value_raw(), theirs.q_value_raw()));
+        merged.set_q_value_weighted(combine(ours.q_value_weighted(), theirs.q_value_weighted()));
+        if let Some(step) = ours.last_updated().max(theirs.last_updated()) {
+            merged.set_last_updated(step);
+        }
+        merged
+    }
+}
+
 /// A bayesian agent.
-pub struct Agent<'a, S, A, AS>
+///
+/// The agent records its statistics in a `QStore`, keyed by the IDs of each
+/// state and action. The store is an in-memory `QMap` unless a different
+/// store is supplied via `new_with_store`.
+pub struct Agent<'a, S, A, AS, QS = DefaultStore<'a, S, A, AS>>
 where
     A: Actioner<'a>,
     S: Stater<'a, A>,
     AS: ActionStatter,
+    QS: QStore<S::Id, A::Id, AS>,
 {
     /// `tie_breaker` is a function that is used to break ties when multiple
-    /// possible actions for a state have the same score. The default value
-    /// for this field is a function that chooses the action at random.
+    /// possible actions for a state have the same score. It is supplied with
+    /// the number of tied actions and the agent's random number generator.
+    /// The default value for this field is a function that chooses the action
+    /// at random using the supplied generator.
     /// However, a different tie breaking function can be supplied here if
     /// desired.
-    pub tie_breaker: Box<dyn Fn(usize) -> usize + 'a>,
-    qmap: Box<QMap<'a, S, A, AS>>,
+    pub tie_breaker: TieBreaker<'a>,
+    rng: Box<dyn RngCore + 'a>,
+    pub(crate) qstore: Box<QS>,
     learning_rate: f64,
     discount_factor: f64,
     priming_threshold: i32,
+    exploration_bonus: f64,
+    ucb_coefficient: f64,
+    action_costs: HashMap<A::Id, f64>,
+    initial_q: f64,
+    sparse_storage: bool,
+    non_finite_policy: NonFinitePolicy,
+    lifecycle: Lifecycle,
+    step_count: u64,
+    recommendations: HashMap<S::Id, HashMap<A::Id, u64>>,
+    q_delta_window: usize,
+    q_deltas: VecDeque<f64>,
+    td_error_window: usize,
+    td_errors: VecDeque<f64>,
+    learning_rate_schedule: Option<LearningRateSchedule<'a>>,
+    discount_override: Option<DiscountOverride<'a, S>>,
+    reward_shaper: Option<RewardShaper<'a, S, A>>,
+    on_learn: Option<LearnHook<'a, S, A>>,
+    on_recommend: Option<RecommendHook<'a, S, A>>,
     _actioner: marker::PhantomData<A>,
     _stater: marker::PhantomData<S>,
+    _stats: marker::PhantomData<AS>,
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 /// `AgentContext` is used to import and export a learning agent's internal
-/// state. This can be used to persist the status of the agent, or otherwise
+/// state.
+///
+/// This can be used to persist the status of the agent, or otherwise
 /// evaluate the agent's internal state without exposing the agent's internals.
-pub struct AgentContext<'a, AS: ActionStatter> {
+pub struct AgentContext<SK, AK, AS>
+where
+    SK: Hash + Eq,
+    AK: Hash + Eq,
+    AS: ActionStatter,
+{
     /// The amount of weight given to new information.
     pub learning_rate: f64,
 
@@ -66,14 +310,248 @@ pub struct AgentContext<'a, AS: ActionStatter> {
     pub priming_threshold: i32,
 
     /// The learning agents internal record of scores for each state and action.
-    pub q_values: HashMap<&'a str, HashMap<&'a str, Box<AS>>>,
+    /// `Agent::get_agent_context` calculates the weighted q-value of each
+    /// stats as it takes the snapshot.
+    pub q_values: HashMap<SK, HashMap<AK, AS>>,
+
+    /// The number of times the agent has learned from each state.
+    #[cfg_attr(feature = "serde", serde(default = "HashMap::new"))]
+    pub state_visits: HashMap<SK, u64>,
+}
+
+/// A change to the q-value of a single state-action pair between two
+/// `AgentContext`s, as reported by `AgentContext::diff`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QValueChange<SK, AK> {
+    /// The ID of the state whose q-value changed.
+    pub state_id: SK,
+
+    /// The ID of the action whose q-value changed.
+    pub action_id: AK,
+
+    /// The raw q-value in the older context, or `None` if the pair was not
+    /// recorded in the older context.
+    pub before: Option<f64>,
+
+    /// The raw q-value in the newer context, or `None` if the pair was not
+    /// recorded in the newer context.
+    pub after: Option<f64>,
+}
+
+impl<SK, AK> QValueChange<SK, AK> {
+    /// Returns the amount by which the q-value changed. A q-value that is
+    /// missing from either context is treated as 0.
+    pub fn delta(&self) -> f64 {
+        self.after.unwrap_or_default() - self.before.unwrap_or_default()
+    }
+}
+
+/// The stats of a single state-action pair, as returned by
+/// `AgentContext::to_records`.
+///
+/// Records are flat, so a list of them serializes to a table that can be
+/// loaded directly by dataframe libraries such as polars or pandas.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct QRecord<SK, AK> {
+    /// The ID of the state.
+    pub state: SK,
+
+    /// The ID of the action.
+    pub action: AK,
+
+    /// The number of times the action has been taken in the state.
+    pub calls: u64,
+
+    /// The raw q-value of the state-action pair.
+    pub q_raw: f64,
+
+    /// The weighted q-value of the state-action pair.
+    pub q_weighted: f64,
+}
+
+impl<SK, AK, AS> AgentContext<SK, AK, AS>
+where
+    SK: Hash + Eq + Ord + Clone,
+    AK: Hash + Eq + Ord + Clone,
+    AS: ActionStatter,
+{
+    /// Returns the state-action pairs whose raw q-values differ between this
+    /// context and a `newer` one, sorted by state ID and then action ID.
+    ///
+    /// Pairs whose q-value changed by no more than `tolerance` are omitted.
+    /// Pairs that are only recorded in one of the contexts are always
+    /// included. This can be used to audit what a training run changed, or
+    /// to detect drift between snapshots of an agent.
+    pub fn diff(&self, newer: &Self, tolerance: f64) -> Vec<QValueChange<SK, AK>> {
+        let raw_q = |context: &Self, state_id: &SK, action_id: &AK| {
+            context
+                .q_values
+                .get(state_id)
+                .and_then(|actions| actions.get(action_id))
+                .map(ActionStatter::q_value_raw)
+        };
+
+        let mut changes: Vec<QValueChange<SK, AK>> = Vec::new();
+        for (state_id, actions) in &self.q_values {
+            for (action_id, stats) in actions {
+                let before = Some(stats.q_value_raw());
+                let after = raw_q(newer, state_id, action_id);
+                if after.is_none_or(|after| (after - stats.q_value_raw()).abs() > tolerance) {
+                    changes.push(QValueChange {
+                        state_id: state_id.clone(),
+                        action_id: action_id.clone(),
+                        before,
+                        after,
+                    });
+                }
+            }
+        }
+        for (state_id, actions) in &newer.q_values {
+            for (action_id, stats) in actions {
+                if raw_q(self, state_id, action_id).is_none() {
+                    changes.push(QValueChange {
+                        state_id: state_id.clone(),
+                        action_id: action_id.clone(),
+                        before: None,
+                        after: Some(stats.q_value_raw()),
+                    });
+                }
+            }
+        }
+        changes.sort_by(|x, y| (&x.state_id, &x.action_id).cmp(&(&y.state_id, &y.action_id)));
+        changes
+    }
+
+    /// Returns one record per state-action pair, sorted by state ID and then
+    /// action ID, flattening the nested `q_values` map.
+    pub fn to_records(&self) -> Vec<QRecord<SK, AK>> {
+        let mut records: Vec<QRecord<SK, AK>> = self
+            .q_values
+            .iter()
+            .flat_map(|(state, actions)| {
+                actions.iter().map(move |(action, stats)| QRecord {
+                    state: state.clone(),
+                    action: action.clone(),
+                    calls: stats.calls(),
+                    q_raw: stats.q_value_raw(),
+                    // The context is a snapshot, and its weighted q-values
+                    // were calculated when it was taken (see `q_values`).
+                    q_weighted: stats.q_value_weighted(),
+                })
+            })
+            .collect();
+        records.sort_by(|x, y| (&x.state, &x.action).cmp(&(&y.state, &y.action)));
+        records
+    }
+}
+
+/// Formats the context's q-values as an aligned table with the columns
+/// `state`, `action`, `calls`, `q_raw`, and `q_weighted`, sorted by state ID
+/// and then action ID. Q-values are written with the formatter's precision,
+/// or 4 decimal places if none is given (so `format!("{:.2}", context)`
+/// writes 2 decimal places).
+impl<SK, AK, AS> fmt::Display for AgentContext<SK, AK, AS>
+where
+    SK: Hash + Eq + Ord + fmt::Display,
+    AK: Hash + Eq + Ord + fmt::Display,
+    AS: ActionStatter,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let precision = f.precision().unwrap_or(4);
+        let mut entries: Vec<(&SK, &AK, &AS)> = self
+            .q_values
+            .iter()
+            .flat_map(|(state, actions)| {
+                actions
+                    .iter()
+                    .map(move |(action, stats)| (state, action, stats))
+            })
+            .collect();
+        entries.sort_by(|x, y| (x.0, x.1).cmp(&(y.0, y.1)));
+        let rows: Vec<[String; 5]> = entries
+            .into_iter()
+            .map(|(state, action, stats)| {
+                [
+                    state.to_string(),
+                    action.to_string(),
+                    stats.calls().to_string(),
+                    format!("{:.precision$}", stats.q_value_raw()),
+                    // Calculated when the snapshot was taken, as in
+                    // `to_records`.
+                    format!("{:.precision$}", stats.q_value_weighted()),
+                ]
+            })
+            .collect();
+        let header = ["state", "action", "calls", "q_raw", "q_weighted"].map(String::from);
+
+        let mut widths = [0; 5];
+        for row in rows.iter().chain(std::iter::once(&header)) {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.chars().count());
+            }
+        }
+        for row in std::iter::once(&header).chain(&rows) {
+            writeln!(
+                f,
+                "{:<w0$}  {:<w1$}  {:>w2$}  {:>w3$}  {:>w4$}",
+                row[0],
+                row[1],
+                row[2],
+                row[3],
+                row[4],
+                w0 = widths[0],
+                w1 = widths[1],
+                w2 = widths[2],
+                w3 = widths[3],
+                w4 = widths[4],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A borrowed view of an `AgentContext`, which allows an agent to be
+/// serialized without first cloning its state and action IDs. Entries are
+/// sorted by ID, so that the snapshots of agents with the same q-values are
+/// identical, whatever order their stores hold the q-values in.
+#[cfg(any(feature = "bincode", feature = "msgpack"))]
+#[derive(Serialize)]
+struct AgentContextRef<'b, SK, AK, AS>
+where
+    SK: Ord,
+    AK: Ord,
+    AS: ActionStatter,
+{
+    learning_rate: f64,
+    discount_factor: f64,
+    priming_threshold: i32,
+    q_values: BTreeMap<&'b SK, BTreeMap<&'b AK, AS>>,
+    state_visits: BTreeMap<&'b SK, u64>,
+}
+
+/// The body of snapshots written in versions 0 and 1 of the snapshot format,
+/// which predate the recording of state visits.
+#[cfg(feature = "bincode")]
+#[derive(Deserialize)]
+struct AgentContextV1<SK, AK, AS>
+where
+    SK: Hash + Eq,
+    AK: Hash + Eq,
+    AS: ActionStatter,
+{
+    learning_rate: f64,
+    discount_factor: f64,
+    priming_threshold: i32,
+    q_values: HashMap<SK, HashMap<AK, AS>>,
 }
 
-impl<'a, S, A, AS> Agenter<'a, S, A> for Agent<'a, S, A, AS>
+impl<'a, S, A, AS, QS> Agenter<'a, S, A> for Agent<'a, S, A, AS, QS>
 where
     S: Stater<'a, A>,
-    A: Actioner<'a>,
+    A: Actioner<'a> + 'a,
     AS: ActionStatter,
+    QS: QStore<S::Id, A::Id, AS>,
 {
     /// 'learn' updates the reinforcement model according to a transition that
     /// has occured from a previous state, through some action, to a current
@@ -82,245 +560,2854 @@ where
     /// may be None if no action has been previously taken or there is no
     /// previous state (aka the model is being bootstrapped). In that case,
     /// learn becomes a no-op.
+    /// An error is returned if the agent is `Frozen`, or if the reward or the
+    /// resulting q-value is NaN or infinite, in which case the agent's
+    /// q-values are left unchanged.
     /// See [https://en.wikipedia.org/wiki/Q-learning#Algorithm](https://en.wikipedia.org/wiki/Q-learning#Algorithm)
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            err,
+            fields(
+                previous_state = ?previous_state.map(Stater::id),
+                action = ?action_taken.id(),
+                current_state = ?current_state.id(),
+                reward,
+            )
+        )
+    )]
     fn learn(
         &mut self,
-        previous_state: Option<&'a S>,
-        action_taken: &'a A,
-        current_state: &'a S,
+        previous_state: Option<&S>,
+        action_taken: &A,
+        current_state: &S,
         reward: f64,
-    ) {
-        if previous_state.is_none() {
-            return;
+    ) -> Result<(), LearnerError> {
+        self.learn_weighted(previous_state, action_taken, current_state, reward, 1.0)
+    }
+
+    /// `transition` applies an action to a given state.
+    #[allow(clippy::use_debug)]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            level = "debug",
+            skip_all,
+            err,
+            fields(state = ?current_state.id(), action = ?action.id())
+        )
+    )]
+    fn transition(&self, current_state: &S, action: &'a A) -> Result<(), LearnerError> {
+        if !current_state.action_is_compatible(action) {
+            return Err(LearnerError::ActionNotCompatible {
+                state: format!("{:?}", current_state.id()),
+                action: format!("{:?}", action.id()),
+            });
         }
-        let previous_state = previous_state.unwrap();
-        let mut stats = match self.qmap.get_stats(previous_state, action_taken) {
-            Some(s) => s,
-            None => Box::new(AS::default()),
+        current_state.apply(action)
+    }
+
+    /// `recommend_action` recommends an action for a given state based on
+    /// behavior of the model that the agent has learned thus far.
+    /// If the q-value for two or more actions are the same, the action is
+    /// chosen according to a tie-breaking function. See Agent docs for
+    /// more information.
+    /// An error is returned if the agent is `Draining`, or if the tie-breaking
+    /// function chooses an index outside the range of tied actions.
+    /// Use `Agent::recommend_action_detailed` to learn why the action was
+    /// chosen.
+    fn recommend_action(&mut self, state: &S) -> Result<&'a A, LearnerError> {
+        Ok(self.recommend_action_detailed(state)?.action)
+    }
+}
+
+impl<'a, S, A, AS, QS> Agent<'a, S, A, AS, QS>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a> + 'a,
+    AS: ActionStatter,
+    QS: QStore<S::Id, A::Id, AS>,
+{
+    /// new returns a reference to a new Agent.
+    ///
+    /// `priming_threshold`:
+    ///  The number of observations required of any action before the action's
+    ///  raw q-value is trusted more than average q-value for all of a state's
+    ///  actions.
+    ///
+    /// `learning_rate`:
+    ///  Typically a number between 0 and 1 (though it can exceed 1)
+    ///  From wikipedia: Determins to what extent newly acquired information
+    ///  overrides old information.
+    ///  see: [https://en.wikipedia.org/wiki/Q-learning#Learning_Rate](https://en.wikipedia.org/wiki/Q-learning#Learning_Rate)
+    ///
+    /// `discount_factor`:
+    ///  From wikipedia: The discount factor determines the importance of future
+    ///  rewards.
+    ///  see: [https://en.wikipedia.org/wiki/Q-learning#Discount_factor](https://en.wikipedia.org/wiki/Q-learning#Discount_factor)
+    pub fn new(priming_threshold: i32, learning_rate: f64, discount_factor: f64) -> Self
+    where
+        QS: Default,
+    {
+        Self::new_with_store(
+            QS::default(),
+            priming_threshold,
+            learning_rate,
+            discount_factor,
+        )
+    }
+
+    /// Returns a new Agent that records its statistics in the supplied store.
+    /// See `new` for a description of the remaining parameters.
+    pub fn new_with_store(
+        qstore: QS,
+        priming_threshold: i32,
+        learning_rate: f64,
+        discount_factor: f64,
+    ) -> Self {
+        Self {
+            tie_breaker: Box::new(|n: usize, rng: &mut dyn RngCore| -> usize {
+                rng.gen_range(0, n)
+            }),
+            rng: Box::new(rng::default_rng()),
+            qstore: Box::new(qstore),
+            learning_rate,
+            discount_factor,
+            priming_threshold,
+            exploration_bonus: 0.0,
+            ucb_coefficient: 0.0,
+            action_costs: HashMap::new(),
+            initial_q: 0.0,
+            sparse_storage: false,
+            non_finite_policy: NonFinitePolicy::Error,
+            lifecycle: Lifecycle::Learning,
+            step_count: 0,
+            recommendations: HashMap::new(),
+            q_delta_window: 0,
+            q_deltas: VecDeque::new(),
+            td_error_window: 0,
+            td_errors: VecDeque::new(),
+            learning_rate_schedule: None,
+            discount_override: None,
+            reward_shaper: None,
+            on_learn: None,
+            on_recommend: None,
+            _actioner: marker::PhantomData {},
+            _stater: marker::PhantomData {},
+            _stats: marker::PhantomData {},
+        }
+    }
+
+    /// Sets the coefficient of the count-based exploration bonus that is added
+    /// to each reward the agent learns from.
+    ///
+    /// The bonus for a state-action pair is `coefficient / sqrt(n)`, where `n`
+    /// is the number of times the action has been observed for the state
+    /// (including the current observation). Rarely visited actions thus
+    /// receive a larger bonus, encouraging the agent to explore them.
+    /// The default coefficient is 0, which disables the bonus.
+    #[must_use]
+    pub fn with_exploration_bonus(mut self, coefficient: f64) -> Self {
+        self.exploration_bonus = coefficient;
+        self
+    }
+
+    /// Enables upper confidence bound (UCB) action selection, in which
+    /// `recommend_action` chooses the action with the highest
+    /// `q + coefficient * sqrt(ln(N) / n)`, where `q` is the action's
+    /// weighted q-value, `n` is the number of times the action has been
+    /// observed for the state, and `N` is the total for all of the state's
+    /// actions. Actions that have not been observed are chosen first.
+    ///
+    /// The bonus shrinks as an action is observed, so the agent explores
+    /// actions whose values are uncertain without needing a separate
+    /// exploration schedule. Remaining ties are broken by `tie_breaker`.
+    /// The bonus affects only which action is recommended, and not the
+    /// values that the agent learns. The default coefficient is 0, which
+    /// disables UCB selection.
+    #[must_use]
+    pub fn with_ucb_exploration(mut self, coefficient: f64) -> Self {
+        self.ucb_coefficient = coefficient;
+        self
+    }
+
+    /// Sets the cost of each of the supplied actions, keyed by action ID.
+    ///
+    /// An action's cost is subtracted from its weighted q-value when
+    /// `recommend_action` ranks actions, so an expensive action is only
+    /// recommended if its learned value exceeds that of cheaper actions by
+    /// at least the difference in cost. Like the UCB bonus, costs affect only
+    /// which action is recommended, and not the values that the agent learns.
+    /// Actions without a cost have a cost of 0. Supplying the cost of an
+    /// action again replaces its previous cost.
+    #[must_use]
+    pub fn with_action_costs<I>(mut self, costs: I) -> Self
+    where
+        I: IntoIterator<Item = (A::Id, f64)>,
+    {
+        self.action_costs.extend(costs);
+        self
+    }
+
+    /// Returns the cost of an action, which is 0 unless one was set with
+    /// `with_action_costs`.
+    pub fn action_cost(&self, action_id: &A::Id) -> f64 {
+        self.action_costs
+            .get(action_id)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Sets the q-value that is assigned to actions the agent has not yet
+    /// observed. The default is 0.
+    ///
+    /// Setting this to a value that is higher than any reward the agent is
+    /// likely to receive (optimistic initialization) causes the agent to
+    /// prefer actions it has not tried yet, which encourages exploration.
+    #[must_use]
+    pub fn with_initial_q(mut self, initial_q: f64) -> Self {
+        self.initial_q = initial_q;
+        self
+    }
+
+    /// Enables or disables sparse storage. Sparse storage is disabled by
+    /// default.
+    ///
+    /// By default, the agent records stats for every possible action of every
+    /// state that it encounters, even if an action has never been taken. With
+    /// sparse storage enabled, stats are only recorded for actions that have
+    /// actually been taken, and the stats of all other actions are computed on
+    /// the fly from the agent's initial q-value. This can greatly reduce the
+    /// memory used by states that have many possible actions.
+    #[must_use]
+    pub fn with_sparse_storage(mut self, sparse_storage: bool) -> Self {
+        self.sparse_storage = sparse_storage;
+        self
+    }
+
+    /// Sets how the agent handles rewards, q-values, and intermediate results
+    /// that are NaN or infinite.
+    ///
+    /// With the default policy, `NonFinitePolicy::Error`, `learn` returns an
+    /// error rather than recording a non-finite q-value, and weighing a state
+    /// whose recorded q-values are not finite (for instance, after importing
+    /// a corrupted `AgentContext`) returns an error from `recommend_action`.
+    /// With `NonFinitePolicy::Clamp`, non-finite values are replaced with the
+    /// nearest finite value and the agent carries on.
+    #[must_use]
+    pub fn with_non_finite_policy(mut self, policy: NonFinitePolicy) -> Self {
+        self.non_finite_policy = policy;
+        self
+    }
+
+    /// Sets a schedule that determines the learning rate for each update from
+    /// the number of times the updated action has been observed for its
+    /// state, in place of the agent's fixed learning rate. For instance,
+    /// `|n| 1.0 / n as f64` makes each q-value the running average of
+    /// its targets, which satisfies the conditions under which tabular
+    /// Q-learning is guaranteed to converge.
+    #[must_use]
+    pub fn with_learning_rate_schedule<F>(mut self, schedule: F) -> Self
+    where
+        F: Fn(u64) -> f64 + 'a,
+    {
+        self.learning_rate_schedule = Some(Box::new(schedule));
+        self
+    }
+
+    /// Sets a function that can override the discount factor for specific
+    /// states. When the agent learns from a transition, the function is
+    /// called with the state that was transitioned to, and if it returns a
+    /// value, that value is used to discount the state's future rewards in
+    /// place of the agent's discount factor. For instance, returning
+    /// `Some(0.0)` for goal states ends the propagation of value at those
+    /// states.
+    #[must_use]
+    pub fn with_discount_override<F>(mut self, discount_override: F) -> Self
+    where
+        F: Fn(&S) -> Option<f64> + 'a,
+    {
+        self.discount_override = Some(Box::new(discount_override));
+        self
+    }
+
+    /// Sets a function that reshapes each reward before the agent learns from
+    /// it, such as potential-based shaping, which adds
+    /// `discount_factor * potential(current_state) - potential(previous_state)`
+    /// to the reward without changing the optimal policy. Stats record the
+    /// original reward via `ActionStatter::observe_reward`, and the
+    /// exploration bonus is added after shaping.
+    #[must_use]
+    pub fn with_reward_shaper<F>(mut self, reward_shaper: F) -> Self
+    where
+        F: Fn(&S, &A, &S, f64) -> f64 + 'a,
+    {
+        self.reward_shaper = Some(Box::new(reward_shaper));
+        self
+    }
+
+    /// Sets a function that is called each time the agent updates a q-value
+    /// in `learn`. This can be used to stream updates to a monitoring
+    /// system.
+    #[must_use]
+    pub fn on_learn<F>(mut self, on_learn: F) -> Self
+    where
+        F: for<'e> FnMut(&LearnEvent<'e, S, A>) + 'a,
+    {
+        self.on_learn = Some(Box::new(on_learn));
+        self
+    }
+
+    /// Sets a function that is called each time the agent recommends an
+    /// action.
+    #[must_use]
+    pub fn on_recommend<F>(mut self, on_recommend: F) -> Self
+    where
+        F: for<'e> FnMut(&RecommendEvent<'e, S, A>) + 'a,
+    {
+        self.on_recommend = Some(Box::new(on_recommend));
+        self
+    }
+
+    /// Has the agent keep track of how much each of its last `window` updates
+    /// changed a q-value (see `mean_q_delta` and `max_q_delta`). A sudden
+    /// rise in these changes after they have settled suggests that the
+    /// environment's dynamics have shifted, and that the agent should explore
+    /// again. The default window is 0, which disables tracking.
+    #[must_use]
+    pub fn with_q_delta_window(mut self, window: usize) -> Self {
+        self.q_delta_window = window;
+        self.q_deltas = VecDeque::with_capacity(window);
+        self
+    }
+
+    /// Has the agent keep track of the temporal difference error of each of
+    /// its last `window` updates (see `mean_td_error` and `max_td_error`).
+    /// The error of an update is how far the discounted value of a transition
+    /// was from the q-value it updated, so errors that grow rather than
+    /// settle suggest that the agent's q-values are diverging. The default
+    /// window is 0, which disables tracking.
+    #[must_use]
+    pub fn with_td_error_window(mut self, window: usize) -> Self {
+        self.td_error_window = window;
+        self.td_errors = VecDeque::with_capacity(window);
+        self
+    }
+
+    /// Sets the random number generator that the agent uses whenever it needs
+    /// to make a random choice (such as when breaking ties between actions).
+    /// By default, the agent uses a `rand::rngs::StdRng` seeded from the
+    /// platform's source of entropy.
+    ///
+    /// Supplying a seeded generator (such as `StdRng::seed_from_u64`) makes
+    /// the agent's behavior fully reproducible.
+    #[must_use]
+    pub fn with_rng<R: RngCore + 'a>(mut self, rng: R) -> Self {
+        self.rng = Box::new(rng);
+        self
+    }
+
+    /// Seeds the agent's random number generator from `config`. See
+    /// `reproducibility::ReproducibilityConfig`.
+    #[must_use]
+    pub fn with_reproducibility(self, config: &ReproducibilityConfig) -> Self {
+        self.with_rng(config.agent_rng())
+    }
+
+    /// Returns the amount of weight the agent gives to new information. See
+    /// `new`.
+    pub fn learning_rate(&self) -> f64 {
+        self.learning_rate
+    }
+
+    /// Sets the amount of weight the agent gives to new information. This
+    /// can be called between calls to `learn` to anneal the learning rate
+    /// without losing the agent's q-values. The learning rate is not used if
+    /// the agent has a learning rate schedule.
+    pub fn set_learning_rate(&mut self, learning_rate: f64) {
+        self.learning_rate = learning_rate;
+    }
+
+    /// Returns the importance the agent gives to future rewards. See `new`.
+    pub fn discount_factor(&self) -> f64 {
+        self.discount_factor
+    }
+
+    /// Sets the importance the agent gives to future rewards. This takes
+    /// effect from the agent's next call to `learn`.
+    pub fn set_discount_factor(&mut self, discount_factor: f64) {
+        self.discount_factor = discount_factor;
+    }
+
+    /// Returns the number of observations of an action required before its
+    /// raw q-value is trusted. See `new`.
+    pub fn priming_threshold(&self) -> i32 {
+        self.priming_threshold
+    }
+
+    /// Sets the number of observations of an action required before its raw
+    /// q-value is trusted. Weighted q-values are recomputed with the new
+    /// threshold as each state is next encountered.
+    pub fn set_priming_threshold(&mut self, priming_threshold: i32) {
+        self.priming_threshold = priming_threshold;
+    }
+
+    /// Returns the agent's current lifecycle stage. New agents start out in
+    /// the `Learning` stage.
+    pub fn lifecycle(&self) -> Lifecycle {
+        self.lifecycle
+    }
+
+    /// Moves the agent from `Learning` to `Frozen`. While frozen, the agent
+    /// continues to recommend actions, but calls to `learn` return an error.
+    pub fn freeze(&mut self) -> Result<(), LearnerError> {
+        self.change_lifecycle(&[Lifecycle::Learning], Lifecycle::Frozen)
+    }
+
+    /// Moves the agent from `Frozen` back to `Learning`.
+    pub fn unfreeze(&mut self) -> Result<(), LearnerError> {
+        self.change_lifecycle(&[Lifecycle::Frozen], Lifecycle::Learning)
+    }
+
+    /// Moves the agent from `Learning` or `Frozen` to `Draining`. While
+    /// draining, the agent continues to learn from outstanding transitions,
+    /// but calls to `recommend_action` return an error. Once an agent is
+    /// draining it cannot be moved to any other stage.
+    pub fn drain(&mut self) -> Result<(), LearnerError> {
+        self.change_lifecycle(
+            &[Lifecycle::Learning, Lifecycle::Frozen],
+            Lifecycle::Draining,
+        )
+    }
+
+    /// Returns the number of transitions that the agent has learned from.
+    /// Stats that record when they were last updated (such as `StepStats`)
+    /// are stamped with this count, so comparing the two shows how long ago
+    /// each state-action pair last changed.
+    pub fn step_count(&self) -> u64 {
+        self.step_count
+    }
+
+    /// Returns the number of times the agent has recommended each action for
+    /// the specified state. Unlike the calls recorded in each action's stats,
+    /// which count the transitions the agent has learned from, these count
+    /// the actions that `recommend_action` returned. Actions that have never
+    /// been recommended are omitted.
+    pub fn recommendation_counts(&self, state_id: &S::Id) -> HashMap<A::Id, u64> {
+        self.recommendations
+            .get(state_id)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Returns the fraction of the agent's recommendations for the specified
+    /// state that chose each action. A state whose recommendations are all
+    /// for a single action has a frequency of 1 for that action, showing that
+    /// the agent has stopped exploring it.
+    pub fn recommendation_frequencies(&self, state_id: &S::Id) -> HashMap<A::Id, f64> {
+        let counts = self.recommendations.get(state_id);
+        let total = counts.map_or(0, |counts| {
+            counts
+                .values()
+                .fold(0_u64, |total, count| total.saturating_add(*count))
+        });
+        counts
+            .into_iter()
+            .flatten()
+            .map(|(action_id, count)| {
+                (
+                    action_id.clone(),
+                    math::safe_divide(math::count_to_f64(*count), math::count_to_f64(total)),
+                )
+            })
+            .collect()
+    }
+
+    /// Returns an iterator over the recommendation counts of every state for
+    /// which the agent has recommended an action. See `recommendation_counts`.
+    pub fn iter_recommendation_counts(
+        &self,
+    ) -> impl Iterator<Item = (&S::Id, &HashMap<A::Id, u64>)> + '_ {
+        self.recommendations.iter()
+    }
+
+    /// Forgets every recommendation the agent has made, so that counting can
+    /// start afresh (for instance, at the start of each audit period).
+    pub fn reset_recommendation_counts(&mut self) {
+        self.recommendations.clear();
+    }
+
+    /// Returns the mean absolute change to a q-value over the agent's most
+    /// recent updates, or `None` if no changes have been tracked. See
+    /// `with_q_delta_window`.
+    pub fn mean_q_delta(&self) -> Option<f64> {
+        mean(&self.q_deltas)
+    }
+
+    /// Returns the largest absolute change to a q-value over the agent's most
+    /// recent updates, or `None` if no changes have been tracked. See
+    /// `with_q_delta_window`.
+    pub fn max_q_delta(&self) -> Option<f64> {
+        self.q_deltas.iter().copied().reduce(f64::max)
+    }
+
+    /// Returns the mean absolute temporal difference error of the agent's most
+    /// recent updates, or `None` if no errors have been tracked. See
+    /// `with_td_error_window`.
+    pub fn mean_td_error(&self) -> Option<f64> {
+        mean(&self.td_errors)
+    }
+
+    /// Returns the largest absolute temporal difference error of the agent's
+    /// most recent updates, or `None` if no errors have been tracked. See
+    /// `with_td_error_window`.
+    pub fn max_td_error(&self) -> Option<f64> {
+        self.td_errors.iter().copied().reduce(f64::max)
+    }
+
+    /// Returns true if none of the agent's most recent updates changed a
+    /// q-value by more than `tolerance`, which indicates that the agent's
+    /// q-values have stopped changing and training can end.
+    ///
+    /// The updates considered are those tracked by `with_q_delta_window`.
+    /// The agent is never considered to have converged until the window has
+    /// filled, so this always returns false if tracking is disabled.
+    pub fn has_converged(&self, tolerance: f64) -> bool {
+        self.q_delta_window > 0
+            && self.q_deltas.len() == self.q_delta_window
+            && self.q_deltas.iter().all(|delta| *delta <= tolerance)
+    }
+
+    /// Recommends an action for `state` as `recommend_action` does, and
+    /// reports why the action was chosen.
+    ///
+    /// The returned `Recommendation` records whether the action was the
+    /// unique greedy choice, was chosen by the tie breaker from several
+    /// equally scored actions, or was picked over an action with a higher
+    /// q-value because of the exploration bonus (see `with_ucb_exploration`).
+    /// It also lists the q-value and score of every candidate action.
+    #[allow(clippy::use_debug)]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip_all, err, fields(state = ?state.id()))
+    )]
+    pub fn recommend_action_detailed(
+        &mut self,
+        state: &S,
+    ) -> Result<Recommendation<'a, A, A::Id>, LearnerError> {
+        if self.lifecycle == Lifecycle::Draining {
+            return Err(LearnerError::Lifecycle(format!(
+                "agent is {} and cannot recommend actions",
+                self.lifecycle
+            )));
+        }
+
+        let action_stats = self.weighted_action_stats(state)?;
+        let total_calls: f64 = action_stats
+            .values()
+            .map(|stats| math::count_to_f64(stats.calls()))
+            .sum();
+        let mut candidates: Vec<Candidate<A::Id>> = action_stats
+            .iter()
+            .map(|(action, stats)| Candidate {
+                action_id: action.clone(),
+                // `weighted_action_stats` has just calculated the weighted
+                // q-values, so they are not stale.
+                q_value: stats.q_value_weighted(),
+                score: self.score(action, stats, total_calls),
+            })
+            .collect();
+
+        // Order of records in a hashmap is nondeterministic, so we sort
+        // by action ID to get a deterministic result.
+        // Note that it is documented that it is the implementor's
+        // responsibility to ensure that each action's ID is unique across all
+        // possible actions within the scope of the agent, and that having
+        // different actions share an ID will cause undefined behavior.
+        candidates.sort_by(|x, y| x.action_id.cmp(&y.action_id));
+        let best_score = candidates
+            .iter()
+            .map(|candidate| candidate.score)
+            .fold(-f64::MAX, f64::max);
+        let best_actions: Vec<&Candidate<A::Id>> = candidates
+            .iter()
+            .filter(|candidate| (candidate.score - best_score).abs() < f64::EPSILON)
+            .collect();
+
+        if best_actions.is_empty() {
+            return Err(LearnerError::NoPossibleActions {
+                state: format!("{:?}", state.id()),
+            });
+        }
+
+        let tied = best_actions.len();
+        let index = (self.tie_breaker)(tied, self.rng.as_mut());
+        let chosen = *best_actions
+            .get(index)
+            .ok_or(LearnerError::InvalidTieBreak {
+                index,
+                candidates: tied,
+            })?;
+        let action = state.get_action(&chosen.action_id)?;
+        let net_value = |candidate: &Candidate<A::Id>| {
+            candidate.q_value - self.action_cost(&candidate.action_id)
+        };
+        let best_value = candidates.iter().map(net_value).fold(-f64::MAX, f64::max);
+        let kind = if best_value - net_value(chosen) > f64::EPSILON {
+            DecisionKind::Exploratory
+        } else if tied > 1 {
+            DecisionKind::TieBreak
+        } else {
+            DecisionKind::Greedy
+        };
+        let q_value = chosen.q_value;
+        let count = self
+            .recommendations
+            .entry(state.id())
+            .or_default()
+            .entry(chosen.action_id.clone())
+            .or_insert(0);
+        *count = count.saturating_add(1);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            action = ?chosen.action_id,
+            q_value,
+            tied,
+            kind = %kind,
+            "recommended action"
+        );
+        if let Some(on_recommend) = self.on_recommend.as_mut() {
+            on_recommend(&RecommendEvent {
+                state,
+                action,
+                q_value,
+                kind,
+            });
+        }
+        Ok(Recommendation {
+            action,
+            kind,
+            candidates,
+        })
+    }
+
+    /// Returns a read-only handle that recommends actions using the agent's
+    /// q-values, without needing mutable access to the agent. See
+    /// `Recommender` for how the handle relates to the agent's store.
+    pub fn recommender(&self) -> Recommender<'a, S, A, AS, QS>
+    where
+        QS: Clone,
+    {
+        Recommender::new(
+            self.qstore.as_ref().clone(),
+            self.priming_threshold,
+            self.initial_q,
+            self.sparse_storage,
+            self.non_finite_policy,
+            self.action_costs.clone(),
+        )
+    }
+
+    /// Returns the number of times the agent has learned from a transition
+    /// out of the specified state.
+    pub fn state_visits(&self, state_id: &S::Id) -> Result<u64, LearnerError> {
+        self.qstore.get_visits(state_id)
+    }
+
+    /// Returns the number of states for which the agent has recorded stats.
+    pub fn state_count(&self) -> Result<usize, LearnerError> {
+        self.qstore.state_count()
+    }
+
+    /// Returns the number of state-action pairs for which the agent has
+    /// recorded stats.
+    pub fn entry_count(&self) -> Result<usize, LearnerError> {
+        self.qstore.entry_count()
+    }
+
+    /// Merges the q-values of another agent's context into this agent's
+    /// q-values. This is useful when several agents are trained in parallel
+    /// (for instance, on different shards of traffic) and their tables need
+    /// to be combined periodically.
+    ///
+    /// State-action pairs that are only present in `other` are copied as-is,
+    /// and pairs that are present in both are combined according to
+    /// `strategy`. State visits are summed when averaging, and otherwise
+    /// replaced or kept in the same way as stats, and the agent's step count
+    /// changes by the same amount as its total visits. The agent's
+    /// hyperparameters are left unchanged.
+    /// An error is returned if the agent is `Frozen`.
+    pub fn merge_from(
+        &mut self,
+        other: &AgentContext<S::Id, A::Id, AS>,
+        strategy: MergeStrategy,
+    ) -> Result<(), LearnerError> {
+        self.ensure_can_learn()?;
+        for (state_id, their_actions) in &other.q_values {
+            let mut our_actions = self.qstore.get_actions_for_state(state_id)?;
+            let merged = their_actions
+                .iter()
+                .map(|(action_id, theirs)| {
+                    let stats = our_actions
+                        .remove(action_id)
+                        .map_or_else(|| theirs.clone(), |ours| strategy.merge(&ours, theirs));
+                    (action_id.clone(), stats)
+                })
+                .collect();
+            self.qstore.update_actions_for_state(state_id, merged)?;
+        }
+        for (state_id, their_visits) in &other.state_visits {
+            let our_visits = self.qstore.get_visits(state_id)?;
+            let visits = match strategy {
+                MergeStrategy::WeightedAverage | MergeStrategy::Average => {
+                    our_visits.saturating_add(*their_visits)
+                }
+                MergeStrategy::Replace => *their_visits,
+                MergeStrategy::KeepExisting if our_visits == 0 => *their_visits,
+                MergeStrategy::KeepExisting => our_visits,
+            };
+            self.qstore.set_visits(state_id, visits)?;
+            self.step_count = if visits >= our_visits {
+                self.step_count.saturating_add(visits - our_visits)
+            } else {
+                self.step_count.saturating_sub(our_visits - visits)
+            };
+        }
+        Ok(())
+    }
+
+    /// Learns from a transition that was collected under a different
+    /// behavior policy, such as a transition read from historical logs.
+    ///
+    /// The learning rate is scaled by `importance_weight`, which is the ratio
+    /// of the probability of the action under the agent's policy to its
+    /// probability under the behavior policy (see `importance_weight`). A
+    /// weight of 0 leaves the agent unchanged, and a weight of 1 is
+    /// equivalent to calling `learn`.
+    /// An error is returned if `importance_weight` is negative or not finite,
+    /// or in any case where `learn` would return an error.
+    pub fn learn_off_policy(
+        &mut self,
+        previous_state: Option<&S>,
+        action_taken: &A,
+        current_state: &S,
+        reward: f64,
+        importance_weight: f64,
+    ) -> Result<(), LearnerError> {
+        if !importance_weight.is_finite() || importance_weight < 0.0 {
+            return Err(LearnerError::InvalidArgument(format!(
+                "importance weight {importance_weight} must be finite and non-negative"
+            )));
+        }
+        if importance_weight == 0.0 {
+            return self.ensure_can_learn();
+        }
+        self.learn_weighted(
+            previous_state,
+            action_taken,
+            current_state,
+            reward,
+            importance_weight,
+        )
+    }
+
+    /// Returns the importance weight of taking `action` in `state`, given the
+    /// probability with which the behavior policy took the action.
+    ///
+    /// The agent's policy is the one `recommend_action` follows: greedy with
+    /// respect to each action's score (its weighted q-value less its cost,
+    /// plus any UCB bonus), choosing uniformly among tied actions. The weight
+    /// is thus `1 / (ties * behavior_probability)` if the action is one of
+    /// the `ties` best scored actions, and 0 otherwise.
+    /// An error is returned if `behavior_probability` is not in `(0, 1]`.
+    pub fn importance_weight(
+        &mut self,
+        state: &S,
+        action: &A,
+        behavior_probability: f64,
+    ) -> Result<f64, LearnerError> {
+        if !(behavior_probability > 0.0 && behavior_probability <= 1.0) {
+            return Err(LearnerError::InvalidArgument(format!(
+                "behavior probability {behavior_probability} must be in (0, 1]"
+            )));
+        }
+        let action_stats = self.weighted_action_stats(state)?;
+        let total_calls: f64 = action_stats
+            .values()
+            .map(|stats| math::count_to_f64(stats.calls()))
+            .sum();
+        let scores: HashMap<&A::Id, f64> = action_stats
+            .iter()
+            .map(|(action_id, stats)| (action_id, self.score(action_id, stats, total_calls)))
+            .collect();
+        let best_score = scores.values().copied().fold(-f64::MAX, f64::max);
+        let is_best = |score: f64| (score - best_score).abs() < f64::EPSILON;
+        let ties = scores.values().filter(|score| is_best(**score)).count();
+        let weight = match scores.get(&action.id()) {
+            Some(score) if is_best(*score) => 1.0 / (math::len_to_f64(ties) * behavior_probability),
+            _ => 0.0,
+        };
+        Ok(weight)
+    }
+
+    /// Learns from a transition with the learning rate scaled by
+    /// `importance_weight`. `learn` uses a weight of 1.
+    fn learn_weighted(
+        &mut self,
+        previous_state: Option<&S>,
+        action_taken: &A,
+        current_state: &S,
+        reward: f64,
+        importance_weight: f64,
+    ) -> Result<(), LearnerError> {
+        self.ensure_can_learn()?;
+        if previous_state.is_none() {
+            return Ok(());
+        }
+        let reward = self
+            .non_finite_policy
+            .resolve(reward, || format!("reward {reward} is not finite"))?;
+        let previous_state = previous_state.unwrap();
+        let previous_state_id = previous_state.id();
+        let observed_reward = reward;
+        let reward = self.reward_shaper.as_ref().map_or(reward, |shaper| {
+            shaper(previous_state, action_taken, current_state, reward)
+        });
+        let discount_factor = self
+            .discount_override
+            .as_ref()
+            .and_then(|discount_override| discount_override(current_state))
+            .unwrap_or(self.discount_factor);
+        let mean = self.mean_raw_q(previous_state)?;
+        let optimal_future_value = self.best_weighted_value(current_state)?;
+
+        // The stats are read and written under a single call to the store, so
+        // that they are updated in place, and so that concurrent updates of
+        // a shared store are not lost.
+        let update = StatsUpdate {
+            initial_q: self.initial_q,
+            sparse_storage: self.sparse_storage,
+            priming_threshold: self.priming_threshold,
+            non_finite_policy: self.non_finite_policy,
+            mean,
+            observed_reward,
+            reward,
+            exploration_bonus: self.exploration_bonus,
+            learning_rate: self.learning_rate,
+            learning_rate_schedule: self.learning_rate_schedule.as_ref(),
+            importance_weight,
+            discount_factor,
+            optimal_future_value,
+            step_count: self.step_count.saturating_add(1),
+        };
+        let created = Cell::new(false);
+        let mut outcome = None;
+        self.qstore.update_stats_with(
+            &previous_state_id,
+            &action_taken.id(),
+            || {
+                created.set(true);
+                new_stats(update.initial_q)
+            },
+            |stats| outcome = Some(update.apply(stats, created.get())),
+        )?;
+        let (old_value, new_value, td_error) = outcome.unwrap_or_else(|| {
+            Err(LearnerError::Storage(
+                "store did not apply the update to the stats".to_string(),
+            ))
+        })?;
+
+        self.step_count = update.step_count;
+        self.qstore.increment_visits(&previous_state_id)?;
+        push_windowed(
+            &mut self.q_deltas,
+            self.q_delta_window,
+            (new_value - old_value).abs(),
+        );
+        push_windowed(&mut self.td_errors, self.td_error_window, td_error.abs());
+        if !self.sparse_storage {
+            // Record the state's other possible actions, if they have not
+            // been recorded already.
+            let unrecorded = self.unrecorded_actions(previous_state)?;
+            let mean = self.mean_raw_q(previous_state)?;
+            self.record_new_actions(&previous_state_id, unrecorded, mean)?;
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(old_q = old_value, new_q = new_value, "updated q-value");
+        if let Some(on_learn) = self.on_learn.as_mut() {
+            on_learn(&LearnEvent {
+                previous_state,
+                action: action_taken,
+                current_state,
+                reward: observed_reward,
+                old_q: old_value,
+                new_q: new_value,
+                td_error,
+            });
+        }
+        Ok(())
+    }
+
+    fn ensure_can_learn(&self) -> Result<(), LearnerError> {
+        if self.lifecycle == Lifecycle::Frozen {
+            return Err(LearnerError::Lifecycle(format!(
+                "agent is {} and cannot learn",
+                self.lifecycle
+            )));
+        }
+        Ok(())
+    }
+
+    fn change_lifecycle(&mut self, from: &[Lifecycle], to: Lifecycle) -> Result<(), LearnerError> {
+        if !from.contains(&self.lifecycle) {
+            return Err(LearnerError::Lifecycle(format!(
+                "agent cannot move from {} to {}",
+                self.lifecycle, to
+            )));
+        }
+        self.lifecycle = to;
+        Ok(())
+    }
+
+    /// Returns the stats of every action that is possible from `state`, with
+    /// their weighted q-values calculated. Unless the agent uses sparse
+    /// storage, actions that have not been recorded yet are recorded. The
+    /// weighted q-values of recorded actions are not written to the store.
+    fn weighted_action_stats(&mut self, state: &S) -> Result<HashMap<A::Id, AS>, LearnerError> {
+        let (action_stats, unrecorded_actions) = weigh_actions(
+            self.qstore.as_ref(),
+            state,
+            self.priming_threshold,
+            self.initial_q,
+            self.sparse_storage,
+            self.non_finite_policy,
+        )?;
+        if !self.sparse_storage && !unrecorded_actions.is_empty() {
+            let new_stats = unrecorded_actions
+                .into_iter()
+                .filter_map(|action_id| {
+                    let stats = action_stats.get(&action_id)?.clone();
+                    Some((action_id, stats))
+                })
+                .collect();
+            self.qstore
+                .update_actions_for_state(&state.id(), new_stats)?;
+        }
+        Ok(action_stats)
+    }
+
+    /// Returns the mean raw q-value of `state`'s actions, towards which the
+    /// q-values of its rarely called actions are weighted, using the state's
+    /// cached raw q-value sum.
+    fn mean_raw_q(&self, state: &S) -> Result<f64, LearnerError> {
+        let (mut raw_value_sum, mut action_count) = self.qstore.raw_q_sum(&state.id())?;
+        if self.sparse_storage {
+            let unrecorded = state.possible_actions().len().saturating_sub(action_count);
+            raw_value_sum += self.initial_q * math::len_to_f64(unrecorded);
+            action_count += unrecorded;
+        }
+        Ok(math::safe_divide(
+            raw_value_sum,
+            math::len_to_f64(action_count),
+        ))
+    }
+
+    /// Returns the highest weighted q-value of `state`'s actions, or 0 if
+    /// none is higher, as `weighted_action_stats` would find it. Unless the
+    /// agent uses sparse storage, actions that have not been recorded yet are
+    /// recorded. The recorded stats are read in place rather than copied.
+    fn best_weighted_value(&mut self, state: &S) -> Result<f64, LearnerError> {
+        let state_id = state.id();
+        let mut recorded = Vec::new();
+        self.qstore.visit_actions_for_state(&state_id, |_, stats| {
+            recorded.push((stats.calls(), stats.q_value_raw()));
+        })?;
+        let unrecorded = self.unrecorded_actions(state)?;
+        let (mut raw_value_sum, mut action_count) = self.qstore.raw_q_sum(&state_id)?;
+        if self.sparse_storage {
+            raw_value_sum += self.initial_q * math::len_to_f64(unrecorded.len());
+            action_count += unrecorded.len();
+        }
+        let mean = math::safe_divide(raw_value_sum, math::len_to_f64(action_count));
+        let unrecorded_values = unrecorded.iter().map(|_| (0, self.initial_q));
+        let mut best_value = 0.0;
+        for (calls, q_raw) in recorded.into_iter().chain(unrecorded_values) {
+            let q = math::checked_bayesian_average(
+                self.non_finite_policy,
+                f64::from(self.priming_threshold),
+                math::count_to_f64(calls),
+                mean,
+                q_raw,
+            )?;
+            if q > best_value {
+                best_value = q;
+            }
+        }
+        self.record_new_actions(&state_id, unrecorded, mean)?;
+        Ok(best_value)
+    }
+
+    /// Returns the IDs of `state`'s possible actions that have no stats
+    /// recorded.
+    fn unrecorded_actions(&self, state: &S) -> Result<Vec<A::Id>, LearnerError> {
+        let mut recorded = HashSet::new();
+        self.qstore
+            .visit_actions_for_state(&state.id(), |action_id, _| {
+                recorded.insert(action_id.clone());
+            })?;
+        Ok(state
+            .possible_actions()
+            .iter()
+            .map(|action| action.id())
+            .filter(|action_id| !recorded.contains(action_id))
+            .collect())
+    }
+
+    /// Records new stats for each of `action_ids` within a state, weighted
+    /// towards `mean`, unless the agent uses sparse storage.
+    fn record_new_actions(
+        &mut self,
+        state_id: &S::Id,
+        action_ids: Vec<A::Id>,
+        mean: f64,
+    ) -> Result<(), LearnerError> {
+        if self.sparse_storage || action_ids.is_empty() {
+            return Ok(());
+        }
+        let new_stats = action_ids
+            .into_iter()
+            .map(|action_id| {
+                let mut stats = self.new_stats();
+                set_weighted_q(
+                    &mut stats,
+                    self.priming_threshold,
+                    mean,
+                    self.non_finite_policy,
+                )?;
+                Ok((action_id, stats))
+            })
+            .collect::<Result<_, LearnerError>>()?;
+        self.qstore.update_actions_for_state(state_id, new_stats)
+    }
+
+    fn new_stats(&self) -> AS {
+        new_stats(self.initial_q)
+    }
+
+    /// Returns the score that `recommend_action` ranks an action by, given
+    /// its stats and the total calls of its state's actions.
+    ///
+    /// The stats must come from `weighted_action_stats`, which calculates
+    /// their weighted q-values, rather than from the store, where the
+    /// weighted q-values may be stale.
+    fn score(&self, action_id: &A::Id, stats: &AS, total_calls: f64) -> f64 {
+        let q_value = stats.q_value_weighted() - self.action_cost(action_id);
+        let score = self.ucb_score(q_value, stats.calls(), total_calls);
+        // Unobserved actions score infinity, which cannot be compared for
+        // ties, so they share the largest finite score instead, ahead of all
+        // other actions.
+        if score == f64::INFINITY {
+            f64::MAX
+        } else {
+            score
+        }
+    }
+
+    /// Returns `q_value` plus the UCB bonus of an action, if UCB selection is
+    /// enabled. Unobserved actions score infinity when UCB selection is
+    /// enabled.
+    fn ucb_score(&self, q_value: f64, calls: u64, total_calls: f64) -> f64 {
+        if self.ucb_coefficient == 0.0 {
+            return q_value;
+        }
+        math::ucb(
+            q_value,
+            math::count_to_f64(calls),
+            total_calls,
+            self.ucb_coefficient,
+        )
+    }
+}
+
+/// The inputs to the update that `Agent::learn` makes to the stats of a
+/// state-action pair, copied out of the agent so that the update can be
+/// applied while the agent's store is borrowed.
+struct StatsUpdate<'u, 'a> {
+    initial_q: f64,
+    sparse_storage: bool,
+    priming_threshold: i32,
+    non_finite_policy: NonFinitePolicy,
+    mean: f64,
+    observed_reward: f64,
+    reward: f64,
+    exploration_bonus: f64,
+    learning_rate: f64,
+    learning_rate_schedule: Option<&'u LearningRateSchedule<'a>>,
+    importance_weight: f64,
+    discount_factor: f64,
+    optimal_future_value: f64,
+    step_count: u64,
+}
+
+impl StatsUpdate<'_, '_> {
+    /// Updates `stats` in place, and returns their old and new raw q-values
+    /// along with the temporal difference error of the update. `created` is
+    /// set if the stats were recorded just for this update. The stats are
+    /// left unchanged if an error is returned.
+    fn apply<AS: ActionStatter>(
+        &self,
+        stats: &mut AS,
+        created: bool,
+    ) -> Result<(f64, f64, f64), LearnerError> {
+        let q_value = if created && !self.sparse_storage {
+            self.initial_q
+        } else {
+            weighted_q(
+                stats,
+                self.priming_threshold,
+                self.mean,
+                self.non_finite_policy,
+            )?
+        };
+        let visits = stats.calls().saturating_add(1);
+        let reward = self.reward
+            + math::exploration_bonus(self.exploration_bonus, math::count_to_f64(visits));
+        let learning_rate = self
+            .learning_rate_schedule
+            .map_or(self.learning_rate, |schedule| schedule(visits))
+            * self.importance_weight;
+        let td_error = math::td_error(
+            q_value,
+            reward,
+            self.discount_factor,
+            self.optimal_future_value,
+        );
+        let new_value = math::checked_bellman(
+            self.non_finite_policy,
+            q_value,
+            learning_rate,
+            reward,
+            self.discount_factor,
+            self.optimal_future_value,
+        )?;
+
+        let old_value = stats.q_value_raw();
+        stats.observe_reward(self.observed_reward);
+        stats.increment_calls();
+        stats.set_q_value_raw(new_value);
+        stats.set_last_updated(self.step_count);
+        Ok((old_value, new_value, td_error))
+    }
+}
+
+/// The stats of each of a state's possible actions, keyed by action ID, along
+/// with the IDs of the actions that had no stats recorded.
+pub(crate) type WeightedActions<AK, AS> = (HashMap<AK, AS>, Vec<AK>);
+
+/// Returns the stats of every action that is possible from `state`, with
+/// their weighted q-values calculated, along with the IDs of the actions
+/// that have no stats recorded in `qstore`. Nothing is written to the store.
+///
+/// The weighted q-values are pulled towards the mean raw q-value of the
+/// state's recorded actions, which is found from the store's `raw_q_sum`.
+/// Unrecorded actions are given new stats with a q-value of `initial_q`. If
+/// `sparse_storage` is set, those q-values also contribute to the mean.
+pub(crate) fn weigh_actions<'a, S, A, AS, QS>(
+    qstore: &QS,
+    state: &S,
+    priming_threshold: i32,
+    initial_q: f64,
+    sparse_storage: bool,
+    non_finite_policy: NonFinitePolicy,
+) -> Result<WeightedActions<A::Id, AS>, LearnerError>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a> + 'a,
+    AS: ActionStatter,
+    QS: QStore<S::Id, A::Id, AS> + ?Sized,
+{
+    let state_id = state.id();
+    let mut action_stats = qstore.get_actions_for_state(&state_id)?;
+    let (mut raw_value_sum, mut existing_action_count) = qstore.raw_q_sum(&state_id)?;
+    let mut unrecorded_actions = Vec::new();
+    for action in state.possible_actions() {
+        let action_id = action.id();
+        if !action_stats.contains_key(&action_id) {
+            if sparse_storage {
+                raw_value_sum += initial_q;
+                existing_action_count += 1;
+            }
+            unrecorded_actions.push(action_id);
+        }
+    }
+    for action_id in &unrecorded_actions {
+        action_stats.insert(action_id.clone(), new_stats(initial_q));
+    }
+
+    let mean = math::safe_divide(raw_value_sum, math::len_to_f64(existing_action_count));
+    for stats in action_stats.values_mut() {
+        set_weighted_q(stats, priming_threshold, mean, non_finite_policy)?;
+    }
+    Ok((action_stats, unrecorded_actions))
+}
+
+/// Sets the weighted q-value of `stats` to the Bayesian average of its raw
+/// q-value and `mean`, given the number of times the action was called.
+/// Non-finite values are handled according to `non_finite_policy`.
+fn set_weighted_q<AS: ActionStatter>(
+    stats: &mut AS,
+    priming_threshold: i32,
+    mean: f64,
+    non_finite_policy: NonFinitePolicy,
+) -> Result<(), LearnerError> {
+    let weighted_mean = weighted_q(stats, priming_threshold, mean, non_finite_policy)?;
+    stats.set_q_value_weighted(weighted_mean);
+    Ok(())
+}
+
+/// Returns the Bayesian average of the raw q-value of `stats` and `mean`, as
+/// `set_weighted_q` would set it, without changing `stats`.
+fn weighted_q<AS: ActionStatter>(
+    stats: &AS,
+    priming_threshold: i32,
+    mean: f64,
+    non_finite_policy: NonFinitePolicy,
+) -> Result<f64, LearnerError> {
+    math::checked_bayesian_average(
+        non_finite_policy,
+        f64::from(priming_threshold),
+        math::count_to_f64(stats.calls()),
+        mean,
+        stats.q_value_raw(),
+    )
+}
+
+/// Appends `value` to `values`, discarding the oldest value if `values` would
+/// otherwise hold more than `window` values. Nothing is kept if `window` is 0.
+fn push_windowed(values: &mut VecDeque<f64>, window: usize, value: f64) {
+    if window == 0 {
+        return;
+    }
+    if values.len() == window {
+        values.pop_front();
+    }
+    values.push_back(value);
+}
+
+/// Returns the mean of `values`, or `None` if there are none.
+fn mean(values: &VecDeque<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    Some(values.iter().sum::<f64>() / math::len_to_f64(values.len()))
+}
+
+/// Returns new stats with raw and weighted q-values of `initial_q`.
+fn new_stats<AS: ActionStatter>(initial_q: f64) -> AS {
+    let mut stats = AS::default();
+    stats.set_q_value_raw(initial_q);
+    stats.set_q_value_weighted(initial_q);
+    stats
+}
+
+impl<'a, S, A, AS> Agent<'a, S, A, AS>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a> + 'a,
+    AS: ActionStatter,
+{
+    /// Returns a new Agent whose hyperparameters and q-values are restored
+    /// from a previously exported `AgentContext`. The agent's step count is
+    /// restored as the total number of state visits in the context.
+    pub fn from_agent_context(context: AgentContext<S::Id, A::Id, AS>) -> Self {
+        let mut agent = Self::new(
+            context.priming_threshold,
+            context.learning_rate,
+            context.discount_factor,
+        );
+        agent.step_count = context
+            .state_visits
+            .values()
+            .fold(0, |total: u64, visits| total.saturating_add(*visits));
+        agent.qstore.data = context.q_values;
+        agent.qstore.visits = context.state_visits;
+        agent
+    }
+
+    /// Returns an iterator over the agent's q-values, yielding the state ID,
+    /// action ID, and stats of each recorded state-action pair. Unlike
+    /// `get_agent_context`, this borrows the IDs and stats rather than
+    /// cloning them. The order of iteration is unspecified.
+    ///
+    /// The stats are yielded as they are recorded. Weighted q-values are not
+    /// kept up to date in the store, so the weighted q-value of each stats is
+    /// only a cache (see `ActionStatter::q_value_weighted`); use
+    /// `weighted_q_value` to find the current value.
+    pub fn iter_q_values(&self) -> impl Iterator<Item = (&S::Id, &A::Id, &AS)> + '_ {
+        self.qstore.iter()
+    }
+
+    /// Returns the current weighted q-value of an action within a state, or
+    /// `None` if no stats have been recorded for the action.
+    ///
+    /// With sparse storage, actions that have not been recorded do not
+    /// contribute to the weighting. Non-finite q-values are passed through
+    /// as they are, whatever the agent's `NonFinitePolicy`, so that they can
+    /// be found.
+    pub fn weighted_q_value(&self, state_id: &S::Id, action_id: &A::Id) -> Option<f64> {
+        let stats = self.qstore.stats(state_id, action_id)?;
+        Some(self.weigh(stats, self.mean_q_value(state_id)))
+    }
+
+    /// Returns an iterator over the agent's q-values, as `iter_q_values`
+    /// does, along with the current weighted q-value of each stats, as
+    /// `weighted_q_value` would return it. The IDs and stats are borrowed,
+    /// so the rows can be passed to exporters such as `export::csv::write_rows`
+    /// without cloning them into an `AgentContext`.
+    pub fn iter_weighted_q_values(&self) -> impl Iterator<Item = (&S::Id, &A::Id, &AS, f64)> + '_ {
+        self.qstore
+            .data
+            .iter()
+            .flat_map(move |(state_id, actions)| {
+                let mean = self.mean_q_value(state_id);
+                actions.iter().map(move |(action_id, stats)| {
+                    (state_id, action_id, stats, self.weigh(stats, mean))
+                })
+            })
+    }
+
+    /// Returns the mean raw q-value of the actions recorded for a state.
+    fn mean_q_value(&self, state_id: &S::Id) -> f64 {
+        self.qstore.raw_q_sum(state_id).map_or(0.0, |(sum, count)| {
+            math::safe_divide(sum, math::len_to_f64(count))
+        })
+    }
+
+    /// Returns the weighted q-value of `stats`, given the mean raw q-value of
+    /// their state.
+    fn weigh(&self, stats: &AS, mean: f64) -> f64 {
+        math::bayesian_average(
+            f64::from(self.priming_threshold),
+            math::count_to_f64(stats.calls()),
+            mean,
+            stats.q_value_raw(),
+        )
+    }
+
+    /// Returns a copy of `stats` with its weighted q-value set to `q_value`.
+    fn with_weighted_q(stats: &AS, q_value: f64) -> AS {
+        let mut stats = stats.clone();
+        stats.set_q_value_weighted(q_value);
+        stats
+    }
+
+    /// Returns the agent's q-values, with their weighted q-values calculated.
+    fn weighted_q_values(&self) -> HashMap<S::Id, HashMap<A::Id, AS>> {
+        let mut q_values: HashMap<S::Id, HashMap<A::Id, AS>> = HashMap::new();
+        for (state_id, action_id, stats, q_value) in self.iter_weighted_q_values() {
+            q_values
+                .entry(state_id.clone())
+                .or_default()
+                .insert(action_id.clone(), Self::with_weighted_q(stats, q_value));
+        }
+        q_values
+    }
+
+    /// Returns a view of the agent's context, sorted by ID, for serializing.
+    #[cfg(any(feature = "bincode", feature = "msgpack"))]
+    fn context_ref(&self) -> AgentContextRef<'_, S::Id, A::Id, AS> {
+        let mut q_values: BTreeMap<&S::Id, BTreeMap<&A::Id, AS>> = BTreeMap::new();
+        for (state_id, action_id, stats, q_value) in self.iter_weighted_q_values() {
+            q_values
+                .entry(state_id)
+                .or_default()
+                .insert(action_id, Self::with_weighted_q(stats, q_value));
+        }
+        AgentContextRef {
+            learning_rate: self.learning_rate,
+            discount_factor: self.discount_factor,
+            priming_threshold: self.priming_threshold,
+            q_values,
+            state_visits: self
+                .qstore
+                .visits
+                .iter()
+                .map(|(id, visits)| (id, *visits))
+                .collect(),
+        }
+    }
+
+    /// Returns the greedy policy learned by the agent: the ID of the action
+    /// with the highest weighted q-value, less the action's cost, for each
+    /// state the agent has recorded. Ties are broken in favor of the action
+    /// with the lowest ID, so the policy is deterministic. The policy can be
+    /// deployed without the agent, and never explores.
+    pub fn extract_policy(&self) -> HashMap<S::Id, A::Id> {
+        let mut best: HashMap<&S::Id, (&A::Id, f64)> = HashMap::new();
+        for (state_id, action_id, _, q) in self.iter_weighted_q_values() {
+            let q = q - self.action_cost(action_id);
+            best.entry(state_id)
+                .and_modify(|(best_id, best_q)| {
+                    if q > *best_q || (q == *best_q && action_id < *best_id) {
+                        *best_id = action_id;
+                        *best_q = q;
+                    }
+                })
+                .or_insert((action_id, q));
+        }
+        best.into_iter()
+            .map(|(state_id, (action_id, _))| (state_id.clone(), action_id.clone()))
+            .collect()
+    }
+
+    /// Returns a `FrozenPolicy` that follows the greedy policy returned by
+    /// `extract_policy`. The frozen policy can be evaluated in place of the
+    /// agent without exploring or learning, and without changing the agent.
+    pub fn frozen_policy(&self) -> FrozenPolicy<S::Id, A::Id> {
+        FrozenPolicy::new(self.extract_policy())
+    }
+
+    /// Returns an estimate of the number of bytes of memory used by the
+    /// agent's q-values. See `QMap::approx_memory_bytes` for the caveats that
+    /// apply to the estimate.
+    pub fn approx_memory_bytes(&self) -> usize {
+        self.qstore.approx_memory_bytes()
+    }
+
+    /// Returns the `AgentContext` representing the current state of the agent.
+    pub fn get_agent_context(&self) -> AgentContext<S::Id, A::Id, AS> {
+        AgentContext {
+            learning_rate: self.learning_rate,
+            discount_factor: self.discount_factor,
+            priming_threshold: self.priming_threshold,
+            q_values: self.weighted_q_values(),
+            state_visits: self.qstore.visits.clone(),
+        }
+    }
+
+    /// Writes a compact binary snapshot of the agent's hyperparameters and
+    /// q-values to `writer`. The snapshot can be restored using `load_from`.
+    ///
+    /// The snapshot begins with a header recording the version of the format
+    /// (see `SNAPSHOT_VERSION`), so that snapshots written by older versions
+    /// of this crate can still be loaded.
+    #[cfg(feature = "bincode")]
+    pub fn save_to<W: io::Write>(&self, mut writer: W) -> Result<(), LearnerError>
+    where
+        S::Id: Serialize,
+        A::Id: Serialize,
+        AS: Serialize,
+    {
+        writer
+            .write_all(&SNAPSHOT_MAGIC)
+            .and_then(|()| writer.write_all(&SNAPSHOT_VERSION.to_le_bytes()))
+            .map_err(|e| {
+                LearnerError::Serialization(format!("unable to save agent snapshot: {e}"))
+            })?;
+        let context = self.context_ref();
+        bincode::serialize_into(writer, &context)
+            .map_err(|e| LearnerError::Serialization(format!("unable to save agent snapshot: {e}")))
+    }
+
+    /// Returns a new Agent restored from a binary snapshot previously written
+    /// by `save_to`. Snapshots written by older versions of this crate are
+    /// migrated to the current format as they are loaded. An error is
+    /// returned if the snapshot was written in a newer format than this
+    /// version of the crate supports.
+    #[cfg(feature = "bincode")]
+    pub fn load_from<R: io::Read>(mut reader: R) -> Result<Self, LearnerError>
+    where
+        S::Id: for<'de> Deserialize<'de>,
+        A::Id: for<'de> Deserialize<'de>,
+        AS: for<'de> Deserialize<'de>,
+    {
+        let load_err =
+            |e| LearnerError::Serialization(format!("unable to load agent snapshot: {e}"));
+        let mut magic = [0; SNAPSHOT_MAGIC.len()];
+        reader.read_exact(&mut magic).map_err(load_err)?;
+        let context = if magic == SNAPSHOT_MAGIC {
+            let mut version = [0; 2];
+            reader.read_exact(&mut version).map_err(load_err)?;
+            Self::migrate_snapshot(u16::from_le_bytes(version), reader)?
+        } else {
+            // Unversioned snapshots have no header, so the bytes that were
+            // read are the start of the snapshot itself.
+            Self::migrate_snapshot(0, io::Read::chain(&magic[..], reader))?
+        };
+        Ok(Self::from_agent_context(context))
+    }
+
+    /// Reads the body of a snapshot written in the specified format version,
+    /// converting it to the current `AgentContext` if necessary.
+    #[cfg(feature = "bincode")]
+    fn migrate_snapshot<R: io::Read>(
+        version: u16,
+        reader: R,
+    ) -> Result<AgentContext<S::Id, A::Id, AS>, LearnerError>
+    where
+        S::Id: for<'de> Deserialize<'de>,
+        A::Id: for<'de> Deserialize<'de>,
+        AS: for<'de> Deserialize<'de>,
+    {
+        let load_err =
+            |e| LearnerError::Serialization(format!("unable to load agent snapshot: {e}"));
+        match version {
+            // Version 1 only added a header to the unversioned format, and
+            // version 2 added state visits, which are assumed to be zero for
+            // older snapshots.
+            0 | 1 => {
+                let context: AgentContextV1<S::Id, A::Id, AS> =
+                    bincode::deserialize_from(reader).map_err(load_err)?;
+                Ok(AgentContext {
+                    learning_rate: context.learning_rate,
+                    discount_factor: context.discount_factor,
+                    priming_threshold: context.priming_threshold,
+                    q_values: context.q_values,
+                    state_visits: HashMap::new(),
+                })
+            }
+            SNAPSHOT_VERSION => bincode::deserialize_from(reader).map_err(load_err),
+            _ => Err(LearnerError::Serialization(format!(
+                "unable to load agent snapshot: version {version} is newer than the \
+                 newest supported version ({SNAPSHOT_VERSION})"
+            ))),
+        }
+    }
+
+    /// Writes a zstd-compressed binary snapshot of the agent to `writer`.
+    /// `level` is the zstd compression level, where 0 selects zstd's default
+    /// level and higher values trade speed for a smaller snapshot. The
+    /// snapshot can be restored using `load_compressed_from`.
+    #[cfg(feature = "zstd")]
+    pub fn save_compressed_to<W: io::Write>(
+        &self,
+        writer: W,
+        level: i32,
+    ) -> Result<(), LearnerError>
+    where
+        S::Id: Serialize,
+        A::Id: Serialize,
+        AS: Serialize,
+    {
+        let compress_err =
+            |e| LearnerError::Serialization(format!("unable to compress agent snapshot: {e}"));
+        let mut encoder = zstd::Encoder::new(writer, level).map_err(compress_err)?;
+        self.save_to(&mut encoder)?;
+        encoder.finish().map(|_| ()).map_err(compress_err)
+    }
+
+    /// Returns a new Agent restored from a compressed snapshot previously
+    /// written by `save_compressed_to`.
+    #[cfg(feature = "zstd")]
+    pub fn load_compressed_from<R: io::Read>(reader: R) -> Result<Self, LearnerError>
+    where
+        S::Id: for<'de> Deserialize<'de>,
+        A::Id: for<'de> Deserialize<'de>,
+        AS: for<'de> Deserialize<'de>,
+    {
+        let decoder = zstd::Decoder::new(reader).map_err(|e| {
+            LearnerError::Serialization(format!("unable to decompress agent snapshot: {e}"))
+        })?;
+        Self::load_from(decoder)
+    }
+
+    /// Writes the agent's hyperparameters and q-values to `writer` as
+    /// MessagePack. The snapshot can be restored using `load_msgpack_from`.
+    ///
+    /// Unlike `save_to`, the snapshot has no header, and is encoded as a map
+    /// with the same field names as `AgentContext`, so that it can be read by
+    /// MessagePack libraries in other languages.
+    #[cfg(feature = "msgpack")]
+    pub fn save_msgpack_to<W: io::Write>(&self, mut writer: W) -> Result<(), LearnerError>
+    where
+        S::Id: Serialize,
+        A::Id: Serialize,
+        AS: Serialize,
+    {
+        let context = self.context_ref();
+        rmp_serde::encode::write_named(&mut writer, &context).map_err(|e| {
+            LearnerError::Serialization(format!("unable to save msgpack snapshot: {e}"))
+        })
+    }
+
+    /// Returns a new Agent restored from a MessagePack snapshot, such as one
+    /// written by `save_msgpack_to`. A missing `state_visits` field is
+    /// treated as empty.
+    #[cfg(feature = "msgpack")]
+    pub fn load_msgpack_from<R: io::Read>(reader: R) -> Result<Self, LearnerError>
+    where
+        S::Id: for<'de> Deserialize<'de>,
+        A::Id: for<'de> Deserialize<'de>,
+        AS: for<'de> Deserialize<'de>,
+    {
+        let context = rmp_serde::from_read(reader).map_err(|e| {
+            LearnerError::Serialization(format!("unable to load msgpack snapshot: {e}"))
+        })?;
+        Ok(Self::from_agent_context(context))
+    }
+}
+
+#[cfg(test)]
+#[allow(
+    clippy::wildcard_imports,
+    clippy::default_trait_access,
+    clippy::panic,
+    clippy::uninlined_format_args,
+    clippy::use_debug
+)]
+mod tests {
+    use super::*;
+    use crate::mocks::*;
+    use crate::stats::actionstats::Stats;
+    use crate::stats::rewardstats::RewardStats;
+    use maplit::hashmap;
+    use std::cell::RefCell;
+
+    #[test]
+    fn learn() {
+        let action_x = MockActioner { return_id: "X" };
+        let action_y = MockActioner { return_id: "Y" };
+        let action_z = MockActioner { return_id: "Z" };
+        let mock_actions = || -> Vec<&MockActioner> { vec![&action_x, &action_y, &action_z] };
+
+        let previous_state = MockStater {
+            return_id: "A",
+            return_possible_actions: mock_actions(),
+            ..Default::default()
+        };
+
+        let current_state = MockStater {
+            return_id: "B",
+            return_possible_actions: mock_actions(),
+            ..Default::default()
+        };
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(10, 1.0, 0.0);
+        let reward = 1.0;
+        ba.learn(Some(&previous_state), &action_x, &current_state, reward)
+            .unwrap();
+        ba.learn(Some(&previous_state), &action_y, &current_state, reward)
+            .unwrap();
+
+        let actual = ba.get_agent_context();
+
+        let expected = AgentContext {
+            learning_rate: 1.0,
+            discount_factor: 0.0,
+            priming_threshold: 10,
+            q_values: hashmap! {
+                "A".to_string() => hashmap! {
+                    "X".to_string() => Stats {call_count: 1, q_raw: 1.0, q_weighted: 0.696_969_696_969_696_9},
+                    "Y".to_string() => Stats {call_count: 1, q_raw: 1.0, q_weighted: 0.696_969_696_969_696_9},
+                    "Z".to_string() => Stats {call_count: 0, q_raw: 0.0, q_weighted: 0.666_666_666_666_666_6},
+                },
+                "B".to_string() => hashmap! {
+                    "X".to_string() => Stats {call_count: 0, q_raw: 0.0, q_weighted: 0.0},
+                    "Y".to_string() => Stats {call_count: 0, q_raw: 0.0, q_weighted: 0.0},
+                    "Z".to_string() => Stats {call_count: 0, q_raw: 0.0, q_weighted: 0.0},
+                },
+            },
+            state_visits: hashmap! { "A".to_string() => 2 },
+        };
+        assert_eq!(expected, actual);
+        assert_eq!(2, ba.state_visits(&"A".to_string()).unwrap());
+        assert_eq!(0, ba.state_visits(&"B".to_string()).unwrap());
+    }
+
+    #[test]
+    fn learn_updates_stats_in_place() {
+        thread_local! {
+            static CLONES: Cell<usize> = const { Cell::new(0) };
+        }
+
+        /// Stats that count how many times they are cloned.
+        #[derive(Debug, Default)]
+        struct CountedStats(Stats);
+
+        impl Clone for CountedStats {
+            fn clone(&self) -> Self {
+                CLONES.with(|clones| clones.set(clones.get() + 1));
+                Self(self.0)
+            }
+        }
+
+        impl ActionStatter for CountedStats {
+            fn calls(&self) -> u64 {
+                self.0.calls()
+            }
+
+            fn set_calls(&mut self, n: u64) {
+                self.0.set_calls(n);
+            }
+
+            fn q_value_raw(&self) -> f64 {
+                self.0.q_value_raw()
+            }
+
+            fn set_q_value_raw(&mut self, q: f64) {
+                self.0.set_q_value_raw(q);
+            }
+
+            fn q_value_weighted(&self) -> f64 {
+                self.0.q_value_weighted()
+            }
+
+            fn set_q_value_weighted(&mut self, q: f64) {
+                self.0.set_q_value_weighted(q);
+            }
+        }
+
+        let action_x = MockActioner { return_id: "X" };
+        let action_y = MockActioner { return_id: "Y" };
+        let state = |return_id| MockStater {
+            return_id,
+            return_possible_actions: vec![&action_x, &action_y],
+            ..Default::default()
+        };
+        let (state_a, state_b) = (state("A"), state("B"));
+
+        for sparse in [false, true] {
+            let mut ba: Agent<MockStater<MockActioner>, MockActioner, CountedStats> =
+                Agent::new(1, 0.5, 0.9).with_sparse_storage(sparse);
+            CLONES.with(|clones| clones.set(0));
+            for _ in 0..3 {
+                ba.learn(Some(&state_a), &action_x, &state_b, 1.0).unwrap();
+                ba.learn(Some(&state_b), &action_y, &state_a, 2.0).unwrap();
+            }
+
+            assert_eq!(0, CLONES.with(Cell::get));
+            let stats = ba.qstore.stats(&"A".to_string(), &"X".to_string()).unwrap();
+            assert_eq!(3, stats.calls());
+        }
+    }
+
+    #[test]
+    fn learn_with_exploration_bonus() {
+        let action_x = MockActioner { return_id: "X" };
+        let mock_actions = || -> Vec<&MockActioner> { vec![&action_x] };
+
+        let previous_state = MockStater {
+            return_id: "A",
+            return_possible_actions: mock_actions(),
+            ..Default::default()
+        };
+
+        let current_state = MockStater {
+            return_id: "B",
+            return_possible_actions: mock_actions(),
+            ..Default::default()
+        };
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> =
+            Agent::new(0, 1.0, 0.0).with_exploration_bonus(2.0);
+        ba.learn(Some(&previous_state), &action_x, &current_state, 1.0)
+            .unwrap();
+        ba.learn(Some(&previous_state), &action_x, &current_state, 1.0)
+            .unwrap();
+        ba.learn(Some(&previous_state), &action_x, &current_state, 1.0)
+            .unwrap();
+        ba.learn(Some(&previous_state), &action_x, &current_state, 1.0)
+            .unwrap();
+
+        let actual = ba.get_agent_context();
+        let stats = &actual.q_values["A"]["X"];
+        assert_eq!(4, stats.call_count);
+        assert_eq!(2.0, stats.q_raw);
+    }
+
+    #[test]
+    fn weighted_q_values_are_calculated_on_read() {
+        let action_x = MockActioner { return_id: "X" };
+        let action_y = MockActioner { return_id: "Y" };
+        let state = |return_id| MockStater {
+            return_id,
+            return_possible_actions: vec![&action_x, &action_y],
+            ..Default::default()
+        };
+        let (state_a, state_b) = (state("A"), state("B"));
+        let (a, y) = ("A".to_string(), "Y".to_string());
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(1, 1.0, 0.0);
+        ba.learn(Some(&state_a), &action_x, &state_b, 1.0).unwrap();
+        assert_eq!(1.0, ba.qstore.stats(&a, &y).unwrap().q_weighted);
+
+        // Learning only writes the stats of the action taken, so the weighted
+        // q-value recorded for Y is now stale, but is recalculated on read.
+        ba.learn(Some(&state_a), &action_x, &state_b, 3.0).unwrap();
+        assert_eq!(1.0, ba.qstore.stats(&a, &y).unwrap().q_weighted);
+        assert_eq!(1.5, ba.get_agent_context().q_values[&a][&y].q_weighted);
+        assert_eq!(Some(1.5), ba.weighted_q_value(&a, &y));
+        assert_eq!(None, ba.weighted_q_value(&"C".to_string(), &y));
+        let (_, _, stats) = ba
+            .iter_q_values()
+            .find(|(state_id, action_id, _)| (*state_id, *action_id) == (&a, &y))
+            .unwrap();
+        assert_eq!(1.0, stats.q_weighted);
+        assert_eq!("X", ba.recommend_action(&state_a).unwrap().id());
+    }
+
+    #[test]
+    fn learn_off_policy() {
+        let action_x = MockActioner { return_id: "X" };
+        let action_y = MockActioner { return_id: "Y" };
+        let state = |return_id| MockStater {
+            return_id,
+            return_possible_actions: vec![&action_x, &action_y],
+            ..Default::default()
+        };
+        let (state_a, state_b) = (state("A"), state("B"));
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(0, 0.5, 0.0);
+        ba.learn_off_policy(Some(&state_a), &action_y, &state_b, 1.0, 0.0)
+            .unwrap();
+        assert_eq!(0, ba.step_count());
+        assert!(ba.get_agent_context().q_values.is_empty());
+
+        ba.learn_off_policy(Some(&state_a), &action_x, &state_b, 1.0, 2.0)
+            .unwrap();
+        assert_eq!(1.0, ba.get_agent_context().q_values["A"]["X"].q_raw);
+
+        assert_eq!(2.0, ba.importance_weight(&state_a, &action_x, 0.5).unwrap());
+        assert_eq!(0.0, ba.importance_weight(&state_a, &action_y, 0.5).unwrap());
+        assert_eq!(0.5, ba.importance_weight(&state_b, &action_y, 1.0).unwrap());
+
+        for weight in [-1.0, f64::NAN, f64::INFINITY] {
+            let err = ba
+                .learn_off_policy(Some(&state_a), &action_x, &state_b, 1.0, weight)
+                .unwrap_err();
+            assert!(matches!(err, LearnerError::InvalidArgument(_)));
+        }
+        for probability in [0.0, 1.5, f64::NAN] {
+            let err = ba
+                .importance_weight(&state_a, &action_x, probability)
+                .unwrap_err();
+            assert!(matches!(err, LearnerError::InvalidArgument(_)));
+        }
+    }
+
+    #[test]
+    fn importance_weight_follows_action_costs() {
+        let action_x = MockActioner { return_id: "X" };
+        let action_y = MockActioner { return_id: "Y" };
+        let state = |return_id| MockStater {
+            return_id,
+            return_possible_actions: vec![&action_x, &action_y],
+            ..Default::default()
+        };
+        let (state_a, state_b) = (state("A"), state("B"));
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(0, 1.0, 0.0);
+        ba.learn(Some(&state_a), &action_x, &state_b, 1.0).unwrap();
+        ba.learn(Some(&state_a), &action_y, &state_b, 0.5).unwrap();
+        assert_eq!(2.0, ba.importance_weight(&state_a, &action_x, 0.5).unwrap());
+        assert_eq!(0.0, ba.importance_weight(&state_a, &action_y, 0.5).unwrap());
+
+        // The cost of X makes Y the greedy action.
+        let mut ba = ba.with_action_costs(vec![("X".to_string(), 1.0)]);
+        assert_eq!("Y", ba.recommend_action(&state_a).unwrap().id());
+        assert_eq!(0.0, ba.importance_weight(&state_a, &action_x, 0.5).unwrap());
+        assert_eq!(2.0, ba.importance_weight(&state_a, &action_y, 0.5).unwrap());
+    }
+
+    #[test]
+    fn learn_rejects_non_finite_values() {
+        let action_x = MockActioner { return_id: "X" };
+        let state = |return_id| MockStater {
+            return_id,
+            return_possible_actions: vec![&action_x],
+            ..Default::default()
+        };
+        let (a, b) = (state("A"), state("B"));
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(0, 0.5, 0.0);
+        ba.learn(Some(&a), &action_x, &b, 1.0).unwrap();
+        let before = ba.get_agent_context();
+
+        for reward in &[f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            assert!(matches!(
+                ba.learn(Some(&a), &action_x, &b, *reward),
+                Err(LearnerError::NonFinite(_))
+            ));
+        }
+        assert_eq!(before, ba.get_agent_context());
+        assert_eq!(1, ba.step_count());
+
+        let mut overflowing: Agent<MockStater<MockActioner>, MockActioner, Stats> =
+            Agent::new(0, 4.0, 0.0);
+        assert!(matches!(
+            overflowing.learn(Some(&a), &action_x, &b, f64::MAX),
+            Err(LearnerError::NonFinite(_))
+        ));
+        assert_eq!(0, overflowing.state_visits(&"A".to_string()).unwrap());
+    }
+
+    #[test]
+    fn learn_with_clamped_non_finite_values() {
+        let action_x = MockActioner { return_id: "X" };
+        let state = |return_id| MockStater {
+            return_id,
+            return_possible_actions: vec![&action_x],
+            ..Default::default()
+        };
+        let (a, b) = (state("A"), state("B"));
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> =
+            Agent::new(0, 1.0, 0.0).with_non_finite_policy(NonFinitePolicy::Clamp);
+        ba.learn(Some(&a), &action_x, &b, f64::INFINITY).unwrap();
+        assert_eq!(f64::MAX, ba.get_agent_context().q_values["A"]["X"].q_raw);
+        ba.learn(Some(&a), &action_x, &b, f64::NAN).unwrap();
+        assert_eq!(0.0, ba.get_agent_context().q_values["A"]["X"].q_raw);
+    }
+
+    #[test]
+    fn recommend_action_with_non_finite_q_values() {
+        let action_x = MockActioner { return_id: "X" };
+        let action_y = MockActioner { return_id: "Y" };
+        let state = MockStater {
+            return_id: "A",
+            return_possible_actions: vec![&action_x, &action_y],
+            ..Default::default()
+        };
+        let context = AgentContext {
+            learning_rate: 1.0,
+            discount_factor: 0.0,
+            priming_threshold: 0,
+            q_values: hashmap! {
+                "A".to_string() => hashmap! {
+                    "X".to_string() => Stats { call_count: 1, q_raw: f64::NAN, q_weighted: 0.0 },
+                    "Y".to_string() => Stats { call_count: 1, q_raw: -1.0, q_weighted: 0.0 },
+                },
+            },
+            state_visits: HashMap::new(),
+        };
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> =
+            Agent::from_agent_context(context.clone());
+        assert!(matches!(
+            ba.recommend_action(&state),
+            Err(LearnerError::NonFinite(_))
+        ));
+        assert!(ba.recommender().recommend_action(&state).is_err());
+        assert!(ba.iter_q_values().any(|(state_id, action_id, _)| ba
+            .weighted_q_value(state_id, action_id)
+            .is_some_and(f64::is_nan)));
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> =
+            Agent::from_agent_context(context).with_non_finite_policy(NonFinitePolicy::Clamp);
+        assert_eq!("X", ba.recommend_action(&state).unwrap().id());
+        assert_eq!("X", ba.recommender().recommend_action(&state).unwrap().id());
+    }
+
+    #[test]
+    fn set_hyperparameters() {
+        let action_x = MockActioner { return_id: "X" };
+        let state = |return_id| MockStater {
+            return_id,
+            return_possible_actions: vec![&action_x],
+            ..Default::default()
+        };
+        let (a, b) = (state("A"), state("B"));
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(0, 1.0, 0.0);
+        ba.learn(Some(&a), &action_x, &b, 4.0).unwrap();
+
+        ba.set_learning_rate(0.5);
+        ba.set_discount_factor(0.9);
+        ba.set_priming_threshold(2);
+        assert_eq!(0.5, ba.learning_rate());
+        assert_eq!(0.9, ba.discount_factor());
+        assert_eq!(2, ba.priming_threshold());
+
+        // The q-table is kept, and the new learning rate is applied to it.
+        ba.learn(Some(&a), &action_x, &b, 0.0).unwrap();
+        let context = ba.get_agent_context();
+        assert_eq!(2, context.q_values["A"]["X"].call_count);
+        assert_eq!(2.0, context.q_values["A"]["X"].q_raw);
+        assert_eq!(2, context.priming_threshold);
+    }
+
+    #[test]
+    fn learning_rate_schedule() {
+        let action_x = MockActioner { return_id: "X" };
+        let state = |return_id| MockStater {
+            return_id,
+            return_possible_actions: vec![&action_x],
+            ..Default::default()
+        };
+        let (a, b) = (state("A"), state("B"));
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> =
+            Agent::new(0, 0.1, 0.0).with_learning_rate_schedule(|n| 1.0 / math::count_to_f64(n));
+        for reward in &[3.0, 6.0, 9.0] {
+            ba.learn(Some(&a), &action_x, &b, *reward).unwrap();
+        }
+
+        // With a rate of 1/n, the q-value is the mean of the rewards.
+        let context = ba.get_agent_context();
+        assert!((context.q_values["A"]["X"].q_raw - 6.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn discount_override() {
+        let action_x = MockActioner { return_id: "X" };
+        let state = |return_id| MockStater {
+            return_id,
+            return_possible_actions: vec![&action_x],
+            ..Default::default()
+        };
+        let (a, b, goal) = (state("A"), state("B"), state("Goal"));
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(0, 1.0, 1.0)
+            .with_discount_override(|s: &MockStater<MockActioner>| {
+                (s.return_id == "Goal").then_some(0.0)
+            });
+        ba.learn(Some(&b), &action_x, &a, 2.0).unwrap();
+        ba.learn(Some(&goal), &action_x, &a, 5.0).unwrap();
+        ba.learn(Some(&a), &action_x, &b, 1.0).unwrap();
+        ba.learn(Some(&b), &action_x, &goal, 1.0).unwrap();
+
+        let context = ba.get_agent_context();
+        // Moving to B is discounted by the default factor of 1...
+        assert_eq!(3.0, context.q_values["A"]["X"].q_raw);
+        // ...but moving to the goal state is not discounted at all.
+        assert_eq!(1.0, context.q_values["B"]["X"].q_raw);
+    }
+
+    #[test]
+    fn reward_shaper() {
+        let action_x = MockActioner { return_id: "X" };
+        let state = |return_id| MockStater {
+            return_id,
+            return_possible_actions: vec![&action_x],
+            ..Default::default()
+        };
+        let (a, b) = (state("A"), state("B"));
+        let potential = |s: &MockStater<MockActioner>| if s.return_id == "B" { 2.0 } else { 0.0 };
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, RewardStats> =
+            Agent::new(0, 1.0, 0.0).with_reward_shaper(move |previous, _, current, reward| {
+                reward + potential(current) - potential(previous)
+            });
+        ba.learn(Some(&a), &action_x, &b, 1.0).unwrap();
+        ba.learn(Some(&b), &action_x, &a, 1.0).unwrap();
+
+        let context = ba.get_agent_context();
+        assert_eq!(3.0, context.q_values["A"]["X"].q_value_raw());
+        assert_eq!(-1.0, context.q_values["B"]["X"].q_value_raw());
+        assert_eq!(1.0, context.q_values["A"]["X"].rewards().mean());
+    }
+
+    #[test]
+    fn hooks() {
+        let action_x = MockActioner { return_id: "X" };
+        let state = |return_id| MockStater {
+            return_id,
+            return_possible_actions: vec![&action_x],
+            ..Default::default()
+        };
+        let (a, b) = (state("A"), state("B"));
+        let learned = RefCell::new(Vec::new());
+        let recommended = RefCell::new(Vec::new());
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(0, 0.5, 0.0)
+            .on_learn(|e: &LearnEvent<MockStater<MockActioner>, MockActioner>| {
+                learned.borrow_mut().push((
+                    e.previous_state.return_id,
+                    e.action.return_id,
+                    e.reward,
+                    e.old_q,
+                    e.new_q,
+                ));
+            })
+            .on_recommend(
+                |e: &RecommendEvent<MockStater<MockActioner>, MockActioner>| {
+                    recommended.borrow_mut().push((
+                        e.state.return_id,
+                        e.action.return_id,
+                        e.q_value,
+                    ));
+                },
+            );
+        ba.learn(Some(&a), &action_x, &b, 4.0).unwrap();
+        ba.learn(Some(&a), &action_x, &b, 4.0).unwrap();
+        ba.recommend_action(&a).unwrap();
+
+        assert_eq!(
+            vec![("A", "X", 4.0, 0.0, 2.0), ("A", "X", 4.0, 2.0, 3.0)],
+            *learned.borrow()
+        );
+        assert_eq!(vec![("A", "X", 3.0)], *recommended.borrow());
+    }
+
+    #[test]
+    fn q_delta_window() {
+        let action_x = MockActioner { return_id: "X" };
+        let state = |return_id| MockStater {
+            return_id,
+            return_possible_actions: vec![&action_x],
+            ..Default::default()
+        };
+        let (a, b) = (state("A"), state("B"));
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> =
+            Agent::new(0, 0.5, 0.0).with_q_delta_window(2);
+        assert_eq!(None, ba.mean_q_delta());
+        for reward in &[8.0, 8.0, 8.0] {
+            ba.learn(Some(&a), &action_x, &b, *reward).unwrap();
+        }
+
+        // The raw q-value moves from 0 to 4, 6, and then 7.
+        assert_eq!(Some(1.5), ba.mean_q_delta());
+        assert_eq!(Some(2.0), ba.max_q_delta());
+
+        let mut untracked: Agent<MockStater<MockActioner>, MockActioner, Stats> =
+            Agent::new(0, 0.5, 0.0);
+        untracked.learn(Some(&a), &action_x, &b, 1.0).unwrap();
+        assert_eq!(None, untracked.max_q_delta());
+        assert_eq!(None, untracked.max_td_error());
+        assert!(!untracked.has_converged(f64::MAX));
+    }
+
+    #[test]
+    fn td_error_window() {
+        let action_x = MockActioner { return_id: "X" };
+        let state = |return_id| MockStater {
+            return_id,
+            return_possible_actions: vec![&action_x],
+            ..Default::default()
+        };
+        let (a, b) = (state("A"), state("B"));
+
+        let errors = RefCell::new(vec![]);
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(0, 0.5, 0.0)
+            .with_td_error_window(2)
+            .on_learn(|e| errors.borrow_mut().push(e.td_error));
+        assert_eq!(None, ba.mean_td_error());
+        for reward in &[8.0, 8.0, 0.0] {
+            ba.learn(Some(&a), &action_x, &b, *reward).unwrap();
+        }
+
+        // The raw q-value moves from 0 to 4, 6, and then 3.
+        assert_eq!(vec![8.0, 4.0, -6.0], *errors.borrow());
+        assert_eq!(Some(5.0), ba.mean_td_error());
+        assert_eq!(Some(6.0), ba.max_td_error());
+    }
+
+    #[test]
+    fn has_converged() {
+        let action_x = MockActioner { return_id: "X" };
+        let state = |return_id| MockStater {
+            return_id,
+            return_possible_actions: vec![&action_x],
+            ..Default::default()
+        };
+        let (a, b) = (state("A"), state("B"));
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> =
+            Agent::new(0, 0.5, 0.0).with_q_delta_window(3);
+        let mut updates = 0;
+        while !ba.has_converged(0.01) {
+            ba.learn(Some(&a), &action_x, &b, 1.0).unwrap();
+            updates += 1;
+            assert!(updates < 100);
+        }
+
+        // The q-value halves its distance to 1 with each update, so the
+        // changes fall to 0.01 or less from the seventh update onwards, and
+        // the window holds only such changes after the ninth.
+        assert_eq!(9, updates);
+        assert!(ba.max_q_delta().unwrap() <= 0.01);
+    }
+
+    #[test]
+    fn recommend_action_with_initial_q() {
+        let action_x = MockActioner { return_id: "X" };
+        let action_y = MockActioner { return_id: "Y" };
+        let mock_actions = || -> Vec<&MockActioner> { vec![&action_x, &action_y] };
+
+        let previous_state = MockStater {
+            return_id: "A",
+            return_possible_actions: mock_actions(),
+            ..Default::default()
+        };
+
+        let current_state = MockStater {
+            return_id: "B",
+            return_possible_actions: mock_actions(),
+            ..Default::default()
+        };
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> =
+            Agent::new(1, 1.0, 0.0).with_initial_q(10.0);
+        ba.learn(Some(&previous_state), &action_x, &current_state, 1.0)
+            .unwrap();
+
+        let context = ba.get_agent_context();
+        assert_eq!(1.0, context.q_values["A"]["X"].q_raw);
+        assert_eq!(10.0, context.q_values["A"]["Y"].q_raw);
+        assert_eq!(10.0, context.q_values["B"]["X"].q_raw);
+
+        let recommended = ba.recommend_action(&previous_state).unwrap();
+        assert_eq!("Y", recommended.id());
+    }
+
+    #[test]
+    fn recommend_action_with_action_costs() {
+        let action_x = MockActioner { return_id: "X" };
+        let action_y = MockActioner { return_id: "Y" };
+        let state = MockStater {
+            return_id: "A",
+            return_possible_actions: vec![&action_x, &action_y],
+            ..Default::default()
+        };
+        let end = MockStater {
+            return_id: "B",
+            ..Default::default()
+        };
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> =
+            Agent::new(0, 1.0, 0.0).with_action_costs(vec![("X".to_string(), 0.5)]);
+        assert_eq!(0.5, ba.action_cost(&"X".to_string()));
+        assert_eq!(0.0, ba.action_cost(&"Y".to_string()));
+        ba.learn(Some(&state), &action_x, &end, 1.0).unwrap();
+        ba.learn(Some(&state), &action_y, &end, 0.6).unwrap();
+
+        // X is worth more, but not by enough to cover its cost.
+        assert_eq!("Y", ba.recommend_action(&state).unwrap().id());
+        assert_eq!("Y", ba.recommender().recommend_action(&state).unwrap().id());
+        let context = ba.get_agent_context();
+        assert_eq!(1.0, context.q_values["A"]["X"].q_raw);
+
+        let mut ba = ba.with_action_costs(vec![("X".to_string(), 0.2)]);
+        assert_eq!("X", ba.recommend_action(&state).unwrap().id());
+    }
+
+    #[test]
+    fn recommendation_counts() {
+        let action_x = MockActioner { return_id: "X" };
+        let action_y = MockActioner { return_id: "Y" };
+        let state = MockStater {
+            return_id: "A",
+            return_possible_actions: vec![&action_x, &action_y],
+            ..Default::default()
+        };
+        let end = MockStater {
+            return_id: "B",
+            ..Default::default()
+        };
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(0, 1.0, 0.0);
+        ba.learn(Some(&state), &action_x, &end, 1.0).unwrap();
+        ba.learn(Some(&state), &action_y, &end, 0.5).unwrap();
+        assert!(ba.recommendation_counts(&"A".to_string()).is_empty());
+        assert!(ba.recommendation_frequencies(&"A".to_string()).is_empty());
+
+        for _ in 0..3 {
+            ba.recommend_action(&state).unwrap();
+        }
+        ba.learn(Some(&state), &action_x, &end, -5.0).unwrap();
+        ba.recommend_action(&state).unwrap();
+
+        let counts = ba.recommendation_counts(&"A".to_string());
+        assert_eq!(3, counts["X"]);
+        assert_eq!(1, counts["Y"]);
+        let frequencies = ba.recommendation_frequencies(&"A".to_string());
+        assert_eq!(0.75, frequencies["X"]);
+        assert_eq!(0.25, frequencies["Y"]);
+        assert_eq!(1, ba.iter_recommendation_counts().count());
+        // Learning is counted separately from recommending.
+        assert_eq!(2, ba.get_agent_context().q_values["A"]["X"].calls());
+
+        ba.reset_recommendation_counts();
+        assert!(ba.recommendation_counts(&"A".to_string()).is_empty());
+    }
+
+    #[test]
+    fn recommend_action_detailed() {
+        let action_x = MockActioner { return_id: "X" };
+        let action_y = MockActioner { return_id: "Y" };
+        let state = MockStater {
+            return_id: "A",
+            return_possible_actions: vec![&action_x, &action_y],
+            ..Default::default()
+        };
+        let end = MockStater {
+            return_id: "B",
+            ..Default::default()
+        };
+
+        let kinds = RefCell::new(vec![]);
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(0, 1.0, 0.0)
+            .with_ucb_exploration(1.0)
+            .on_recommend(|e| kinds.borrow_mut().push(e.kind));
+        ba.tie_breaker = Box::new(|n, _| n - 1);
+        let recommendation = ba.recommend_action_detailed(&state).unwrap();
+        assert_eq!("Y", recommendation.action.id());
+        assert_eq!(DecisionKind::TieBreak, recommendation.kind);
+
+        ba.learn(Some(&state), &action_x, &end, 1.0).unwrap();
+        let recommendation = ba.recommend_action_detailed(&state).unwrap();
+        assert_eq!("Y", recommendation.action.id());
+        assert_eq!(DecisionKind::Exploratory, recommendation.kind);
+        let ids: Vec<&str> = recommendation
+            .candidates
+            .iter()
+            .map(|candidate| candidate.action_id.as_str())
+            .collect();
+        assert_eq!(vec!["X", "Y"], ids);
+        assert_eq!(1.0, recommendation.candidates[0].q_value);
+        assert_eq!(0.0, recommendation.candidates[1].q_value);
+        assert!(recommendation.candidates[1].score > recommendation.candidates[0].score);
+
+        ba.learn(Some(&state), &action_y, &end, 0.5).unwrap();
+        let recommendation = ba.recommend_action_detailed(&state).unwrap();
+        assert_eq!("X", recommendation.action.id());
+        assert_eq!(DecisionKind::Greedy, recommendation.kind);
+        assert_eq!("greedy", recommendation.kind.to_string());
+
+        assert_eq!(
+            vec![
+                DecisionKind::TieBreak,
+                DecisionKind::Exploratory,
+                DecisionKind::Greedy
+            ],
+            *kinds.borrow()
+        );
+        assert_eq!(
+            3,
+            ba.recommendation_counts(&"A".to_string())
+                .values()
+                .sum::<u64>()
+        );
+    }
+
+    #[test]
+    fn recommend_action_with_ucb_exploration() {
+        let action_x = MockActioner { return_id: "X" };
+        let action_y = MockActioner { return_id: "Y" };
+        let state = MockStater {
+            return_id: "A",
+            return_possible_actions: vec![&action_x, &action_y],
+            ..Default::default()
+        };
+        let end = MockStater {
+            return_id: "B",
+            ..Default::default()
+        };
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> =
+            Agent::new(0, 1.0, 0.0).with_ucb_exploration(1.0);
+        ba.learn(Some(&state), &action_x, &end, 1.0).unwrap();
+
+        // Y is unobserved, so it is preferred despite its lower q-value.
+        assert_eq!("Y", ba.recommend_action(&state).unwrap().id());
+
+        // Once Y has been observed as often as X, the q-values decide.
+        ba.learn(Some(&state), &action_y, &end, 0.5).unwrap();
+        assert_eq!("X", ba.recommend_action(&state).unwrap().id());
+
+        // X's bonus shrinks as it is observed more often, until the less
+        // certain Y is worth exploring again:
+        // 1 + sqrt(ln(6) / 5) < 0.5 + sqrt(ln(6) / 1)
+        for _ in 0..4 {
+            ba.learn(Some(&state), &action_x, &end, 1.0).unwrap();
+        }
+        assert_eq!("Y", ba.recommend_action(&state).unwrap().id());
+
+        // Without UCB selection the q-values alone decide.
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(0, 1.0, 0.0);
+        ba.learn(Some(&state), &action_x, &end, 1.0).unwrap();
+        assert_eq!("X", ba.recommend_action(&state).unwrap().id());
+    }
+
+    #[test]
+    fn recommend_action_with_seeded_rng() {
+        use rand::rngs::StdRng;
+        use rand::SeedableRng;
+
+        let actions: Vec<MockActioner> = ["A", "B", "C", "D", "E"]
+            .iter()
+            .map(|id| MockActioner { return_id: id })
+            .collect();
+        let state = MockStater {
+            return_id: "S",
+            return_possible_actions: actions.iter().collect(),
+            ..Default::default()
+        };
+
+        let recommend_many = |seed: u64| -> Vec<String> {
+            let mut a: Agent<MockStater<MockActioner>, MockActioner, Stats> =
+                Agent::new(0, 0.0, 0.0).with_rng(StdRng::seed_from_u64(seed));
+            (0..20)
+                .map(|_| a.recommend_action(&state).unwrap().id())
+                .collect()
+        };
+
+        assert_eq!(recommend_many(42), recommend_many(42));
+        assert_ne!(recommend_many(42), recommend_many(7));
+    }
+
+    #[test]
+    fn learn_with_sparse_storage() {
+        let action_x = MockActioner { return_id: "X" };
+        let action_y = MockActioner { return_id: "Y" };
+        let action_z = MockActioner { return_id: "Z" };
+        let mock_actions = || -> Vec<&MockActioner> { vec![&action_x, &action_y, &action_z] };
+
+        let previous_state = MockStater {
+            return_id: "A",
+            return_possible_actions: mock_actions(),
+            ..Default::default()
+        };
+
+        let current_state = MockStater {
+            return_id: "B",
+            return_possible_actions: mock_actions(),
+            ..Default::default()
+        };
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(1, 1.0, 0.0)
+            .with_initial_q(2.0)
+            .with_sparse_storage(true);
+        ba.tie_breaker = Box::new(|_, _| 0);
+        ba.learn(Some(&previous_state), &action_x, &current_state, 1.0)
+            .unwrap();
+        assert_eq!("Y", ba.recommend_action(&previous_state).unwrap().id());
+        ba.learn(Some(&previous_state), &action_y, &current_state, 1.0)
+            .unwrap();
+        assert_eq!("Z", ba.recommend_action(&previous_state).unwrap().id());
+
+        let context = ba.get_agent_context();
+        assert_eq!(1, context.q_values.len());
+        assert_eq!(Ok(1), ba.state_count());
+        assert_eq!(Ok(2), ba.entry_count());
+        let mut iterated: Vec<(&String, &String, u64)> = ba
+            .iter_q_values()
+            .map(|(state_id, action_id, stats)| (state_id, action_id, stats.call_count))
+            .collect();
+        iterated.sort();
+        assert_eq!(
+            vec![
+                (&"A".to_string(), &"X".to_string(), 1),
+                (&"A".to_string(), &"Y".to_string(), 1)
+            ],
+            iterated
+        );
+        let stored_actions = &context.q_values["A"];
+        assert_eq!(2, stored_actions.len());
+        assert_eq!(1, stored_actions["X"].call_count);
+        assert_eq!(1.0, stored_actions["X"].q_raw);
+        assert_eq!(1, stored_actions["Y"].call_count);
+        assert_eq!(1.0, stored_actions["Y"].q_raw);
+    }
+
+    #[test]
+    fn lifecycle_transitions() {
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(0, 0.0, 0.0);
+        assert_eq!(Lifecycle::Learning, ba.lifecycle());
+        assert!(ba.unfreeze().is_err());
+
+        assert!(ba.freeze().is_ok());
+        assert_eq!(Lifecycle::Frozen, ba.lifecycle());
+        assert!(ba.freeze().is_err());
+
+        assert!(ba.unfreeze().is_ok());
+        assert_eq!(Lifecycle::Learning, ba.lifecycle());
+
+        assert!(ba.drain().is_ok());
+        assert_eq!(Lifecycle::Draining, ba.lifecycle());
+        assert_eq!(
+            "agent cannot move from draining to learning",
+            ba.unfreeze().unwrap_err().message()
+        );
+        assert!(ba.freeze().is_err());
+    }
+
+    #[test]
+    fn learn_when_frozen() {
+        let action_x = MockActioner { return_id: "X" };
+        let previous_state = MockStater {
+            return_id: "A",
+            return_possible_actions: vec![&action_x],
+            ..Default::default()
+        };
+        let current_state = MockStater {
+            return_id: "B",
+            return_possible_actions: vec![&action_x],
+            ..Default::default()
+        };
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(0, 1.0, 0.0);
+        ba.freeze().unwrap();
+        let result = ba.learn(Some(&previous_state), &action_x, &current_state, 1.0);
+
+        assert_eq!(
+            "agent is frozen and cannot learn",
+            result.unwrap_err().message()
+        );
+        assert!(ba.get_agent_context().q_values.is_empty());
+    }
+
+    #[test]
+    fn recommend_action_when_draining() {
+        let action_x = MockActioner { return_id: "X" };
+        let state = MockStater {
+            return_id: "A",
+            return_possible_actions: vec![&action_x],
+            ..Default::default()
+        };
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(0, 1.0, 0.0);
+        ba.drain().unwrap();
+        let result = ba.recommend_action(&state);
+
+        assert_eq!(
+            "agent is draining and cannot recommend actions",
+            result.unwrap_err().message()
+        );
+    }
+
+    #[test]
+    fn agent_context_display() {
+        let context = AgentContext {
+            learning_rate: 1.0,
+            discount_factor: 0.0,
+            priming_threshold: 0,
+            q_values: hashmap! {
+                "B" => hashmap! {
+                    "X" => Stats { call_count: 12, q_raw: -1.5, q_weighted: 0.25 },
+                },
+                "A" => hashmap! {
+                    "Y" => Stats { call_count: 1, q_raw: 10.0, q_weighted: 0.5 },
+                    "X" => Stats { call_count: 2, q_raw: 1.0, q_weighted: 0.75 },
+                },
+            },
+            state_visits: HashMap::new(),
         };
 
-        self.apply_action_weights(current_state);
-        let new_value = math::bellman(
-            stats.q_value_weighted(),
-            self.learning_rate,
-            reward,
-            self.discount_factor,
-            self.get_best_value(current_state),
-        );
-        stats.set_calls(stats.calls() + 1);
-        stats.set_q_value_raw(new_value);
-        self.qmap.update_stats(previous_state, action_taken, stats);
-        self.apply_action_weights(previous_state);
+        let expected = "state  action  calls  q_raw  q_weighted\n\
+                        A      X           2   1.00        0.75\n\
+                        A      Y           1  10.00        0.50\n\
+                        B      X          12  -1.50        0.25\n";
+        assert_eq!(expected, format!("{:.2}", context));
+        assert!(context.to_string().contains("10.0000"));
     }
 
-    /// `transition` applies an action to a given state.
-    fn transition(&self, current_state: &'a S, action: &'a A) -> Result<(), LearnerError> {
-        if !current_state.action_is_compatible(action) {
-            return Err(LearnerError::new(format!(
-                "action {} is not compatible with state {}",
-                action.id().to_string(),
-                current_state.id().to_string()
-            )));
-        }
-        current_state.apply(action)
+    #[test]
+    fn extract_policy() {
+        let stats = |q| Stats {
+            call_count: 1,
+            q_raw: q,
+            q_weighted: q,
+        };
+        let ba: Agent<MockStater<MockActioner>, MockActioner, Stats> =
+            Agent::from_agent_context(AgentContext {
+                learning_rate: 1.0,
+                discount_factor: 0.0,
+                priming_threshold: 0,
+                q_values: hashmap! {
+                    "A".to_string() => hashmap! {
+                        "X".to_string() => stats(1.0),
+                        "Y".to_string() => stats(2.0),
+                    },
+                    "B".to_string() => hashmap! {
+                        "Y".to_string() => stats(0.5),
+                        "X".to_string() => stats(0.5),
+                        "Z".to_string() => stats(-1.0),
+                    },
+                },
+                state_visits: HashMap::new(),
+            });
+
+        assert_eq!(
+            hashmap! {
+                "A".to_string() => "Y".to_string(),
+                "B".to_string() => "X".to_string(),
+            },
+            ba.extract_policy()
+        );
+
+        let ba = ba.with_action_costs(vec![("Y".to_string(), 1.5)]);
+        assert_eq!(
+            hashmap! {
+                "A".to_string() => "X".to_string(),
+                "B".to_string() => "X".to_string(),
+            },
+            ba.extract_policy()
+        );
     }
 
-    /// `recommend_action` recommends an action for a given state based on
-    /// behavior of the model that the agent has learned thus far.
-    /// If the q-value for two or more actions are the same, the action is
-    /// chosen according to a tie-breaking function. See Agent docs for
-    /// more information.
-    fn recommend_action(&mut self, state: &'a S) -> Result<&'a A, LearnerError> {
-        #[allow(clippy::missing_docs_in_private_items)]
-        struct ActionValue<'a> {
-            a: &'a str,
-            v: f64,
-        }
+    #[test]
+    fn from_agent_context() {
+        let action_x = MockActioner { return_id: "X" };
+        let previous_state = MockStater {
+            return_id: "A",
+            return_possible_actions: vec![&action_x],
+            ..Default::default()
+        };
+        let current_state = MockStater {
+            return_id: "B",
+            return_possible_actions: vec![&action_x],
+            ..Default::default()
+        };
 
-        let mut best_actions: Vec<ActionValue> = Vec::new();
-        let mut best_value = -1.0 * f64::MAX;
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(3, 0.5, 0.9);
+        ba.learn(Some(&previous_state), &action_x, &current_state, 1.0)
+            .unwrap();
 
-        self.apply_action_weights(state);
-        for (action, stats) in self.qmap.get_actions_for_state(state) {
-            let av = ActionValue {
-                a: action,
-                v: stats.q_value_weighted(),
-            };
+        let restored: Agent<MockStater<MockActioner>, MockActioner, Stats> =
+            Agent::from_agent_context(ba.get_agent_context());
+        assert_eq!(ba.get_agent_context(), restored.get_agent_context());
+    }
 
-            if av.v > best_value {
-                best_value = av.v;
-                best_actions = vec![av];
-            } else if (av.v - best_value).abs() < f64::EPSILON {
-                best_actions.push(av);
-            }
-        }
+    #[cfg(feature = "serde")]
+    #[test]
+    fn agent_context_json_round_trip() {
+        let action_x = MockActioner { return_id: "X" };
+        let previous_state = MockStater {
+            return_id: "A",
+            return_possible_actions: vec![&action_x],
+            ..Default::default()
+        };
+        let current_state = MockStater {
+            return_id: "B",
+            return_possible_actions: vec![&action_x],
+            ..Default::default()
+        };
 
-        if best_actions.is_empty() {
-            return Err(LearnerError::new(format!(
-                "state '{}' reports no possible actions",
-                state.id()
-            )));
-        }
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(3, 0.5, 0.9);
+        ba.learn(Some(&previous_state), &action_x, &current_state, 1.0)
+            .unwrap();
 
-        // Order of records in a hashmap is nondeterministic, so we sort
-        // alphabetically by action ID to get a deterministic result.
-        // Note that it is documented that it is the implementor's
-        // responsibility to ensure that each action's ID is unique across all
-        // possible actions within the scope of the agent, and that having
-        // different actions share an ID will cause undefined behavior.
-        best_actions.sort_by(|x, y| x.a.cmp(y.a));
-        let tie_breaker = (self.tie_breaker)(best_actions.len());
-        state.get_action(best_actions[tie_breaker].a)
+        let json = serde_json::to_string(&ba.get_agent_context()).unwrap();
+        let context: AgentContext<String, String, Stats> = serde_json::from_str(&json).unwrap();
+        assert_eq!(ba.get_agent_context(), context);
     }
-}
 
-impl<'a, S, A: 'a, AS> Agent<'a, S, A, AS>
-where
-    S: Stater<'a, A>,
-    A: Actioner<'a>,
-    AS: ActionStatter,
-{
-    /// new returns a reference to a new Agent.
-    ///
-    /// `priming_threshold`:
-    ///  The number of observations required of any action before the action's
-    ///  raw q-value is trusted more than average q-value for all of a state's
-    ///  actions.
-    ///
-    /// `learning_rate`:
-    ///  Typically a number between 0 and 1 (though it can exceed 1)
-    ///  From wikipedia: Determins to what extent newly acquired information
-    ///  overrides old information.
-    ///  see: [https://en.wikipedia.org/wiki/Q-learning#Learning_Rate](https://en.wikipedia.org/wiki/Q-learning#Learning_Rate)
-    ///
-    /// `discount_factor`:
-    ///  From wikipedia: The discount factor determines the importance of future
-    ///  rewards.
-    ///  see: [https://en.wikipedia.org/wiki/Q-learning#Discount_factor](https://en.wikipedia.org/wiki/Q-learning#Discount_factor)
-    pub fn new(
-        priming_threshold: i32,
-        learning_rate: f64,
-        discount_factor: f64,
-    ) -> Agent<'a, S, A, AS>
-    where
-        S: Stater<'a, A>,
-        A: Actioner<'a>,
-        AS: ActionStatter,
-    {
-        Agent {
-            tie_breaker: Box::new(|n: usize| -> usize { rand::thread_rng().gen_range(0, n) }),
-            qmap: Box::new(QMap::new()),
-            learning_rate,
-            discount_factor,
-            priming_threshold,
-            _actioner: marker::PhantomData {},
-            _stater: marker::PhantomData {},
-        }
-    }
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn save_to_and_load_from() {
+        let action_x = MockActioner { return_id: "X" };
+        let action_y = MockActioner { return_id: "Y" };
+        let previous_state = MockStater {
+            return_id: "A",
+            return_possible_actions: vec![&action_x, &action_y],
+            ..Default::default()
+        };
+        let current_state = MockStater {
+            return_id: "B",
+            return_possible_actions: vec![&action_x, &action_y],
+            ..Default::default()
+        };
 
-    /// Returns the `AgentContext` representing the current state of the agent.
-    pub fn get_agent_context(&self) -> AgentContext<AS> {
-        AgentContext {
-            learning_rate: self.learning_rate,
-            discount_factor: self.discount_factor,
-            priming_threshold: self.priming_threshold,
-            q_values: self.qmap.data.clone(),
-        }
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(3, 0.5, 0.9);
+        ba.learn(Some(&previous_state), &action_x, &current_state, 1.0)
+            .unwrap();
+        ba.learn(Some(&current_state), &action_y, &previous_state, -1.0)
+            .unwrap();
+
+        let mut snapshot = Vec::new();
+        ba.save_to(&mut snapshot).unwrap();
+        let restored: Agent<MockStater<MockActioner>, MockActioner, Stats> =
+            Agent::load_from(snapshot.as_slice()).unwrap();
+        assert_eq!(ba.get_agent_context(), restored.get_agent_context());
+
+        let truncated: Result<Agent<MockStater<MockActioner>, MockActioner, Stats>, _> =
+            Agent::load_from(&snapshot[..snapshot.len() / 2]);
+        assert!(truncated.is_err());
     }
 
-    fn apply_action_weights(&mut self, state: &'a S) {
-        let mut raw_value_sum = 0.0;
-        let mut existing_action_count = 0;
-        for action in state.possible_actions() {
-            match self.qmap.get_stats(state, action) {
-                Some(s) => {
-                    raw_value_sum += s.q_value_raw();
-                    existing_action_count += 1;
-                }
-                None => self
-                    .qmap
-                    .update_stats(state, action, Box::new(AS::default())),
-            }
-        }
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn load_from_migrates_snapshot_versions() {
+        let action_x = MockActioner { return_id: "X" };
+        let previous_state = MockStater {
+            return_id: "A",
+            return_possible_actions: vec![&action_x],
+            ..Default::default()
+        };
+        let current_state = MockStater {
+            return_id: "B",
+            return_possible_actions: vec![&action_x],
+            ..Default::default()
+        };
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(3, 0.5, 0.9);
+        ba.learn(Some(&previous_state), &action_x, &current_state, 1.0)
+            .unwrap();
 
-        let mean = math::safe_divide(raw_value_sum, f64::from(existing_action_count));
-        let action_stats = self.qmap.get_actions_for_state(state);
-        for stats in action_stats.values_mut() {
-            let weighted_mean = math::bayesian_average(
-                f64::from(self.priming_threshold),
-                f64::from(stats.calls()),
-                mean,
-                stats.q_value_raw(),
-            );
-            stats.set_q_value_weighted(weighted_mean);
+        let mut snapshot = Vec::new();
+        ba.save_to(&mut snapshot).unwrap();
+        assert_eq!(SNAPSHOT_MAGIC, snapshot[..4]);
+        assert_eq!(SNAPSHOT_VERSION.to_le_bytes(), snapshot[4..6]);
+
+        // Snapshots written before the format was versioned have no header,
+        // and neither they nor version 1 snapshots record state visits.
+        let context = ba.get_agent_context();
+        let v1_body = bincode::serialize(&(0.5, 0.9, 3, &context.q_values)).unwrap();
+        let mut v1 = snapshot[..4].to_vec();
+        v1.extend_from_slice(&1_u16.to_le_bytes());
+        v1.extend_from_slice(&v1_body);
+        for old in &[v1_body, v1] {
+            let restored: Agent<MockStater<MockActioner>, MockActioner, Stats> =
+                Agent::load_from(old.as_slice()).unwrap();
+            let restored = restored.get_agent_context();
+            assert_eq!(context.q_values, restored.q_values);
+            assert!(restored.state_visits.is_empty());
         }
+
+        let mut future = snapshot;
+        future[4..6].copy_from_slice(&(SNAPSHOT_VERSION + 1).to_le_bytes());
+        let result: Result<Agent<MockStater<MockActioner>, MockActioner, Stats>, _> =
+            Agent::load_from(future.as_slice());
+        assert_eq!(
+            format!(
+                "unable to load agent snapshot: version {} is newer than the newest supported version ({})",
+                SNAPSHOT_VERSION + 1,
+                SNAPSHOT_VERSION
+            ),
+            result.err().unwrap().message()
+        );
     }
 
-    fn get_best_value(&mut self, state: &'a S) -> f64 {
-        let mut best_q_value = 0.0;
-        for stat in self.qmap.get_actions_for_state(state).values() {
-            let q = stat.q_value_weighted();
-            if q > best_q_value {
-                best_q_value = q;
+    #[cfg(feature = "bincode")]
+    #[test]
+    fn save_to_is_deterministic() {
+        let moves = MockCorridor::moves();
+        let cell = |position| MockCell {
+            position,
+            moves: &moves,
+        };
+        let snapshot = |positions: &mut dyn Iterator<Item = usize>| {
+            let mut agent: Agent<MockCell, MockActioner, Stats> = Agent::new(0, 0.5, 0.0);
+            for position in positions {
+                let action = &moves[position % 2];
+                agent
+                    .learn(Some(&cell(position)), action, &cell(position + 1), 1.0)
+                    .unwrap();
             }
-        }
-        best_q_value
+            let mut snapshot = Vec::new();
+            agent.save_to(&mut snapshot).unwrap();
+            snapshot
+        };
+        assert_eq!(snapshot(&mut (0..20)), snapshot(&mut (0..20).rev()));
     }
-}
-
-#[cfg(test)]
-#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
-mod tests {
-    use super::*;
-    use crate::mocks::*;
-    use crate::stats::actionstats::Stats;
-    use maplit::hashmap;
-    use std::cell::RefCell;
 
     #[test]
-    fn learn() {
+    #[cfg(feature = "msgpack")]
+    fn save_and_load_msgpack() {
         let action_x = MockActioner { return_id: "X" };
-        let action_y = MockActioner { return_id: "Y" };
-        let action_z = MockActioner { return_id: "Z" };
-        let mock_actions = || -> Vec<&MockActioner> { vec![&action_x, &action_y, &action_z] };
-
         let previous_state = MockStater {
             return_id: "A",
-            return_possible_actions: mock_actions(),
+            return_possible_actions: vec![&action_x],
             ..Default::default()
         };
-
         let current_state = MockStater {
             return_id: "B",
-            return_possible_actions: mock_actions(),
+            return_possible_actions: vec![&action_x],
             ..Default::default()
         };
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(3, 0.5, 0.9);
+        ba.learn(Some(&previous_state), &action_x, &current_state, 1.0)
+            .unwrap();
 
-        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(10, 1.0, 0.0);
-        let reward = 1.0;
-        ba.learn(Some(&previous_state), &action_x, &current_state, reward);
-        ba.learn(Some(&previous_state), &action_y, &current_state, reward);
+        let mut snapshot = Vec::new();
+        ba.save_msgpack_to(&mut snapshot).unwrap();
+        let json = serde_json::to_vec(&ba.get_agent_context()).unwrap();
+        assert!(snapshot.len() < json.len());
 
-        let actual = ba.get_agent_context();
+        // Fields are named, so other MessagePack readers can find them.
+        let field = b"priming_threshold";
+        assert!(snapshot.windows(field.len()).any(|w| w == field));
 
-        let expected = AgentContext {
-            learning_rate: 1.0,
-            discount_factor: 0.0,
-            priming_threshold: 10,
-            q_values: hashmap! {
-                "A" => hashmap! {
-                    "X" => Box::new(Stats {call_count: 1, q_raw: 1.0, q_weighted: 0.696_969_696_969_696_9}),
-                    "Y" => Box::new(Stats {call_count: 1, q_raw: 1.0, q_weighted: 0.696_969_696_969_696_9}),
-                    "Z" => Box::new(Stats {call_count: 0, q_raw: 0.0, q_weighted: 0.666_666_666_666_666_6}),
-                },
-                "B" => hashmap! {
-                    "X" => Box::new(Stats {call_count: 0, q_raw: 0.0, q_weighted: 0.0}),
-                    "Y" => Box::new(Stats {call_count: 0, q_raw: 0.0, q_weighted: 0.0}),
-                    "Z" => Box::new(Stats {call_count: 0, q_raw: 0.0, q_weighted: 0.0}),
-                },
-            },
-        };
-        assert_eq!(expected, actual);
+        let restored: Agent<MockStater<MockActioner>, MockActioner, Stats> =
+            Agent::load_msgpack_from(snapshot.as_slice()).unwrap();
+        assert_eq!(ba.get_agent_context(), restored.get_agent_context());
+
+        let invalid: Result<Agent<MockStater<MockActioner>, MockActioner, Stats>, _> =
+            Agent::load_msgpack_from(&snapshot[..snapshot.len() / 2]);
+        assert!(invalid.is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn save_and_load_compressed() {
+        let actions: Vec<MockActioner> = ["X", "Y", "Z"]
+            .iter()
+            .map(|id| MockActioner { return_id: id })
+            .collect();
+        let state_ids: Vec<String> = (0..50).map(|i| format!("state-{}", i)).collect();
+        let states: Vec<MockStater<MockActioner>> = state_ids
+            .iter()
+            .map(|id| MockStater {
+                return_id: id,
+                return_possible_actions: actions.iter().collect(),
+                ..Default::default()
+            })
+            .collect();
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(3, 0.5, 0.9);
+        for pair in states.windows(2) {
+            ba.learn(Some(&pair[0]), &actions[0], &pair[1], 1.0)
+                .unwrap();
+        }
+
+        let mut uncompressed = Vec::new();
+        ba.save_to(&mut uncompressed).unwrap();
+        let mut compressed = Vec::new();
+        ba.save_compressed_to(&mut compressed, 0).unwrap();
+        assert!(compressed.len() < uncompressed.len());
+
+        let restored: Agent<MockStater<MockActioner>, MockActioner, Stats> =
+            Agent::load_compressed_from(compressed.as_slice()).unwrap();
+        assert_eq!(ba.get_agent_context(), restored.get_agent_context());
+
+        let not_compressed: Result<Agent<MockStater<MockActioner>, MockActioner, Stats>, _> =
+            Agent::load_compressed_from(uncompressed.as_slice());
+        assert!(not_compressed.is_err());
+    }
+
+    #[test]
+    fn learn_from_short_lived_states() {
+        let action_x = MockActioner { return_id: "X" };
+        let action_y = MockActioner { return_id: "Y" };
+        let state_ids: Vec<String> = (0..5).map(|i| format!("state-{}", i)).collect();
+
+        let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(0, 1.0, 0.0);
+        let mut previous_state_id: Option<&str> = None;
+        let mut previous_action: Option<&MockActioner> = None;
+        for state_id in &state_ids {
+            // Each state only lives for a single iteration of the loop.
+            let state = MockStater {
+                return_id: state_id,
+                return_possible_actions: vec![&action_x, &action_y],
+                ..Default::default()
+            };
+            if let (Some(previous_state_id), Some(previous_action)) =
+                (previous_state_id, previous_action)
+            {
+                let previous_state = MockStater {
+                    return_id: previous_state_id,
+                    return_possible_actions: vec![&action_x, &action_y],
+                    ..Default::default()
+                };
+                ba.learn(Some(&previous_state), previous_action, &state, 1.0)
+                    .unwrap();
+            }
+            previous_action = Some(ba.recommend_action(&state).unwrap());
+            previous_state_id = Some(state_id);
+        }
+
+        let context = ba.get_agent_context();
+        assert_eq!(state_ids.len(), context.q_values.len());
+        let learned_calls: u64 = context
+            .q_values
+            .values()
+            .flat_map(|actions| actions.values())
+            .map(|stats| stats.call_count)
+            .sum();
+        assert_eq!(4, learned_calls);
     }
 
     #[test]
@@ -328,7 +3415,7 @@ mod tests {
         let action_x = MockActioner { return_id: "X" };
         let mock_actions = vec![&action_x];
 
-        let applied_action_id: RefCell<Option<&str>> = RefCell::new(None);
+        let applied_action_id: RefCell<Option<String>> = RefCell::new(None);
         let current_state = MockStater {
             return_id: "A",
             return_possible_actions: mock_actions,
@@ -345,7 +3432,7 @@ mod tests {
 
         assert!(transition_result.is_ok());
         assert!(applied_action_id.borrow().is_some());
-        assert_eq!(action_x.id(), applied_action_id.borrow().unwrap());
+        assert_eq!(Some(action_x.id()), *applied_action_id.borrow());
     }
 
     #[test]
@@ -357,7 +3444,7 @@ mod tests {
         let known_action = MockActioner { return_id: "known" };
         let known_actions = vec![&known_action];
 
-        let applied_action_id: RefCell<Option<&str>> = RefCell::new(None);
+        let applied_action_id: RefCell<Option<String>> = RefCell::new(None);
         let current_state = MockStater {
             return_id: "A",
             return_possible_actions: known_actions,
@@ -374,12 +3461,217 @@ mod tests {
 
         assert!(transition_result.is_err());
         assert_eq!(
-            format!("action {} is not compatible with state {}", "unknown", "A"),
+            format!(
+                "action {:?} is not compatible with state {:?}",
+                "unknown", "A"
+            ),
             transition_result.unwrap_err().message()
         );
         assert!(applied_action_id.borrow().is_none());
     }
 
+    #[test]
+    fn merge_from() {
+        let stats = |call_count, q| Stats {
+            call_count,
+            q_raw: q,
+            q_weighted: q,
+        };
+        let other = AgentContext {
+            learning_rate: 0.1,
+            discount_factor: 0.2,
+            priming_threshold: 3,
+            q_values: hashmap! {
+                "A".to_string() => hashmap! {
+                    "X".to_string() => stats(3, 4.0),
+                    "Y".to_string() => stats(2, 5.0),
+                },
+            },
+            state_visits: hashmap! { "A".to_string() => 5 },
+        };
+
+        let cases = vec![
+            (MergeStrategy::WeightedAverage, stats(4, 3.25), 6),
+            (MergeStrategy::Average, stats(4, 2.5), 6),
+            (MergeStrategy::Replace, stats(3, 4.0), 5),
+            (MergeStrategy::KeepExisting, stats(1, 1.0), 1),
+        ];
+        for (strategy, expected_x, expected_visits) in cases {
+            let mut ba: Agent<MockStater<MockActioner>, MockActioner, Stats> =
+                Agent::from_agent_context(AgentContext {
+                    learning_rate: 1.0,
+                    discount_factor: 0.0,
+                    priming_threshold: 0,
+                    q_values: hashmap! {
+                        "A".to_string() => hashmap! {
+                            "X".to_string() => stats(1, 1.0),
+                        },
+                    },
+                    state_visits: hashmap! { "A".to_string() => 1 },
+                });
+            ba.merge_from(&other, strategy).unwrap();
+
+            let context = ba.get_agent_context();
+            assert_eq!(1.0, context.learning_rate);
+            assert_eq!(expected_x, context.q_values["A"]["X"], "{:?}", strategy);
+            assert_eq!(stats(2, 5.0), context.q_values["A"]["Y"], "{:?}", strategy);
+            assert_eq!(expected_visits, context.state_visits["A"], "{:?}", strategy);
+            assert_eq!(expected_visits, ba.step_count(), "{:?}", strategy);
+        }
+
+        let mut frozen: Agent<MockStater<MockActioner>, MockActioner, Stats> =
+            Agent::new(0, 0.0, 0.0);
+        frozen.freeze().unwrap();
+        assert!(frozen
+            .merge_from(&other, MergeStrategy::WeightedAverage)
+            .is_err());
+    }
+
+    #[test]
+    fn agent_context_diff() {
+        let stats = |q| Stats {
+            call_count: 1,
+            q_raw: q,
+            q_weighted: q,
+        };
+        let context = |q_values| AgentContext {
+            learning_rate: 1.0,
+            discount_factor: 0.0,
+            priming_threshold: 0,
+            q_values,
+            state_visits: HashMap::new(),
+        };
+        let older = context(hashmap! {
+            "A" => hashmap! { "X" => stats(1.0), "Y" => stats(2.0) },
+            "B" => hashmap! { "X" => stats(3.0) },
+        });
+        let newer = context(hashmap! {
+            "A" => hashmap! { "X" => stats(1.5), "Y" => stats(2.05), "Z" => stats(4.0) },
+        });
+
+        let changes = older.diff(&newer, 0.1);
+        let change = |state_id, action_id, before, after| QValueChange {
+            state_id,
+            action_id,
+            before,
+            after,
+        };
+        assert_eq!(
+            vec![
+                change("A", "X", Some(1.0), Some(1.5)),
+                change("A", "Z", None, Some(4.0)),
+                change("B", "X", Some(3.0), None),
+            ],
+            changes
+        );
+        assert_eq!(0.5, changes[0].delta());
+        assert_eq!(-3.0, changes[2].delta());
+        assert!(older.diff(&older, 0.0).is_empty());
+    }
+
+    #[test]
+    fn agent_context_to_records() {
+        let context = AgentContext {
+            learning_rate: 1.0,
+            discount_factor: 0.0,
+            priming_threshold: 0,
+            q_values: hashmap! {
+                "B" => hashmap! { "X" => Stats { call_count: 0, q_raw: 0.0, q_weighted: 0.25 } },
+                "A" => hashmap! {
+                    "Y" => Stats { call_count: 1, q_raw: -1.5, q_weighted: 0.5 },
+                    "X" => Stats { call_count: 2, q_raw: 1.0, q_weighted: 0.75 },
+                },
+            },
+            state_visits: HashMap::new(),
+        };
+        let record = |state, action, calls, q_raw, q_weighted| QRecord {
+            state,
+            action,
+            calls,
+            q_raw,
+            q_weighted,
+        };
+        assert_eq!(
+            vec![
+                record("A", "X", 2, 1.0, 0.75),
+                record("A", "Y", 1, -1.5, 0.5),
+                record("B", "X", 0, 0.0, 0.25),
+            ],
+            context.to_records()
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn q_records_json() {
+        let records = vec![QRecord {
+            state: "A",
+            action: "X",
+            calls: 2,
+            q_raw: 1.0,
+            q_weighted: 0.75,
+        }];
+        assert_eq!(
+            r#"[{"state":"A","action":"X","calls":2,"q_raw":1.0,"q_weighted":0.75}]"#,
+            serde_json::to_string(&records).unwrap()
+        );
+    }
+
+    #[test]
+    fn learn_with_tuple_ids() {
+        struct Move(u8);
+        impl Actioner<'_> for Move {
+            type Id = u8;
+            fn id(&self) -> u8 {
+                self.0
+            }
+        }
+
+        struct Cell<'a> {
+            pos: (i32, i32),
+            moves: Vec<&'a Move>,
+        }
+        impl<'a> Stater<'a, Move> for Cell<'a> {
+            type Id = (i32, i32);
+            fn possible_actions(&self) -> Vec<&'a Move> {
+                self.moves.clone()
+            }
+            fn action_is_compatible(&self, _: &'a Move) -> bool {
+                true
+            }
+            fn get_action(&self, action_id: &u8) -> Result<&'a Move, LearnerError> {
+                self.moves
+                    .iter()
+                    .find(|m| m.0 == *action_id)
+                    .copied()
+                    .ok_or_else(|| LearnerError::new("no such move".to_string()))
+            }
+            fn id(&self) -> (i32, i32) {
+                self.pos
+            }
+            fn apply(&self, _: &'a Move) -> Result<(), LearnerError> {
+                Ok(())
+            }
+        }
+
+        let (left, right) = (Move(0), Move(1));
+        let origin = Cell {
+            pos: (0, 0),
+            moves: vec![&left, &right],
+        };
+        let goal = Cell {
+            pos: (1, 0),
+            moves: vec![&left, &right],
+        };
+
+        let mut agent: Agent<Cell, Move, Stats> = Agent::new(1, 1.0, 0.0);
+        agent.learn(Some(&origin), &right, &goal, 1.0).unwrap();
+
+        let context = agent.get_agent_context();
+        assert_eq!(1.0, context.q_values[&(0, 0)][&1].q_raw);
+        assert_eq!(1, agent.recommend_action(&origin).unwrap().id());
+    }
+
     #[test]
     fn recommend_action() {
         const TEST_STATE_ID: &str = "testStateID";
@@ -400,10 +3692,9 @@ mod tests {
                 name: "Error if no actions",
                 possible_actions: vec![],
                 tie_break_index: 0,
-                exp_result: Err(LearnerError::new(format!(
-                    "state '{}' reports no possible actions",
-                    TEST_STATE_ID
-                ))),
+                exp_result: Err(LearnerError::NoPossibleActions {
+                    state: format!("{:?}", TEST_STATE_ID),
+                }),
             },
             TestCase {
                 name: "Action returned when bootstrapping",
@@ -417,6 +3708,15 @@ mod tests {
                 tie_break_index: 1,
                 exp_result: Ok("B"),
             },
+            TestCase {
+                name: "Error if tie breaker is out of range",
+                possible_actions: vec![&action_a, &action_b],
+                tie_break_index: 2,
+                exp_result: Err(LearnerError::InvalidTieBreak {
+                    index: 2,
+                    candidates: 2,
+                }),
+            },
         ];
 
         for test_case in test_cases {
@@ -429,7 +3729,7 @@ mod tests {
 
             let mut a: Agent<MockStater<MockActioner>, MockActioner, Stats> =
                 Agent::new(0, 0.0, 0.0);
-            a.tie_breaker = Box::new(|_| tie_breaker_index);
+            a.tie_breaker = Box::new(|_, _| tie_breaker_index);
             let act_result = a.recommend_action(&state);
             let test_name = test_case.name;
 
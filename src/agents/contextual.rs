@@ -0,0 +1,334 @@
+//! A contextual bandit agent, which learns the immediate reward of each action
+//! in each context, without the bootstrapping of a full MDP agent.
+//!
+//! Each state passed to the agent is treated as a context: the agent keeps an
+//! independent estimate of the reward of each action in each context, and an
+//! action's estimate is moved towards the reward that it earns, ignoring the
+//! state that follows. This is equivalent to learning with a discount factor
+//! of 0, but the agent also explores by itself, according to its
+//! `Exploration` strategy, so it can be used directly in a control loop.
+
+use crate::actions::Actioner;
+use crate::agents::Agenter;
+use crate::errors::LearnerError;
+use crate::internal::{math, rng};
+use crate::states::Stater;
+use rand::{Rng, RngCore};
+use std::collections::HashMap;
+
+/// How a `ContextualBandit` balances exploring actions with exploiting the
+/// action with the best estimated reward.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Exploration {
+    /// Recommend a uniformly random action with the specified probability,
+    /// and the best action otherwise.
+    EpsilonGreedy(f64),
+
+    /// Recommend the action with the highest upper confidence bound,
+    /// `estimate + c * sqrt(ln(N) / n)`, where `c` is the specified
+    /// coefficient, `n` is the number of times the action has been taken in
+    /// the context, and `N` is the total for all of the context's actions.
+    /// Actions that have not been taken in the context are recommended first.
+    Ucb(f64),
+}
+
+/// The agent's record of an action in a context.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ArmEstimate {
+    /// The number of times the action has been taken in the context.
+    pub pulls: u64,
+
+    /// The estimated reward of the action in the context.
+    pub value: f64,
+}
+
+/// An agent that learns the expected immediate reward of each action in each
+/// context. See the module documentation for details.
+pub struct ContextualBandit<'a, S, A>
+where
+    A: Actioner<'a>,
+    S: Stater<'a, A>,
+{
+    exploration: Exploration,
+    step_size: Option<f64>,
+    initial_value: f64,
+    estimates: HashMap<S::Id, HashMap<A::Id, ArmEstimate>>,
+    rng: Box<dyn RngCore + 'a>,
+}
+
+impl<'a, S, A> ContextualBandit<'a, S, A>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a> + 'a,
+{
+    /// Returns a new agent that explores with the supplied strategy. By
+    /// default, each estimate is the average of the rewards its action has
+    /// earned in its context.
+    pub fn new(exploration: Exploration) -> Self {
+        Self {
+            exploration,
+            step_size: None,
+            initial_value: 0.0,
+            estimates: HashMap::new(),
+            rng: Box::new(rng::default_rng()),
+        }
+    }
+
+    /// Moves each estimate towards the latest reward by a constant fraction
+    /// `step_size` of the difference, rather than averaging all rewards, so
+    /// that recent rewards count for more. Use this when the rewards change
+    /// over time.
+    #[must_use]
+    pub fn with_step_size(mut self, step_size: f64) -> Self {
+        self.step_size = Some(step_size);
+        self
+    }
+
+    /// Sets the estimate of actions that have not been taken in a context.
+    /// The default is 0. An optimistic initial value encourages the agent to
+    /// try every action, even when exploring greedily.
+    #[must_use]
+    pub fn with_initial_value(mut self, initial_value: f64) -> Self {
+        self.initial_value = initial_value;
+        self
+    }
+
+    /// Sets the random number generator used to explore and to break ties
+    /// between actions with equal estimates.
+    #[must_use]
+    pub fn with_rng<R: RngCore + 'a>(mut self, rng: R) -> Self {
+        self.rng = Box::new(rng);
+        self
+    }
+
+    /// Returns the agent's record of an action in a context, or `None` if the
+    /// action has not been taken in the context.
+    pub fn estimate(&self, context_id: &S::Id, action_id: &A::Id) -> Option<ArmEstimate> {
+        self.estimates
+            .get(context_id)
+            .and_then(|actions| actions.get(action_id))
+            .copied()
+    }
+
+    /// Returns the estimated reward of an action in a context.
+    pub fn value(&self, context_id: &S::Id, action_id: &A::Id) -> f64 {
+        self.estimate(context_id, action_id)
+            .map_or(self.initial_value, |estimate| estimate.value)
+    }
+
+    /// Returns the agent's records of every action in every context.
+    pub fn estimates(&self) -> &HashMap<S::Id, HashMap<A::Id, ArmEstimate>> {
+        &self.estimates
+    }
+
+    /// Returns the score that actions are ranked by in `context_id`.
+    fn score(&self, context_id: &S::Id, action_id: &A::Id, total_pulls: u64) -> f64 {
+        let estimate = self.estimate(context_id, action_id);
+        let value = estimate.map_or(self.initial_value, |estimate| estimate.value);
+        match self.exploration {
+            Exploration::EpsilonGreedy(_) => value,
+            Exploration::Ucb(coefficient) => match estimate {
+                Some(estimate) if estimate.pulls > 0 => {
+                    let bonus = (math::count_to_f64(total_pulls).ln()
+                        / math::count_to_f64(estimate.pulls))
+                    .sqrt();
+                    coefficient.mul_add(bonus, value)
+                }
+                // Untaken actions tie with each other, ahead of all others.
+                _ => f64::MAX,
+            },
+        }
+    }
+}
+
+impl<'a, S, A> Agenter<'a, S, A> for ContextualBandit<'a, S, A>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a> + 'a,
+{
+    /// Recommends an action for the context according to the agent's
+    /// exploration strategy, breaking ties at random.
+    fn recommend_action(&mut self, context: &S) -> Result<&'a A, LearnerError> {
+        let context_id = context.id();
+        let actions = context.possible_actions();
+        if actions.is_empty() {
+            return Err(LearnerError::NoPossibleActions {
+                state: format!("{context_id:?}"),
+            });
+        }
+        if let Exploration::EpsilonGreedy(epsilon) = self.exploration {
+            if self.rng.gen::<f64>() < epsilon {
+                return Ok(actions[self.rng.gen_range(0, actions.len())]);
+            }
+        }
+
+        let total_pulls = self
+            .estimates
+            .get(&context_id)
+            .map_or(0, |actions| actions.values().map(|e| e.pulls).sum());
+        let scores: Vec<f64> = actions
+            .iter()
+            .map(|a| self.score(&context_id, &a.id(), total_pulls))
+            .collect();
+        let best = scores.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let greedy: Vec<&'a A> = actions
+            .into_iter()
+            .zip(scores)
+            .filter(|(_, score)| *score == best)
+            .map(|(a, _)| a)
+            .collect();
+        Ok(greedy[self.rng.gen_range(0, greedy.len())])
+    }
+
+    fn transition(&self, context: &S, action: &'a A) -> Result<(), LearnerError> {
+        if !context.action_is_compatible(action) {
+            return Err(LearnerError::ActionNotCompatible {
+                state: format!("{:?}", context.id()),
+                action: format!("{:?}", action.id()),
+            });
+        }
+        context.apply(action)
+    }
+
+    /// Moves the estimate of `action_taken` in the `previous_state` context
+    /// towards `reward`. The state that followed is ignored, and nothing is
+    /// learned if there is no previous state.
+    fn learn(
+        &mut self,
+        previous_state: Option<&S>,
+        action_taken: &A,
+        _current_state: &S,
+        reward: f64,
+    ) -> Result<(), LearnerError> {
+        if !reward.is_finite() {
+            return Err(LearnerError::NonFinite(format!(
+                "reward {reward} is not finite"
+            )));
+        }
+        let Some(context) = previous_state else {
+            return Ok(());
+        };
+        let initial_value = self.initial_value;
+        let estimate = self
+            .estimates
+            .entry(context.id())
+            .or_default()
+            .entry(action_taken.id())
+            .or_insert(ArmEstimate {
+                pulls: 0,
+                value: initial_value,
+            });
+        estimate.pulls = estimate.pulls.saturating_add(1);
+        let step_size = self
+            .step_size
+            .unwrap_or_else(|| 1.0 / math::count_to_f64(estimate.pulls));
+        estimate.value = step_size.mul_add(reward - estimate.value, estimate.value);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::environments::bandit::{Bandit, BanditState};
+    use crate::environments::Environment;
+    use crate::mocks::*;
+    use crate::training::Trainer;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    static X: MockActioner = MockActioner { return_id: "x" };
+    static Y: MockActioner = MockActioner { return_id: "y" };
+
+    type Agent = ContextualBandit<
+        'static,
+        MockStater<'static, MockActioner<'static>>,
+        MockActioner<'static>,
+    >;
+
+    fn context(id: &'static str) -> MockStater<'static, MockActioner<'static>> {
+        MockStater {
+            return_id: id,
+            return_possible_actions: vec![&X, &Y],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn learns_immediate_rewards() {
+        let (c0, c1) = (context("c0"), context("c1"));
+        let mut agent: Agent = ContextualBandit::new(Exploration::EpsilonGreedy(0.0));
+        agent.learn(Some(&c1), &X, &c0, 10.0).unwrap();
+
+        // Nothing is bootstrapped from c1, which follows the action.
+        agent.learn(Some(&c0), &X, &c1, 1.0).unwrap();
+        agent.learn(Some(&c0), &X, &c1, 2.0).unwrap();
+        assert_eq!(
+            Some(ArmEstimate {
+                pulls: 2,
+                value: 1.5
+            }),
+            agent.estimate(&"c0".to_string(), &"x".to_string())
+        );
+        assert_eq!(0.0, agent.value(&"c0".to_string(), &"y".to_string()));
+        assert_eq!("x", agent.recommend_action(&c0).unwrap().return_id);
+
+        let mut agent: Agent =
+            ContextualBandit::new(Exploration::EpsilonGreedy(0.0)).with_step_size(0.5);
+        agent.learn(Some(&c0), &X, &c1, 2.0).unwrap();
+        agent.learn(Some(&c0), &X, &c1, 4.0).unwrap();
+        assert_eq!(2.5, agent.value(&"c0".to_string(), &"x".to_string()));
+
+        agent.learn(None, &X, &c1, 4.0).unwrap();
+        assert!(agent.learn(Some(&c0), &X, &c1, f64::NAN).is_err());
+    }
+
+    #[test]
+    fn ucb_tries_each_action_first() {
+        let c0 = context("c0");
+        let mut agent: Agent = ContextualBandit::new(Exploration::Ucb(1.0));
+        agent.learn(Some(&c0), &X, &c0, 1.0).unwrap();
+        assert_eq!("y", agent.recommend_action(&c0).unwrap().return_id);
+        agent.learn(Some(&c0), &Y, &c0, 0.0).unwrap();
+        assert_eq!("x", agent.recommend_action(&c0).unwrap().return_id);
+    }
+
+    #[test]
+    fn learns_best_action_per_context() {
+        let contexts = [context("c0"), context("c1")];
+        for exploration in [Exploration::EpsilonGreedy(0.1), Exploration::Ucb(0.5)] {
+            let mut agent: Agent =
+                ContextualBandit::new(exploration).with_rng(StdRng::seed_from_u64(1));
+            for i in 0..400 {
+                let context = &contexts[i % 2];
+                let action = agent.recommend_action(context).unwrap();
+                // x pays in c0, and y pays in c1.
+                let pays = (context.return_id == "c0") == (action.return_id == "x");
+                let reward = if pays { 1.0 } else { 0.0 };
+                agent.learn(Some(context), action, context, reward).unwrap();
+            }
+            assert_eq!("x", agent.recommend_action(&contexts[0]).unwrap().return_id);
+            assert_eq!("y", agent.recommend_action(&contexts[1]).unwrap().return_id);
+        }
+    }
+
+    #[test]
+    fn trains_against_bandit() {
+        let arms = Bandit::arms(5);
+        let mut bandit = Bandit::gaussian_testbed(&arms, StdRng::seed_from_u64(3));
+        let mut agent: ContextualBandit<BanditState, _> =
+            ContextualBandit::new(Exploration::Ucb(1.0)).with_rng(StdRng::seed_from_u64(4));
+        Trainer::new(2000, 1)
+            .train(&mut agent, &mut bandit)
+            .unwrap();
+
+        let best = agent.estimates()[&()]
+            .iter()
+            .max_by(|a, b| a.1.value.total_cmp(&b.1.value))
+            .map(|(arm, _)| *arm);
+        assert_eq!(Some(bandit.optimal_arm().index()), best);
+        let state = bandit.reset().unwrap();
+        assert!(agent.recommend_action(&state).is_ok());
+    }
+}
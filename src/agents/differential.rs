@@ -0,0 +1,384 @@
+//! An agent for continuing tasks, which never reset, that learns with
+//! differential semi-gradient SARSA.
+//!
+//! Discounting future rewards makes little sense when a task runs forever,
+//! so the agent instead maximizes the average reward per step. It learns a
+//! running estimate `R̄` of the average reward, and approximates the
+//! differential q-value of each action (how much better than average the
+//! rewards that follow it are) as a linear function `w · x(s, a)` of the
+//! features of the state and action. After each transition `s -a-> s'`
+//! followed by `a'`, the agent computes
+//!
+//! ```text
+//! δ = r - R̄ + w · x(s', a') - w · x(s, a)
+//! R̄ ← R̄ + β δ
+//! w ← w + α δ x(s, a)
+//! ```
+//!
+//! See Sutton and Barto, "Reinforcement Learning: An Introduction" (2018),
+//! section 10.3.
+//!
+//! As for `qsigma::QSigmaAgent`, `learn` is not told which action will be
+//! taken in the next state, so each update is deferred until the following
+//! call to `learn` reveals it. If the following call does not continue from
+//! the same state, or `flush` is called first, the deferred update uses the
+//! next state's greedy action instead. Transitions into a state that reports
+//! no possible actions are learned from immediately, with no future value.
+
+use crate::actions::Actioner;
+use crate::agents::Agenter;
+use crate::errors::LearnerError;
+use crate::internal::rng;
+use crate::states::Stater;
+use rand::{Rng, RngCore};
+
+/// A function that describes an action taken in a state as a vector of
+/// features, such as the active tiles of a
+/// `features::tile_coding::TileCoder` offset by the index of the action.
+pub type ActionFeatureFn<'a, S, A> = Box<dyn Fn(&S, &A) -> Vec<f64> + 'a>;
+
+/// A transition whose update is waiting for the next action to be known.
+struct Deferred<SK, AK> {
+    features: Vec<f64>,
+    reward: f64,
+    next_state: SK,
+    /// The features of each action that is possible in the next state.
+    next_actions: Vec<(AK, Vec<f64>)>,
+}
+
+/// An agent that learns the differential q-values of a continuing task with
+/// differential semi-gradient SARSA. See the module documentation for
+/// details.
+pub struct DifferentialSarsaAgent<'a, S, A>
+where
+    A: Actioner<'a>,
+    S: Stater<'a, A>,
+{
+    learning_rate: f64,
+    average_reward_rate: f64,
+    features: ActionFeatureFn<'a, S, A>,
+    weights: Vec<f64>,
+    average_reward: f64,
+    deferred: Option<Deferred<S::Id, A::Id>>,
+    update_count: u64,
+    rng: Box<dyn RngCore + 'a>,
+}
+
+impl<'a, S, A> DifferentialSarsaAgent<'a, S, A>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a> + 'a,
+{
+    /// Returns a new agent that describes each action in each state with
+    /// `features`, which must always return vectors of the same length.
+    /// `learning_rate` (α) is the step size of the weights, and
+    /// `average_reward_rate` (β) is the step size of the average reward
+    /// estimate, which is usually smaller than α.
+    pub fn new<F>(learning_rate: f64, average_reward_rate: f64, features: F) -> Self
+    where
+        F: Fn(&S, &A) -> Vec<f64> + 'a,
+    {
+        Self {
+            learning_rate,
+            average_reward_rate,
+            features: Box::new(features),
+            weights: Vec::new(),
+            average_reward: 0.0,
+            deferred: None,
+            update_count: 0,
+            rng: Box::new(rng::default_rng()),
+        }
+    }
+
+    /// Sets the initial estimate of the average reward, which is 0 by
+    /// default.
+    #[must_use]
+    pub fn with_average_reward(mut self, average_reward: f64) -> Self {
+        self.average_reward = average_reward;
+        self
+    }
+
+    /// Sets the random number generator used to break ties between actions
+    /// with equal q-values.
+    #[must_use]
+    pub fn with_rng<R: RngCore + 'a>(mut self, rng: R) -> Self {
+        self.rng = Box::new(rng);
+        self
+    }
+
+    /// Returns the agent's estimate of the average reward per step.
+    pub fn average_reward(&self) -> f64 {
+        self.average_reward
+    }
+
+    /// Returns the weights of the features, which are empty until the agent
+    /// has seen its first feature vector.
+    pub fn weights(&self) -> &[f64] {
+        &self.weights
+    }
+
+    /// Returns the number of updates the agent has completed.
+    pub fn update_count(&self) -> u64 {
+        self.update_count
+    }
+
+    /// Returns the differential q-value of taking `action` in `state`.
+    pub fn q_value(&mut self, state: &S, action: &A) -> Result<f64, LearnerError> {
+        let features = self.features_of(state, action)?;
+        Ok(self.value(&features))
+    }
+
+    /// Completes any deferred update, using the greedy action of the next
+    /// state since the action actually taken is unknown. Call this before
+    /// handing the control loop to a different source of transitions.
+    pub fn flush(&mut self) {
+        if let Some(deferred) = self.deferred.take() {
+            self.complete(&deferred, None);
+        }
+    }
+
+    /// Completes a deferred update, given the action taken in the next state
+    /// if it is known.
+    fn complete(&mut self, deferred: &Deferred<S::Id, A::Id>, next_action: Option<&A::Id>) {
+        let taken = next_action.and_then(|next_action| {
+            deferred
+                .next_actions
+                .iter()
+                .find(|(id, _)| id == next_action)
+                .map(|(_, features)| self.value(features))
+        });
+        let future = taken.unwrap_or_else(|| {
+            deferred
+                .next_actions
+                .iter()
+                .map(|(_, features)| self.value(features))
+                .fold(f64::NEG_INFINITY, f64::max)
+        });
+        self.update(&deferred.features, deferred.reward, future);
+    }
+
+    /// Applies the differential update to the weights and the average reward.
+    fn update(&mut self, features: &[f64], reward: f64, future: f64) {
+        let error = reward - self.average_reward + future - self.value(features);
+        self.average_reward = self.average_reward_rate.mul_add(error, self.average_reward);
+        for (weight, x) in self.weights.iter_mut().zip(features) {
+            *weight = (self.learning_rate * error).mul_add(*x, *weight);
+        }
+        self.update_count += 1;
+    }
+
+    fn value(&self, features: &[f64]) -> f64 {
+        self.weights.iter().zip(features).map(|(w, x)| w * x).sum()
+    }
+
+    /// Returns the features of an action taken in a state, sizing the
+    /// weights on first use, and checking that the features are finite and
+    /// of the expected length thereafter.
+    fn features_of(&mut self, state: &S, action: &A) -> Result<Vec<f64>, LearnerError> {
+        let features = (self.features)(state, action);
+        if self.weights.is_empty() {
+            self.weights = vec![0.0; features.len()];
+        }
+        if features.len() != self.weights.len() {
+            return Err(LearnerError::InvalidArgument(format!(
+                "expected {} features, got {}",
+                self.weights.len(),
+                features.len()
+            )));
+        }
+        if let Some(x) = features.iter().find(|x| !x.is_finite()) {
+            return Err(LearnerError::NonFinite(format!(
+                "features must be finite, got {x}"
+            )));
+        }
+        Ok(features)
+    }
+}
+
+impl<'a, S, A> Agenter<'a, S, A> for DifferentialSarsaAgent<'a, S, A>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a> + 'a,
+{
+    /// Recommends the possible action with the highest differential q-value,
+    /// breaking ties at random.
+    fn recommend_action(&mut self, state: &S) -> Result<&'a A, LearnerError> {
+        let actions = state.possible_actions();
+        let values = actions
+            .iter()
+            .map(|a| self.q_value(state, a))
+            .collect::<Result<Vec<f64>, LearnerError>>()?;
+        let best = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let greedy: Vec<&'a A> = actions
+            .into_iter()
+            .zip(values)
+            .filter(|(_, v)| *v == best)
+            .map(|(a, _)| a)
+            .collect();
+        if greedy.is_empty() {
+            return Err(LearnerError::NoPossibleActions {
+                state: format!("{:?}", state.id()),
+            });
+        }
+        Ok(greedy[self.rng.gen_range(0, greedy.len())])
+    }
+
+    fn transition(&self, state: &S, action: &'a A) -> Result<(), LearnerError> {
+        if !state.action_is_compatible(action) {
+            return Err(LearnerError::ActionNotCompatible {
+                state: format!("{:?}", state.id()),
+                action: format!("{:?}", action.id()),
+            });
+        }
+        state.apply(action)
+    }
+
+    /// Completes the deferred update of the previous transition, if any, and
+    /// defers the update of this one. See the module documentation.
+    fn learn(
+        &mut self,
+        previous_state: Option<&S>,
+        action_taken: &A,
+        current_state: &S,
+        reward: f64,
+    ) -> Result<(), LearnerError> {
+        if !reward.is_finite() {
+            return Err(LearnerError::NonFinite(format!(
+                "reward {reward} is not finite"
+            )));
+        }
+        let Some(previous_state) = previous_state else {
+            self.flush();
+            return Ok(());
+        };
+        let features = self.features_of(previous_state, action_taken)?;
+        let next_actions = current_state
+            .possible_actions()
+            .into_iter()
+            .map(|a| Ok((a.id(), self.features_of(current_state, a)?)))
+            .collect::<Result<Vec<_>, LearnerError>>()?;
+
+        if let Some(deferred) = self.deferred.take() {
+            let continues = deferred.next_state == previous_state.id();
+            self.complete(&deferred, continues.then_some(&action_taken.id()));
+        }
+        if next_actions.is_empty() {
+            self.update(&features, reward, 0.0);
+        } else {
+            self.deferred = Some(Deferred {
+                features,
+                reward,
+                next_state: current_state.id(),
+                next_actions,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::mocks::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    static A: MockActioner = MockActioner { return_id: "a" };
+    static B: MockActioner = MockActioner { return_id: "b" };
+
+    type Agent = DifferentialSarsaAgent<
+        'static,
+        MockStater<'static, MockActioner<'static>>,
+        MockActioner<'static>,
+    >;
+
+    /// One-hot features of the action, regardless of the state.
+    fn one_hot(_: &MockStater<'static, MockActioner<'static>>, action: &MockActioner) -> Vec<f64> {
+        if action.return_id == "a" {
+            vec![1.0, 0.0]
+        } else {
+            vec![0.0, 1.0]
+        }
+    }
+
+    fn state() -> MockStater<'static, MockActioner<'static>> {
+        MockStater {
+            return_id: "s",
+            return_possible_actions: vec![&A, &B],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn deferred_updates() {
+        let s = state();
+        let mut agent: Agent = DifferentialSarsaAgent::new(0.5, 0.1, one_hot);
+
+        agent.learn(Some(&s), &A, &s, 1.0).unwrap();
+        assert_eq!(0, agent.update_count());
+        assert_eq!(vec![0.0, 0.0], agent.weights());
+
+        // δ = 1 - 0 + 0 - 0
+        agent.learn(Some(&s), &B, &s, 2.0).unwrap();
+        assert_eq!(1, agent.update_count());
+        assert_eq!(vec![0.5, 0.0], agent.weights());
+        assert!((0.1 - agent.average_reward()).abs() < 1e-12);
+
+        // The update of the second transition uses the greedy action a,
+        // since the next action is unknown: δ = 2 - 0.1 + 0.5 - 0
+        agent.flush();
+        assert_eq!(2, agent.update_count());
+        assert!((1.2 - agent.weights()[1]).abs() < 1e-12);
+        assert!((0.34 - agent.average_reward()).abs() < 1e-12);
+
+        assert!(agent.learn(Some(&s), &A, &s, f64::NAN).is_err());
+    }
+
+    #[test]
+    fn invalid_features() {
+        let s = state();
+        let mut agent: Agent = DifferentialSarsaAgent::new(0.5, 0.1, |_, action: &MockActioner| {
+            if action.return_id == "a" {
+                vec![1.0]
+            } else {
+                vec![1.0, 1.0]
+            }
+        });
+        assert!(agent.q_value(&s, &A).is_ok());
+        assert!(matches!(
+            agent.q_value(&s, &B),
+            Err(LearnerError::InvalidArgument(_))
+        ));
+
+        let mut agent: Agent = DifferentialSarsaAgent::new(0.5, 0.1, |_, _| vec![f64::NAN]);
+        assert!(matches!(
+            agent.learn(Some(&s), &A, &s, 1.0),
+            Err(LearnerError::NonFinite(_))
+        ));
+    }
+
+    #[test]
+    fn learns_average_reward() {
+        // A continuing task with a single state, in which b earns more than a.
+        let s = state();
+        let mut agent: Agent =
+            DifferentialSarsaAgent::new(0.1, 0.01, one_hot).with_rng(StdRng::seed_from_u64(1));
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..2000 {
+            let action = if rng.gen::<f64>() < 0.1 {
+                [&A, &B][rng.gen_range(0, 2)]
+            } else {
+                agent.recommend_action(&s).unwrap()
+            };
+            let reward = if action.return_id == "a" { 1.0 } else { 2.0 };
+            agent.learn(Some(&s), action, &s, reward).unwrap();
+        }
+        assert_eq!("b", agent.recommend_action(&s).unwrap().return_id);
+        assert!(
+            (agent.average_reward() - 1.95).abs() < 0.1,
+            "{:?}",
+            agent.average_reward()
+        );
+    }
+}
@@ -0,0 +1,581 @@
+//! A deep Q-network (DQN) agent, which approximates q-values with a small
+//! neural network rather than a table.
+//!
+//! Approximating q-values lets the agent learn from state spaces that are
+//! too large to enumerate. The agent is enabled by the `dqn` feature, and
+//! uses `candle` for its network.
+//!
+//! States are described to the network by a feature function, which maps
+//! each state onto a vector of numbers of a fixed length (for instance, the
+//! output of a `features::tile_coding::TileCoder` or
+//! `features::fourier::FourierBasis`). States that implement
+//! `states::FeatureStater` can describe themselves, and agents for them can
+//! be created with `DqnAgent::new_with_state_features`. The network has one
+//! output for each action of a fixed action space.
+//!
+//! As described by Mnih et al., "Human-level control through deep
+//! reinforcement learning" (2015), the agent stores each transition it
+//! learns from in a replay buffer, and trains the network on a random batch
+//! of stored transitions after each one. Targets are computed by a separate
+//! target network, which is only synchronized with the trained network
+//! periodically, to keep the targets from chasing the network's own updates.
+//!
+//! The agent always recommends its greedy action; exploration can be added
+//! with `Trainer::with_epsilon`. A state that reports no possible actions is
+//! treated as terminal, so that no future value is bootstrapped from it.
+
+use crate::actions::Actioner;
+use crate::agents::Agenter;
+use crate::errors::LearnerError;
+use crate::internal::{math, rng};
+use crate::reproducibility::ReproducibilityConfig;
+use crate::states::{FeatureStater, Stater};
+use candle_core::{Device, Tensor, Var};
+use candle_nn::optim::{AdamW, Optimizer, ParamsAdamW};
+use rand::{Rng, RngCore};
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
+/// A function that describes a state as a vector of features.
+pub type FeatureFn<'a, S> = Box<dyn Fn(&S) -> Vec<f64> + 'a>;
+
+/// A transition stored in the replay buffer.
+struct Experience {
+    state: Vec<f32>,
+    action: u32,
+    reward: f32,
+    next_state: Vec<f32>,
+    /// 1 for each action that is possible in the next state, and 0 otherwise.
+    next_actions: Vec<f32>,
+}
+
+/// A fully connected network with rectified linear activations between its layers.
+struct Network {
+    layers: Vec<(Var, Var)>,
+}
+
+impl Network {
+    /// Returns a network whose layers have the specified sizes, with weights
+    /// and biases drawn uniformly from `±1/sqrt(fan_in)`.
+    fn new(sizes: &[usize], rng: &mut dyn RngCore, device: &Device) -> candle_core::Result<Self> {
+        let layers = sizes
+            .windows(2)
+            .map(|pair| {
+                let (inputs, outputs) = (pair[0], pair[1]);
+                let bound = 1.0 / math::len_to_f64(inputs).sqrt();
+                let mut uniform = |n| -> Vec<f32> {
+                    (0..n)
+                        .map(|_| to_f32(rng.gen_range(-bound, bound)))
+                        .collect()
+                };
+                let weights =
+                    Tensor::from_vec(uniform(outputs * inputs), (outputs, inputs), device)?;
+                let biases = Tensor::from_vec(uniform(outputs), outputs, device)?;
+                Ok((Var::from_tensor(&weights)?, Var::from_tensor(&biases)?))
+            })
+            .collect::<candle_core::Result<_>>()?;
+        Ok(Self { layers })
+    }
+
+    fn forward(&self, input: &Tensor) -> candle_core::Result<Tensor> {
+        let mut output = input.clone();
+        for (i, (weights, biases)) in self.layers.iter().enumerate() {
+            output = output.matmul(&weights.t()?)?.broadcast_add(biases)?;
+            if i + 1 < self.layers.len() {
+                output = output.relu()?;
+            }
+        }
+        Ok(output)
+    }
+
+    fn vars(&self) -> Vec<Var> {
+        self.layers
+            .iter()
+            .flat_map(|(weights, biases)| [weights.clone(), biases.clone()])
+            .collect()
+    }
+
+    fn copy_from(&self, other: &Self) -> candle_core::Result<()> {
+        for (var, source) in self.vars().iter().zip(other.vars()) {
+            var.set(source.as_tensor())?;
+        }
+        Ok(())
+    }
+}
+
+/// The networks of an agent, which are built once the length of the agent's
+/// feature vectors is known.
+struct Model {
+    input_size: usize,
+    online: Network,
+    target: Network,
+    optimizer: AdamW,
+}
+
+/// An agent that learns q-values with a deep Q-network. See the module
+/// documentation for details.
+pub struct DqnAgent<'a, S, A> {
+    actions: &'a [A],
+    features: FeatureFn<'a, S>,
+    discount_factor: f64,
+    learning_rate: f64,
+    hidden_layers: Vec<usize>,
+    replay_capacity: usize,
+    batch_size: usize,
+    target_sync_interval: u64,
+    replay: VecDeque<Experience>,
+    steps: u64,
+    rng: Box<dyn RngCore + 'a>,
+    replay_rng: Option<Box<dyn RngCore + 'a>>,
+    device: Device,
+    model: Option<Model>,
+}
+
+impl<'a, S, A> DqnAgent<'a, S, A>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a> + 'a,
+{
+    /// Returns a new agent that chooses between `actions`, which must include
+    /// every action that any state can report as possible, and describes
+    /// states with `features`. `discount_factor` is the importance of future
+    /// rewards, as for `bayesian::Agent::new`.
+    ///
+    /// By default the network has a single hidden layer of 64 units and is
+    /// trained with Adam at a learning rate of 0.001, on batches of 32
+    /// transitions drawn from the last 10,000, and the target network is
+    /// synchronized every 100 steps.
+    pub fn new<F>(actions: &'a [A], discount_factor: f64, features: F) -> Self
+    where
+        F: Fn(&S) -> Vec<f64> + 'a,
+    {
+        Self {
+            actions,
+            features: Box::new(features),
+            discount_factor,
+            learning_rate: 0.001,
+            hidden_layers: vec![64],
+            replay_capacity: 10_000,
+            batch_size: 32,
+            target_sync_interval: 100,
+            replay: VecDeque::new(),
+            steps: 0,
+            rng: Box::new(rng::default_rng()),
+            replay_rng: None,
+            device: Device::Cpu,
+            model: None,
+        }
+    }
+
+    /// Returns a new agent that chooses between `actions`, and describes each
+    /// state with its own `FeatureStater::features`. See `new`.
+    pub fn new_with_state_features(actions: &'a [A], discount_factor: f64) -> Self
+    where
+        S: FeatureStater<'a, A> + 'a,
+    {
+        Self::new(actions, discount_factor, S::features)
+    }
+
+    /// Sets the sizes of the network's hidden layers. The network is built
+    /// when the agent first sees a state, so this has no effect afterwards.
+    #[must_use]
+    pub fn with_hidden_layers(mut self, hidden_layers: Vec<usize>) -> Self {
+        self.hidden_layers = hidden_layers;
+        self
+    }
+
+    /// Sets the learning rate of the network's optimizer. This has no effect
+    /// once the agent has seen a state.
+    #[must_use]
+    pub fn with_learning_rate(mut self, learning_rate: f64) -> Self {
+        self.learning_rate = learning_rate;
+        self
+    }
+
+    /// Sets the number of transitions that the replay buffer holds. Once the
+    /// buffer is full, the oldest transitions are discarded.
+    #[must_use]
+    pub fn with_replay_capacity(mut self, replay_capacity: usize) -> Self {
+        self.replay_capacity = replay_capacity.max(1);
+        self
+    }
+
+    /// Sets the number of transitions that the network is trained on after
+    /// each step. Training starts once the replay buffer holds this many
+    /// transitions.
+    #[must_use]
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Sets the number of steps between synchronizations of the target
+    /// network with the trained network.
+    #[must_use]
+    pub fn with_target_sync_interval(mut self, target_sync_interval: u64) -> Self {
+        self.target_sync_interval = target_sync_interval.max(1);
+        self
+    }
+
+    /// Sets the random number generator used to initialize the network, and
+    /// to sample from the replay buffer unless a separate generator is
+    /// supplied via `with_replay_rng`. Supplying a seeded generator makes
+    /// training reproducible.
+    #[must_use]
+    pub fn with_rng<R: RngCore + 'a>(mut self, rng: R) -> Self {
+        self.rng = Box::new(rng);
+        self
+    }
+
+    /// Sets a separate random number generator used to sample from the
+    /// replay buffer.
+    #[must_use]
+    pub fn with_replay_rng<R: RngCore + 'a>(mut self, rng: R) -> Self {
+        self.replay_rng = Some(Box::new(rng));
+        self
+    }
+
+    /// Seeds the agent's generators from `config`, using its agent seed to
+    /// initialize the network and its replay seed to sample from the replay
+    /// buffer. See `reproducibility::ReproducibilityConfig`.
+    #[must_use]
+    pub fn with_reproducibility(self, config: &ReproducibilityConfig) -> Self {
+        self.with_rng(config.agent_rng())
+            .with_replay_rng(config.replay_rng())
+    }
+
+    /// Returns the number of transitions that the agent has learned from.
+    pub fn step_count(&self) -> u64 {
+        self.steps
+    }
+
+    /// Returns the number of transitions held in the replay buffer.
+    pub fn replay_len(&self) -> usize {
+        self.replay.len()
+    }
+
+    /// Returns the network's estimate of the q-value of each action of the
+    /// action space in `state`, in the order of the action space.
+    pub fn q_values(&mut self, state: &S) -> Result<Vec<f64>, LearnerError> {
+        let input = self.observe(state)?;
+        let model = self.model.as_ref().ok_or_else(missing_model)?;
+        let input = Tensor::from_vec(input, (1, model.input_size), &self.device).map_err(nn_err)?;
+        let output = model
+            .online
+            .forward(&input)
+            .and_then(|output| output.squeeze(0)?.to_vec1::<f32>())
+            .map_err(nn_err)?;
+        Ok(output.into_iter().map(f64::from).collect())
+    }
+
+    /// Returns the features of `state`, building the networks if this is the
+    /// first state that the agent has seen.
+    fn observe(&mut self, state: &S) -> Result<Vec<f32>, LearnerError> {
+        let features = (self.features)(state);
+        if let Some(x) = features.iter().find(|x| !x.is_finite()) {
+            return Err(LearnerError::NonFinite(format!(
+                "feature {x} of state {:?} is not finite",
+                state.id()
+            )));
+        }
+        match &self.model {
+            Some(model) if model.input_size != features.len() => {
+                return Err(LearnerError::InvalidArgument(format!(
+                    "expected {} features for state {:?}, got {}",
+                    model.input_size,
+                    state.id(),
+                    features.len()
+                )));
+            }
+            Some(_) => {}
+            None => self.model = Some(self.build_model(features.len()).map_err(nn_err)?),
+        }
+        Ok(features.into_iter().map(to_f32).collect())
+    }
+
+    fn build_model(&mut self, input_size: usize) -> candle_core::Result<Model> {
+        let sizes: Vec<usize> = std::iter::once(input_size)
+            .chain(self.hidden_layers.iter().copied())
+            .chain(std::iter::once(self.actions.len()))
+            .collect();
+        let online = Network::new(&sizes, &mut self.rng, &self.device)?;
+        let target = Network::new(&sizes, &mut self.rng, &self.device)?;
+        target.copy_from(&online)?;
+        let optimizer = AdamW::new(
+            online.vars(),
+            ParamsAdamW {
+                lr: self.learning_rate,
+                weight_decay: 0.0,
+                ..ParamsAdamW::default()
+            },
+        )?;
+        Ok(Model {
+            input_size,
+            online,
+            target,
+            optimizer,
+        })
+    }
+
+    /// Returns the index of `action` within the action space.
+    fn index_of(&self, action: &A) -> Result<usize, LearnerError> {
+        let id = action.id();
+        self.actions
+            .iter()
+            .position(|a| a.id() == id)
+            .ok_or_else(|| LearnerError::ActionNotFound {
+                action: format!("{id:?}"),
+                reason: format!("action {id:?} is not in the agent's action space"),
+            })
+    }
+
+    /// Trains the network on a batch of transitions sampled from the replay
+    /// buffer.
+    fn train_batch(&mut self) -> candle_core::Result<()> {
+        let Some(model) = self.model.as_mut() else {
+            return Ok(());
+        };
+        let replay = &self.replay;
+        let rng = self.replay_rng.as_mut().unwrap_or(&mut self.rng);
+        let batch: Vec<&Experience> = (0..self.batch_size)
+            .map(|_| &replay[rng.gen_range(0, replay.len())])
+            .collect();
+        let (rows, inputs, outputs) = (batch.len(), model.input_size, self.actions.len());
+        let device = &self.device;
+        let column =
+            |values: Vec<f32>, columns: usize| Tensor::from_vec(values, (rows, columns), device);
+
+        let states = column(batch.iter().flat_map(|e| e.state.clone()).collect(), inputs)?;
+        let actions = Tensor::from_vec(
+            batch.iter().map(|e| e.action).collect::<Vec<u32>>(),
+            (rows, 1),
+            device,
+        )?;
+        let rewards = Tensor::from_vec(
+            batch.iter().map(|e| e.reward).collect::<Vec<f32>>(),
+            rows,
+            device,
+        )?;
+        let next_states = column(
+            batch.iter().flat_map(|e| e.next_state.clone()).collect(),
+            inputs,
+        )?;
+        let next_actions = column(
+            batch.iter().flat_map(|e| e.next_actions.clone()).collect(),
+            outputs,
+        )?;
+
+        // Only the possible actions of the next state are considered, and
+        // terminal states (which have none) are worth nothing.
+        let next_q = model.target.forward(&next_states)?.detach();
+        let masked = ((next_q * &next_actions)? + ((&next_actions - 1.0)? * 1e9)?)?;
+        let not_terminal = next_actions.max(1)?;
+        let next_value = (masked.max(1)? * not_terminal)?;
+        let targets = (rewards + (next_value * self.discount_factor)?)?.detach();
+
+        let q = model
+            .online
+            .forward(&states)?
+            .gather(&actions, 1)?
+            .squeeze(1)?;
+        let loss = candle_nn::loss::mse(&q, &targets)?;
+        model.optimizer.backward_step(&loss)
+    }
+}
+
+impl<'a, S, A> Agenter<'a, S, A> for DqnAgent<'a, S, A>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a> + 'a,
+{
+    /// Recommends the possible action with the highest estimated q-value.
+    /// Ties are broken in favor of the action that comes first in the action
+    /// space.
+    fn recommend_action(&mut self, state: &S) -> Result<&'a A, LearnerError> {
+        let q_values = self.q_values(state)?;
+        let mut best: Option<(f64, &'a A)> = None;
+        for action in state.possible_actions() {
+            let q = q_values[self.index_of(action)?];
+            if best.is_none_or(|(best_q, _)| q > best_q) {
+                best = Some((q, action));
+            }
+        }
+        best.map(|(_, action)| action)
+            .ok_or_else(|| LearnerError::NoPossibleActions {
+                state: format!("{:?}", state.id()),
+            })
+    }
+
+    fn transition(&self, state: &S, action: &'a A) -> Result<(), LearnerError> {
+        if !state.action_is_compatible(action) {
+            return Err(LearnerError::ActionNotCompatible {
+                state: format!("{:?}", state.id()),
+                action: format!("{:?}", action.id()),
+            });
+        }
+        state.apply(action)
+    }
+
+    /// Stores the transition in the replay buffer, then trains the network on
+    /// a batch of stored transitions if the buffer holds enough of them.
+    fn learn(
+        &mut self,
+        previous_state: Option<&S>,
+        action_taken: &A,
+        current_state: &S,
+        reward: f64,
+    ) -> Result<(), LearnerError> {
+        let Some(previous_state) = previous_state else {
+            return Ok(());
+        };
+        if !reward.is_finite() {
+            return Err(LearnerError::NonFinite(format!(
+                "reward {reward} is not finite"
+            )));
+        }
+        let action = u32::try_from(self.index_of(action_taken)?)
+            .map_err(|_| LearnerError::InvalidArgument("the action space is too large".into()))?;
+        let mut next_actions = vec![0.0; self.actions.len()];
+        for a in current_state.possible_actions() {
+            next_actions[self.index_of(a)?] = 1.0;
+        }
+        let experience = Experience {
+            state: self.observe(previous_state)?,
+            action,
+            reward: to_f32(reward),
+            next_state: self.observe(current_state)?,
+            next_actions,
+        };
+
+        self.replay.push_back(experience);
+        while self.replay.len() > self.replay_capacity {
+            self.replay.pop_front();
+        }
+        self.steps += 1;
+        if self.replay.len() >= self.batch_size {
+            self.train_batch().map_err(nn_err)?;
+        }
+        if self.steps.is_multiple_of(self.target_sync_interval) {
+            if let Some(model) = &self.model {
+                model.target.copy_from(&model.online).map_err(nn_err)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[allow(clippy::needless_pass_by_value)]
+fn nn_err(err: candle_core::Error) -> LearnerError {
+    LearnerError::Other(format!("neural network error: {err}"))
+}
+
+fn missing_model() -> LearnerError {
+    LearnerError::Other("the network has not been built".to_string())
+}
+
+#[allow(clippy::as_conversions, clippy::cast_possible_truncation)]
+fn to_f32(x: f64) -> f32 {
+    x as f32
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::mocks::*;
+    use crate::training::{Schedule, Trainer};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn one_hot(length: usize) -> impl Fn(&MockCell) -> Vec<f64> {
+        move |cell| {
+            (0..length)
+                .map(|i| if i == cell.position { 1.0 } else { 0.0 })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn learns_corridor() {
+        let moves = MockCorridor::moves();
+        let mut agent = DqnAgent::new(&moves, 0.9, one_hot(4))
+            .with_hidden_layers(vec![16])
+            .with_learning_rate(0.01)
+            .with_batch_size(16)
+            .with_target_sync_interval(20)
+            .with_rng(StdRng::seed_from_u64(1));
+        let mut env = MockCorridor::new(&moves, 4);
+        Trainer::new(60, 20)
+            .with_epsilon(Schedule::Linear {
+                start: 1.0,
+                end: 0.05,
+                episodes: 40,
+            })
+            .with_rng(StdRng::seed_from_u64(2))
+            .train(&mut agent, &mut env)
+            .unwrap();
+
+        assert!(agent.step_count() > 0);
+        for position in 0..3 {
+            let cell = MockCell {
+                position,
+                moves: &moves,
+            };
+            assert_eq!("R", agent.recommend_action(&cell).unwrap().return_id);
+        }
+    }
+
+    #[test]
+    fn replay_capacity() {
+        let moves = MockCorridor::moves();
+        let mut agent = DqnAgent::new(&moves, 0.9, one_hot(2))
+            .with_replay_capacity(3)
+            .with_batch_size(2)
+            .with_rng(StdRng::seed_from_u64(1));
+        let cell = |position| MockCell {
+            position,
+            moves: &moves,
+        };
+        agent.learn(None, &moves[1], &cell(0), 0.0).unwrap();
+        assert_eq!(0, agent.replay_len());
+        for _ in 0..5 {
+            agent
+                .learn(Some(&cell(0)), &moves[1], &cell(1), 1.0)
+                .unwrap();
+        }
+        assert_eq!(5, agent.step_count());
+        assert_eq!(3, agent.replay_len());
+        assert_eq!(2, agent.q_values(&cell(0)).unwrap().len());
+
+        let mut agent: DqnAgent<MockCell, MockActioner> =
+            DqnAgent::new_with_state_features(&moves, 0.9);
+        agent
+            .learn(Some(&cell(0)), &moves[1], &cell(1), 1.0)
+            .unwrap();
+        assert_eq!(2, agent.q_values(&cell(2)).unwrap().len());
+    }
+
+    #[test]
+    fn invalid_inputs() {
+        let moves = MockCorridor::moves();
+        let other = MockActioner { return_id: "X" };
+        let mut agent = DqnAgent::new(&moves, 0.9, |cell: &MockCell| vec![1.0; cell.position + 1]);
+        let cell = |position| MockCell {
+            position,
+            moves: &moves,
+        };
+        agent.recommend_action(&cell(0)).unwrap();
+        assert!(matches!(
+            agent.recommend_action(&cell(1)),
+            Err(LearnerError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            agent.learn(Some(&cell(0)), &other, &cell(0), 0.0),
+            Err(LearnerError::ActionNotFound { .. })
+        ));
+        assert!(matches!(
+            agent.learn(Some(&cell(0)), &moves[0], &cell(0), f64::NAN),
+            Err(LearnerError::NonFinite(_))
+        ));
+    }
+}
@@ -0,0 +1,298 @@
+//! EXP3 (exponential-weight algorithm for exploration and exploitation), a
+//! bandit agent for adversarial rewards.
+//!
+//! Agents that estimate the value of each action assume that an action's
+//! rewards are drawn from a fixed distribution. EXP3 makes no such
+//! assumption, and keeps its regret low even when rewards are chosen by an
+//! adversary that adapts to the agent's choices. It keeps a weight `w(a)` for
+//! each of the `K` actions, and samples actions with probabilities
+//!
+//! ```text
+//! p(a) = (1 - γ) w(a) / Σ w(b) + γ / K
+//! ```
+//!
+//! so that every action keeps a probability of at least `γ / K`. When an
+//! action `a` earns a reward `x` (scaled to `[0, 1]`), only its weight is
+//! updated, with the importance-weighted estimate `x / p(a)` of its reward:
+//!
+//! ```text
+//! w(a) ← w(a) exp(γ x / (p(a) K))
+//! ```
+//!
+//! See Auer et al., "The Nonstochastic Multiarmed Bandit Problem" (2002).
+//!
+//! The agent is intended for problems with a single state, and ignores the
+//! IDs of the states it is passed. The actions of each state must be the
+//! same. Weights are stored as logarithms, so they cannot overflow.
+
+use crate::actions::Actioner;
+use crate::agents::Agenter;
+use crate::errors::LearnerError;
+use crate::internal::{math, rng};
+use crate::states::Stater;
+use rand::{Rng, RngCore};
+use std::collections::HashMap;
+
+/// An agent that chooses actions with the EXP3 algorithm. See the module
+/// documentation for details.
+pub struct Exp3<'a, A>
+where
+    A: Actioner<'a>,
+{
+    gamma: f64,
+    reward_range: (f64, f64),
+    log_weights: HashMap<A::Id, f64>,
+    rng: Box<dyn RngCore + 'a>,
+}
+
+impl<'a, A> Exp3<'a, A>
+where
+    A: Actioner<'a> + 'a,
+{
+    /// Returns a new agent with the exploration rate `gamma`, which must be
+    /// in `(0, 1]`. Larger values explore more, and adapt faster to changes
+    /// in the rewards. Rewards are expected to be in `[0, 1]` by default.
+    pub fn new(gamma: f64) -> Result<Self, LearnerError> {
+        if !(gamma > 0.0 && gamma <= 1.0) {
+            return Err(LearnerError::InvalidArgument(format!(
+                "EXP3 gamma {gamma} must be in (0, 1]"
+            )));
+        }
+        Ok(Self {
+            gamma,
+            reward_range: (0.0, 1.0),
+            log_weights: HashMap::new(),
+            rng: Box::new(rng::default_rng()),
+        })
+    }
+
+    /// Sets the range of the rewards that the agent learns from, which are
+    /// scaled to `[0, 1]` before they are used. Learning from a reward
+    /// outside of the range returns an error. The default range is `[0, 1]`.
+    pub fn with_reward_range(mut self, min: f64, max: f64) -> Result<Self, LearnerError> {
+        if !min.is_finite() || !max.is_finite() || min >= max {
+            return Err(LearnerError::InvalidArgument(format!(
+                "reward range ({min}, {max}) must be finite, with min < max"
+            )));
+        }
+        self.reward_range = (min, max);
+        Ok(self)
+    }
+
+    /// Sets the random number generator used to sample actions.
+    #[must_use]
+    pub fn with_rng<R: RngCore + 'a>(mut self, rng: R) -> Self {
+        self.rng = Box::new(rng);
+        self
+    }
+
+    /// Returns the natural logarithm of an action's weight, which is 0 until
+    /// the action earns a reward.
+    pub fn log_weight(&self, action_id: &A::Id) -> f64 {
+        self.log_weights.get(action_id).copied().unwrap_or_default()
+    }
+
+    /// Returns the probability of recommending each of `state`'s possible
+    /// actions, in the order that the state reports them.
+    pub fn probabilities<S: Stater<'a, A>>(&self, state: &S) -> Vec<(&'a A, f64)> {
+        let actions = state.possible_actions();
+        let explore = self.gamma / math::len_to_f64(actions.len());
+        let log_weights: Vec<f64> = actions.iter().map(|a| self.log_weight(&a.id())).collect();
+        let max = log_weights
+            .iter()
+            .copied()
+            .fold(f64::NEG_INFINITY, f64::max);
+        let weights: Vec<f64> = log_weights.iter().map(|w| (w - max).exp()).collect();
+        let total: f64 = weights.iter().sum();
+        actions
+            .into_iter()
+            .zip(weights)
+            .map(|(action, weight)| (action, (1.0 - self.gamma).mul_add(weight / total, explore)))
+            .collect()
+    }
+}
+
+impl<'a, S, A> Agenter<'a, S, A> for Exp3<'a, A>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a> + 'a,
+{
+    /// Samples an action from the agent's current probabilities.
+    fn recommend_action(&mut self, state: &S) -> Result<&'a A, LearnerError> {
+        let probabilities = self.probabilities(state);
+        let Some(last) = probabilities.last().map(|(action, _)| *action) else {
+            return Err(LearnerError::NoPossibleActions {
+                state: format!("{:?}", state.id()),
+            });
+        };
+        let mut remaining = self.rng.gen::<f64>();
+        for (action, probability) in probabilities {
+            if remaining < probability {
+                return Ok(action);
+            }
+            remaining -= probability;
+        }
+        // Rounding can leave a sliver of probability unaccounted for.
+        Ok(last)
+    }
+
+    fn transition(&self, state: &S, action: &'a A) -> Result<(), LearnerError> {
+        if !state.action_is_compatible(action) {
+            return Err(LearnerError::ActionNotCompatible {
+                state: format!("{:?}", state.id()),
+                action: format!("{:?}", action.id()),
+            });
+        }
+        state.apply(action)
+    }
+
+    /// Updates the weight of `action_taken`, using its probability under the
+    /// agent's current weights. The update is only unbiased if the action was
+    /// sampled from those same weights, so each recommendation should be
+    /// learned from before the next is made. Nothing is learned if there is
+    /// no previous state.
+    /// An error is returned if the reward is outside of the reward range, or
+    /// if the action is not possible in the previous state.
+    fn learn(
+        &mut self,
+        previous_state: Option<&S>,
+        action_taken: &A,
+        _current_state: &S,
+        reward: f64,
+    ) -> Result<(), LearnerError> {
+        let (min, max) = self.reward_range;
+        if !(min..=max).contains(&reward) {
+            return Err(LearnerError::InvalidArgument(format!(
+                "EXP3 reward {reward} must be in [{min}, {max}]"
+            )));
+        }
+        let Some(state) = previous_state else {
+            return Ok(());
+        };
+        let taken = action_taken.id();
+        let probabilities = self.probabilities(state);
+        let action_count = probabilities.len();
+        let probability = probabilities
+            .into_iter()
+            .find(|(action, _)| action.id() == taken)
+            .map(|(_, probability)| probability)
+            .ok_or_else(|| LearnerError::ActionNotCompatible {
+                state: format!("{:?}", state.id()),
+                action: format!("{taken:?}"),
+            })?;
+
+        let scaled = (reward - min) / (max - min);
+        let estimate = scaled / probability;
+        *self.log_weights.entry(taken).or_default() +=
+            self.gamma * estimate / math::len_to_f64(action_count);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::environments::bandit::{Arm, ArmDistribution, Bandit, BanditState};
+    use crate::environments::Environment;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn state(arms: &[Arm]) -> BanditState<'_> {
+        Bandit::new(
+            arms,
+            vec![ArmDistribution::Bernoulli { p: 1.0 }; arms.len()],
+        )
+        .unwrap()
+        .reset()
+        .unwrap()
+    }
+
+    fn probabilities<'a>(agent: &Exp3<'a, Arm>, state: &BanditState<'a>) -> Vec<f64> {
+        agent.probabilities(state).iter().map(|(_, p)| *p).collect()
+    }
+
+    #[test]
+    fn updates_weights() {
+        let arms = Bandit::arms(2);
+        let state = state(&arms);
+        let mut agent: Exp3<Arm> = Exp3::new(0.5)
+            .unwrap()
+            .with_reward_range(-1.0, 1.0)
+            .unwrap();
+        assert_eq!(vec![0.5, 0.5], probabilities(&agent, &state));
+
+        // A reward of 1 scales to 1, estimated as 1 / 0.5, and the weight
+        // grows by exp(0.5 * 2 / 2).
+        agent.learn(Some(&state), &arms[0], &state, 1.0).unwrap();
+        assert_eq!(0.5, agent.log_weight(&0));
+        assert_eq!(0.0, agent.log_weight(&1));
+
+        // The minimum reward leaves the weight unchanged.
+        agent.learn(Some(&state), &arms[1], &state, -1.0).unwrap();
+        assert_eq!(0.0, agent.log_weight(&1));
+
+        agent.learn(None, &arms[0], &state, 1.0).unwrap();
+        assert!(agent.learn(Some(&state), &arms[0], &state, 2.0).is_err());
+        assert!(agent
+            .learn(Some(&state), &arms[0], &state, f64::NAN)
+            .is_err());
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        assert!(Exp3::<Arm>::new(0.0).is_err());
+        assert!(Exp3::<Arm>::new(1.5).is_err());
+        assert!(Exp3::<Arm>::new(0.1)
+            .unwrap()
+            .with_reward_range(1.0, 1.0)
+            .is_err());
+
+        let arms = Bandit::arms(3);
+        let other_arms = Bandit::arms(2);
+        let state = state(&other_arms);
+        let mut agent: Exp3<Arm> = Exp3::new(0.1).unwrap();
+        assert!(matches!(
+            agent.learn(Some(&state), &arms[2], &state, 1.0),
+            Err(LearnerError::ActionNotCompatible { .. })
+        ));
+    }
+
+    #[test]
+    fn keeps_exploring() {
+        let arms = Bandit::arms(4);
+        let state = state(&arms);
+        let mut agent: Exp3<Arm> = Exp3::new(0.2).unwrap();
+        for _ in 0..10_000 {
+            agent.learn(Some(&state), &arms[0], &state, 1.0).unwrap();
+        }
+        let probabilities = probabilities(&agent, &state);
+        assert!((probabilities[0] - 0.85).abs() < 1e-9);
+        for p in &probabilities[1..] {
+            assert!((p - 0.05).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn adapts_to_adversary() {
+        // The adversary pays arm 0 at first, and then switches to paying arm
+        // 2, after the agent has come to prefer arm 0.
+        let arms = Bandit::arms(3);
+        let state = state(&arms);
+        let mut agent: Exp3<Arm> = Exp3::new(0.1).unwrap().with_rng(StdRng::seed_from_u64(1));
+        for round in 0..3000 {
+            let paid = if round < 1000 { 0 } else { 2 };
+            let action = agent.recommend_action(&state).unwrap();
+            let reward = if action.index() == paid { 1.0 } else { 0.0 };
+            agent.learn(Some(&state), action, &state, reward).unwrap();
+            if round == 999 {
+                assert!(probabilities(&agent, &state)[0] > 0.9);
+            }
+        }
+        assert!(
+            probabilities(&agent, &state)[2] > 0.9,
+            "{:?}",
+            probabilities(&agent, &state)
+        );
+    }
+}
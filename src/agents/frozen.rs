@@ -0,0 +1,179 @@
+//! Contains a lightweight agent that follows a fixed, greedy policy.
+//!
+//! A `FrozenPolicy` is extracted from a trained agent (see
+//! `bayesian::Agent::frozen_policy`), and can be deployed in place of the
+//! agent wherever a policy needs to be evaluated without being changed. It
+//! always recommends the same action for a given state, never explores, and
+//! ignores calls to `learn`.
+
+use crate::actions::Actioner;
+use crate::agents::Agenter;
+use crate::errors::LearnerError;
+use crate::states::Stater;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+/// An agent that recommends actions from a fixed map of state IDs to action
+/// IDs.
+///
+/// For states that are not in the map, the policy recommends the possible
+/// action with the lowest ID, so that recommendations are always
+/// deterministic. `learn` is a no-op, so the policy never changes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct FrozenPolicy<SK, AK>
+where
+    SK: Hash + Eq,
+{
+    policy: HashMap<SK, AK>,
+}
+
+impl<SK, AK> FrozenPolicy<SK, AK>
+where
+    SK: Hash + Eq,
+{
+    /// Instantiates a new `FrozenPolicy` from a map of state IDs to the IDs
+    /// of the actions to take in those states.
+    pub fn new(policy: HashMap<SK, AK>) -> Self {
+        Self { policy }
+    }
+
+    /// Returns the ID of the action that the policy takes for a state, or
+    /// `None` if the state is not in the policy.
+    pub fn action_for(&self, state_id: &SK) -> Option<&AK> {
+        self.policy.get(state_id)
+    }
+
+    /// Returns the number of states in the policy.
+    pub fn len(&self) -> usize {
+        self.policy.len()
+    }
+
+    /// Returns true if the policy contains no states.
+    pub fn is_empty(&self) -> bool {
+        self.policy.is_empty()
+    }
+
+    /// Consumes the policy, returning the underlying map of state IDs to
+    /// action IDs.
+    pub fn into_inner(self) -> HashMap<SK, AK> {
+        self.policy
+    }
+}
+
+impl<SK, AK> From<HashMap<SK, AK>> for FrozenPolicy<SK, AK>
+where
+    SK: Hash + Eq,
+{
+    fn from(policy: HashMap<SK, AK>) -> Self {
+        Self::new(policy)
+    }
+}
+
+impl<'a, S, A, SK, AK> Agenter<'a, S, A> for FrozenPolicy<SK, AK>
+where
+    S: Stater<'a, A, Id = SK>,
+    A: Actioner<'a, Id = AK>,
+    SK: Hash + Eq + Debug,
+    AK: Ord + Debug,
+{
+    /// Recommends the action that the policy holds for the state. If the
+    /// state is not in the policy, the possible action with the lowest ID is
+    /// recommended.
+    /// An error is returned if the state is not in the policy and reports no
+    /// possible actions, or if the state does not recognize the policy's
+    /// action.
+    #[allow(clippy::use_debug)]
+    fn recommend_action(&mut self, state: &S) -> Result<&'a A, LearnerError> {
+        let state_id = state.id();
+        if let Some(action_id) = self.policy.get(&state_id) {
+            return state.get_action(action_id);
+        }
+        state
+            .possible_actions()
+            .into_iter()
+            .min_by(|x, y| x.id().cmp(&y.id()))
+            .ok_or_else(|| LearnerError::NoPossibleActions {
+                state: format!("{state_id:?}"),
+            })
+    }
+
+    /// Applies an action to a given state.
+    /// An error is returned if the action is not compatible with the state.
+    #[allow(clippy::use_debug)]
+    fn transition(&self, state: &S, action: &'a A) -> Result<(), LearnerError> {
+        if !state.action_is_compatible(action) {
+            return Err(LearnerError::ActionNotCompatible {
+                state: format!("{:?}", state.id()),
+                action: format!("{:?}", action.id()),
+            });
+        }
+        state.apply(action)
+    }
+
+    /// Does nothing. A frozen policy never learns.
+    fn learn(&mut self, _: Option<&S>, _: &A, _: &S, _: f64) -> Result<(), LearnerError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::agents::bayesian::Agent;
+    use crate::mocks::*;
+    use crate::stats::actionstats::Stats;
+    use crate::training::Trainer;
+    use maplit::hashmap;
+
+    #[test]
+    fn recommend_action() {
+        let moves = MockCorridor::moves();
+        let cell = |position| MockCell {
+            position,
+            moves: &moves,
+        };
+        let mut policy = FrozenPolicy::new(hashmap! { 0 => "R".to_string() });
+
+        let action = policy.recommend_action(&cell(0)).unwrap();
+        assert_eq!("R", action.id());
+
+        // State 1 is not in the policy, so the lowest action ID is chosen.
+        let action = policy.recommend_action(&cell(1)).unwrap();
+        assert_eq!("L", action.id());
+
+        let empty = MockStater::<MockActioner> {
+            return_id: "A",
+            ..Default::default()
+        };
+        let result: Result<&MockActioner, _> =
+            FrozenPolicy::new(HashMap::new()).recommend_action(&empty);
+        assert_eq!(
+            Some(LearnerError::NoPossibleActions {
+                state: "\"A\"".to_string()
+            }),
+            result.err()
+        );
+    }
+
+    #[test]
+    fn learn_is_a_no_op() {
+        let moves = MockCorridor::moves();
+        let mut agent: Agent<MockCell, MockActioner, Stats> = Agent::new(1, 1.0, 0.9);
+        let mut env = MockCorridor::new(&moves, 4);
+        Trainer::new(20, 50).train(&mut agent, &mut env).unwrap();
+
+        let mut policy = agent.frozen_policy();
+        assert_eq!(agent.extract_policy().len(), policy.len());
+
+        let before = policy.clone();
+        let mut env = MockCorridor::new(&moves, 4);
+        let reports = Trainer::new(5, 50).train(&mut policy, &mut env).unwrap();
+        assert_eq!(before, policy);
+        assert!(reports.iter().all(|r| r.terminated));
+    }
+}
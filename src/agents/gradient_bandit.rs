@@ -0,0 +1,300 @@
+//! The gradient bandit algorithm, which learns a preference for each action
+//! rather than an estimate of its value.
+//!
+//! Actions are chosen at random with softmax probabilities
+//! `π(a) = exp(H(a)) / Σ exp(H(b))` over the preferences `H`. After an action
+//! `a` earns a reward `r`, the preferences are moved by stochastic gradient
+//! ascent on the expected reward:
+//!
+//! ```text
+//! H(a) ← H(a) + α (r - R̄) (1 - π(a))
+//! H(b) ← H(b) - α (r - R̄) π(b)    for every other action b
+//! ```
+//!
+//! where the baseline `R̄` is the average of the rewards earned before `r`.
+//! Rewards above the baseline make the action more likely, and rewards below
+//! it make the action less likely. See Sutton and Barto, "Reinforcement
+//! Learning: An Introduction" (2018), section 2.8.
+//!
+//! The agent is intended for problems with a single state, such as the
+//! `environments::bandit::Bandit`, and ignores the IDs of the states it is
+//! passed. The actions of each state must be the same.
+
+use crate::actions::Actioner;
+use crate::agents::Agenter;
+use crate::errors::LearnerError;
+use crate::internal::{math, rng};
+use crate::states::Stater;
+use rand::{Rng, RngCore};
+use std::collections::HashMap;
+
+/// An agent that learns action preferences with the gradient bandit
+/// algorithm. See the module documentation for details.
+pub struct GradientBandit<'a, A>
+where
+    A: Actioner<'a>,
+{
+    step_size: f64,
+    baseline_step_size: Option<f64>,
+    use_baseline: bool,
+    preferences: HashMap<A::Id, f64>,
+    baseline: f64,
+    reward_count: u64,
+    rng: Box<dyn RngCore + 'a>,
+}
+
+impl<'a, A> GradientBandit<'a, A>
+where
+    A: Actioner<'a> + 'a,
+{
+    /// Returns a new agent whose preferences are updated with the step size
+    /// `step_size` (α). All preferences start at 0, so every action is
+    /// equally likely at first.
+    pub fn new(step_size: f64) -> Self {
+        Self {
+            step_size,
+            baseline_step_size: None,
+            use_baseline: true,
+            preferences: HashMap::new(),
+            baseline: 0.0,
+            reward_count: 0,
+            rng: Box::new(rng::default_rng()),
+        }
+    }
+
+    /// Moves the baseline towards each reward by a constant fraction of the
+    /// difference, rather than averaging all rewards, so that it can track
+    /// rewards that change over time.
+    #[must_use]
+    pub fn with_baseline_step_size(mut self, step_size: f64) -> Self {
+        self.baseline_step_size = Some(step_size);
+        self
+    }
+
+    /// Disables the baseline, so that preferences are moved by the reward
+    /// itself. Without a baseline, learning is much slower when rewards are
+    /// offset from 0.
+    #[must_use]
+    pub fn without_baseline(mut self) -> Self {
+        self.use_baseline = false;
+        self
+    }
+
+    /// Sets the random number generator used to sample actions.
+    #[must_use]
+    pub fn with_rng<R: RngCore + 'a>(mut self, rng: R) -> Self {
+        self.rng = Box::new(rng);
+        self
+    }
+
+    /// Returns the preference for an action, which is 0 until the agent
+    /// learns about the action.
+    pub fn preference(&self, action_id: &A::Id) -> f64 {
+        self.preferences.get(action_id).copied().unwrap_or_default()
+    }
+
+    /// Returns the current reward baseline, which is 0 if the baseline is
+    /// disabled.
+    pub fn baseline(&self) -> f64 {
+        self.baseline
+    }
+
+    /// Returns the probability of recommending each of `state`'s possible
+    /// actions, in the order that the state reports them.
+    pub fn probabilities<S: Stater<'a, A>>(&self, state: &S) -> Vec<(&'a A, f64)> {
+        let actions = state.possible_actions();
+        let preferences: Vec<f64> = actions.iter().map(|a| self.preference(&a.id())).collect();
+        actions.into_iter().zip(softmax(&preferences)).collect()
+    }
+}
+
+impl<'a, S, A> Agenter<'a, S, A> for GradientBandit<'a, A>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a> + 'a,
+{
+    /// Samples an action from the softmax of the preferences.
+    fn recommend_action(&mut self, state: &S) -> Result<&'a A, LearnerError> {
+        let probabilities = self.probabilities(state);
+        let Some(last) = probabilities.last().map(|(action, _)| *action) else {
+            return Err(LearnerError::NoPossibleActions {
+                state: format!("{:?}", state.id()),
+            });
+        };
+        let mut remaining = self.rng.gen::<f64>();
+        for (action, probability) in probabilities {
+            if remaining < probability {
+                return Ok(action);
+            }
+            remaining -= probability;
+        }
+        // Rounding can leave a sliver of probability unaccounted for.
+        Ok(last)
+    }
+
+    fn transition(&self, state: &S, action: &'a A) -> Result<(), LearnerError> {
+        if !state.action_is_compatible(action) {
+            return Err(LearnerError::ActionNotCompatible {
+                state: format!("{:?}", state.id()),
+                action: format!("{:?}", action.id()),
+            });
+        }
+        state.apply(action)
+    }
+
+    /// Updates the preferences of `previous_state`'s possible actions, given
+    /// the reward earned by `action_taken`, and then the baseline. Nothing is
+    /// learned if there is no previous state.
+    fn learn(
+        &mut self,
+        previous_state: Option<&S>,
+        action_taken: &A,
+        _current_state: &S,
+        reward: f64,
+    ) -> Result<(), LearnerError> {
+        if !reward.is_finite() {
+            return Err(LearnerError::NonFinite(format!(
+                "reward {reward} is not finite"
+            )));
+        }
+        let Some(state) = previous_state else {
+            return Ok(());
+        };
+        let taken = action_taken.id();
+        let advantage = reward - self.baseline;
+        let updates: Vec<(A::Id, f64)> = self
+            .probabilities(state)
+            .into_iter()
+            .map(|(action, probability)| {
+                let id = action.id();
+                let gradient = if id == taken {
+                    1.0 - probability
+                } else {
+                    -probability
+                };
+                (id, gradient)
+            })
+            .collect();
+        for (id, gradient) in updates {
+            let preference = self.preferences.entry(id).or_default();
+            *preference = (self.step_size * advantage).mul_add(gradient, *preference);
+        }
+
+        self.reward_count = self.reward_count.saturating_add(1);
+        if self.use_baseline {
+            let step_size = self
+                .baseline_step_size
+                .unwrap_or_else(|| 1.0 / math::count_to_f64(self.reward_count));
+            self.baseline = step_size.mul_add(advantage, self.baseline);
+        }
+        Ok(())
+    }
+}
+
+/// Returns the softmax of `preferences`, shifted by their maximum so that the
+/// exponentials cannot overflow.
+fn softmax(preferences: &[f64]) -> Vec<f64> {
+    let max = preferences
+        .iter()
+        .copied()
+        .fold(f64::NEG_INFINITY, f64::max);
+    let exponentials: Vec<f64> = preferences.iter().map(|h| (h - max).exp()).collect();
+    let total: f64 = exponentials.iter().sum();
+    exponentials.iter().map(|e| e / total).collect()
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::environments::bandit::{Arm, ArmDistribution, Bandit, BanditState};
+    use crate::environments::Environment;
+    use crate::training::Trainer;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn updates_preferences_and_baseline() {
+        let arms = Bandit::arms(2);
+        let mut bandit =
+            Bandit::new(&arms, vec![ArmDistribution::Bernoulli { p: 1.0 }; 2]).unwrap();
+        let state = bandit.reset().unwrap();
+        let mut agent: GradientBandit<Arm> = GradientBandit::new(0.5);
+        assert_eq!(
+            vec![0.5, 0.5],
+            agent
+                .probabilities(&state)
+                .iter()
+                .map(|(_, p)| *p)
+                .collect::<Vec<_>>()
+        );
+
+        // With a baseline of 0: H(0) = 0.5 * 2 * 0.5, H(1) = -0.5 * 2 * 0.5
+        agent.learn(Some(&state), &arms[0], &state, 2.0).unwrap();
+        assert_eq!(0.5, agent.preference(&0));
+        assert_eq!(-0.5, agent.preference(&1));
+        assert_eq!(2.0, agent.baseline());
+
+        // A reward equal to the baseline changes nothing.
+        agent.learn(Some(&state), &arms[1], &state, 2.0).unwrap();
+        assert_eq!(0.5, agent.preference(&0));
+        assert_eq!(2.0, agent.baseline());
+
+        // A reward below the baseline makes the action less likely.
+        agent.learn(Some(&state), &arms[0], &state, 0.0).unwrap();
+        assert!(agent.preference(&0) < 0.5);
+        assert!((agent.baseline() - 4.0 / 3.0).abs() < 1e-12);
+
+        agent.learn(None, &arms[0], &state, 0.0).unwrap();
+        assert!(agent
+            .learn(Some(&state), &arms[0], &state, f64::NAN)
+            .is_err());
+    }
+
+    #[test]
+    fn without_baseline() {
+        let arms = Bandit::arms(2);
+        let state = Bandit::new(&arms, vec![ArmDistribution::Bernoulli { p: 1.0 }; 2])
+            .unwrap()
+            .reset()
+            .unwrap();
+        let mut agent: GradientBandit<Arm> = GradientBandit::new(0.5).without_baseline();
+        agent.learn(Some(&state), &arms[0], &state, 2.0).unwrap();
+        agent.learn(Some(&state), &arms[0], &state, 2.0).unwrap();
+        assert_eq!(0.0, agent.baseline());
+        assert!(agent.preference(&0) > 0.5);
+    }
+
+    #[test]
+    fn softmax_is_stable() {
+        let probabilities = softmax(&[1000.0, 1000.0, f64::MIN]);
+        assert_eq!(vec![0.5, 0.5, 0.0], probabilities);
+    }
+
+    #[test]
+    fn finds_optimal_arm() {
+        // Rewards are offset from 0, which the baseline compensates for.
+        let arms = Bandit::arms(5);
+        let distributions = (0..5)
+            .map(|i| ArmDistribution::Gaussian {
+                mean: f64::from(i).mul_add(0.5, 4.0),
+                std_dev: 1.0,
+            })
+            .collect();
+        let mut bandit = Bandit::new(&arms, distributions).unwrap().with_seed(1);
+        let mut agent: GradientBandit<Arm> =
+            GradientBandit::new(0.1).with_rng(StdRng::seed_from_u64(2));
+        Trainer::new(2000, 1)
+            .train(&mut agent, &mut bandit)
+            .unwrap();
+
+        let state: BanditState = bandit.reset().unwrap();
+        let (best, probability) = agent
+            .probabilities(&state)
+            .into_iter()
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap();
+        assert_eq!(bandit.optimal_arm(), best);
+        assert!(probability > 0.5, "{:?}", probability);
+    }
+}
@@ -4,17 +4,71 @@
 //! - Recommending an action given a state.
 //! - Transitioning from one state to another state given some action.
 //! - Learning from the level of success achieved when moving from one
-//! state to another via some action.
+//!   state to another via some action.
+//!
+//! When the `tracing` feature is enabled, `bayesian::Agent` records a
+//! debug-level `tracing` span for each call to `learn`, `recommend_action`,
+//! and `transition`, along with events describing each q-value update and
+//! recommendation.
 
 pub mod bayesian;
+pub mod contextual;
+pub mod differential;
+#[cfg(feature = "dqn")]
+pub mod dqn;
+pub mod exp3;
+pub mod frozen;
+pub mod gradient_bandit;
+pub mod multi;
+pub mod qsigma;
+pub mod recommender;
+pub mod returns;
+pub mod shared;
 
 use crate::actions::Actioner;
 use crate::errors::LearnerError;
 use crate::states::Stater;
+use std::fmt;
+
+/// The lifecycle stage of an agent, which governs which operations the agent
+/// will accept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lifecycle {
+    /// The agent recommends actions and learns from transitions.
+    Learning,
+
+    /// The agent recommends actions, but refuses to learn.
+    Frozen,
+
+    /// The agent is being retired. It continues to learn from transitions
+    /// that are still in flight, but refuses to recommend new actions.
+    /// Draining is a terminal stage.
+    Draining,
+}
+
+impl fmt::Display for Lifecycle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Learning => "learning",
+            Self::Frozen => "frozen",
+            Self::Draining => "draining",
+        };
+        f.write_str(name)
+    }
+}
 
 /// Represents something that is capabile of recommending actions, applying
 /// actions to a given state, and learning based on the transition from one
 /// state to another.
+///
+/// Agents do not hold on to the states that are passed to them, so states can
+/// be created on the fly and discarded once the agent has seen them. Actions,
+/// on the other hand, must live for the lifetime `'a`, because agents
+/// recommend actions by reference.
+///
+/// The trait is object safe, so agents of different types can be stored as
+/// `Box<dyn Agenter<'a, S, A>>` and chosen at runtime. A boxed agent is
+/// itself an `Agenter`, and can be used anywhere an agent is expected.
 pub trait Agenter<'a, S, A>
 where
     S: Stater<'a, A>,
@@ -22,19 +76,78 @@ where
 {
     /// Recommends an action given a state and the model that the agent has
     /// learned thus far.
-    fn recommend_action(&mut self, stater: &'a S) -> Result<&'a A, LearnerError>;
+    fn recommend_action(&mut self, stater: &S) -> Result<&'a A, LearnerError>;
 
     /// Applies an action to a given state.
     /// Implementors should take care to ensure that this method returns an
     /// error if the supplied action is not applicable to the specified state.
-    fn transition(&self, stater: &'a S, actioner: &'a A) -> Result<(), LearnerError>;
+    fn transition(&self, stater: &S, actioner: &'a A) -> Result<(), LearnerError>;
 
     /// Updates the model for a given state and action using the provided reward.
+    /// Implementors should return an error if the agent is not currently able
+    /// to learn (for instance, if it has been frozen).
     fn learn(
         &mut self,
-        previous_state: Option<&'a S>,
-        action_taken: &'a A,
-        current_state: &'a S,
+        previous_state: Option<&S>,
+        action_taken: &A,
+        current_state: &S,
         reward: f64,
-    );
+    ) -> Result<(), LearnerError>;
+}
+
+impl<'a, S, A, G> Agenter<'a, S, A> for Box<G>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a>,
+    G: Agenter<'a, S, A> + ?Sized,
+{
+    fn recommend_action(&mut self, stater: &S) -> Result<&'a A, LearnerError> {
+        (**self).recommend_action(stater)
+    }
+
+    fn transition(&self, stater: &S, actioner: &'a A) -> Result<(), LearnerError> {
+        (**self).transition(stater, actioner)
+    }
+
+    fn learn(
+        &mut self,
+        previous_state: Option<&S>,
+        action_taken: &A,
+        current_state: &S,
+        reward: f64,
+    ) -> Result<(), LearnerError> {
+        (**self).learn(previous_state, action_taken, current_state, reward)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::agents::bayesian::Agent;
+    use crate::mocks::*;
+    use crate::stats::actionstats::Stats;
+    use crate::stats::rewardstats::RewardStats;
+    use crate::training::Trainer;
+
+    #[test]
+    fn boxed_agents() {
+        let moves = MockCorridor::moves();
+        let mut agents: Vec<Box<dyn Agenter<MockCell, MockActioner>>> = vec![
+            Box::new(Agent::<MockCell, MockActioner, Stats>::new(1, 1.0, 0.9)),
+            Box::new(Agent::<MockCell, MockActioner, RewardStats>::new(
+                1, 0.5, 0.9,
+            )),
+        ];
+
+        for agent in &mut agents {
+            let mut env = MockCorridor::new(&moves, 3);
+            let reports = Trainer::new(5, 20).train(agent, &mut env).unwrap();
+            assert_eq!(5, reports.len());
+        }
+
+        let agent: &mut dyn Agenter<MockCell, MockActioner> = agents[0].as_mut();
+        let mut env = MockCorridor::new(&moves, 3);
+        assert!(Trainer::new(1, 20).train(agent, &mut env).is_ok());
+    }
 }
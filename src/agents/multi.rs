@@ -0,0 +1,577 @@
+//! Cooperative learning with several agents.
+//!
+//! A `MultiAgentManager` holds one agent per member of a team. Each agent
+//! observes its own state and recommends its own action, and the manager
+//! passes each agent its share of a step. How the agents learn from rewards
+//! is chosen per manager with a `TeamMode`:
+//!
+//! - `TeamMode::Independent`: each agent learns from its own reward alone.
+//! - `TeamMode::SharedReward`: each agent keeps its own (factored) q-table,
+//!   but learns from the team's reward, which is the sum of every agent's
+//!   reward. The agents are credited for the team's success as a whole, so
+//!   they learn to cooperate rather than to compete.
+//!
+//! Factored q-tables stay small as the team grows, but an agent cannot learn
+//! the value of its action in combination with its teammates' actions. When
+//! that matters, as in coordination games, a single agent can instead learn
+//! over joint actions: a `JointState` combines the states of every member,
+//! and its possible actions are the `JointAction`s whose components are each
+//! possible in the respective member's state. Any agent can be used, since
+//! `JointState` and `JointAction` implement `Stater` and `Actioner`. The
+//! number of joint actions grows exponentially with the size of the team.
+//!
+//! Agents that play interchangeable roles can share a single q-table, so that
+//! memory does not grow with the size of the team, and each agent benefits
+//! from what the others have learned. `MultiAgentManager::with_q_tables`
+//! builds a team whose agents either share one store or keep independent
+//! ones, as selected by `QTables`.
+
+use crate::actions::Actioner;
+use crate::agents::Agenter;
+use crate::errors::LearnerError;
+use crate::states::Stater;
+use crate::stores::shared::SharedQStore;
+use std::collections::HashSet;
+use std::marker::PhantomData;
+
+/// How the agents of a `MultiAgentManager` learn from rewards. See the module
+/// documentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TeamMode {
+    /// Each agent learns from its own reward.
+    Independent,
+
+    /// Each agent learns from the sum of every agent's reward.
+    SharedReward,
+}
+
+/// Whether the agents built by `MultiAgentManager::with_q_tables` share a
+/// q-table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QTables {
+    /// Each agent keeps its own store.
+    Independent,
+
+    /// Every agent learns into, and recommends from, a single store.
+    Shared,
+}
+
+/// Manages a team of agents, each of which acts in its own state. See the
+/// module documentation.
+///
+/// Each method takes one state, action, or reward per agent, in the order
+/// that the agents were supplied to `new`.
+pub struct MultiAgentManager<'a, S, A, G>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a>,
+    G: Agenter<'a, S, A>,
+{
+    agents: Vec<G>,
+    mode: TeamMode,
+    phantom: PhantomData<(&'a A, S)>,
+}
+
+impl<'a, S, A, G> MultiAgentManager<'a, S, A, G>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a> + 'a,
+    G: Agenter<'a, S, A>,
+{
+    /// Returns a manager of `agents`, which learn according to `mode`. An
+    /// error is returned if there are no agents.
+    pub fn new(agents: Vec<G>, mode: TeamMode) -> Result<Self, LearnerError> {
+        if agents.is_empty() {
+            return Err(LearnerError::InvalidArgument(
+                "a multi-agent manager requires at least one agent".to_string(),
+            ));
+        }
+        Ok(Self {
+            agents,
+            mode,
+            phantom: PhantomData,
+        })
+    }
+
+    /// Returns a manager of `count` agents, which learn according to `mode`.
+    /// Each agent is built by `new_agent` from a handle to its store. With
+    /// `QTables::Shared`, `new_store` is called once and every agent is given
+    /// a handle to the same store; with `QTables::Independent`, it is called
+    /// once per agent. An error is returned if `count` is 0.
+    pub fn with_q_tables<QS, N, F>(
+        count: usize,
+        mode: TeamMode,
+        tables: QTables,
+        mut new_store: N,
+        mut new_agent: F,
+    ) -> Result<Self, LearnerError>
+    where
+        N: FnMut() -> QS,
+        F: FnMut(SharedQStore<QS>) -> G,
+    {
+        let shared = match tables {
+            QTables::Shared if count > 0 => Some(SharedQStore::new(new_store())),
+            _ => None,
+        };
+        let agents = (0..count)
+            .map(|_| {
+                let store = shared
+                    .clone()
+                    .unwrap_or_else(|| SharedQStore::new(new_store()));
+                new_agent(store)
+            })
+            .collect();
+        Self::new(agents, mode)
+    }
+
+    /// Returns the mode that the agents learn in.
+    pub fn mode(&self) -> TeamMode {
+        self.mode
+    }
+
+    /// Returns the number of agents.
+    pub fn len(&self) -> usize {
+        self.agents.len()
+    }
+
+    /// Returns true if there are no agents, which is never the case for a
+    /// manager returned by `new`.
+    pub fn is_empty(&self) -> bool {
+        self.agents.is_empty()
+    }
+
+    /// Returns the agents, in order.
+    pub fn agents(&self) -> &[G] {
+        &self.agents
+    }
+
+    /// Returns the agent at `index`, if there is one.
+    pub fn agent(&self, index: usize) -> Option<&G> {
+        self.agents.get(index)
+    }
+
+    /// Returns the agent at `index` mutably, if there is one.
+    pub fn agent_mut(&mut self, index: usize) -> Option<&mut G> {
+        self.agents.get_mut(index)
+    }
+
+    /// Returns the manager's agents, consuming the manager.
+    pub fn into_agents(self) -> Vec<G> {
+        self.agents
+    }
+
+    /// Returns each agent's recommended action in its state.
+    pub fn recommend_actions(&mut self, states: &[S]) -> Result<Vec<&'a A>, LearnerError> {
+        self.check_len("states", states.len())?;
+        self.agents
+            .iter_mut()
+            .zip(states)
+            .map(|(agent, state)| agent.recommend_action(state))
+            .collect()
+    }
+
+    /// Applies each agent's action to its state. Actions are applied in
+    /// order, and the first error is returned.
+    pub fn transition(&self, states: &[S], actions: &[&'a A]) -> Result<(), LearnerError> {
+        self.check_len("states", states.len())?;
+        self.check_len("actions", actions.len())?;
+        self.agents
+            .iter()
+            .zip(states)
+            .zip(actions)
+            .try_for_each(|((agent, state), action)| agent.transition(state, action))
+    }
+
+    /// Updates each agent's model for the transition from its previous state
+    /// to its current state, through the action it took. Each agent learns
+    /// from its own reward, or from the team's reward, depending on the
+    /// manager's mode. As with `Agenter::learn`, `previous_states` may be
+    /// `None` at the start of an episode.
+    pub fn learn(
+        &mut self,
+        previous_states: Option<&[S]>,
+        actions_taken: &[&A],
+        current_states: &[S],
+        rewards: &[f64],
+    ) -> Result<(), LearnerError> {
+        if let Some(previous_states) = previous_states {
+            self.check_len("previous states", previous_states.len())?;
+        }
+        self.check_len("actions", actions_taken.len())?;
+        self.check_len("current states", current_states.len())?;
+        self.check_len("rewards", rewards.len())?;
+        let team_reward: f64 = rewards.iter().sum();
+        for (i, agent) in self.agents.iter_mut().enumerate() {
+            let reward = match self.mode {
+                TeamMode::Independent => rewards[i],
+                TeamMode::SharedReward => team_reward,
+            };
+            agent.learn(
+                previous_states.map(|states| &states[i]),
+                actions_taken[i],
+                &current_states[i],
+                reward,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn check_len(&self, what: &str, len: usize) -> Result<(), LearnerError> {
+        if len == self.agents.len() {
+            Ok(())
+        } else {
+            Err(LearnerError::InvalidArgument(format!(
+                "expected {} {what}, one per agent, but got {len}",
+                self.agents.len()
+            )))
+        }
+    }
+}
+
+/// An action for each member of a team, taken together. Its ID is the list
+/// of its components' IDs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JointAction<'a, A> {
+    components: Vec<&'a A>,
+}
+
+impl<'a, A> JointAction<'a, A>
+where
+    A: Actioner<'a>,
+{
+    /// Returns a joint action made up of `components`, one per member.
+    pub fn new(components: Vec<&'a A>) -> Self {
+        Self { components }
+    }
+
+    /// Returns every combination of the members' actions, where
+    /// `member_actions` lists the actions available to each member. There is
+    /// one joint action for each element of the cartesian product, so the
+    /// number of joint actions is the product of the members' action counts.
+    pub fn all(member_actions: &[&'a [A]]) -> Vec<Self> {
+        member_actions
+            .iter()
+            .fold(vec![Vec::new()], |partials, actions| {
+                partials
+                    .iter()
+                    .flat_map(|partial| {
+                        actions.iter().map(move |action| {
+                            let mut components = partial.clone();
+                            components.push(action);
+                            components
+                        })
+                    })
+                    .collect()
+            })
+            .into_iter()
+            .map(Self::new)
+            .collect()
+    }
+
+    /// Returns the action of each member, in order.
+    pub fn components(&self) -> &[&'a A] {
+        &self.components
+    }
+}
+
+impl<'a, A> Actioner<'a> for JointAction<'a, A>
+where
+    A: Actioner<'a>,
+{
+    type Id = Vec<A::Id>;
+
+    fn id(&self) -> Vec<A::Id> {
+        self.components.iter().map(|action| action.id()).collect()
+    }
+}
+
+/// The states of every member of a team, taken together.
+///
+/// Its ID is the list of its members' state IDs, and its possible actions
+/// are drawn from a fixed set of joint actions, such as those returned by
+/// `JointAction::all`.
+pub struct JointState<'a, S, A> {
+    states: Vec<S>,
+    joint_actions: &'a [JointAction<'a, A>],
+}
+
+impl<'a, S, A> JointState<'a, S, A>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a>,
+{
+    /// Returns the joint state of `states`, one per member, whose possible
+    /// actions are drawn from `joint_actions`.
+    pub fn new(states: Vec<S>, joint_actions: &'a [JointAction<'a, A>]) -> Self {
+        Self {
+            states,
+            joint_actions,
+        }
+    }
+
+    /// Returns the state of each member, in order.
+    pub fn states(&self) -> &[S] {
+        &self.states
+    }
+}
+
+impl<'a, S, A> Stater<'a, JointAction<'a, A>> for JointState<'a, S, A>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a> + 'a,
+{
+    type Id = Vec<S::Id>;
+
+    /// Returns the joint actions whose components are each one of the
+    /// possible actions of the respective member's state.
+    fn possible_actions(&self) -> Vec<&'a JointAction<'a, A>> {
+        let possible: Vec<HashSet<A::Id>> = self
+            .states
+            .iter()
+            .map(|state| state.possible_actions().iter().map(|a| a.id()).collect())
+            .collect();
+        self.joint_actions
+            .iter()
+            .filter(|joint| {
+                joint.components.len() == possible.len()
+                    && joint
+                        .components
+                        .iter()
+                        .zip(&possible)
+                        .all(|(action, ids)| ids.contains(&action.id()))
+            })
+            .collect()
+    }
+
+    fn action_is_compatible(&self, joint: &'a JointAction<'a, A>) -> bool {
+        joint.components.len() == self.states.len()
+            && joint
+                .components
+                .iter()
+                .zip(&self.states)
+                .all(|(action, state)| state.action_is_compatible(action))
+    }
+
+    fn get_action(&self, action_id: &Vec<A::Id>) -> Result<&'a JointAction<'a, A>, LearnerError> {
+        self.possible_actions()
+            .into_iter()
+            .find(|joint| joint.id() == *action_id)
+            .ok_or_else(|| LearnerError::ActionNotFound {
+                action: format!("{action_id:?}"),
+                reason: format!("not a possible joint action of {:?}", self.id()),
+            })
+    }
+
+    fn id(&self) -> Vec<S::Id> {
+        self.states.iter().map(Stater::id).collect()
+    }
+
+    /// Applies each component of `joint` to the respective member's state, in
+    /// order, and returns the first error.
+    fn apply(&self, joint: &'a JointAction<'a, A>) -> Result<(), LearnerError> {
+        if joint.components.len() != self.states.len() {
+            return Err(LearnerError::ActionNotCompatible {
+                state: format!("{:?}", self.id()),
+                action: format!("{:?}", joint.id()),
+            });
+        }
+        self.states
+            .iter()
+            .zip(&joint.components)
+            .try_for_each(|(state, action)| state.apply(action))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::agents::bayesian::Agent;
+    use crate::mocks::*;
+    use crate::stats::actionstats::Stats;
+    use crate::stats::ActionStatter;
+    use crate::stores::QMap;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    type CellAgent<'a> = Agent<'a, MockCell<'a>, MockActioner<'a>, Stats>;
+
+    fn cells<'a>(moves: &'a [MockActioner<'a>], positions: &[usize]) -> Vec<MockCell<'a>> {
+        positions
+            .iter()
+            .map(|&position| MockCell { position, moves })
+            .collect()
+    }
+
+    fn manager<'a>(
+        mode: TeamMode,
+    ) -> MultiAgentManager<'a, MockCell<'a>, MockActioner<'a>, CellAgent<'a>> {
+        let agents = (0..2)
+            .map(|i| CellAgent::new(1, 1.0, 0.0).with_rng(StdRng::seed_from_u64(i)))
+            .collect();
+        MultiAgentManager::new(agents, mode).unwrap()
+    }
+
+    fn q_value(agent: &CellAgent, position: usize, action: &str) -> f64 {
+        agent
+            .iter_q_values()
+            .find(|(state, id, _)| **state == position && id.as_str() == action)
+            .map(|(_, _, stats)| stats.q_value_raw())
+            .unwrap()
+    }
+
+    #[test]
+    fn independent_and_shared_rewards() {
+        let moves = MockCorridor::moves();
+        let previous = cells(&moves, &[0, 0]);
+        let current = cells(&moves, &[1, 1]);
+        let actions = [&moves[1], &moves[1]];
+
+        let mut independent = manager(TeamMode::Independent);
+        independent
+            .learn(Some(&previous), &actions, &current, &[1.0, 3.0])
+            .unwrap();
+        assert_eq!(1.0, q_value(&independent.agents()[0], 0, "R"));
+        assert_eq!(3.0, q_value(&independent.agents()[1], 0, "R"));
+
+        let mut shared = manager(TeamMode::SharedReward);
+        assert_eq!(TeamMode::SharedReward, shared.mode());
+        shared
+            .learn(Some(&previous), &actions, &current, &[1.0, 3.0])
+            .unwrap();
+        assert_eq!(4.0, q_value(&shared.agents()[0], 0, "R"));
+        assert_eq!(4.0, q_value(&shared.agents()[1], 0, "R"));
+
+        let recommended = shared.recommend_actions(&previous).unwrap();
+        assert_eq!(
+            vec!["R", "R"],
+            recommended.iter().map(|a| a.id()).collect::<Vec<_>>()
+        );
+        shared.transition(&previous, &recommended).unwrap();
+        shared.learn(None, &actions, &current, &[0.0, 0.0]).unwrap();
+    }
+
+    #[test]
+    fn shared_and_independent_q_tables() {
+        type SharedAgent<'a> = Agent<
+            'a,
+            MockCell<'a>,
+            MockActioner<'a>,
+            Stats,
+            SharedQStore<QMap<usize, String, Stats>>,
+        >;
+        let moves = MockCorridor::moves();
+        let previous = cells(&moves, &[0, 0, 2]);
+        let current = cells(&moves, &[1, 1, 3]);
+        let actions = [&moves[1], &moves[1], &moves[0]];
+        let build = |tables| {
+            MultiAgentManager::with_q_tables(3, TeamMode::Independent, tables, QMap::new, |store| {
+                SharedAgent::new_with_store(store, 1, 1.0, 0.0)
+            })
+            .unwrap()
+        };
+
+        let mut shared = build(QTables::Shared);
+        shared
+            .learn(Some(&previous), &actions, &current, &[1.0, 1.0, 0.0])
+            .unwrap();
+        for agent in shared.agents() {
+            assert_eq!(4, agent.state_count().unwrap());
+            assert_eq!(2, agent.state_visits(&0).unwrap());
+        }
+
+        let mut independent = build(QTables::Independent);
+        independent
+            .learn(Some(&previous), &actions, &current, &[1.0, 1.0, 0.0])
+            .unwrap();
+        for agent in independent.agents() {
+            assert_eq!(2, agent.state_count().unwrap());
+        }
+        assert_eq!(1, independent.agents()[0].state_visits(&0).unwrap());
+        assert_eq!(0, independent.agents()[2].state_visits(&0).unwrap());
+
+        assert!(MultiAgentManager::with_q_tables(
+            0,
+            TeamMode::Independent,
+            QTables::Shared,
+            QMap::new,
+            |store| SharedAgent::new_with_store(store, 1, 1.0, 0.0),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn mismatched_lengths() {
+        let moves = MockCorridor::moves();
+        let states = cells(&moves, &[0, 0]);
+        let mut manager = manager(TeamMode::Independent);
+        assert_eq!(2, manager.len());
+        assert!(manager.agent(1).is_some() && manager.agent_mut(2).is_none());
+        assert!(matches!(
+            manager.recommend_actions(&states[..1]),
+            Err(LearnerError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            manager.learn(Some(&states), &[&moves[0]], &states, &[0.0, 0.0]),
+            Err(LearnerError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            manager.learn(None, &[&moves[0], &moves[0]], &states, &[0.0]),
+            Err(LearnerError::InvalidArgument(_))
+        ));
+        assert!(MultiAgentManager::<MockCell, MockActioner, CellAgent>::new(
+            vec![],
+            TeamMode::Independent
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn joint_actions() {
+        let moves = MockCorridor::moves();
+        let joint_actions = JointAction::all(&[&moves, &moves]);
+        let short = JointAction::new(vec![&moves[0]]);
+        let ids: Vec<Vec<String>> = joint_actions.iter().map(Actioner::id).collect();
+        let id = |a: &str, b: &str| vec![a.to_string(), b.to_string()];
+        assert_eq!(
+            vec![id("L", "L"), id("L", "R"), id("R", "L"), id("R", "R")],
+            ids
+        );
+
+        let state = JointState::new(cells(&moves, &[0, 2]), &joint_actions);
+        assert_eq!(vec![0, 2], state.id());
+        assert_eq!(4, state.possible_actions().len());
+        assert_eq!(id("L", "R"), state.get_action(&id("L", "R")).unwrap().id());
+        assert!(state.get_action(&vec!["L".to_string()]).is_err());
+        assert!(state.action_is_compatible(&joint_actions[2]));
+        assert!(state.apply(&joint_actions[3]).is_ok());
+
+        assert!(!state.action_is_compatible(&short));
+        assert!(state.apply(&short).is_err());
+    }
+
+    #[test]
+    fn learns_to_coordinate() {
+        // Both members are rewarded only when they move the same way, and
+        // more so when they both move right. Neither member's move is any
+        // better than the other on its own.
+        let moves = MockCorridor::moves();
+        let joint_actions = JointAction::all(&[&moves, &moves]);
+        let payoff = |joint: &JointAction<MockActioner>| match joint.id().as_slice() {
+            [a, b] if a == b && a == "R" => 2.0,
+            [a, b] if a == b => 1.0,
+            _ => 0.0,
+        };
+        let state = JointState::new(cells(&moves, &[0, 0]), &joint_actions);
+        let mut agent: Agent<JointState<MockCell, MockActioner>, JointAction<MockActioner>, Stats> =
+            Agent::new(1, 1.0, 0.0).with_rng(StdRng::seed_from_u64(3));
+        for joint in &joint_actions {
+            agent
+                .learn(Some(&state), joint, &state, payoff(joint))
+                .unwrap();
+        }
+        let recommended = agent.recommend_action(&state).unwrap();
+        assert_eq!(vec!["R", "R"], recommended.id());
+        agent.transition(&state, recommended).unwrap();
+    }
+}
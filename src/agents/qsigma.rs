@@ -0,0 +1,545 @@
+//! A tabular agent that learns with the Q(σ) update rule, which unifies
+//! SARSA and Expected SARSA.
+//!
+//! The q-value of the action taken in a state is moved towards the target
+//!
+//! ```text
+//! r + γ (σ Q(s', a') + (1 - σ) Σ π(a|s') Q(s', a))
+//! ```
+//!
+//! where `a'` is the action that is actually taken in the next state `s'`,
+//! and `π` is the agent's target policy, which is epsilon-greedy with
+//! respect to the agent's q-values. With a σ of 1 the update is SARSA's,
+//! which samples the next action. With a σ of 0 it is Expected SARSA's (the
+//! one-step tree backup), which averages over the next actions, and which is
+//! Q-learning when the target policy is greedy. σ can be varied from step to
+//! step with a schedule. See De Asis et al., "Multi-step Reinforcement
+//! Learning: A Unifying Algorithm" (2018).
+//!
+//! `learn` is not told which action will be taken in the next state, so the
+//! agent defers each update until the following call to `learn`, which
+//! reveals that action. If the following call does not continue from the
+//! same state (for instance, because a new episode has started), or
+//! `end_episode` is called first, the deferred update is completed with σ
+//! treated as 0. Transitions into a state that reports no possible actions
+//! are terminal, and are learned from immediately.
+//!
+//! `learn_trajectory` learns from logged trajectories collected by another
+//! policy instead, using the multi-step off-policy targets of the `returns`
+//! module.
+
+use crate::actions::Actioner;
+use crate::agents::returns::{self, LoggedStep, TraceCorrection, TraceStep};
+use crate::agents::Agenter;
+use crate::errors::LearnerError;
+use crate::internal::math;
+use crate::internal::rng;
+use crate::states::Stater;
+use rand::{Rng, RngCore};
+use std::collections::HashMap;
+
+/// A function that returns the value of σ to use for an update, given the
+/// number of updates the agent has completed before it.
+pub type SigmaSchedule<'a> = Box<dyn Fn(u64) -> f64 + 'a>;
+
+/// A transition whose update is waiting for the next action to be known.
+struct Deferred<SK, AK> {
+    state: SK,
+    action: AK,
+    reward: f64,
+    next_state: SK,
+    next_actions: Vec<AK>,
+}
+
+/// An agent that learns tabular q-values with the Q(σ) update rule. See the
+/// module documentation for details.
+pub struct QSigmaAgent<'a, S, A>
+where
+    A: Actioner<'a>,
+    S: Stater<'a, A>,
+{
+    learning_rate: f64,
+    discount_factor: f64,
+    sigma: f64,
+    sigma_schedule: Option<SigmaSchedule<'a>>,
+    target_epsilon: f64,
+    q_values: HashMap<S::Id, HashMap<A::Id, f64>>,
+    deferred: Option<Deferred<S::Id, A::Id>>,
+    update_count: u64,
+    rng: Box<dyn RngCore + 'a>,
+}
+
+impl<'a, S, A> QSigmaAgent<'a, S, A>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a> + 'a,
+{
+    /// Returns a new agent. `learning_rate` and `discount_factor` are as for
+    /// `bayesian::Agent::new`, and `sigma`, which is clamped to `[0, 1]`,
+    /// weighs the sampled next action against the expectation over next
+    /// actions. The target policy is greedy by default.
+    pub fn new(learning_rate: f64, discount_factor: f64, sigma: f64) -> Self {
+        Self {
+            learning_rate,
+            discount_factor,
+            sigma: sigma.clamp(0.0, 1.0),
+            sigma_schedule: None,
+            target_epsilon: 0.0,
+            q_values: HashMap::new(),
+            deferred: None,
+            update_count: 0,
+            rng: Box::new(rng::default_rng()),
+        }
+    }
+
+    /// Sets a schedule that supplies σ for each update, in place of the
+    /// fixed σ passed to `new`. Values are clamped to `[0, 1]`. A common
+    /// choice is to start near 1 and decay towards 0 as the q-values settle.
+    #[must_use]
+    pub fn with_sigma_schedule<F: Fn(u64) -> f64 + 'a>(mut self, schedule: F) -> Self {
+        self.sigma_schedule = Some(Box::new(schedule));
+        self
+    }
+
+    /// Sets the exploration rate of the target policy used for the expected
+    /// part of the update. Set it to the exploration rate of the behavior
+    /// policy to learn the value of the policy being followed.
+    #[must_use]
+    pub fn with_target_epsilon(mut self, epsilon: f64) -> Self {
+        self.target_epsilon = epsilon.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Sets the random number generator used to break ties between actions
+    /// with equal q-values.
+    #[must_use]
+    pub fn with_rng<R: RngCore + 'a>(mut self, rng: R) -> Self {
+        self.rng = Box::new(rng);
+        self
+    }
+
+    /// Sets the σ used by subsequent updates, if the agent has no schedule.
+    pub fn set_sigma(&mut self, sigma: f64) {
+        self.sigma = sigma.clamp(0.0, 1.0);
+    }
+
+    /// Returns the σ that the next update will use.
+    pub fn sigma(&self) -> f64 {
+        self.sigma_schedule
+            .as_ref()
+            .map_or(self.sigma, |schedule| schedule(self.update_count))
+            .clamp(0.0, 1.0)
+    }
+
+    /// Returns the number of updates the agent has completed.
+    pub fn update_count(&self) -> u64 {
+        self.update_count
+    }
+
+    /// Returns the q-value of an action in a state, or 0 if the agent has not
+    /// learned it.
+    pub fn q_value(&self, state_id: &S::Id, action_id: &A::Id) -> f64 {
+        self.q_values
+            .get(state_id)
+            .and_then(|actions| actions.get(action_id))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Returns the agent's q-values.
+    pub fn q_values(&self) -> &HashMap<S::Id, HashMap<A::Id, f64>> {
+        &self.q_values
+    }
+
+    /// Completes any deferred update, treating σ as 0 since the next action
+    /// is unknown. Call this at the end of an episode that was cut short
+    /// before reaching a terminal state.
+    pub fn end_episode(&mut self) {
+        if let Some(deferred) = self.deferred.take() {
+            self.complete(deferred, None);
+        }
+    }
+
+    /// Learns from a trajectory that was collected by a behavior policy,
+    /// such as replayed or logged experience, moving the q-value of each
+    /// step towards its multi-step off-policy target (see
+    /// `returns::off_policy_targets`). The targets are computed from the
+    /// q-values as they were before the call, and use the agent's target
+    /// policy. Any deferred update is completed first.
+    ///
+    /// An error is returned if a step does not continue from the previous
+    /// step's next state, or if a reward is not finite.
+    pub fn learn_trajectory(
+        &mut self,
+        trajectory: &[LoggedStep<S::Id, A::Id>],
+        lambda: f64,
+        correction: TraceCorrection,
+    ) -> Result<(), LearnerError> {
+        self.end_episode();
+        if let Some(step) = trajectory.iter().find(|step| !step.reward.is_finite()) {
+            return Err(LearnerError::NonFinite(format!(
+                "reward {} is not finite",
+                step.reward
+            )));
+        }
+        if let Some(pair) = trajectory
+            .windows(2)
+            .find(|pair| pair[0].next_state != pair[1].state)
+        {
+            return Err(LearnerError::InvalidArgument(format!(
+                "a step into state {:?} is followed by a step from state {:?}",
+                pair[0].next_state, pair[1].state
+            )));
+        }
+
+        let steps: Vec<TraceStep> = trajectory
+            .iter()
+            .enumerate()
+            .map(|(t, step)| TraceStep {
+                reward: step.reward,
+                q_value: self.q_value(&step.state, &step.action),
+                next_value: self.expected_value(&step.next_state, &step.next_actions),
+                // The first step's trace is never used, and the actions
+                // possible in its state are unknown.
+                target_probability: t.checked_sub(1).map_or(1.0, |previous| {
+                    self.target_probability(
+                        &step.state,
+                        &trajectory[previous].next_actions,
+                        &step.action,
+                    )
+                }),
+                behavior_probability: step.behavior_probability,
+                terminal: step.next_actions.is_empty(),
+            })
+            .collect();
+        let targets =
+            returns::off_policy_targets(&steps, self.discount_factor, lambda, correction)?;
+        for (step, target) in trajectory.iter().zip(targets) {
+            // The target already includes the discounted future value.
+            self.update(step.state.clone(), step.action.clone(), target, 0.0);
+        }
+        Ok(())
+    }
+
+    /// Completes a deferred update, given the action taken in the next state
+    /// if it is known.
+    fn complete(&mut self, deferred: Deferred<S::Id, A::Id>, next_action: Option<&A::Id>) {
+        let expected = self.expected_value(&deferred.next_state, &deferred.next_actions);
+        let future = match next_action {
+            Some(next_action) => {
+                let sigma = self.sigma();
+                let sampled = self.q_value(&deferred.next_state, next_action);
+                sigma.mul_add(sampled - expected, expected)
+            }
+            None => expected,
+        };
+        self.update(deferred.state, deferred.action, deferred.reward, future);
+    }
+
+    /// Moves a q-value towards the reward plus the discounted future value.
+    fn update(&mut self, state: S::Id, action: A::Id, reward: f64, future: f64) {
+        let q = self
+            .q_values
+            .entry(state)
+            .or_default()
+            .entry(action)
+            .or_default();
+        *q = math::bellman(*q, self.learning_rate, reward, self.discount_factor, future);
+        self.update_count += 1;
+    }
+
+    /// Returns the expected q-value of a state's actions under the
+    /// epsilon-greedy target policy, which takes a greedy action with
+    /// probability `1 - epsilon`, and a uniformly random action otherwise.
+    fn expected_value(&self, state: &S::Id, actions: &[A::Id]) -> f64 {
+        if actions.is_empty() {
+            return 0.0;
+        }
+        let values: Vec<f64> = actions.iter().map(|a| self.q_value(state, a)).collect();
+        let best = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let mean = values.iter().sum::<f64>() / math::len_to_f64(values.len());
+        (1.0 - self.target_epsilon).mul_add(best, self.target_epsilon * mean)
+    }
+
+    /// Returns the probability that the epsilon-greedy target policy takes
+    /// `action` in a state where `actions` are possible.
+    fn target_probability(&self, state: &S::Id, actions: &[A::Id], action: &A::Id) -> f64 {
+        if !actions.contains(action) {
+            return 0.0;
+        }
+        let values: Vec<f64> = actions.iter().map(|a| self.q_value(state, a)).collect();
+        let best = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let greedy = values.iter().filter(|v| **v == best).count();
+        let explore = self.target_epsilon / math::len_to_f64(actions.len());
+        if self.q_value(state, action) == best {
+            explore + (1.0 - self.target_epsilon) / math::len_to_f64(greedy)
+        } else {
+            explore
+        }
+    }
+}
+
+impl<'a, S, A> Agenter<'a, S, A> for QSigmaAgent<'a, S, A>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a> + 'a,
+{
+    /// Recommends the possible action with the highest q-value, breaking ties
+    /// at random.
+    fn recommend_action(&mut self, state: &S) -> Result<&'a A, LearnerError> {
+        let state_id = state.id();
+        let actions = state.possible_actions();
+        let values: Vec<f64> = actions
+            .iter()
+            .map(|a| self.q_value(&state_id, &a.id()))
+            .collect();
+        let best = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let greedy: Vec<&'a A> = actions
+            .into_iter()
+            .zip(values)
+            .filter(|(_, v)| *v == best)
+            .map(|(a, _)| a)
+            .collect();
+        if greedy.is_empty() {
+            return Err(LearnerError::NoPossibleActions {
+                state: format!("{state_id:?}"),
+            });
+        }
+        Ok(greedy[self.rng.gen_range(0, greedy.len())])
+    }
+
+    fn transition(&self, state: &S, action: &'a A) -> Result<(), LearnerError> {
+        if !state.action_is_compatible(action) {
+            return Err(LearnerError::ActionNotCompatible {
+                state: format!("{:?}", state.id()),
+                action: format!("{:?}", action.id()),
+            });
+        }
+        state.apply(action)
+    }
+
+    /// Completes the deferred update of the previous transition, if any, and
+    /// defers the update of this one. See the module documentation.
+    fn learn(
+        &mut self,
+        previous_state: Option<&S>,
+        action_taken: &A,
+        current_state: &S,
+        reward: f64,
+    ) -> Result<(), LearnerError> {
+        if !reward.is_finite() {
+            return Err(LearnerError::NonFinite(format!(
+                "reward {reward} is not finite"
+            )));
+        }
+        let Some(previous_state) = previous_state else {
+            self.end_episode();
+            return Ok(());
+        };
+        let state_id = previous_state.id();
+        let action_id = action_taken.id();
+        if let Some(deferred) = self.deferred.take() {
+            let continues = deferred.next_state == state_id;
+            self.complete(deferred, continues.then_some(&action_id));
+        }
+
+        let next_actions: Vec<A::Id> = current_state
+            .possible_actions()
+            .iter()
+            .map(|a| a.id())
+            .collect();
+        if next_actions.is_empty() {
+            self.update(state_id, action_id, reward, 0.0);
+        } else {
+            self.deferred = Some(Deferred {
+                state: state_id,
+                action: action_id,
+                reward,
+                next_state: current_state.id(),
+                next_actions,
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::mocks::*;
+    use crate::training::{Schedule, Trainer};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    static A: MockActioner = MockActioner { return_id: "a" };
+    static X: MockActioner = MockActioner { return_id: "x" };
+    static Y: MockActioner = MockActioner { return_id: "y" };
+
+    type Agent =
+        QSigmaAgent<'static, MockStater<'static, MockActioner<'static>>, MockActioner<'static>>;
+
+    /// Learns that `x` is worth 1 in `s1`, then learns from `s0 -a-> s1`
+    /// followed by taking `y` (which is worth 0) in `s1`, and returns the
+    /// resulting value of `a` in `s0`.
+    fn value_after_taking_y(mut agent: Agent) -> f64 {
+        let state = |id, actions| MockStater {
+            return_id: id,
+            return_possible_actions: actions,
+            ..Default::default()
+        };
+        let s0 = state("s0", vec![&A]);
+        let s1 = state("s1", vec![&X, &Y]);
+        let end = state("end", vec![]);
+
+        agent.learn(Some(&s1), &X, &end, 1.0).unwrap();
+        agent.learn(Some(&s0), &A, &s1, 0.0).unwrap();
+        assert_eq!(0.0, agent.q_value(&"s0".to_string(), &"a".to_string()));
+        agent.learn(Some(&s1), &Y, &end, 0.0).unwrap();
+        assert_eq!(3, agent.update_count());
+        agent.q_value(&"s0".to_string(), &"a".to_string())
+    }
+
+    #[test]
+    fn sigma_interpolates_between_sarsa_and_expected_sarsa() {
+        assert_eq!(0.0, value_after_taking_y(QSigmaAgent::new(1.0, 1.0, 1.0)));
+        assert_eq!(1.0, value_after_taking_y(QSigmaAgent::new(1.0, 1.0, 0.0)));
+        assert_eq!(0.5, value_after_taking_y(QSigmaAgent::new(1.0, 1.0, 0.5)));
+        assert_eq!(
+            0.75,
+            value_after_taking_y(QSigmaAgent::new(1.0, 1.0, 0.0).with_target_epsilon(0.5))
+        );
+        assert_eq!(
+            1.0,
+            value_after_taking_y(QSigmaAgent::new(1.0, 1.0, 1.0).with_sigma_schedule(|step| {
+                if step < 1 {
+                    1.0
+                } else {
+                    0.0
+                }
+            }))
+        );
+    }
+
+    #[test]
+    fn deferred_updates() {
+        let (a, b) = (
+            MockActioner { return_id: "a" },
+            MockActioner { return_id: "b" },
+        );
+        let state = |id| MockStater {
+            return_id: id,
+            return_possible_actions: vec![&a, &b],
+            ..Default::default()
+        };
+        let (s0, s1) = (state("s0"), state("s1"));
+        let mut agent = QSigmaAgent::new(0.5, 0.9, 1.0);
+
+        agent.learn(Some(&s0), &a, &s1, 1.0).unwrap();
+        assert_eq!(0, agent.update_count());
+        agent.end_episode();
+        assert_eq!(1, agent.update_count());
+        assert_eq!(0.5, agent.q_value(&"s0".to_string(), &"a".to_string()));
+
+        // A transition that does not continue from the deferred one's next
+        // state completes it without a sampled next action.
+        agent.learn(Some(&s0), &b, &s1, 1.0).unwrap();
+        agent.learn(Some(&s0), &a, &s1, 0.0).unwrap();
+        assert_eq!(2, agent.update_count());
+        assert_eq!(0.5, agent.q_value(&"s0".to_string(), &"b".to_string()));
+
+        agent.learn(None, &a, &s0, 0.0).unwrap();
+        assert_eq!(3, agent.update_count());
+        assert!(agent.learn(Some(&s0), &a, &s1, f64::NAN).is_err());
+    }
+
+    fn logged_step(
+        state: &str,
+        action: &str,
+        reward: f64,
+        next_state: &str,
+        next_actions: &[&str],
+    ) -> LoggedStep<String, String> {
+        LoggedStep {
+            state: state.to_string(),
+            action: action.to_string(),
+            reward,
+            next_state: next_state.to_string(),
+            next_actions: next_actions.iter().map(|a| (*a).to_string()).collect(),
+            behavior_probability: 0.5,
+        }
+    }
+
+    #[test]
+    fn learn_trajectory() {
+        let trajectory = [
+            logged_step("s0", "a", 0.0, "s1", &["x", "y"]),
+            logged_step("s1", "y", 1.0, "end", &[]),
+        ];
+        let value_of_a = |correction| {
+            let mut agent: Agent = QSigmaAgent::new(1.0, 0.9, 0.0);
+            agent
+                .learn_trajectory(&trajectory, 1.0, correction)
+                .unwrap();
+            assert_eq!(2, agent.update_count());
+            assert_eq!(1.0, agent.q_value(&"s1".to_string(), &"y".to_string()));
+            agent.q_value(&"s0".to_string(), &"a".to_string())
+        };
+        // x and y tie, so the greedy target policy takes y with probability
+        // 0.5, as the behavior policy did.
+        assert!((0.9 - value_of_a(TraceCorrection::Retrace)).abs() < 1e-12);
+        assert!((0.45 - value_of_a(TraceCorrection::TreeBackup)).abs() < 1e-12);
+
+        // Once x is preferred, the target policy never takes y, which cuts
+        // the trace.
+        let mut agent: Agent = QSigmaAgent::new(1.0, 0.9, 0.0);
+        agent
+            .learn_trajectory(
+                &[logged_step("s1", "x", 2.0, "end", &[])],
+                0.0,
+                TraceCorrection::Retrace,
+            )
+            .unwrap();
+        agent
+            .learn_trajectory(&trajectory, 1.0, TraceCorrection::Retrace)
+            .unwrap();
+        assert!((1.8 - agent.q_value(&"s0".to_string(), &"a".to_string())).abs() < 1e-12);
+    }
+
+    #[test]
+    fn learn_trajectory_invalid() {
+        let mut agent: Agent = QSigmaAgent::new(1.0, 0.9, 0.0);
+        let disconnected = [
+            logged_step("s0", "a", 0.0, "s1", &["x"]),
+            logged_step("s2", "x", 1.0, "end", &[]),
+        ];
+        assert!(agent
+            .learn_trajectory(&disconnected, 1.0, TraceCorrection::Retrace)
+            .is_err());
+        let nan = [logged_step("s0", "a", f64::NAN, "end", &[])];
+        assert!(agent
+            .learn_trajectory(&nan, 1.0, TraceCorrection::Retrace)
+            .is_err());
+        assert_eq!(0, agent.update_count());
+    }
+
+    #[test]
+    fn learns_corridor() {
+        let moves = MockCorridor::moves();
+        let mut agent = QSigmaAgent::new(0.5, 0.9, 0.5).with_rng(StdRng::seed_from_u64(1));
+        let mut env = MockCorridor::new(&moves, 4);
+        Trainer::new(50, 20)
+            .with_epsilon(Schedule::Constant(0.2))
+            .with_rng(StdRng::seed_from_u64(2))
+            .train(&mut agent, &mut env)
+            .unwrap();
+
+        for position in 0..3 {
+            let cell = MockCell {
+                position,
+                moves: &moves,
+            };
+            assert_eq!("R", agent.recommend_action(&cell).unwrap().return_id);
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! Contains a read-only handle for querying a bayesian agent's policy.
+//!
+//! `bayesian::Agent::recommend_action` requires mutable access to the agent,
+//! because it records the actions of states it has not seen before and uses
+//! the agent's random number generator to break ties. A `Recommender` only
+//! needs shared access. It calculates each action's weighted q-value when it
+//! is queried, without writing anything to the store, subtracts the action's
+//! cost, and breaks ties by choosing the action with the lowest ID.
+//!
+//! Action costs and the `NonFinitePolicy` set on the agent are copied into
+//! the recommender when it is created. If the agent's store is `Sync`, a
+//! single recommender can be queried from many threads at once.
+
+use crate::actions::Actioner;
+use crate::agents::bayesian::{weigh_actions, NonFinitePolicy};
+use crate::errors::LearnerError;
+use crate::states::Stater;
+use crate::stats::ActionStatter;
+use crate::stores::QStore;
+use std::collections::HashMap;
+use std::marker;
+
+/// A read-only handle that recommends actions using a bayesian agent's
+/// q-values. A `Recommender` is returned by `bayesian::Agent::recommender`.
+///
+/// The recommender reads from a clone of the agent's store. If the store is
+/// a `stores::concurrent::ConcurrentQMap`, the clone shares the agent's
+/// data, so the recommender sees the agent's updates as the agent learns.
+/// A `QMap` is copied, so the recommender sees a snapshot of the q-values
+/// as they were when the recommender was created.
+pub struct Recommender<'a, S, A, AS, QS>
+where
+    A: Actioner<'a>,
+    S: Stater<'a, A>,
+    AS: ActionStatter,
+    QS: QStore<S::Id, A::Id, AS>,
+{
+    qstore: QS,
+    priming_threshold: i32,
+    initial_q: f64,
+    sparse_storage: bool,
+    non_finite_policy: NonFinitePolicy,
+    action_costs: HashMap<A::Id, f64>,
+    // The handle never owns states, actions, or stats, so the markers do not
+    // require them to be `Send` or `Sync`.
+    _actioner: marker::PhantomData<fn() -> &'a A>,
+    _stater: marker::PhantomData<fn(&S)>,
+    _stats: marker::PhantomData<fn() -> AS>,
+}
+
+impl<'a, S, A, AS, QS> Recommender<'a, S, A, AS, QS>
+where
+    A: Actioner<'a> + 'a,
+    S: Stater<'a, A>,
+    AS: ActionStatter,
+    QS: QStore<S::Id, A::Id, AS>,
+{
+    pub(crate) fn new(
+        qstore: QS,
+        priming_threshold: i32,
+        initial_q: f64,
+        sparse_storage: bool,
+        non_finite_policy: NonFinitePolicy,
+        action_costs: HashMap<A::Id, f64>,
+    ) -> Self {
+        Self {
+            qstore,
+            priming_threshold,
+            initial_q,
+            sparse_storage,
+            non_finite_policy,
+            action_costs,
+            _actioner: marker::PhantomData,
+            _stater: marker::PhantomData,
+            _stats: marker::PhantomData,
+        }
+    }
+
+    /// Recommends the action with the highest weighted q-value, less its
+    /// cost, for a given state. If the values of two or more actions are the
+    /// same, the action with the lowest ID is recommended.
+    /// An error is returned if the state reports no possible actions, if the
+    /// store cannot be read, or if a q-value is not finite and the agent's
+    /// `NonFinitePolicy` is `NonFinitePolicy::Error`.
+    #[allow(clippy::use_debug)]
+    pub fn recommend_action(&self, state: &S) -> Result<&'a A, LearnerError> {
+        let (action_stats, _) = weigh_actions(
+            &self.qstore,
+            state,
+            self.priming_threshold,
+            self.initial_q,
+            self.sparse_storage,
+            self.non_finite_policy,
+        )?;
+        let mut best: Option<(&A::Id, f64)> = None;
+        for (action_id, stats) in &action_stats {
+            let q = stats.q_value_weighted()
+                - self
+                    .action_costs
+                    .get(action_id)
+                    .copied()
+                    .unwrap_or_default();
+            best = match best {
+                Some((best_id, best_q))
+                    if q < best_q || ((q - best_q).abs() < f64::EPSILON && best_id < action_id) =>
+                {
+                    Some((best_id, best_q))
+                }
+                _ => Some((action_id, q)),
+            };
+        }
+        let (action_id, _) = best.ok_or_else(|| LearnerError::NoPossibleActions {
+            state: format!("{:?}", state.id()),
+        })?;
+        state.get_action(action_id)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::agents::bayesian::Agent;
+    use crate::agents::Agenter;
+    use crate::mocks::*;
+    use crate::stats::actionstats::Stats;
+    use crate::stores::concurrent::ConcurrentQMap;
+    use std::thread;
+
+    #[test]
+    fn recommend_action() {
+        let moves = MockCorridor::moves();
+        let cell = |position| MockCell {
+            position,
+            moves: &moves,
+        };
+        let (left, right) = (&moves[0], &moves[1]);
+        let mut agent: Agent<MockCell, MockActioner, Stats, _> =
+            Agent::new_with_store(ConcurrentQMap::new(), 0, 1.0, 0.0);
+        let recommender = agent.recommender();
+
+        // With no q-values, the tie is broken in favor of the lowest ID.
+        assert_eq!("L", recommender.recommend_action(&cell(0)).unwrap().id());
+
+        agent.learn(Some(&cell(0)), right, &cell(1), 1.0).unwrap();
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let action = recommender.recommend_action(&cell(0)).unwrap();
+                    assert_eq!("R", action.id());
+                });
+            }
+        });
+
+        agent.learn(Some(&cell(0)), left, &cell(1), 2.0).unwrap();
+        assert_eq!("L", recommender.recommend_action(&cell(0)).unwrap().id());
+    }
+
+    #[test]
+    fn recommend_action_no_possible_actions() {
+        let agent: Agent<MockStater<MockActioner>, MockActioner, Stats> = Agent::new(0, 1.0, 0.0);
+        let state = MockStater {
+            return_id: "A",
+            ..Default::default()
+        };
+        let result = agent.recommender().recommend_action(&state);
+        assert_eq!(
+            Some(LearnerError::NoPossibleActions {
+                state: "\"A\"".to_string()
+            }),
+            result.err()
+        );
+    }
+}
@@ -0,0 +1,252 @@
+//! Multi-step returns for learning from trajectories that were collected by
+//! a different (behavior) policy than the one being learned (the target
+//! policy), such as replayed or logged experience.
+//!
+//! Multi-step targets propagate rewards faster than one-step targets, but
+//! off-policy data has to be corrected for the difference between the two
+//! policies. The targets computed here follow the general form of Munos et
+//! al., "Safe and Efficient Off-Policy Reinforcement Learning" (2016), in
+//! which the target of step `t` is
+//!
+//! ```text
+//! G(t) = r(t) + γ (V(t + 1) + c(t + 1) (G(t + 1) - Q(t + 1)))
+//! ```
+//!
+//! where `V(t + 1)` is the expected q-value of the next state under the
+//! target policy, `Q(t + 1)` is the q-value of the action that was taken
+//! there, and the trace `c` decides how much of the remaining trajectory is
+//! trusted. `TraceCorrection` selects how `c` is computed. Tree backup and
+//! Retrace keep `c` at or below `λ`, so the targets cannot diverge however
+//! far the behavior policy strays from the target policy.
+
+use crate::errors::LearnerError;
+
+/// How the trace of each step is computed from the probability `π` of the
+/// step's action under the target policy, and its probability `μ` under the
+/// behavior policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceCorrection {
+    /// `c = λ π`. Safe for any behavior policy, but cuts traces short even
+    /// when the data is on-policy.
+    TreeBackup,
+
+    /// `c = λ min(1, π / μ)`. Safe for any behavior policy, and does not cut
+    /// traces short when the data is on-policy.
+    Retrace,
+
+    /// `c = λ π / μ`. Unbiased, but the product of the ratios can grow
+    /// without bound, so targets can have a very high variance.
+    ImportanceSampling,
+}
+
+impl TraceCorrection {
+    /// Returns the trace of a step whose action has the probability
+    /// `target_probability` under the target policy and
+    /// `behavior_probability` under the behavior policy.
+    pub fn trace(self, lambda: f64, target_probability: f64, behavior_probability: f64) -> f64 {
+        let ratio = target_probability / behavior_probability;
+        lambda
+            * match self {
+                Self::TreeBackup => target_probability,
+                Self::Retrace => ratio.min(1.0),
+                Self::ImportanceSampling => ratio,
+            }
+    }
+}
+
+/// A single step of a trajectory, described by the values needed to compute
+/// its target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TraceStep {
+    /// The reward earned by the step's action.
+    pub reward: f64,
+
+    /// The q-value of the step's action in the step's state.
+    pub q_value: f64,
+
+    /// The expected q-value of the next state under the target policy. This
+    /// should be 0 if the step is terminal.
+    pub next_value: f64,
+
+    /// The probability of the step's action under the target policy.
+    pub target_probability: f64,
+
+    /// The probability of the step's action under the behavior policy.
+    pub behavior_probability: f64,
+
+    /// Whether the step ended the episode.
+    pub terminal: bool,
+}
+
+/// A single logged step of a trajectory, identified by state and action IDs,
+/// from which an agent can build a `TraceStep`. See
+/// `QSigmaAgent::learn_trajectory`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoggedStep<SK, AK> {
+    /// The state in which the action was taken.
+    pub state: SK,
+
+    /// The action that was taken.
+    pub action: AK,
+
+    /// The reward earned by the action.
+    pub reward: f64,
+
+    /// The state that the action led to.
+    pub next_state: SK,
+
+    /// The actions that were possible in the next state. No actions means
+    /// the step ended the episode.
+    pub next_actions: Vec<AK>,
+
+    /// The probability that the behavior policy took the action.
+    pub behavior_probability: f64,
+}
+
+/// Returns the multi-step target of each step of a trajectory, in order.
+///
+/// `lambda` (in `[0, 1]`) controls how far ahead each target looks: 0 gives
+/// one-step targets, and 1 gives the longest traces that the correction
+/// allows. The trace of the first step is never used, so its probabilities
+/// are not checked. No trace crosses a terminal step.
+/// An error is returned if `lambda` is not in `[0, 1]`, if a target
+/// probability is not in `[0, 1]`, or if a behavior probability is not in
+/// `(0, 1]`.
+pub fn off_policy_targets(
+    steps: &[TraceStep],
+    discount_factor: f64,
+    lambda: f64,
+    correction: TraceCorrection,
+) -> Result<Vec<f64>, LearnerError> {
+    if !(0.0..=1.0).contains(&lambda) {
+        return Err(LearnerError::InvalidArgument(format!(
+            "lambda {lambda} must be in [0, 1]"
+        )));
+    }
+    for step in steps.iter().skip(1) {
+        if !(0.0..=1.0).contains(&step.target_probability) {
+            return Err(LearnerError::InvalidArgument(format!(
+                "target probability {} must be in [0, 1]",
+                step.target_probability
+            )));
+        }
+        if !(step.behavior_probability > 0.0 && step.behavior_probability <= 1.0) {
+            return Err(LearnerError::InvalidArgument(format!(
+                "behavior probability {} must be in (0, 1]",
+                step.behavior_probability
+            )));
+        }
+    }
+
+    let mut targets = vec![0.0; steps.len()];
+    let mut later: Option<(f64, &TraceStep)> = None;
+    for (t, step) in steps.iter().enumerate().rev() {
+        let mut future = step.next_value;
+        if let Some((later_target, later_step)) = later.filter(|_| !step.terminal) {
+            let trace = correction.trace(
+                lambda,
+                later_step.target_probability,
+                later_step.behavior_probability,
+            );
+            future += trace * (later_target - later_step.q_value);
+        }
+        targets[t] = discount_factor.mul_add(future, step.reward);
+        later = Some((targets[t], step));
+    }
+    Ok(targets)
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+
+    fn step(reward: f64, q_value: f64, next_value: f64, target_probability: f64) -> TraceStep {
+        TraceStep {
+            reward,
+            q_value,
+            next_value,
+            target_probability,
+            behavior_probability: 0.5,
+            terminal: false,
+        }
+    }
+
+    #[test]
+    fn one_step_targets() {
+        let steps = [step(1.0, 0.0, 10.0, 1.0), step(2.0, 10.0, 20.0, 1.0)];
+        assert_eq!(
+            vec![6.0, 12.0],
+            off_policy_targets(&steps, 0.5, 0.0, TraceCorrection::Retrace).unwrap()
+        );
+    }
+
+    #[test]
+    fn on_policy_retrace_is_monte_carlo() {
+        let steps = [
+            step(1.0, 0.0, 10.0, 1.0),
+            step(2.0, 10.0, 20.0, 1.0),
+            step(3.0, 20.0, 30.0, 1.0),
+        ]
+        .map(|s| TraceStep {
+            behavior_probability: 1.0,
+            ..s
+        });
+        // 1 + 0.5 * 2 + 0.25 * 3 + 0.125 * 30
+        assert_eq!(
+            vec![6.5, 11.0, 18.0],
+            off_policy_targets(&steps, 0.5, 1.0, TraceCorrection::Retrace).unwrap()
+        );
+    }
+
+    #[test]
+    fn corrections() {
+        // The second action is twice as likely under the target policy as
+        // under the behavior policy.
+        let steps = [step(0.0, 0.0, 1.0, 1.0), step(4.0, 1.0, 0.0, 1.0)];
+        let targets = |correction| off_policy_targets(&steps, 1.0, 1.0, correction).unwrap()[0];
+        assert_eq!(4.0, targets(TraceCorrection::TreeBackup));
+        assert_eq!(4.0, targets(TraceCorrection::Retrace));
+        assert_eq!(7.0, targets(TraceCorrection::ImportanceSampling));
+
+        // Actions the target policy would never take cut the trace.
+        let steps = [step(0.0, 0.0, 1.0, 1.0), step(4.0, 1.0, 0.0, 0.0)];
+        let targets = |correction| off_policy_targets(&steps, 1.0, 1.0, correction).unwrap()[0];
+        assert_eq!(1.0, targets(TraceCorrection::TreeBackup));
+        assert_eq!(1.0, targets(TraceCorrection::Retrace));
+        assert_eq!(1.0, targets(TraceCorrection::ImportanceSampling));
+    }
+
+    #[test]
+    fn terminal_steps_end_traces() {
+        let mut first = step(1.0, 0.0, 0.0, 1.0);
+        first.terminal = true;
+        let steps = [first, step(5.0, 0.0, 0.0, 1.0)];
+        assert_eq!(
+            vec![1.0, 5.0],
+            off_policy_targets(&steps, 0.9, 1.0, TraceCorrection::ImportanceSampling).unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        let steps = [step(0.0, 0.0, 0.0, 1.0), step(0.0, 0.0, 0.0, 1.0)];
+        assert!(off_policy_targets(&steps, 1.0, 1.5, TraceCorrection::Retrace).is_err());
+
+        let mut bad = steps;
+        bad[1].behavior_probability = 0.0;
+        assert!(off_policy_targets(&bad, 1.0, 1.0, TraceCorrection::Retrace).is_err());
+
+        let mut bad = steps;
+        bad[1].target_probability = 1.5;
+        assert!(off_policy_targets(&bad, 1.0, 1.0, TraceCorrection::Retrace).is_err());
+
+        // The first step's probabilities are never used.
+        let mut first = steps;
+        first[0].behavior_probability = 0.0;
+        assert!(off_policy_targets(&first, 1.0, 1.0, TraceCorrection::Retrace).is_ok());
+        assert!(off_policy_targets(&[], 1.0, 1.0, TraceCorrection::Retrace)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,215 @@
+//! A handle that lets many callers use one agent at once.
+//!
+//! Recommending an action and learning both require mutable access to an
+//! agent, so an agent that serves several tasks or threads would otherwise
+//! need its own synchronization in every caller. A `SharedAgent` wraps the
+//! agent in a lock, and every clone of the handle refers to the same agent.
+//! The handle implements `Agenter`, so it can be passed anywhere an agent is
+//! expected, such as to `Trainer::train`.
+//!
+//! A `SharedAgent` is `Send` and `Sync` if the agent it wraps is. The crate's
+//! own learning agents hold boxed random number generators and callbacks that
+//! are not required to be `Send`, so a handle to one of them can only be
+//! shared by tasks on a single thread. Agents such as
+//! `frozen::FrozenPolicy`, and any agent whose fields are `Send` and `Sync`,
+//! can be shared between threads.
+
+use crate::actions::Actioner;
+use crate::agents::Agenter;
+use crate::errors::LearnerError;
+use crate::states::Stater;
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// A cloneable handle to an agent guarded by a lock. See the module
+/// documentation.
+///
+/// `recommend_action` and `learn` lock the agent exclusively, while
+/// `transition` and `read` share the lock with other readers. Each method
+/// returns an error if another caller panicked while holding the lock.
+pub struct SharedAgent<G> {
+    agent: Arc<RwLock<G>>,
+}
+
+impl<G> SharedAgent<G> {
+    /// Returns a handle to `agent`.
+    pub fn new(agent: G) -> Self {
+        Self {
+            agent: Arc::new(RwLock::new(agent)),
+        }
+    }
+
+    /// Locks the agent for reading, so that it can be inspected. Other
+    /// readers are not blocked, but callers that need to recommend actions or
+    /// learn must wait until the guard is dropped.
+    pub fn read(&self) -> Result<RwLockReadGuard<'_, G>, LearnerError> {
+        self.agent.read().map_err(|_| poisoned())
+    }
+
+    /// Locks the agent exclusively, so that it can be reconfigured (for
+    /// instance, frozen).
+    pub fn write(&self) -> Result<RwLockWriteGuard<'_, G>, LearnerError> {
+        self.agent.write().map_err(|_| poisoned())
+    }
+
+    /// Returns the agent if this is the only handle to it, or the handle
+    /// otherwise.
+    pub fn try_unwrap(self) -> Result<G, Self> {
+        match Arc::try_unwrap(self.agent) {
+            Ok(lock) => Ok(lock.into_inner().unwrap_or_else(PoisonError::into_inner)),
+            Err(agent) => Err(Self { agent }),
+        }
+    }
+
+    /// Returns the number of handles to the agent, including this one.
+    pub fn handle_count(&self) -> usize {
+        Arc::strong_count(&self.agent)
+    }
+
+    /// Recommends an action for `state`, as `Agenter::recommend_action`
+    /// does, without requiring mutable access to the handle.
+    pub fn recommend_action<'a, S, A>(&self, state: &S) -> Result<&'a A, LearnerError>
+    where
+        S: Stater<'a, A>,
+        A: Actioner<'a>,
+        G: Agenter<'a, S, A>,
+    {
+        self.write()?.recommend_action(state)
+    }
+
+    /// Applies an action to a state, as `Agenter::transition` does.
+    pub fn transition<'a, S, A>(&self, state: &S, action: &'a A) -> Result<(), LearnerError>
+    where
+        S: Stater<'a, A>,
+        A: Actioner<'a>,
+        G: Agenter<'a, S, A>,
+    {
+        self.read()?.transition(state, action)
+    }
+
+    /// Learns from a transition, as `Agenter::learn` does, without requiring
+    /// mutable access to the handle.
+    pub fn learn<'a, S, A>(
+        &self,
+        previous_state: Option<&S>,
+        action_taken: &A,
+        current_state: &S,
+        reward: f64,
+    ) -> Result<(), LearnerError>
+    where
+        S: Stater<'a, A>,
+        A: Actioner<'a>,
+        G: Agenter<'a, S, A>,
+    {
+        self.write()?
+            .learn(previous_state, action_taken, current_state, reward)
+    }
+}
+
+impl<G> Clone for SharedAgent<G> {
+    fn clone(&self) -> Self {
+        Self {
+            agent: Arc::clone(&self.agent),
+        }
+    }
+}
+
+impl<'a, S, A, G> Agenter<'a, S, A> for SharedAgent<G>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a>,
+    G: Agenter<'a, S, A>,
+{
+    fn recommend_action(&mut self, state: &S) -> Result<&'a A, LearnerError> {
+        Self::recommend_action(self, state)
+    }
+
+    fn transition(&self, state: &S, action: &'a A) -> Result<(), LearnerError> {
+        Self::transition(self, state, action)
+    }
+
+    fn learn(
+        &mut self,
+        previous_state: Option<&S>,
+        action_taken: &A,
+        current_state: &S,
+        reward: f64,
+    ) -> Result<(), LearnerError> {
+        Self::learn(self, previous_state, action_taken, current_state, reward)
+    }
+}
+
+fn poisoned() -> LearnerError {
+    LearnerError::Other("shared agent lock is poisoned".to_string())
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::agents::bayesian::Agent;
+    use crate::agents::frozen::FrozenPolicy;
+    use crate::environments::bandit::{Arm, ArmDistribution, Bandit};
+    use crate::environments::Environment;
+    use crate::mocks::*;
+    use crate::stats::actionstats::Stats;
+    use crate::training::Trainer;
+    use std::collections::HashMap;
+    use std::thread;
+
+    #[test]
+    fn handles_share_an_agent() {
+        let moves = MockCorridor::moves();
+        let mut env = MockCorridor::new(&moves, 3);
+        let agent: Agent<MockCell, MockActioner, Stats> = Agent::new(1, 1.0, 0.9);
+        let mut shared = SharedAgent::new(agent);
+        let handle = shared.clone();
+        assert_eq!(2, handle.handle_count());
+
+        Trainer::new(3, 20).train(&mut shared, &mut env).unwrap();
+        let steps = handle.read().unwrap().step_count();
+        assert!(steps >= 6);
+
+        let cell = |position| MockCell {
+            position,
+            moves: &moves,
+        };
+        handle
+            .learn(Some(&cell(0)), &moves[1], &cell(1), 1.0)
+            .unwrap();
+        assert_eq!(steps + 1, shared.read().unwrap().step_count());
+        let action = handle.recommend_action(&cell(0)).unwrap();
+        handle.transition(&cell(0), action).unwrap();
+
+        handle.write().unwrap().freeze().unwrap();
+        assert!(shared
+            .learn(Some(&cell(0)), &moves[1], &cell(1), 1.0)
+            .is_err());
+
+        let Err(shared) = shared.try_unwrap() else {
+            panic!("the agent has two handles");
+        };
+        drop(handle);
+        assert!(shared.try_unwrap().is_ok());
+    }
+
+    #[test]
+    fn shared_between_threads() {
+        let arms = Bandit::arms(3);
+        let policy: FrozenPolicy<(), usize> = HashMap::from([((), 2)]).into();
+        let shared = SharedAgent::new(policy);
+
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                let handle = shared.clone();
+                let arms = &arms;
+                scope.spawn(move || {
+                    let mut bandit =
+                        Bandit::new(arms, vec![ArmDistribution::Bernoulli { p: 0.5 }; 3]).unwrap();
+                    let state = bandit.reset().unwrap();
+                    let action: &Arm = handle.recommend_action(&state).unwrap();
+                    assert_eq!(2, action.index());
+                });
+            }
+        });
+    }
+}
@@ -0,0 +1,31 @@
+//! Serves a single learning agent over gRPC. See the `rlr::grpc` module.
+//!
+//! Usage: `rlr-grpc [ADDRESS [PRIMING_THRESHOLD LEARNING_RATE DISCOUNT_FACTOR]]`
+//!
+//! The server listens on 127.0.0.1:50051 by default, and the agent's
+//! hyperparameters default to 1, 0.5, and 0.9.
+
+use rlr::grpc::AgentService;
+use std::env;
+use std::error::Error;
+use tonic::transport::Server;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = env::args().skip(1);
+    let address = args
+        .next()
+        .as_deref()
+        .unwrap_or("127.0.0.1:50051")
+        .parse()?;
+    let priming_threshold = args.next().map_or(Ok(1), |a| a.parse())?;
+    let learning_rate = args.next().map_or(Ok(0.5), |a| a.parse())?;
+    let discount_factor = args.next().map_or(Ok(0.9), |a| a.parse())?;
+
+    let service = AgentService::new(priming_threshold, learning_rate, discount_factor)?;
+    Server::builder()
+        .add_service(service)
+        .serve(address)
+        .await?;
+    Ok(())
+}
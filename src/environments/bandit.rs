@@ -0,0 +1,368 @@
+//! A k-armed bandit, which can be used to benchmark the exploration behavior
+//! of agents.
+//!
+//! The bandit has a single state, whose actions are the bandit's arms. Pulling
+//! an arm earns a reward drawn from that arm's distribution, and ends the
+//! episode. Because the next state never depends on the arm that was pulled,
+//! agents should be trained against a bandit with a discount factor of 0.
+
+use crate::actions::Actioner;
+use crate::environments::{Environment, Step};
+use crate::errors::LearnerError;
+use crate::internal::rng;
+use crate::reproducibility::ReproducibilityConfig;
+use crate::states::Stater;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use std::f64::consts::PI;
+
+/// The distribution of the rewards earned by pulling an arm.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ArmDistribution {
+    /// Rewards are drawn from a normal distribution.
+    Gaussian {
+        /// The mean reward.
+        mean: f64,
+        /// The standard deviation of the reward.
+        std_dev: f64,
+    },
+
+    /// A reward of 1 is earned with probability `p`, and 0 otherwise.
+    Bernoulli {
+        /// The probability of earning a reward.
+        p: f64,
+    },
+}
+
+impl ArmDistribution {
+    /// Returns the mean reward of the distribution.
+    pub fn expected_reward(&self) -> f64 {
+        match *self {
+            Self::Gaussian { mean, .. } => mean,
+            Self::Bernoulli { p } => p,
+        }
+    }
+
+    fn sample(&self, rng: &mut dyn RngCore) -> f64 {
+        match *self {
+            Self::Gaussian { mean, std_dev } => std_dev.mul_add(standard_normal(rng), mean),
+            Self::Bernoulli { p } => {
+                if rng.gen::<f64>() < p {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    fn drift(&mut self, amount: f64) {
+        match self {
+            Self::Gaussian { mean, .. } => *mean += amount,
+            Self::Bernoulli { p } => *p = (*p + amount).clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// One of a bandit's arms.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Arm(usize);
+
+impl Arm {
+    /// Returns the index of the arm.
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+impl Actioner<'_> for Arm {
+    type Id = usize;
+
+    fn id(&self) -> usize {
+        self.0
+    }
+}
+
+/// The single state of a bandit, whose possible actions are the bandit's arms.
+#[derive(Debug, Clone, Copy)]
+pub struct BanditState<'a> {
+    arms: &'a [Arm],
+}
+
+impl<'a> Stater<'a, Arm> for BanditState<'a> {
+    type Id = ();
+
+    fn possible_actions(&self) -> Vec<&'a Arm> {
+        self.arms.iter().collect()
+    }
+
+    fn action_is_compatible(&self, action: &'a Arm) -> bool {
+        action.0 < self.arms.len()
+    }
+
+    fn get_action(&self, action_id: &usize) -> Result<&'a Arm, LearnerError> {
+        self.arms
+            .get(*action_id)
+            .ok_or_else(|| LearnerError::ActionNotFound {
+                action: format!("{action_id:?}"),
+                reason: format!("the bandit has no arm {action_id}"),
+            })
+    }
+
+    fn id(&self) {}
+
+    fn apply(&self, _: &'a Arm) -> Result<(), LearnerError> {
+        Ok(())
+    }
+}
+
+/// A k-armed bandit.
+///
+/// By default the bandit is stationary, meaning that the distribution of
+/// each arm never changes. A non-stationary bandit can be created via
+/// `with_drift`.
+pub struct Bandit<'a> {
+    arms: &'a [Arm],
+    distributions: Vec<ArmDistribution>,
+    drift: f64,
+    rng: Box<dyn RngCore + 'a>,
+}
+
+impl<'a> Bandit<'a> {
+    /// Returns `k` arms, for use with a bandit that has `k` arms.
+    pub fn arms(k: usize) -> Vec<Arm> {
+        (0..k).map(Arm).collect()
+    }
+
+    /// Returns a new bandit whose arms earn rewards drawn from the supplied
+    /// distributions. `arms` must contain one arm per distribution, as
+    /// returned by `Bandit::arms`.
+    pub fn new(arms: &'a [Arm], distributions: Vec<ArmDistribution>) -> Result<Self, LearnerError> {
+        if arms.len() != distributions.len() || arms.iter().enumerate().any(|(i, arm)| arm.0 != i) {
+            return Err(LearnerError::InvalidArgument(format!(
+                "a bandit with {} distributions requires the arms returned by Bandit::arms({})",
+                distributions.len(),
+                distributions.len()
+            )));
+        }
+        Ok(Self {
+            arms,
+            distributions,
+            drift: 0.0,
+            rng: Box::new(rng::default_rng()),
+        })
+    }
+
+    /// Returns a new bandit in which each arm's rewards are drawn from a
+    /// normal distribution with a standard deviation of 1, and a mean that is
+    /// itself drawn from a standard normal distribution. This is the
+    /// "10-armed testbed" described by Sutton and Barto, generalized to any
+    /// number of arms.
+    pub fn gaussian_testbed<R: RngCore + 'a>(arms: &'a [Arm], mut rng: R) -> Self {
+        let distributions = arms
+            .iter()
+            .map(|_| ArmDistribution::Gaussian {
+                mean: standard_normal(&mut rng),
+                std_dev: 1.0,
+            })
+            .collect();
+        Self {
+            arms,
+            distributions,
+            drift: 0.0,
+            rng: Box::new(rng),
+        }
+    }
+
+    /// Makes the bandit non-stationary. After each pull, the mean of every
+    /// arm takes a random step drawn from a normal distribution with the
+    /// supplied standard deviation (Bernoulli probabilities are kept within
+    /// the range 0 to 1).
+    #[must_use]
+    pub fn with_drift(mut self, std_dev: f64) -> Self {
+        self.drift = std_dev;
+        self
+    }
+
+    /// Sets the random number generator that the bandit uses to draw rewards.
+    /// By default, the bandit uses a `rand::rngs::StdRng` seeded from the
+    /// platform's source of entropy.
+    #[must_use]
+    pub fn with_rng<R: RngCore + 'a>(mut self, rng: R) -> Self {
+        self.rng = Box::new(rng);
+        self
+    }
+
+    /// Seeds the bandit's random number generator, so that the rewards it
+    /// returns (and, if it is non-stationary, the way its arms drift) are the
+    /// same every time an experiment is run.
+    #[must_use]
+    pub fn with_seed(self, seed: u64) -> Self {
+        self.with_rng(StdRng::seed_from_u64(seed))
+    }
+
+    /// Seeds the bandit's random number generator from `config`. See
+    /// `reproducibility::ReproducibilityConfig`.
+    #[must_use]
+    pub fn with_reproducibility(self, config: &ReproducibilityConfig) -> Self {
+        self.with_rng(config.environment_rng())
+    }
+
+    /// Returns the current distribution of each arm.
+    pub fn distributions(&self) -> &[ArmDistribution] {
+        &self.distributions
+    }
+
+    /// Returns the arm with the highest expected reward.
+    pub fn optimal_arm(&self) -> &'a Arm {
+        let best = self
+            .distributions
+            .iter()
+            .enumerate()
+            .fold(0, |best, (i, d)| {
+                if d.expected_reward() > self.distributions[best].expected_reward() {
+                    i
+                } else {
+                    best
+                }
+            });
+        &self.arms[best]
+    }
+
+    fn state(&self) -> BanditState<'a> {
+        BanditState { arms: self.arms }
+    }
+}
+
+impl<'a> Environment<'a, BanditState<'a>, Arm> for Bandit<'a> {
+    fn reset(&mut self) -> Result<BanditState<'a>, LearnerError> {
+        Ok(self.state())
+    }
+
+    fn step(&mut self, action: &'a Arm) -> Result<Step<BanditState<'a>>, LearnerError> {
+        let distribution =
+            self.distributions
+                .get(action.0)
+                .ok_or_else(|| LearnerError::ActionNotFound {
+                    action: format!("{:?}", action.0),
+                    reason: format!("the bandit has no arm {}", action.0),
+                })?;
+        let reward = distribution.sample(self.rng.as_mut());
+        if self.drift != 0.0 {
+            for distribution in &mut self.distributions {
+                distribution.drift(self.drift * standard_normal(self.rng.as_mut()));
+            }
+        }
+        Ok(Step {
+            next_state: self.state(),
+            reward,
+            done: true,
+        })
+    }
+}
+
+/// Draws a sample from a standard normal distribution using the Box-Muller
+/// transform.
+fn standard_normal(rng: &mut dyn RngCore) -> f64 {
+    let u1 = 1.0 - rng.gen::<f64>();
+    let u2 = rng.gen::<f64>();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::agents::bayesian::Agent;
+    use crate::agents::Agenter;
+    use crate::stats::actionstats::Stats;
+    use crate::training::{Schedule, Trainer};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn rewards_follow_distributions() {
+        fn mean_reward<'a>(bandit: &mut Bandit<'a>, arm: &'a Arm) -> f64 {
+            let total: f64 = (0..2000).map(|_| bandit.step(arm).unwrap().reward).sum();
+            total / 2000.0
+        }
+
+        let arms = Bandit::arms(2);
+        let mut bandit = Bandit::new(
+            &arms,
+            vec![
+                ArmDistribution::Bernoulli { p: 0.25 },
+                ArmDistribution::Gaussian {
+                    mean: 3.0,
+                    std_dev: 0.5,
+                },
+            ],
+        )
+        .unwrap()
+        .with_rng(StdRng::seed_from_u64(1));
+
+        assert!((mean_reward(&mut bandit, &arms[0]) - 0.25).abs() < 0.05);
+        assert!((mean_reward(&mut bandit, &arms[1]) - 3.0).abs() < 0.05);
+        assert_eq!(&arms[1], bandit.optimal_arm());
+    }
+
+    #[test]
+    fn with_seed() {
+        let arms = Bandit::arms(2);
+        let pulls = |seed| -> Vec<f64> {
+            let distributions = vec![
+                ArmDistribution::Bernoulli { p: 0.5 },
+                ArmDistribution::Gaussian {
+                    mean: 0.0,
+                    std_dev: 1.0,
+                },
+            ];
+            let mut bandit = Bandit::new(&arms, distributions)
+                .unwrap()
+                .with_drift(0.1)
+                .with_seed(seed);
+            (0..20)
+                .map(|i| bandit.step(&arms[i % 2]).unwrap().reward)
+                .collect()
+        };
+
+        assert_eq!(pulls(1), pulls(1));
+        assert_ne!(pulls(1), pulls(2));
+    }
+
+    #[test]
+    fn new_requires_matching_arms() {
+        let arms = Bandit::arms(2);
+        assert!(Bandit::new(&arms, vec![ArmDistribution::Bernoulli { p: 0.5 }]).is_err());
+    }
+
+    #[test]
+    fn drift() {
+        let arms = Bandit::arms(3);
+        let mut bandit = Bandit::gaussian_testbed(&arms, StdRng::seed_from_u64(1)).with_drift(0.1);
+        let before = bandit.distributions().to_vec();
+        let step = bandit.step(&arms[0]).unwrap();
+        assert!(step.done);
+        assert_ne!(before, bandit.distributions());
+    }
+
+    #[test]
+    fn agent_finds_optimal_arm() {
+        let arms = Bandit::arms(5);
+        let mut bandit = Bandit::gaussian_testbed(&arms, StdRng::seed_from_u64(3));
+        let mut agent: Agent<BanditState, Arm, Stats> =
+            Agent::new(1, 0.1, 0.0).with_rng(StdRng::seed_from_u64(4));
+
+        Trainer::new(2000, 1)
+            .with_epsilon(Schedule::Constant(0.1))
+            .with_rng(StdRng::seed_from_u64(5))
+            .train(&mut agent, &mut bandit)
+            .unwrap();
+
+        let state = bandit.reset().unwrap();
+        assert_eq!(
+            bandit.optimal_arm(),
+            agent.recommend_action(&state).unwrap()
+        );
+    }
+}
@@ -0,0 +1,398 @@
+//! An adapter for Gymnasium environments with discrete action spaces.
+//!
+//! `GymEnv` speaks to a Gymnasium environment over a simple line-based JSON
+//! protocol. Each request is a single line containing a JSON object, and each
+//! response is a single line containing a JSON object:
+//!
+//! - `{"cmd": "reset"}` responds with `{"observation": ...}`. The request
+//!   includes a `"seed"` field if the adapter has been given a seed.
+//! - `{"cmd": "step", "action": n}` responds with `{"observation": ...,
+//!   "reward": r, "terminated": bool, "truncated": bool}`.
+//!
+//! Any response may instead be `{"error": "message"}`. The session ends when
+//! the adapter closes its side of the connection.
+//!
+//! Observations can be any JSON value. Because the crate's agents are
+//! tabular, each observation is mapped to a state ID by a user-supplied
+//! discretizer. The numbers within an observation are also available as the
+//! state's features, for agents that approximate values from features.
+//!
+//! The protocol can be served by a small Python bridge, which `GymEnv::spawn`
+//! can run as a subprocess:
+//!
+//! ```python
+//! import json, sys
+//! import gymnasium as gym
+//! import numpy as np
+//!
+//! env = gym.make(sys.argv[1])
+//! for line in sys.stdin:
+//!     req = json.loads(line)
+//!     try:
+//!         if req["cmd"] == "reset":
+//!             obs, _ = env.reset(seed=req.get("seed"))
+//!             resp = {"observation": np.asarray(obs).tolist()}
+//!         else:
+//!             obs, reward, terminated, truncated, _ = env.step(req["action"])
+//!             resp = {"observation": np.asarray(obs).tolist(), "reward": float(reward),
+//!                     "terminated": bool(terminated), "truncated": bool(truncated)}
+//!     except Exception as e:
+//!         resp = {"error": str(e)}
+//!     print(json.dumps(resp), flush=True)
+//! ```
+
+use crate::actions::Actioner;
+use crate::environments::{Environment, Step};
+use crate::errors::LearnerError;
+use crate::states::{FeatureStater, Stater};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+/// An action in a Gymnasium environment's discrete action space.
+#[derive(Debug, PartialEq, Eq)]
+pub struct GymAction(usize);
+
+impl GymAction {
+    /// Returns the actions of a discrete action space of size `n`.
+    pub fn space(n: usize) -> Vec<Self> {
+        (0..n).map(GymAction).collect()
+    }
+
+    /// Returns the index of the action within the action space.
+    pub fn index(&self) -> usize {
+        self.0
+    }
+}
+
+impl Actioner<'_> for GymAction {
+    type Id = usize;
+
+    fn id(&self) -> usize {
+        self.0
+    }
+}
+
+/// A state of a Gymnasium environment, identified by its discretized
+/// observation.
+#[derive(Debug, Clone)]
+pub struct GymState<'a, K> {
+    id: K,
+    observation: Value,
+    actions: &'a [GymAction],
+}
+
+impl<K> GymState<'_, K> {
+    /// Returns the raw observation that the state was created from.
+    pub fn observation(&self) -> &Value {
+        &self.observation
+    }
+}
+
+impl<'a, K> Stater<'a, GymAction> for GymState<'a, K>
+where
+    K: Hash + Eq + Ord + Clone + Debug,
+{
+    type Id = K;
+
+    fn possible_actions(&self) -> Vec<&'a GymAction> {
+        self.actions.iter().collect()
+    }
+
+    fn action_is_compatible(&self, action: &'a GymAction) -> bool {
+        action.0 < self.actions.len()
+    }
+
+    fn get_action(&self, action_id: &usize) -> Result<&'a GymAction, LearnerError> {
+        self.actions
+            .get(*action_id)
+            .ok_or_else(|| LearnerError::ActionNotFound {
+                action: format!("{action_id:?}"),
+                reason: format!("the action space has no action {action_id}"),
+            })
+    }
+
+    fn id(&self) -> K {
+        self.id.clone()
+    }
+
+    fn apply(&self, _: &'a GymAction) -> Result<(), LearnerError> {
+        Ok(())
+    }
+}
+
+impl<'a, K> FeatureStater<'a, GymAction> for GymState<'a, K>
+where
+    K: Hash + Eq + Ord + Clone + Debug,
+{
+    /// Returns the numbers within the state's observation, flattening any
+    /// nested arrays in order. Values that are not numbers are skipped, and
+    /// booleans are treated as 0 or 1.
+    fn features(&self) -> Vec<f64> {
+        let mut features = Vec::new();
+        flatten(&self.observation, &mut features);
+        features
+    }
+}
+
+fn flatten(value: &Value, features: &mut Vec<f64>) {
+    match value {
+        Value::Number(n) => features.extend(n.as_f64()),
+        Value::Bool(b) => features.push(if *b { 1.0 } else { 0.0 }),
+        Value::Array(values) => {
+            for value in values {
+                flatten(value, features);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The fields of a response from the bridge.
+#[derive(Deserialize)]
+struct Response {
+    error: Option<String>,
+    #[serde(default)]
+    observation: Value,
+    #[serde(default)]
+    reward: f64,
+    #[serde(default)]
+    terminated: bool,
+    #[serde(default)]
+    truncated: bool,
+}
+
+/// A Gymnasium environment, reached through the protocol described in the
+/// module documentation.
+///
+/// Episodes end when the Gymnasium environment reports that they have either
+/// terminated or been truncated.
+pub struct GymEnv<'a, K, R, W> {
+    reader: R,
+    writer: W,
+    actions: &'a [GymAction],
+    discretizer: Box<dyn Fn(&Value) -> K + 'a>,
+    seed: Option<u64>,
+    // Declared after `writer`, so that the subprocess's input is closed
+    // (ending its session) before the subprocess is waited on.
+    child: Option<Subprocess>,
+}
+
+/// A subprocess serving the protocol, which is waited on when dropped.
+struct Subprocess(Child);
+
+impl Drop for Subprocess {
+    fn drop(&mut self) {
+        // The subprocess may already have exited, in which case there is
+        // nothing left to clean up.
+        let _ = self.0.wait();
+    }
+}
+
+impl<'a, K, R, W> GymEnv<'a, K, R, W>
+where
+    K: Hash + Eq + Ord + Clone + Debug,
+    R: BufRead,
+    W: Write,
+{
+    /// Returns an adapter that sends requests to `writer` and reads responses
+    /// from `reader`. `actions` must be the actions returned by
+    /// `GymAction::space` for the environment's action space, and
+    /// `discretizer` maps each observation to a state ID.
+    pub fn new<D>(reader: R, writer: W, actions: &'a [GymAction], discretizer: D) -> Self
+    where
+        D: Fn(&Value) -> K + 'a,
+    {
+        Self {
+            reader,
+            writer,
+            actions,
+            discretizer: Box::new(discretizer),
+            seed: None,
+            child: None,
+        }
+    }
+
+    /// Seeds the environment's random number generator, so that its episodes
+    /// are the same every time an experiment is run. The seed is sent with
+    /// the next reset, after which the environment continues to draw from
+    /// the seeded generator (as Gymnasium environments do).
+    #[must_use]
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    fn request(&mut self, request: &Value) -> Result<Response, LearnerError> {
+        let io_err = |e| LearnerError::Environment(format!("unable to reach gym environment: {e}"));
+        writeln!(self.writer, "{request}").map_err(io_err)?;
+        self.writer.flush().map_err(io_err)?;
+
+        let mut line = String::new();
+        if self.reader.read_line(&mut line).map_err(io_err)? == 0 {
+            return Err(LearnerError::Environment(
+                "gym environment closed the connection".to_string(),
+            ));
+        }
+        let response: Response = serde_json::from_str(&line).map_err(|e| {
+            LearnerError::Environment(format!("invalid response from gym environment: {e}"))
+        })?;
+        match response.error {
+            Some(error) => Err(LearnerError::Environment(format!(
+                "gym environment error: {error}"
+            ))),
+            None => Ok(response),
+        }
+    }
+
+    fn state(&self, observation: Value) -> GymState<'a, K> {
+        GymState {
+            id: (self.discretizer)(&observation),
+            observation,
+            actions: self.actions,
+        }
+    }
+}
+
+impl<'a, K> GymEnv<'a, K, BufReader<ChildStdout>, ChildStdin>
+where
+    K: Hash + Eq + Ord + Clone + Debug,
+{
+    /// Runs `command` as a subprocess that serves the protocol over its
+    /// standard input and output, such as the Python bridge shown in the
+    /// module documentation. The subprocess's input is closed when the
+    /// adapter is dropped, which ends its session, and the adapter then waits
+    /// for the subprocess to exit.
+    pub fn spawn<D>(
+        command: &mut Command,
+        actions: &'a [GymAction],
+        discretizer: D,
+    ) -> Result<Self, LearnerError>
+    where
+        D: Fn(&Value) -> K + 'a,
+    {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| {
+                LearnerError::Environment(format!("unable to start gym environment: {e}"))
+            })?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            return Err(LearnerError::Environment(
+                "unable to connect to gym environment".to_string(),
+            ));
+        };
+        let mut env = Self::new(BufReader::new(stdout), stdin, actions, discretizer);
+        env.child = Some(Subprocess(child));
+        Ok(env)
+    }
+}
+
+impl<'a, K, R, W> Environment<'a, GymState<'a, K>, GymAction> for GymEnv<'a, K, R, W>
+where
+    K: Hash + Eq + Ord + Clone + Debug,
+    R: BufRead,
+    W: Write,
+{
+    fn reset(&mut self) -> Result<GymState<'a, K>, LearnerError> {
+        let request = self.seed.take().map_or_else(
+            || json!({"cmd": "reset"}),
+            |seed| json!({"cmd": "reset", "seed": seed}),
+        );
+        let response = self.request(&request)?;
+        Ok(self.state(response.observation))
+    }
+
+    fn step(&mut self, action: &'a GymAction) -> Result<Step<GymState<'a, K>>, LearnerError> {
+        let response = self.request(&json!({"cmd": "step", "action": action.0}))?;
+        Ok(Step {
+            reward: response.reward,
+            done: response.terminated || response.truncated,
+            next_state: self.state(response.observation),
+        })
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn observation_features() {
+        let actions = GymAction::space(1);
+        let state = GymState {
+            id: 0,
+            observation: json!([[1, 2.5], true, "skipped", null, [false]]),
+            actions: &actions,
+        };
+        assert_eq!(vec![1.0, 2.5, 1.0, 0.0], state.features());
+    }
+
+    #[test]
+    fn reset_and_step() {
+        let responses = "{\"observation\": [0.4, -1.2]}\n\
+                         {\"observation\": [0.6, 0.1], \"reward\": 1.5, \"terminated\": false, \"truncated\": true}\n\
+                         {\"observation\": [0.0, 0.0]}\n";
+        let actions = GymAction::space(2);
+        let mut requests = Vec::new();
+        let mut env = GymEnv::new(
+            Cursor::new(responses.as_bytes()),
+            &mut requests,
+            &actions,
+            |obs: &Value| obs[0].as_f64().map_or(0, |x| i64::from(x >= 0.5)),
+        )
+        .with_seed(7);
+
+        let state = env.reset().unwrap();
+        assert_eq!(0, state.id());
+        assert_eq!(2, state.possible_actions().len());
+
+        let step = env.step(&actions[1]).unwrap();
+        assert_eq!(1, step.next_state.id());
+        assert_eq!(&json!([0.6, 0.1]), step.next_state.observation());
+        assert_eq!(vec![0.6, 0.1], step.next_state.features());
+        assert_eq!(1.5, step.reward);
+        assert!(step.done);
+        env.reset().unwrap();
+
+        drop(env);
+        let requests: Vec<Value> = String::from_utf8(requests)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            vec![
+                json!({"cmd": "reset", "seed": 7}),
+                json!({"cmd": "step", "action": 1}),
+                json!({"cmd": "reset"})
+            ],
+            requests
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let actions = GymAction::space(1);
+        let mut env = GymEnv::new(
+            Cursor::new(&b"{\"error\": \"bad action\"}\n"[..]),
+            Vec::new(),
+            &actions,
+            |_: &Value| 0_i64,
+        );
+        assert_eq!(
+            "gym environment error: bad action",
+            env.step(&actions[0]).err().unwrap().message()
+        );
+        assert_eq!(
+            "gym environment closed the connection",
+            env.reset().err().unwrap().message()
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! Environments represent the world that an agent interacts with.
+//!
+//! An environment owns the true state of the world. It hands the agent a
+//! state to act upon, applies the action that the agent chooses, and reports
+//! the reward that the action earned and whether the episode has ended. This
+//! keeps the source of rewards and termination out of the caller's hands.
+//!
+//! Environments that are remote services, such as a simulator behind an
+//! HTTP API or a robot controller, can implement `AsyncEnvironment` instead,
+//! so that waiting for the result of an action does not block a thread. Such
+//! environments are trained against with `Trainer::run_async`.
+
+pub mod bandit;
+#[cfg(feature = "gym")]
+pub mod gym;
+pub mod tictactoe;
+pub mod wrappers;
+
+use crate::actions::Actioner;
+use crate::errors::LearnerError;
+use crate::states::Stater;
+use std::future::Future;
+
+/// The outcome of applying an action to an environment.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Step<S> {
+    /// The state of the environment after the action was applied.
+    pub next_state: S,
+
+    /// The reward earned by the action.
+    pub reward: f64,
+
+    /// Whether the episode ended as a result of the action. Once an episode
+    /// has ended, `reset` must be called before `step` is called again.
+    pub done: bool,
+}
+
+/// Represents an environment that an agent can learn from by repeatedly
+/// stepping through episodes.
+pub trait Environment<'a, S, A>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a>,
+{
+    /// Starts a new episode, returning the initial state of the environment.
+    fn reset(&mut self) -> Result<S, LearnerError>;
+
+    /// Applies an action to the environment's current state, returning the
+    /// resulting state, the reward earned, and whether the episode has ended.
+    fn step(&mut self, action: &'a A) -> Result<Step<S>, LearnerError>;
+}
+
+/// An environment whose episodes are reset and stepped asynchronously. See
+/// `Environment` for the meaning of each method.
+///
+/// Implementations may use `async fn` for either method. The futures are not
+/// required to be `Send`, so they can hold references to the environment's
+/// own state across await points.
+pub trait AsyncEnvironment<'a, S, A>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a>,
+{
+    /// Starts a new episode, resolving to the initial state of the
+    /// environment.
+    fn reset(&mut self) -> impl Future<Output = Result<S, LearnerError>>;
+
+    /// Applies an action to the environment's current state, resolving to
+    /// the resulting state, the reward earned, and whether the episode has
+    /// ended.
+    fn step(&mut self, action: &'a A) -> impl Future<Output = Result<Step<S>, LearnerError>>;
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::agents::bayesian::Agent;
+    use crate::agents::Agenter;
+    use crate::mocks::*;
+    use crate::stats::actionstats::Stats;
+
+    #[test]
+    fn agent_learns_from_environment() {
+        let moves = MockCorridor::moves();
+        let mut env = MockCorridor::new(&moves, 4);
+        let mut agent: Agent<MockCell, MockActioner, Stats> = Agent::new(1, 1.0, 0.9);
+
+        for _ in 0..20 {
+            let mut state = env.reset().unwrap();
+            loop {
+                let action = agent.recommend_action(&state).unwrap();
+                let step = env.step(action).unwrap();
+                agent
+                    .learn(Some(&state), action, &step.next_state, step.reward)
+                    .unwrap();
+                state = step.next_state;
+                if step.done {
+                    break;
+                }
+            }
+        }
+
+        let start = env.reset().unwrap();
+        assert_eq!("R", agent.recommend_action(&start).unwrap().id());
+    }
+}
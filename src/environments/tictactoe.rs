@@ -0,0 +1,401 @@
+//! A game of tic-tac-toe, which can be used to train two agents against one
+//! another.
+//!
+//! Each state of the environment is a `Board`, and each action is a `Move`
+//! that places the current player's mark in one of the board's nine cells.
+//! A board only reports the moves that are legal for it, so agents never
+//! consider moves into occupied cells.
+
+use crate::actions::Actioner;
+use crate::agents::Agenter;
+use crate::environments::{Environment, Step};
+use crate::errors::LearnerError;
+use crate::states::Stater;
+use std::fmt;
+
+/// The rows, columns, and diagonals that win the game.
+const LINES: [[usize; 3]; 8] = [
+    [0, 1, 2],
+    [3, 4, 5],
+    [6, 7, 8],
+    [0, 3, 6],
+    [1, 4, 7],
+    [2, 5, 8],
+    [0, 4, 8],
+    [2, 4, 6],
+];
+
+/// Every possible move, one for each cell of the board.
+pub static MOVES: [Move; 9] = [
+    Move(0),
+    Move(1),
+    Move(2),
+    Move(3),
+    Move(4),
+    Move(5),
+    Move(6),
+    Move(7),
+    Move(8),
+];
+
+/// A player's mark. `X` always moves first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mark {
+    /// The first player.
+    X,
+    /// The second player.
+    O,
+}
+
+impl Mark {
+    /// Returns the mark of the other player.
+    #[must_use]
+    pub fn opponent(self) -> Self {
+        match self {
+            Self::X => Self::O,
+            Self::O => Self::X,
+        }
+    }
+}
+
+/// A move that places a mark in a cell of the board. Cells are numbered 0 to
+/// 8, left to right and top to bottom.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Move(usize);
+
+impl Move {
+    /// Returns the cell that the move places a mark in.
+    pub fn cell(&self) -> usize {
+        self.0
+    }
+}
+
+impl Actioner<'_> for Move {
+    type Id = usize;
+
+    fn id(&self) -> usize {
+        self.0
+    }
+}
+
+/// The state of a game of tic-tac-toe.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Board {
+    cells: [Option<Mark>; 9],
+}
+
+impl Board {
+    /// Returns an empty board.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the mark in the specified cell, if any.
+    pub fn cell(&self, cell: usize) -> Option<Mark> {
+        self.cells.get(cell).copied().flatten()
+    }
+
+    /// Returns the mark of the player whose turn it is.
+    pub fn to_move(&self) -> Mark {
+        let marks = self.cells.iter().flatten().count();
+        if marks.is_multiple_of(2) {
+            Mark::X
+        } else {
+            Mark::O
+        }
+    }
+
+    /// Returns the mark of the player that has won the game, if any.
+    pub fn winner(&self) -> Option<Mark> {
+        LINES.iter().find_map(|&[a, b, c]| match self.cells[a] {
+            Some(mark) if self.cells[b] == Some(mark) && self.cells[c] == Some(mark) => Some(mark),
+            _ => None,
+        })
+    }
+
+    /// Returns true if the board is full and neither player has won.
+    pub fn is_draw(&self) -> bool {
+        self.winner().is_none() && self.cells.iter().all(Option::is_some)
+    }
+
+    /// Returns true if the game has ended in a win or a draw.
+    pub fn is_over(&self) -> bool {
+        self.winner().is_some() || self.is_draw()
+    }
+
+    /// Returns true if the move is legal, meaning that the game is not over
+    /// and the move's cell is empty.
+    pub fn is_legal(&self, action: &Move) -> bool {
+        !self.is_over() && self.cell(action.0).is_none()
+    }
+
+    /// Returns the board that results from the current player making the
+    /// specified move, or an error if the move is not legal.
+    pub fn play(&self, action: &Move) -> Result<Self, LearnerError> {
+        if !self.is_legal(action) {
+            return Err(LearnerError::ActionNotCompatible {
+                state: format!("{:?}", self.id()),
+                action: format!("{:?}", action.0),
+            });
+        }
+        let mut next = *self;
+        next.cells[action.0] = Some(self.to_move());
+        Ok(next)
+    }
+}
+
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for row in self.cells.chunks(3) {
+            for cell in row {
+                let mark = match cell {
+                    Some(Mark::X) => 'X',
+                    Some(Mark::O) => 'O',
+                    None => '.',
+                };
+                write!(f, "{mark}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl<'a> Stater<'a, Move> for Board {
+    /// The board encoded as a base 3 number, where each digit represents a
+    /// cell that is either empty (0), `X` (1), or `O` (2).
+    type Id = u32;
+
+    /// Returns the legal moves for the board. No moves are returned once the
+    /// game is over.
+    fn possible_actions(&self) -> Vec<&'a Move> {
+        MOVES.iter().filter(|m| self.is_legal(m)).collect()
+    }
+
+    fn action_is_compatible(&self, action: &'a Move) -> bool {
+        self.is_legal(action)
+    }
+
+    fn get_action(&self, action_id: &usize) -> Result<&'a Move, LearnerError> {
+        match MOVES.get(*action_id) {
+            Some(action) if self.is_legal(action) => Ok(action),
+            _ => Err(LearnerError::ActionNotFound {
+                action: format!("{action_id:?}"),
+                reason: format!(
+                    "move {action_id} is not legal on board {}",
+                    Stater::<Move>::id(self)
+                ),
+            }),
+        }
+    }
+
+    fn id(&self) -> u32 {
+        self.cells.iter().rev().fold(0, |id, cell| {
+            id * 3
+                + match cell {
+                    None => 0,
+                    Some(Mark::X) => 1,
+                    Some(Mark::O) => 2,
+                }
+        })
+    }
+
+    /// Boards are immutable, so applying a move only checks that the move is
+    /// legal. Use `Board::play` or `TicTacToe::step` to make a move.
+    fn apply(&self, action: &'a Move) -> Result<(), LearnerError> {
+        self.play(action).map(|_| ())
+    }
+}
+
+/// A tic-tac-toe environment in which the players take turns.
+///
+/// Each step makes a move for the player whose turn it is, and rewards that
+/// player with 1 if the move wins the game, and 0 otherwise. To train two
+/// agents against one another, use `self_play`.
+#[derive(Debug, Clone, Default)]
+pub struct TicTacToe {
+    board: Board,
+}
+
+impl TicTacToe {
+    /// Returns a new game with an empty board.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the current board.
+    pub fn board(&self) -> Board {
+        self.board
+    }
+}
+
+impl<'a> Environment<'a, Board, Move> for TicTacToe {
+    fn reset(&mut self) -> Result<Board, LearnerError> {
+        self.board = Board::new();
+        Ok(self.board)
+    }
+
+    fn step(&mut self, action: &'a Move) -> Result<Step<Board>, LearnerError> {
+        let mover = self.board.to_move();
+        self.board = self.board.play(action)?;
+        Ok(Step {
+            next_state: self.board,
+            reward: if self.board.winner() == Some(mover) {
+                1.0
+            } else {
+                0.0
+            },
+            done: self.board.is_over(),
+        })
+    }
+}
+
+/// Plays a single game between two agents, having each agent learn from its
+/// own moves, and returns the winner (or `None` for a draw).
+///
+/// Each agent learns from the transition between the board on which it moved
+/// and the board on which it next moves (after its opponent has replied). At
+/// the end of the game, each agent is rewarded with 1 for a win, -1 for a
+/// loss, and 0 for a draw.
+pub fn self_play<'a, G>(
+    env: &mut TicTacToe,
+    x: &mut G,
+    o: &mut G,
+) -> Result<Option<Mark>, LearnerError>
+where
+    G: Agenter<'a, Board, Move> + ?Sized,
+{
+    let mut pending: [Option<(Board, &'a Move)>; 2] = [None, None];
+    let mut board = env.reset()?;
+    loop {
+        let mover = board.to_move();
+        let agent = match mover {
+            Mark::X => &mut *x,
+            Mark::O => &mut *o,
+        };
+        let slot = &mut pending[player_index(mover)];
+        if let Some((previous, action)) = slot.take() {
+            agent.learn(Some(&previous), action, &board, 0.0)?;
+        }
+        let action = agent.recommend_action(&board)?;
+        let step = env.step(action)?;
+        *slot = Some((board, action));
+        board = step.next_state;
+        if step.done {
+            break;
+        }
+    }
+
+    let winner = board.winner();
+    for (mark, agent) in [(Mark::X, x), (Mark::O, o)] {
+        if let Some((previous, action)) = pending[player_index(mark)].take() {
+            let reward = match winner {
+                Some(w) if w == mark => 1.0,
+                Some(_) => -1.0,
+                None => 0.0,
+            };
+            agent.learn(Some(&previous), action, &board, reward)?;
+        }
+    }
+    Ok(winner)
+}
+
+fn player_index(mark: Mark) -> usize {
+    match mark {
+        Mark::X => 0,
+        Mark::O => 1,
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::agents::bayesian::Agent;
+    use crate::stats::actionstats::Stats;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn board(moves: &[usize]) -> Board {
+        moves
+            .iter()
+            .fold(Board::new(), |b, &m| b.play(&MOVES[m]).unwrap())
+    }
+
+    #[test]
+    fn win_and_draw_detection() {
+        let x_wins = board(&[0, 3, 1, 4, 2]);
+        assert_eq!(Some(Mark::X), x_wins.winner());
+        assert!(x_wins.is_over());
+        assert!(Stater::<Move>::possible_actions(&x_wins).is_empty());
+
+        let draw = board(&[0, 1, 2, 4, 3, 5, 7, 6, 8]);
+        assert_eq!(None, draw.winner());
+        assert!(draw.is_draw());
+
+        let in_progress = board(&[4]);
+        assert_eq!(Mark::O, in_progress.to_move());
+        assert!(!in_progress.is_over());
+    }
+
+    #[test]
+    fn legal_move_masking() {
+        let b = board(&[4, 0]);
+        let legal: Vec<usize> = Stater::<Move>::possible_actions(&b)
+            .iter()
+            .map(|m| m.cell())
+            .collect();
+        assert_eq!(vec![1, 2, 3, 5, 6, 7, 8], legal);
+        assert!(Stater::<Move>::get_action(&b, &4).is_err());
+        assert!(b.play(&MOVES[0]).is_err());
+
+        let mut env = TicTacToe::new();
+        env.reset().unwrap();
+        env.step(&MOVES[4]).unwrap();
+        assert!(env.step(&MOVES[4]).is_err());
+    }
+
+    #[test]
+    fn ids_are_unique() {
+        assert_eq!(0, Stater::<Move>::id(&Board::new()));
+        assert_eq!(1, Stater::<Move>::id(&board(&[0])));
+        assert_eq!(2 * 3, Stater::<Move>::id(&board(&[8, 1])) % 9);
+        assert_ne!(
+            Stater::<Move>::id(&board(&[0, 1])),
+            Stater::<Move>::id(&board(&[1, 0]))
+        );
+    }
+
+    #[test]
+    fn step_rewards_the_winner() {
+        let mut env = TicTacToe::new();
+        env.reset().unwrap();
+        for m in &[0, 3, 1, 4] {
+            let step = env.step(&MOVES[*m]).unwrap();
+            assert_eq!(0.0, step.reward);
+            assert!(!step.done);
+        }
+        let step = env.step(&MOVES[2]).unwrap();
+        assert_eq!(1.0, step.reward);
+        assert!(step.done);
+    }
+
+    #[test]
+    fn self_play_trains_both_agents() {
+        let new_agent = |seed| -> Agent<Board, Move, Stats> {
+            Agent::new(1, 0.5, 0.9).with_rng(StdRng::seed_from_u64(seed))
+        };
+        let mut x = new_agent(1);
+        let mut o = new_agent(2);
+        let mut env = TicTacToe::new();
+
+        for _ in 0..50 {
+            self_play(&mut env, &mut x, &mut o).unwrap();
+            assert!(env.board().is_over());
+        }
+
+        assert!(x.entry_count().unwrap() > 0);
+        assert!(o.entry_count().unwrap() > 0);
+        assert!(x.iter_q_values().any(|(_, _, stats)| stats.q_raw != 0.0));
+    }
+}
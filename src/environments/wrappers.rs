@@ -0,0 +1,175 @@
+//! Wrappers that modify the behavior of an environment.
+//!
+//! Each wrapper is itself an `Environment`, so wrappers can be layered on top
+//! of one another. For example, `ScaleReward::new(ClipReward::new(env, -1.0,
+//! 1.0), 0.5)` clips each reward and then halves it.
+
+use crate::actions::Actioner;
+use crate::environments::{Environment, Step};
+use crate::errors::LearnerError;
+use crate::states::Stater;
+
+/// Ends each episode after a fixed number of steps, if the wrapped
+/// environment has not already ended it.
+#[derive(Debug, Clone)]
+pub struct TimeLimit<E> {
+    env: E,
+    max_steps: usize,
+    steps: usize,
+}
+
+impl<E> TimeLimit<E> {
+    /// Wraps `env` so that its episodes end after at most `max_steps` steps.
+    pub fn new(env: E, max_steps: usize) -> Self {
+        Self {
+            env,
+            max_steps,
+            steps: 0,
+        }
+    }
+
+    /// Returns the number of steps taken in the current episode.
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    /// Returns the wrapped environment.
+    pub fn into_inner(self) -> E {
+        self.env
+    }
+}
+
+impl<'a, S, A, E> Environment<'a, S, A> for TimeLimit<E>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a>,
+    E: Environment<'a, S, A>,
+{
+    fn reset(&mut self) -> Result<S, LearnerError> {
+        self.steps = 0;
+        self.env.reset()
+    }
+
+    fn step(&mut self, action: &'a A) -> Result<Step<S>, LearnerError> {
+        let mut step = self.env.step(action)?;
+        self.steps += 1;
+        step.done |= self.steps >= self.max_steps;
+        Ok(step)
+    }
+}
+
+/// Limits each reward to the range `min` to `max`.
+#[derive(Debug, Clone)]
+pub struct ClipReward<E> {
+    env: E,
+    min: f64,
+    max: f64,
+}
+
+impl<E> ClipReward<E> {
+    /// Wraps `env` so that its rewards are clipped to the range `min` to
+    /// `max`. An error is returned if `min` is greater than `max`.
+    pub fn new(env: E, min: f64, max: f64) -> Result<Self, LearnerError> {
+        if min > max {
+            return Err(LearnerError::InvalidArgument(format!(
+                "the minimum reward ({min}) cannot exceed the maximum reward ({max})"
+            )));
+        }
+        Ok(Self { env, min, max })
+    }
+
+    /// Returns the wrapped environment.
+    pub fn into_inner(self) -> E {
+        self.env
+    }
+}
+
+impl<'a, S, A, E> Environment<'a, S, A> for ClipReward<E>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a>,
+    E: Environment<'a, S, A>,
+{
+    fn reset(&mut self) -> Result<S, LearnerError> {
+        self.env.reset()
+    }
+
+    fn step(&mut self, action: &'a A) -> Result<Step<S>, LearnerError> {
+        let mut step = self.env.step(action)?;
+        step.reward = step.reward.clamp(self.min, self.max);
+        Ok(step)
+    }
+}
+
+/// Multiplies each reward by a constant factor.
+#[derive(Debug, Clone)]
+pub struct ScaleReward<E> {
+    env: E,
+    factor: f64,
+}
+
+impl<E> ScaleReward<E> {
+    /// Wraps `env` so that its rewards are multiplied by `factor`.
+    pub fn new(env: E, factor: f64) -> Self {
+        Self { env, factor }
+    }
+
+    /// Returns the wrapped environment.
+    pub fn into_inner(self) -> E {
+        self.env
+    }
+}
+
+impl<'a, S, A, E> Environment<'a, S, A> for ScaleReward<E>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a>,
+    E: Environment<'a, S, A>,
+{
+    fn reset(&mut self) -> Result<S, LearnerError> {
+        self.env.reset()
+    }
+
+    fn step(&mut self, action: &'a A) -> Result<Step<S>, LearnerError> {
+        let mut step = self.env.step(action)?;
+        step.reward *= self.factor;
+        Ok(step)
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::mocks::*;
+
+    #[test]
+    fn time_limit() {
+        let moves = MockCorridor::moves();
+        let mut env = TimeLimit::new(MockCorridor::new(&moves, 10), 3);
+
+        for _ in 0..2 {
+            env.reset().unwrap();
+            assert!(!env.step(&moves[1]).unwrap().done);
+            assert!(!env.step(&moves[1]).unwrap().done);
+            assert!(env.step(&moves[1]).unwrap().done);
+            assert_eq!(3, env.steps());
+        }
+    }
+
+    #[test]
+    fn clip_and_scale_reward() {
+        let moves = MockCorridor::moves();
+        let mut env = ScaleReward::new(
+            ClipReward::new(MockCorridor::new(&moves, 2), 0.0, 0.5).unwrap(),
+            -2.0,
+        );
+
+        env.reset().unwrap();
+        let step = env.step(&moves[1]).unwrap();
+        assert!(step.done);
+        assert_eq!(-1.0, step.reward);
+
+        assert!(ClipReward::new(env.into_inner().into_inner(), 1.0, 0.0).is_err());
+    }
+}
@@ -1,19 +1,142 @@
 //! Error types associated with the reinforcement learning process.
 
-#[derive(Debug, Clone, PartialEq)]
-/// A general error that has occurred during a learning operation.
-pub struct LearnerError {
-    msg: String,
+use std::error::Error;
+use std::fmt;
+
+/// An error that has occurred during a learning operation.
+///
+/// Each variant describes a different kind of failure, so that callers can
+/// match on the kind of failure rather than parsing messages. State and
+/// action IDs are recorded using their `Debug` representation. More variants
+/// may be added in the future.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum LearnerError {
+    /// An action was applied to a state that does not permit it.
+    ActionNotCompatible {
+        /// The ID of the state.
+        state: String,
+        /// The ID of the action.
+        action: String,
+    },
+
+    /// A state reported that no actions are possible from it, so no action
+    /// could be recommended.
+    NoPossibleActions {
+        /// The ID of the state.
+        state: String,
+    },
+
+    /// An action ID did not refer to any action that is available.
+    ActionNotFound {
+        /// The ID of the action.
+        action: String,
+        /// A description of why the action is not available.
+        reason: String,
+    },
+
+    /// An agent's tie breaker chose an index outside the range of tied
+    /// actions.
+    InvalidTieBreak {
+        /// The index that the tie breaker returned.
+        index: usize,
+        /// The number of tied actions.
+        candidates: usize,
+    },
+
+    /// A value that must be finite, such as a reward or q-value, was NaN or
+    /// infinite.
+    NonFinite(String),
+
+    /// The operation is not permitted in the agent's current lifecycle stage.
+    Lifecycle(String),
+
+    /// A value supplied to the operation was invalid.
+    InvalidArgument(String),
+
+    /// An environment failed to reset or step.
+    Environment(String),
+
+    /// Data could not be serialized, deserialized, or written.
+    Serialization(String),
+
+    /// A q-store could not read or write its data.
+    Storage(String),
+
+    /// Any other failure.
+    Other(String),
 }
 
-impl<'a> LearnerError {
-    /// Instantiates a new `LearnerError` with a message.
+impl LearnerError {
+    /// Instantiates a new `LearnerError::Other` with a message.
     pub fn new(msg: String) -> Self {
-        Self { msg }
+        Self::Other(msg)
     }
 
-    /// A message associated with this error.
+    /// A message associated with this error. This is the same as the error's
+    /// `Display` representation.
     pub fn message(&self) -> String {
-        self.msg.clone()
+        self.to_string()
+    }
+}
+
+impl fmt::Display for LearnerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ActionNotCompatible { state, action } => {
+                write!(f, "action {action} is not compatible with state {state}")
+            }
+            Self::NoPossibleActions { state } => {
+                write!(f, "state {state} reports no possible actions")
+            }
+            Self::ActionNotFound { reason, .. } => f.write_str(reason),
+            Self::InvalidTieBreak { index, candidates } => write!(
+                f,
+                "tie breaker chose action {index}, but only {candidates} actions were tied"
+            ),
+            Self::NonFinite(msg)
+            | Self::Lifecycle(msg)
+            | Self::InvalidArgument(msg)
+            | Self::Environment(msg)
+            | Self::Serialization(msg)
+            | Self::Storage(msg)
+            | Self::Other(msg) => f.write_str(msg),
+        }
+    }
+}
+
+impl Error for LearnerError {}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_and_error() {
+        let err = LearnerError::new("something went wrong".to_string());
+        assert_eq!("something went wrong", err.to_string());
+        assert_eq!(err.message(), err.to_string());
+
+        let boxed: Box<dyn Error> = Box::new(err);
+        assert!(boxed.source().is_none());
+        assert_eq!("something went wrong", boxed.to_string());
+    }
+
+    #[test]
+    fn structured_messages() {
+        let err = LearnerError::ActionNotCompatible {
+            state: "\"A\"".to_string(),
+            action: "\"X\"".to_string(),
+        };
+        assert_eq!(
+            "action \"X\" is not compatible with state \"A\"",
+            err.message()
+        );
+
+        let err = LearnerError::NoPossibleActions {
+            state: "3".to_string(),
+        };
+        assert_eq!("state 3 reports no possible actions", err.message());
     }
 }
@@ -0,0 +1,170 @@
+//! Exports q-values as comma separated values.
+
+use crate::agents::bayesian::AgentContext;
+use crate::errors::LearnerError;
+use crate::stats::ActionStatter;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::io::Write;
+
+/// The header row written by `write_q_values`.
+const HEADER: &str = "state_id,action_id,calls,q_raw,q_weighted";
+
+/// Writes the q-values of an `AgentContext` to `writer` as CSV.
+///
+/// The output begins with a header row, followed by one row per state-action
+/// pair with the columns `state_id`, `action_id`, `calls`, `q_raw`, and
+/// `q_weighted`. Rows are sorted by state ID, then action ID, so the output
+/// for a given context is deterministic. IDs are written using their `Display`
+/// implementation, and IDs that contain commas, quotes, or line breaks are
+/// quoted as described in RFC 4180.
+pub fn write_q_values<W, SK, AK, AS>(
+    writer: W,
+    context: &AgentContext<SK, AK, AS>,
+) -> Result<(), LearnerError>
+where
+    W: Write,
+    SK: Hash + Ord + Display,
+    AK: Hash + Ord + Display,
+    AS: ActionStatter,
+{
+    write_rows(
+        writer,
+        context.q_values.iter().flat_map(|(state, actions)| {
+            actions
+                .iter()
+                .map(move |(action, stats)| (state, action, stats, stats.q_value_weighted()))
+        }),
+    )
+}
+
+/// Writes q-values supplied by an iterator (such as
+/// `Agent::iter_weighted_q_values`) to `writer` as CSV, without first cloning
+/// them into an `AgentContext`.
+///
+/// Each row holds a state ID, action ID, the action's stats, and its weighted
+/// q-value, which is written in the `q_weighted` column in place of the
+/// (possibly stale) weighted q-value recorded in the stats.
+pub fn write_rows<'r, W, I, SK, AK, AS>(mut writer: W, q_values: I) -> Result<(), LearnerError>
+where
+    W: Write,
+    I: IntoIterator<Item = (&'r SK, &'r AK, &'r AS, f64)>,
+    SK: Ord + Display + 'r,
+    AK: Ord + Display + 'r,
+    AS: ActionStatter + 'r,
+{
+    let mut rows: Vec<(&SK, &AK, &AS, f64)> = q_values.into_iter().collect();
+    rows.sort_by(|x, y| (x.0, x.1).cmp(&(y.0, y.1)));
+
+    let write_err = |e| LearnerError::Serialization(format!("unable to write csv: {e}"));
+    writeln!(writer, "{HEADER}").map_err(write_err)?;
+    for (state, action, stats, q_weighted) in rows {
+        writeln!(
+            writer,
+            "{},{},{},{},{}",
+            escape(&state.to_string()),
+            escape(&action.to_string()),
+            stats.calls(),
+            stats.q_value_raw(),
+            q_weighted
+        )
+        .map_err(write_err)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn escape(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::panic)]
+mod tests {
+    use crate::agents::bayesian::{Agent, AgentContext};
+    use crate::agents::Agenter;
+    use crate::export::csv;
+    use crate::mocks::{MockActioner, MockStater};
+    use crate::stats::actionstats::Stats;
+    use maplit::hashmap;
+
+    #[test]
+    fn write_q_values() {
+        let context = AgentContext {
+            learning_rate: 1.0,
+            discount_factor: 0.0,
+            priming_threshold: 10,
+            q_values: hashmap! {
+                "B".to_string() => hashmap! {
+                    "X".to_string() => Stats {call_count: 0, q_raw: 0.0, q_weighted: 0.25},
+                },
+                "A".to_string() => hashmap! {
+                    "Y, \"quoted\"".to_string() => Stats {call_count: 1, q_raw: -1.5, q_weighted: 0.5},
+                    "X".to_string() => Stats {call_count: 2, q_raw: 1.0, q_weighted: 0.75},
+                },
+            },
+            state_visits: hashmap! {},
+        };
+
+        let mut output = Vec::new();
+        csv::write_q_values(&mut output, &context).unwrap();
+
+        let expected = "state_id,action_id,calls,q_raw,q_weighted\n\
+                        A,X,2,1,0.75\n\
+                        A,\"Y, \"\"quoted\"\"\",1,-1.5,0.5\n\
+                        B,X,0,0,0.25\n";
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn write_rows() {
+        let stats = Stats {
+            call_count: 1,
+            q_raw: 2.0,
+            q_weighted: 3.0,
+        };
+        let rows = vec![(&10_u32, &7_u8, &stats, 4.0), (&2_u32, &3_u8, &stats, 5.0)];
+
+        let mut output = Vec::new();
+        csv::write_rows(&mut output, rows).unwrap();
+
+        let expected = "state_id,action_id,calls,q_raw,q_weighted\n\
+                        2,3,1,2,5\n\
+                        10,7,1,2,4\n";
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn write_rows_matches_agent_context() {
+        let action_x = MockActioner { return_id: "X" };
+        let action_y = MockActioner { return_id: "Y" };
+        let state = |return_id| MockStater {
+            return_id,
+            return_possible_actions: vec![&action_x, &action_y],
+            ..Default::default()
+        };
+        let (state_a, state_b) = (state("A"), state("B"));
+        let mut agent: Agent<MockStater<MockActioner>, MockActioner, Stats> =
+            Agent::new(1, 1.0, 0.0);
+        // The second update leaves the weighted q-value cached for Y stale.
+        agent
+            .learn(Some(&state_a), &action_x, &state_b, 1.0)
+            .unwrap();
+        agent
+            .learn(Some(&state_a), &action_x, &state_b, 3.0)
+            .unwrap();
+
+        let mut from_rows = Vec::new();
+        csv::write_rows(&mut from_rows, agent.iter_weighted_q_values()).unwrap();
+        let mut from_context = Vec::new();
+        csv::write_q_values(&mut from_context, &agent.get_agent_context()).unwrap();
+
+        assert_eq!(
+            String::from_utf8(from_context).unwrap(),
+            String::from_utf8(from_rows).unwrap()
+        );
+    }
+}
@@ -0,0 +1,134 @@
+//! Exports q-values as a Graphviz DOT graph, for visualizing small learned
+//! policies.
+
+use crate::agents::bayesian::AgentContext;
+use crate::errors::LearnerError;
+use crate::stats::ActionStatter;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::io::Write;
+
+/// Writes the q-values of an `AgentContext` to `writer` as a DOT graph.
+///
+/// Each state is drawn as an ellipse, with an edge to a box for each of its
+/// actions. Edges are labeled with the action's weighted q-value, and the
+/// edge to the greedy action of each state (the action with the highest
+/// weighted q-value, with ties broken in favor of the lowest ID) is drawn in
+/// bold, while the others are dashed. States and actions are written in
+/// sorted order, so the output for a given context is deterministic. IDs are
+/// labeled using their `Display` implementation.
+///
+/// The output can be rendered with, for instance, `dot -Tsvg`.
+pub fn write_q_values<W, SK, AK, AS>(
+    writer: W,
+    context: &AgentContext<SK, AK, AS>,
+) -> Result<(), LearnerError>
+where
+    W: Write,
+    SK: Hash + Ord + Display,
+    AK: Hash + Ord + Display,
+    AS: ActionStatter,
+{
+    write_rows(
+        writer,
+        context.q_values.iter().flat_map(|(state, actions)| {
+            actions
+                .iter()
+                .map(move |(action, stats)| (state, action, stats, stats.q_value_weighted()))
+        }),
+    )
+}
+
+/// Writes q-values supplied by an iterator (such as
+/// `Agent::iter_weighted_q_values`) to `writer` as DOT, without first cloning
+/// them into an `AgentContext`.
+///
+/// Each row holds a state ID, action ID, the action's stats, and its weighted
+/// q-value. Edges are labeled, and greedy actions chosen, by the weighted
+/// q-value of each row rather than the one recorded in the stats.
+pub fn write_rows<'r, W, I, SK, AK, AS>(mut writer: W, q_values: I) -> Result<(), LearnerError>
+where
+    W: Write,
+    I: IntoIterator<Item = (&'r SK, &'r AK, &'r AS, f64)>,
+    SK: Ord + Display + 'r,
+    AK: Ord + Display + 'r,
+    AS: ActionStatter + 'r,
+{
+    let mut rows: Vec<(&SK, &AK, &AS, f64)> = q_values.into_iter().collect();
+    rows.sort_by(|x, y| (x.0, x.1).cmp(&(y.0, y.1)));
+
+    let write_err = |e| LearnerError::Serialization(format!("unable to write dot: {e}"));
+    writeln!(writer, "digraph q_values {{").map_err(write_err)?;
+    for (s, state_rows) in rows.chunk_by(|x, y| x.0 == y.0).enumerate() {
+        let state = state_rows[0].0;
+        writeln!(writer, "    s{s} [label={}];", quote(state)).map_err(write_err)?;
+        let greedy = state_rows.iter().enumerate().fold(0, |best, (i, row)| {
+            if row.3 > state_rows[best].3 {
+                i
+            } else {
+                best
+            }
+        });
+        for (a, (_, action, _, q_weighted)) in state_rows.iter().enumerate() {
+            let style = if a == greedy { "bold" } else { "dashed" };
+            writeln!(
+                writer,
+                "    s{s}a{a} [label={}, shape=box];\n    s{s} -> s{s}a{a} [label=\"{}\", style={style}];",
+                quote(action),
+                q_weighted
+            )
+            .map_err(write_err)?;
+        }
+    }
+    writeln!(writer, "}}").map_err(write_err)
+}
+
+fn quote(id: &impl Display) -> String {
+    format!(
+        "\"{}\"",
+        id.to_string().replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+#[cfg(test)]
+#[allow(clippy::panic)]
+mod tests {
+    use crate::agents::bayesian::AgentContext;
+    use crate::export::dot;
+    use crate::stats::actionstats::Stats;
+    use maplit::hashmap;
+
+    #[test]
+    fn write_q_values() {
+        let context = AgentContext {
+            learning_rate: 1.0,
+            discount_factor: 0.0,
+            priming_threshold: 10,
+            q_values: hashmap! {
+                "B".to_string() => hashmap! {
+                    "X".to_string() => Stats {call_count: 0, q_raw: 0.0, q_weighted: 0.25},
+                },
+                "A".to_string() => hashmap! {
+                    "Y \"quoted\"".to_string() => Stats {call_count: 1, q_raw: -1.5, q_weighted: 0.5},
+                    "X".to_string() => Stats {call_count: 2, q_raw: 1.0, q_weighted: 0.75},
+                },
+            },
+            state_visits: hashmap! {},
+        };
+
+        let mut output = Vec::new();
+        dot::write_q_values(&mut output, &context).unwrap();
+
+        let expected = "digraph q_values {\n    \
+                        s0 [label=\"A\"];\n    \
+                        s0a0 [label=\"X\", shape=box];\n    \
+                        s0 -> s0a0 [label=\"0.75\", style=bold];\n    \
+                        s0a1 [label=\"Y \\\"quoted\\\"\", shape=box];\n    \
+                        s0 -> s0a1 [label=\"0.5\", style=dashed];\n    \
+                        s1 [label=\"B\"];\n    \
+                        s1a0 [label=\"X\", shape=box];\n    \
+                        s1 -> s1a0 [label=\"0.25\", style=bold];\n\
+                        }\n";
+        assert_eq!(expected, String::from_utf8(output).unwrap());
+    }
+}
@@ -0,0 +1,205 @@
+//! Reads and writes agent contexts in the format used by the Go
+//! reinforcement-learning library that this crate is modelled on.
+//!
+//! The Go library's bayesian agent exports its context with
+//! `encoding/json`, so the format is a JSON object whose field names are
+//! those of the Go structs:
+//!
+//! ```json
+//! {
+//!   "LearningRate": 0.5,
+//!   "DiscountFactor": 0.9,
+//!   "PrimingThreshold": 1,
+//!   "QValues": {
+//!     "state": {
+//!       "action": {"CallCount": 2, "QRaw": 1.5, "QWeighted": 0.75}
+//!     }
+//!   }
+//! }
+//! ```
+//!
+//! State and action IDs are strings in the Go library, so they are written
+//! using their `Display` implementation and read back using `FromStr`. The
+//! Go format has no record of state visits, so visits are dropped when a
+//! context is written, and are empty when one is read.
+
+use crate::agents::bayesian::AgentContext;
+use crate::errors::LearnerError;
+use crate::stats::ActionStatter;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::hash::Hash;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+/// The Go library's `AgentContext`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GoAgentContext {
+    learning_rate: f64,
+    discount_factor: f64,
+    priming_threshold: i32,
+    #[serde(default)]
+    q_values: BTreeMap<String, BTreeMap<String, GoActionStats>>,
+}
+
+/// The Go library's `ActionStats`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct GoActionStats {
+    call_count: u64,
+    q_raw: f64,
+    q_weighted: f64,
+}
+
+/// Writes an `AgentContext` to `writer` in the Go library's format. Keys are
+/// sorted, as they are by Go's `encoding/json`.
+pub fn write_context<W, SK, AK, AS>(
+    writer: W,
+    context: &AgentContext<SK, AK, AS>,
+) -> Result<(), LearnerError>
+where
+    W: Write,
+    SK: Hash + Eq + Display,
+    AK: Hash + Eq + Display,
+    AS: ActionStatter,
+{
+    let q_values = context
+        .q_values
+        .iter()
+        .map(|(state_id, actions)| {
+            let actions = actions
+                .iter()
+                .map(|(action_id, stats)| {
+                    let stats = GoActionStats {
+                        call_count: stats.calls(),
+                        q_raw: stats.q_value_raw(),
+                        q_weighted: stats.q_value_weighted(),
+                    };
+                    (action_id.to_string(), stats)
+                })
+                .collect();
+            (state_id.to_string(), actions)
+        })
+        .collect();
+    let go_context = GoAgentContext {
+        learning_rate: context.learning_rate,
+        discount_factor: context.discount_factor,
+        priming_threshold: context.priming_threshold,
+        q_values,
+    };
+    serde_json::to_writer(writer, &go_context)
+        .map_err(|e| LearnerError::Serialization(format!("unable to write go context: {e}")))
+}
+
+/// Reads an `AgentContext` written in the Go library's format.
+/// An error is returned if the input is not a valid context, or if a state
+/// or action ID cannot be parsed.
+pub fn read_context<R, SK, AK, AS>(reader: R) -> Result<AgentContext<SK, AK, AS>, LearnerError>
+where
+    R: Read,
+    SK: Hash + Eq + FromStr,
+    SK::Err: Display,
+    AK: Hash + Eq + FromStr,
+    AK::Err: Display,
+    AS: ActionStatter,
+{
+    let go_context: GoAgentContext = serde_json::from_reader(reader)
+        .map_err(|e| LearnerError::Serialization(format!("unable to read go context: {e}")))?;
+    let mut q_values: HashMap<SK, HashMap<AK, AS>> = HashMap::new();
+    for (state_id, go_actions) in go_context.q_values {
+        let actions = q_values.entry(parse(&state_id)?).or_default();
+        for (action_id, go_stats) in go_actions {
+            let mut stats = AS::default();
+            stats.set_calls(go_stats.call_count);
+            stats.set_q_value_raw(go_stats.q_raw);
+            stats.set_q_value_weighted(go_stats.q_weighted);
+            actions.insert(parse(&action_id)?, stats);
+        }
+    }
+    Ok(AgentContext {
+        learning_rate: go_context.learning_rate,
+        discount_factor: go_context.discount_factor,
+        priming_threshold: go_context.priming_threshold,
+        q_values,
+        state_visits: HashMap::new(),
+    })
+}
+
+fn parse<T>(id: &str) -> Result<T, LearnerError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    id.parse()
+        .map_err(|e| LearnerError::Serialization(format!("unable to parse ID {id:?}: {e}")))
+}
+
+#[cfg(test)]
+#[allow(clippy::panic)]
+mod tests {
+    use crate::agents::bayesian::AgentContext;
+    use crate::errors::LearnerError;
+    use crate::export::go;
+    use crate::stats::actionstats::Stats;
+    use maplit::hashmap;
+
+    #[test]
+    fn write_context() {
+        let context = AgentContext {
+            learning_rate: 0.5,
+            discount_factor: 0.9,
+            priming_threshold: 1,
+            q_values: hashmap! {
+                "B".to_string() => hashmap! {
+                    "X".to_string() => Stats {call_count: 0, q_raw: 0.0, q_weighted: 0.25},
+                },
+                "A".to_string() => hashmap! {
+                    "Y".to_string() => Stats {call_count: 1, q_raw: -1.5, q_weighted: 0.5},
+                    "X".to_string() => Stats {call_count: 2, q_raw: 1.0, q_weighted: 0.75},
+                },
+            },
+            state_visits: hashmap! { "A".to_string() => 3 },
+        };
+
+        let mut output = Vec::new();
+        go::write_context(&mut output, &context).unwrap();
+        let expected =
+            r#"{"LearningRate":0.5,"DiscountFactor":0.9,"PrimingThreshold":1,"QValues":{"#
+                .to_string()
+                + r#""A":{"X":{"CallCount":2,"QRaw":1.0,"QWeighted":0.75},"#
+                + r#""Y":{"CallCount":1,"QRaw":-1.5,"QWeighted":0.5}},"#
+                + r#""B":{"X":{"CallCount":0,"QRaw":0.0,"QWeighted":0.25}}}}"#;
+        assert_eq!(expected, String::from_utf8(output.clone()).unwrap());
+
+        let restored: AgentContext<String, String, Stats> =
+            go::read_context(output.as_slice()).unwrap();
+        assert_eq!(context.q_values, restored.q_values);
+        assert!(restored.state_visits.is_empty());
+    }
+
+    #[test]
+    fn read_context() {
+        let input = r#"{
+            "LearningRate": 1,
+            "DiscountFactor": 0,
+            "PrimingThreshold": 10,
+            "QValues": {"1": {"2": {"CallCount": 3, "QRaw": 0.5, "QWeighted": 0.25}}}
+        }"#;
+        let context: AgentContext<u32, u8, Stats> = go::read_context(input.as_bytes()).unwrap();
+        assert_eq!(10, context.priming_threshold);
+        assert_eq!(
+            Stats {
+                call_count: 3,
+                q_raw: 0.5,
+                q_weighted: 0.25
+            },
+            context.q_values[&1][&2]
+        );
+
+        let result =
+            go::read_context::<_, u32, u8, Stats>(input.replace("\"1\"", "\"x\"").as_bytes());
+        assert!(matches!(result, Err(LearnerError::Serialization(_))));
+    }
+}
@@ -0,0 +1,270 @@
+//! Exports state values and greedy actions as 2D grids, for rendering
+//! heatmaps of policies learned over grid-like state spaces.
+//!
+//! The caller supplies a function that maps each state ID to the `(x, y)`
+//! cell it occupies. Each cell of the resulting `Heatmap` holds the value of
+//! its state (the highest weighted q-value of the state's actions) and the
+//! state's greedy action, or `None` if no state with recorded q-values maps
+//! to the cell.
+//!
+//! ```
+//! use rlr::agents::bayesian::AgentContext;
+//! use rlr::export::heatmap;
+//! use rlr::stats::actionstats::Stats;
+//! use rlr::stats::ActionStatter;
+//! use std::collections::HashMap;
+//!
+//! let stats = |q| {
+//!     let mut stats = Stats::default();
+//!     stats.set_q_value_weighted(q);
+//!     stats
+//! };
+//! let context = AgentContext {
+//!     learning_rate: 1.0,
+//!     discount_factor: 0.9,
+//!     priming_threshold: 0,
+//!     q_values: HashMap::from([
+//!         (0, HashMap::from([("right", stats(0.5)), ("down", stats(0.25))])),
+//!         (3, HashMap::from([("right", stats(1.0))])),
+//!     ]),
+//!     state_visits: HashMap::new(),
+//! };
+//!
+//! // States are numbered row by row across a 2x2 grid.
+//! let map = heatmap::from_context(&context, 2, 2, |id: &usize| Some((id % 2, id / 2))).unwrap();
+//! assert_eq!(Some(0.5), map.value(0, 0));
+//! assert_eq!(Some(&"right"), map.action(1, 1));
+//! assert_eq!(None, map.value(1, 0));
+//! ```
+
+use crate::agents::bayesian::AgentContext;
+use crate::errors::LearnerError;
+use crate::stats::ActionStatter;
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::io::Write;
+
+/// A grid of state values and greedy actions. Cells are indexed by `(x, y)`,
+/// and the rows returned by `values` and `actions` are ordered by `y`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Heatmap<AK> {
+    width: usize,
+    height: usize,
+    values: Vec<Vec<Option<f64>>>,
+    actions: Vec<Vec<Option<AK>>>,
+}
+
+impl<AK> Heatmap<AK> {
+    /// Returns the number of columns in the grid.
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Returns the number of rows in the grid.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Returns the value of the state at `(x, y)`, or `None` if no state
+    /// occupies the cell or the cell is outside the grid.
+    pub fn value(&self, x: usize, y: usize) -> Option<f64> {
+        self.values.get(y)?.get(x).copied().flatten()
+    }
+
+    /// Returns the greedy action of the state at `(x, y)`, or `None` if no
+    /// state occupies the cell or the cell is outside the grid.
+    pub fn action(&self, x: usize, y: usize) -> Option<&AK> {
+        self.actions.get(y)?.get(x)?.as_ref()
+    }
+
+    /// Returns the value of every cell, as `height` rows of `width` cells.
+    pub fn values(&self) -> &[Vec<Option<f64>>] {
+        &self.values
+    }
+
+    /// Returns the greedy action of every cell, as `height` rows of `width`
+    /// cells.
+    pub fn actions(&self) -> &[Vec<Option<AK>>] {
+        &self.actions
+    }
+
+    /// Writes the grid of values to `writer` as CSV, one row of the grid per
+    /// line, with no header. Empty cells are written as empty fields, which
+    /// most plotting tools read as missing values.
+    pub fn write_values<W: Write>(&self, mut writer: W) -> Result<(), LearnerError> {
+        let write_err = |e| LearnerError::Serialization(format!("unable to write heatmap: {e}"));
+        for row in &self.values {
+            let fields: Vec<String> = row
+                .iter()
+                .map(|value| value.map(|value| value.to_string()).unwrap_or_default())
+                .collect();
+            writeln!(writer, "{}", fields.join(",")).map_err(write_err)?;
+        }
+        Ok(())
+    }
+}
+
+/// Builds a `Heatmap` of the q-values of an `AgentContext`, placing each
+/// state at the cell returned by `position`. See `from_rows`.
+pub fn from_context<SK, AK, AS, F>(
+    context: &AgentContext<SK, AK, AS>,
+    width: usize,
+    height: usize,
+    position: F,
+) -> Result<Heatmap<AK>, LearnerError>
+where
+    SK: Hash + Eq + Debug,
+    AK: Hash + Ord + Clone,
+    AS: ActionStatter,
+    F: FnMut(&SK) -> Option<(usize, usize)>,
+{
+    from_rows(
+        context.q_values.iter().flat_map(|(state, actions)| {
+            actions
+                .iter()
+                .map(move |(action, stats)| (state, action, stats, stats.q_value_weighted()))
+        }),
+        width,
+        height,
+        position,
+    )
+}
+
+/// Builds a `Heatmap` from q-values supplied by an iterator, without first
+/// cloning them into an `AgentContext`.
+///
+/// Each row holds a state ID, action ID, the action's stats, and its
+/// weighted q-value, as yielded by `Agent::iter_weighted_q_values`.
+///
+/// Each state is placed at the cell returned by `position`. States for which
+/// `position` returns `None` are left out. A state's greedy action is the
+/// action with the highest weighted q-value, as given by the rows rather than
+/// the stats, with ties broken in favor of the lowest ID. An error is returned if a state is placed outside the grid, or
+/// in a cell that another state already occupies.
+pub fn from_rows<'r, I, SK, AK, AS, F>(
+    q_values: I,
+    width: usize,
+    height: usize,
+    mut position: F,
+) -> Result<Heatmap<AK>, LearnerError>
+where
+    I: IntoIterator<Item = (&'r SK, &'r AK, &'r AS, f64)>,
+    SK: Hash + Eq + Debug + 'r,
+    AK: Ord + Clone + 'r,
+    AS: ActionStatter + 'r,
+    F: FnMut(&SK) -> Option<(usize, usize)>,
+{
+    let mut values = vec![vec![None; width]; height];
+    let mut actions = vec![vec![None; width]; height];
+    let mut occupants: Vec<Vec<Option<&SK>>> = vec![vec![None; width]; height];
+    for (state, action, _, q) in q_values {
+        let Some((x, y)) = position(state) else {
+            continue;
+        };
+        let Some(occupant) = occupants.get_mut(y).and_then(|row| row.get_mut(x)) else {
+            return Err(LearnerError::InvalidArgument(format!(
+                "state {state:?} is placed at ({x}, {y}), outside a {width}x{height} grid"
+            )));
+        };
+        match occupant {
+            Some(other) if *other != state => {
+                return Err(LearnerError::InvalidArgument(format!(
+                    "states {other:?} and {state:?} are both placed at ({x}, {y})"
+                )));
+            }
+            _ => *occupant = Some(state),
+        }
+
+        let value = &mut values[y][x];
+        let greedy = &mut actions[y][x];
+        let is_better = match (*value, greedy.as_ref()) {
+            (Some(best), Some(best_action)) => q > best || (q == best && action < best_action),
+            _ => true,
+        };
+        if is_better {
+            *value = Some(q);
+            *greedy = Some(action.clone());
+        }
+    }
+    Ok(Heatmap {
+        width,
+        height,
+        values,
+        actions,
+    })
+}
+
+#[cfg(test)]
+#[allow(clippy::panic)]
+mod tests {
+    use crate::agents::bayesian::AgentContext;
+    use crate::errors::LearnerError;
+    use crate::export::heatmap;
+    use crate::stats::actionstats::Stats;
+    use maplit::hashmap;
+
+    fn stats(q: f64) -> Stats {
+        Stats {
+            call_count: 1,
+            q_raw: q,
+            q_weighted: q,
+        }
+    }
+
+    fn context() -> AgentContext<(usize, usize), &'static str, Stats> {
+        AgentContext {
+            learning_rate: 1.0,
+            discount_factor: 0.0,
+            priming_threshold: 0,
+            q_values: hashmap! {
+                (0, 0) => hashmap! { "R" => stats(0.5), "D" => stats(0.5), "L" => stats(-1.0) },
+                (2, 0) => hashmap! { "D" => stats(1.0) },
+                (1, 1) => hashmap! { "L" => stats(-0.25), "U" => stats(0.0) },
+            },
+            state_visits: hashmap! {},
+        }
+    }
+
+    #[test]
+    fn from_context() {
+        let map = heatmap::from_context(&context(), 3, 2, |id| Some(*id)).unwrap();
+        assert_eq!(3, map.width());
+        assert_eq!(2, map.height());
+        assert_eq!(
+            vec![
+                vec![Some(0.5), None, Some(1.0)],
+                vec![None, Some(0.0), None]
+            ],
+            map.values()
+        );
+        assert_eq!(
+            vec![
+                vec![Some("D"), None, Some("D")],
+                vec![None, Some("U"), None]
+            ],
+            map.actions()
+        );
+        assert_eq!(None, map.value(3, 0));
+        assert_eq!(None, map.action(0, 2));
+
+        let mut output = Vec::new();
+        map.write_values(&mut output).unwrap();
+        assert_eq!("0.5,,1\n,0,\n", String::from_utf8(output).unwrap());
+    }
+
+    #[test]
+    fn skips_unplaced_states() {
+        let map =
+            heatmap::from_context(&context(), 3, 1, |&(x, y)| (y == 0).then_some((x, y))).unwrap();
+        assert_eq!(vec![vec![Some(0.5), None, Some(1.0)]], map.values());
+    }
+
+    #[test]
+    fn rejects_invalid_positions() {
+        let outside = heatmap::from_context(&context(), 2, 2, |id| Some(*id));
+        assert!(matches!(outside, Err(LearnerError::InvalidArgument(_))));
+
+        let shared = heatmap::from_context(&context(), 3, 2, |_| Some((0, 0)));
+        assert!(matches!(shared, Err(LearnerError::InvalidArgument(_))));
+    }
+}
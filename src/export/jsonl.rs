@@ -0,0 +1,254 @@
+//! Records the transitions an agent learns from as JSON lines, so that runs
+//! can be audited, replayed, or used for off-policy training later.
+//!
+//! Each line of a log is a `TransitionEntry` with the fields `state`,
+//! `action`, `next_state`, `reward`, `q_before`, and `q_after`. State and
+//! action IDs are written using their `Serialize` implementations, so they
+//! can be read back as the same types with `read_transitions`.
+//!
+//! A `TransitionLog` is usually fed from the agent's `on_learn` hook:
+//!
+//! ```ignore
+//! let log = RefCell::new(TransitionLog::new(File::create("transitions.jsonl")?));
+//! let mut agent = Agent::new(1, 0.5, 0.9).on_learn(|e| log.borrow_mut().record(e));
+//! // ...train the agent...
+//! drop(agent);
+//! log.into_inner().into_inner()?;
+//! ```
+
+use crate::actions::Actioner;
+use crate::agents::bayesian::LearnEvent;
+use crate::errors::LearnerError;
+use crate::states::Stater;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io::{BufRead, Write};
+
+/// A transition that an agent has learned from, keyed by the IDs of its
+/// states and action.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransitionEntry<SK, AK> {
+    /// The ID of the state that was transitioned from.
+    pub state: SK,
+
+    /// The ID of the action that was taken.
+    pub action: AK,
+
+    /// The ID of the state that was transitioned to.
+    pub next_state: SK,
+
+    /// The reward passed to `learn`, before any shaping.
+    pub reward: f64,
+
+    /// The raw q-value of the state-action pair before the update.
+    pub q_before: f64,
+
+    /// The raw q-value of the state-action pair after the update.
+    pub q_after: f64,
+}
+
+impl<SK, AK> TransitionEntry<SK, AK> {
+    /// Returns an entry for the transition described by `event`.
+    pub fn from_event<'a, S, A>(event: &LearnEvent<'_, S, A>) -> Self
+    where
+        S: Stater<'a, A, Id = SK>,
+        A: Actioner<'a, Id = AK>,
+    {
+        Self {
+            state: event.previous_state.id(),
+            action: event.action.id(),
+            next_state: event.current_state.id(),
+            reward: event.reward,
+            q_before: event.old_q,
+            q_after: event.new_q,
+        }
+    }
+}
+
+/// Appends a JSON line to a writer for every transition it records.
+///
+/// `record` does not return errors, so that it can be called from an
+/// `on_learn` hook. Instead, the first error is kept, nothing more is
+/// written, and the error is returned by `into_inner`.
+#[derive(Debug)]
+pub struct TransitionLog<W> {
+    writer: W,
+    recorded: u64,
+    error: Option<LearnerError>,
+}
+
+impl<W: Write> TransitionLog<W> {
+    /// Returns a log that appends to `writer`.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            recorded: 0,
+            error: None,
+        }
+    }
+
+    /// Appends the transition described by `event` to the log.
+    pub fn record<'a, S, A>(&mut self, event: &LearnEvent<'_, S, A>)
+    where
+        S: Stater<'a, A>,
+        A: Actioner<'a>,
+        S::Id: Serialize,
+        A::Id: Serialize,
+    {
+        self.append(&TransitionEntry::from_event(event));
+    }
+
+    /// Appends `entry` to the log.
+    pub fn append<SK, AK>(&mut self, entry: &TransitionEntry<SK, AK>)
+    where
+        SK: Serialize,
+        AK: Serialize,
+    {
+        if self.error.is_some() {
+            return;
+        }
+        let result = serde_json::to_writer(&mut self.writer, entry)
+            .map_err(write_err)
+            .and_then(|()| writeln!(self.writer).map_err(write_err));
+        match result {
+            Ok(()) => self.recorded = self.recorded.saturating_add(1),
+            Err(e) => self.error = Some(e),
+        }
+    }
+
+    /// Returns the number of transitions that have been written to the log.
+    pub fn recorded(&self) -> u64 {
+        self.recorded
+    }
+
+    /// Flushes and returns the underlying writer, or returns the first error
+    /// that occurred while writing.
+    pub fn into_inner(mut self) -> Result<W, LearnerError> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        self.writer.flush().map_err(write_err)?;
+        Ok(self.writer)
+    }
+}
+
+/// Returns an iterator over the entries of a log written by a
+/// `TransitionLog`. Blank lines are skipped, and lines that cannot be read or
+/// parsed are returned as errors.
+pub fn read_transitions<R, SK, AK>(
+    reader: R,
+) -> impl Iterator<Item = Result<TransitionEntry<SK, AK>, LearnerError>>
+where
+    R: BufRead,
+    SK: DeserializeOwned,
+    AK: DeserializeOwned,
+{
+    reader
+        .lines()
+        .filter(|line| line.as_ref().map_or(true, |line| !line.trim().is_empty()))
+        .map(|line| {
+            let line = line.map_err(read_err)?;
+            serde_json::from_str(&line).map_err(read_err)
+        })
+}
+
+fn write_err(e: impl fmt::Display) -> LearnerError {
+    LearnerError::Serialization(format!("unable to write transition: {e}"))
+}
+
+fn read_err(e: impl fmt::Display) -> LearnerError {
+    LearnerError::Serialization(format!("unable to read transition: {e}"))
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::agents::bayesian::Agent;
+    use crate::agents::Agenter;
+    use crate::mocks::*;
+    use crate::stats::actionstats::Stats;
+    use std::cell::RefCell;
+    use std::io;
+
+    #[test]
+    fn records_and_reads_transitions() {
+        let moves = MockCorridor::moves();
+        let cell = |position| MockCell {
+            position,
+            moves: &moves,
+        };
+        let log = RefCell::new(TransitionLog::new(Vec::new()));
+        let mut agent: Agent<MockCell, MockActioner, Stats> =
+            Agent::new(0, 0.5, 0.0).on_learn(|e| log.borrow_mut().record(e));
+        agent
+            .learn(Some(&cell(0)), &moves[1], &cell(1), 2.0)
+            .unwrap();
+        agent
+            .learn(Some(&cell(1)), &moves[0], &cell(0), -1.0)
+            .unwrap();
+        drop(agent);
+
+        let log = log.into_inner();
+        assert_eq!(2, log.recorded());
+        let output = log.into_inner().unwrap();
+        let text = String::from_utf8(output.clone()).unwrap();
+        assert_eq!(
+            "{\"state\":0,\"action\":\"R\",\"next_state\":1,\"reward\":2.0,\"q_before\":0.0,\"q_after\":1.0}\n",
+            text.lines().next().unwrap().to_string() + "\n"
+        );
+
+        let entries: Vec<TransitionEntry<usize, String>> = read_transitions(&output[..])
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            TransitionEntry {
+                state: 1,
+                action: "L".to_string(),
+                next_state: 0,
+                reward: -1.0,
+                q_before: 0.0,
+                q_after: -0.5,
+            },
+            entries[1]
+        );
+
+        let invalid = read_transitions::<_, usize, String>(&b"\n{\"state\":0}\n"[..]);
+        let results: Vec<_> = invalid.collect();
+        assert_eq!(1, results.len());
+        assert!(matches!(results[0], Err(LearnerError::Serialization(_))));
+    }
+
+    struct Broken;
+
+    impl Write for Broken {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("broken"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn keeps_the_first_error() {
+        let mut log = TransitionLog::new(Broken);
+        let entry = TransitionEntry {
+            state: 0,
+            action: 1,
+            next_state: 2,
+            reward: 0.0,
+            q_before: 0.0,
+            q_after: 0.0,
+        };
+        log.append(&entry);
+        log.append(&entry);
+        assert_eq!(0, log.recorded());
+        assert!(matches!(
+            log.into_inner(),
+            Err(LearnerError::Serialization(_))
+        ));
+    }
+}
@@ -0,0 +1,14 @@
+//! Exporters that write an agent's learned values to external formats, for
+//! analysis or for use by other programs.
+
+pub mod csv;
+pub mod dot;
+#[cfg(feature = "go")]
+pub mod go;
+pub mod heatmap;
+#[cfg(feature = "jsonl")]
+pub mod jsonl;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
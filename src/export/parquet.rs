@@ -0,0 +1,326 @@
+//! Exports q-values and recorded transitions as Apache Parquet files, for
+//! offline analysis of large runs with tools such as Spark, `DuckDB`, or
+//! pandas.
+//!
+//! Q-values are written with the same columns as the CSV exporter:
+//!
+//! ```text
+//! state_id: string, action_id: string, calls: int64,
+//! q_raw: double, q_weighted: double
+//! ```
+//!
+//! Transitions are written one row per `TransitionRecord`, with the columns:
+//!
+//! ```text
+//! previous_state_id: string, action_id: string, current_state_id: string,
+//! reward: double, old_q: double, new_q: double
+//! ```
+//!
+//! All columns are required, IDs are written using their `Display`
+//! implementation, and each file holds a single, uncompressed row group.
+
+use crate::actions::Actioner;
+use crate::agents::bayesian::{AgentContext, LearnEvent};
+use crate::errors::LearnerError;
+use crate::states::Stater;
+use crate::stats::ActionStatter;
+use parquet::data_type::{ByteArray, ByteArrayType, DoubleType, Int64Type};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::{SerializedColumnWriter, SerializedFileWriter};
+use parquet::schema::parser::parse_message_type;
+use std::convert::TryFrom;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::io::Write;
+use std::sync::Arc;
+
+/// The schema of the files written by `write_q_values`.
+const Q_VALUES_SCHEMA: &str = "message q_values {
+    required binary state_id (UTF8);
+    required binary action_id (UTF8);
+    required int64 calls;
+    required double q_raw;
+    required double q_weighted;
+}";
+
+/// The schema of the files written by `write_transitions`.
+const TRANSITIONS_SCHEMA: &str = "message transitions {
+    required binary previous_state_id (UTF8);
+    required binary action_id (UTF8);
+    required binary current_state_id (UTF8);
+    required double reward;
+    required double old_q;
+    required double new_q;
+}";
+
+/// A transition that an agent has learned from, with its state and action
+/// IDs rendered as strings so that it can be kept after the states and
+/// actions themselves are gone.
+///
+/// Records are usually collected from the agent's `on_learn` hook:
+///
+/// ```ignore
+/// let log = RefCell::new(Vec::new());
+/// let agent = Agent::new(1, 0.5, 0.9)
+///     .on_learn(|e| log.borrow_mut().push(TransitionRecord::from_event(e)));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransitionRecord {
+    /// The ID of the state that was transitioned from.
+    pub previous_state_id: String,
+
+    /// The ID of the action that was taken.
+    pub action_id: String,
+
+    /// The ID of the state that was transitioned to.
+    pub current_state_id: String,
+
+    /// The reward passed to `learn`, before any shaping.
+    pub reward: f64,
+
+    /// The raw q-value of the state-action pair before the update.
+    pub old_q: f64,
+
+    /// The raw q-value of the state-action pair after the update.
+    pub new_q: f64,
+}
+
+impl TransitionRecord {
+    /// Returns a record of the transition described by `event`.
+    pub fn from_event<'a, S, A>(event: &LearnEvent<'_, S, A>) -> Self
+    where
+        S: Stater<'a, A>,
+        A: Actioner<'a>,
+        S::Id: Display,
+        A::Id: Display,
+    {
+        Self {
+            previous_state_id: event.previous_state.id().to_string(),
+            action_id: event.action.id().to_string(),
+            current_state_id: event.current_state.id().to_string(),
+            reward: event.reward,
+            old_q: event.old_q,
+            new_q: event.new_q,
+        }
+    }
+}
+
+/// Writes the q-values of an `AgentContext` to `writer` as a Parquet file.
+///
+/// Rows are sorted by state ID, then action ID, so the output for a given
+/// context is deterministic.
+pub fn write_q_values<W, SK, AK, AS>(
+    writer: W,
+    context: &AgentContext<SK, AK, AS>,
+) -> Result<(), LearnerError>
+where
+    W: Write + Send,
+    SK: Hash + Ord + Display,
+    AK: Hash + Ord + Display,
+    AS: ActionStatter,
+{
+    write_rows(
+        writer,
+        context.q_values.iter().flat_map(|(state, actions)| {
+            actions
+                .iter()
+                .map(move |(action, stats)| (state, action, stats, stats.q_value_weighted()))
+        }),
+    )
+}
+
+/// Writes q-values supplied by an iterator (such as
+/// `Agent::iter_weighted_q_values`) to `writer` as Parquet, without first
+/// cloning them into an `AgentContext`.
+///
+/// Each row holds a state ID, action ID, the action's stats, and its weighted
+/// q-value, which fills the `q_weighted` column.
+pub fn write_rows<'r, W, I, SK, AK, AS>(writer: W, q_values: I) -> Result<(), LearnerError>
+where
+    W: Write + Send,
+    I: IntoIterator<Item = (&'r SK, &'r AK, &'r AS, f64)>,
+    SK: Ord + Display + 'r,
+    AK: Ord + Display + 'r,
+    AS: ActionStatter + 'r,
+{
+    let mut rows: Vec<(&SK, &AK, &AS, f64)> = q_values.into_iter().collect();
+    rows.sort_by(|x, y| (x.0, x.1).cmp(&(y.0, y.1)));
+
+    write_columns(
+        writer,
+        Q_VALUES_SCHEMA,
+        vec![
+            Column::Text(rows.iter().map(|r| r.0.to_string()).collect()),
+            Column::Text(rows.iter().map(|r| r.1.to_string()).collect()),
+            Column::Int64(
+                rows.iter()
+                    .map(|r| i64::try_from(r.2.calls()).unwrap_or(i64::MAX))
+                    .collect(),
+            ),
+            Column::Double(rows.iter().map(|r| r.2.q_value_raw()).collect()),
+            Column::Double(rows.iter().map(|r| r.3).collect()),
+        ],
+    )
+}
+
+/// Writes `transitions` to `writer` as a Parquet file, in the order given.
+pub fn write_transitions<W: Write + Send>(
+    writer: W,
+    transitions: &[TransitionRecord],
+) -> Result<(), LearnerError> {
+    let text = |f: fn(&TransitionRecord) -> &String| {
+        Column::Text(transitions.iter().map(|t| f(t).clone()).collect())
+    };
+    let double =
+        |f: fn(&TransitionRecord) -> f64| Column::Double(transitions.iter().map(f).collect());
+    write_columns(
+        writer,
+        TRANSITIONS_SCHEMA,
+        vec![
+            text(|t| &t.previous_state_id),
+            text(|t| &t.action_id),
+            text(|t| &t.current_state_id),
+            double(|t| t.reward),
+            double(|t| t.old_q),
+            double(|t| t.new_q),
+        ],
+    )
+}
+
+/// The values of a single column, in the order of the schema's fields.
+enum Column {
+    Text(Vec<String>),
+    Int64(Vec<i64>),
+    Double(Vec<f64>),
+}
+
+fn write_columns<W: Write + Send>(
+    writer: W,
+    schema: &str,
+    columns: Vec<Column>,
+) -> Result<(), LearnerError> {
+    let write_err = |e| LearnerError::Serialization(format!("unable to write parquet: {e}"));
+    let schema = Arc::new(parse_message_type(schema).map_err(write_err)?);
+    let properties = Arc::new(WriterProperties::builder().build());
+    let mut file = SerializedFileWriter::new(writer, schema, properties).map_err(write_err)?;
+    let mut row_group = file.next_row_group().map_err(write_err)?;
+    for column in columns {
+        let mut writer = row_group
+            .next_column()
+            .map_err(write_err)?
+            .ok_or_else(|| LearnerError::Serialization("schema has too few columns".to_string()))?;
+        write_column(&mut writer, &column).map_err(write_err)?;
+        writer.close().map_err(write_err)?;
+    }
+    row_group.close().map_err(write_err)?;
+    file.close().map_err(write_err)?;
+    Ok(())
+}
+
+fn write_column(
+    writer: &mut SerializedColumnWriter<'_>,
+    column: &Column,
+) -> parquet::errors::Result<usize> {
+    match column {
+        Column::Text(values) => {
+            let values: Vec<ByteArray> = values.iter().map(|v| v.as_str().into()).collect();
+            writer
+                .typed::<ByteArrayType>()
+                .write_batch(&values, None, None)
+        }
+        Column::Int64(values) => writer.typed::<Int64Type>().write_batch(values, None, None),
+        Column::Double(values) => writer.typed::<DoubleType>().write_batch(values, None, None),
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::agents::bayesian::Agent;
+    use crate::agents::Agenter;
+    use crate::environments::tictactoe::{Board, Move};
+    use crate::stats::actionstats::Stats;
+    use maplit::hashmap;
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    use parquet::record::Row;
+    use std::cell::RefCell;
+    use std::fs::File;
+
+    fn read(name: &str, write: impl FnOnce(File)) -> Vec<Row> {
+        let path =
+            std::env::temp_dir().join(format!("rlr-parquet-{name}-{}.parquet", std::process::id()));
+        write(File::create(&path).unwrap());
+        let reader = SerializedFileReader::new(File::open(&path).unwrap()).unwrap();
+        let rows = reader
+            .get_row_iter(None)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        std::fs::remove_file(&path).unwrap();
+        rows
+    }
+
+    #[test]
+    fn write_q_values() {
+        let context = AgentContext {
+            learning_rate: 1.0,
+            discount_factor: 0.0,
+            priming_threshold: 10,
+            q_values: hashmap! {
+                "B".to_string() => hashmap! {
+                    "X".to_string() => Stats {call_count: 0, q_raw: 0.0, q_weighted: 0.25},
+                },
+                "A".to_string() => hashmap! {
+                    "Y".to_string() => Stats {call_count: 1, q_raw: -1.5E0, q_weighted: 0.5},
+                    "X".to_string() => Stats {call_count: 2, q_raw: 1.0, q_weighted: 0.75},
+                },
+            },
+            state_visits: hashmap! {},
+        };
+
+        let rows = read("q-values", |file| {
+            super::write_q_values(file, &context).unwrap();
+        });
+
+        // Parquet rows display small doubles in scientific notation.
+        let rows: Vec<String> = rows.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            vec![
+                r#"{state_id: "A", action_id: "X", calls: 2, q_raw: 1.0, q_weighted: 0.75}"#,
+                r#"{state_id: "A", action_id: "Y", calls: 1, q_raw: -1.5E0, q_weighted: 0.5}"#,
+                r#"{state_id: "B", action_id: "X", calls: 0, q_raw: 0E0, q_weighted: 0.25}"#,
+            ],
+            rows
+        );
+    }
+
+    #[test]
+    fn write_transitions() {
+        let log = RefCell::new(Vec::new());
+        let mut agent: Agent<Board, Move, Stats> = Agent::new(0, 1.0, 0.0)
+            .on_learn(|e| log.borrow_mut().push(TransitionRecord::from_event(e)));
+        let empty = Board::new();
+        let x = empty.get_action(&0).unwrap();
+        let after_x = empty.play(x).unwrap();
+        let o = after_x.get_action(&4).unwrap();
+        let after_o = after_x.play(o).unwrap();
+        agent.learn(Some(&empty), x, &after_x, 1.0).unwrap();
+        agent.learn(Some(&after_x), o, &after_o, -0.5).unwrap();
+        drop(agent);
+        let log = log.into_inner();
+
+        let rows = read("transitions", |file| {
+            super::write_transitions(file, &log).unwrap();
+        });
+
+        let rows: Vec<String> = rows.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            vec![
+                r#"{previous_state_id: "0", action_id: "0", current_state_id: "1", reward: 1.0, old_q: 0E0, new_q: 1.0}"#,
+                r#"{previous_state_id: "1", action_id: "4", current_state_id: "163", reward: -5E-1, old_q: 0E0, new_q: -5E-1}"#,
+            ],
+            rows
+        );
+    }
+}
@@ -0,0 +1,265 @@
+//! Reads and writes agent contexts as protobuf messages.
+//!
+//! The messages are declared in `proto/snapshot.proto`, so that snapshots of
+//! agents trained with this crate can be read by programs written in other
+//! languages, and vice versa. State and action IDs are written as strings
+//! using their `Display` implementation, and read back using `FromStr`.
+//!
+//! The message types below are declared by hand rather than generated, so
+//! that building the crate does not require `protoc`. They must be kept in
+//! step with the `.proto` file.
+
+use crate::agents::bayesian::AgentContext;
+use crate::errors::LearnerError;
+use crate::stats::ActionStatter;
+use prost::Message;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+/// An agent's hyperparameters and q-values.
+#[derive(Clone, PartialEq, Message)]
+pub struct AgentSnapshot {
+    /// See `AgentContext::learning_rate`.
+    #[prost(double, tag = "1")]
+    pub learning_rate: f64,
+
+    /// See `AgentContext::discount_factor`.
+    #[prost(double, tag = "2")]
+    pub discount_factor: f64,
+
+    /// See `AgentContext::priming_threshold`.
+    #[prost(int32, tag = "3")]
+    pub priming_threshold: i32,
+
+    /// The stats recorded for each state and action.
+    #[prost(message, repeated, tag = "4")]
+    pub q_values: Vec<QEntry>,
+
+    /// The number of times the agent has learned from each state.
+    #[prost(message, repeated, tag = "5")]
+    pub state_visits: Vec<StateVisits>,
+}
+
+/// The stats recorded for a single state and action.
+#[derive(Clone, PartialEq, Message)]
+pub struct QEntry {
+    /// The ID of the state.
+    #[prost(string, tag = "1")]
+    pub state_id: String,
+
+    /// The ID of the action.
+    #[prost(string, tag = "2")]
+    pub action_id: String,
+
+    /// The number of times the action has been taken in the state.
+    #[prost(uint64, tag = "3")]
+    pub calls: u64,
+
+    /// The raw q-value of the action.
+    #[prost(double, tag = "4")]
+    pub q_raw: f64,
+
+    /// The weighted q-value of the action.
+    #[prost(double, tag = "5")]
+    pub q_weighted: f64,
+}
+
+/// The number of times the agent has learned from a state.
+#[derive(Clone, PartialEq, Eq, Message)]
+pub struct StateVisits {
+    /// The ID of the state.
+    #[prost(string, tag = "1")]
+    pub state_id: String,
+
+    /// The number of visits.
+    #[prost(uint64, tag = "2")]
+    pub visits: u64,
+}
+
+impl AgentSnapshot {
+    /// Returns a snapshot of an `AgentContext`. Entries are sorted by state
+    /// ID, then action ID, so the snapshot of a given context is
+    /// deterministic.
+    pub fn from_context<SK, AK, AS>(context: &AgentContext<SK, AK, AS>) -> Self
+    where
+        SK: Hash + Eq + Ord + Display,
+        AK: Hash + Eq + Ord + Display,
+        AS: ActionStatter,
+    {
+        let mut q_values: Vec<_> = context
+            .q_values
+            .iter()
+            .flat_map(|(state_id, actions)| {
+                actions
+                    .iter()
+                    .map(move |(action_id, stats)| (state_id, action_id, stats))
+            })
+            .collect();
+        q_values.sort_by(|x, y| (x.0, x.1).cmp(&(y.0, y.1)));
+        let mut state_visits: Vec<_> = context.state_visits.iter().collect();
+        state_visits.sort_by(|x, y| x.0.cmp(y.0));
+
+        Self {
+            learning_rate: context.learning_rate,
+            discount_factor: context.discount_factor,
+            priming_threshold: context.priming_threshold,
+            q_values: q_values
+                .into_iter()
+                .map(|(state_id, action_id, stats)| QEntry {
+                    state_id: state_id.to_string(),
+                    action_id: action_id.to_string(),
+                    calls: stats.calls(),
+                    q_raw: stats.q_value_raw(),
+                    q_weighted: stats.q_value_weighted(),
+                })
+                .collect(),
+            state_visits: state_visits
+                .into_iter()
+                .map(|(state_id, visits)| StateVisits {
+                    state_id: state_id.to_string(),
+                    visits: *visits,
+                })
+                .collect(),
+        }
+    }
+
+    /// Converts the snapshot to an `AgentContext`.
+    /// An error is returned if a state or action ID cannot be parsed.
+    pub fn into_context<SK, AK, AS>(self) -> Result<AgentContext<SK, AK, AS>, LearnerError>
+    where
+        SK: Hash + Eq + FromStr,
+        SK::Err: Display,
+        AK: Hash + Eq + FromStr,
+        AK::Err: Display,
+        AS: ActionStatter,
+    {
+        let mut q_values: HashMap<SK, HashMap<AK, AS>> = HashMap::new();
+        for entry in self.q_values {
+            let mut stats = AS::default();
+            stats.set_calls(entry.calls);
+            stats.set_q_value_raw(entry.q_raw);
+            stats.set_q_value_weighted(entry.q_weighted);
+            q_values
+                .entry(parse(&entry.state_id)?)
+                .or_default()
+                .insert(parse(&entry.action_id)?, stats);
+        }
+        let state_visits = self
+            .state_visits
+            .into_iter()
+            .map(|v| Ok((parse(&v.state_id)?, v.visits)))
+            .collect::<Result<_, LearnerError>>()?;
+        Ok(AgentContext {
+            learning_rate: self.learning_rate,
+            discount_factor: self.discount_factor,
+            priming_threshold: self.priming_threshold,
+            q_values,
+            state_visits,
+        })
+    }
+}
+
+/// Writes an `AgentContext` to `writer` as an encoded `AgentSnapshot`.
+pub fn write_context<W, SK, AK, AS>(
+    mut writer: W,
+    context: &AgentContext<SK, AK, AS>,
+) -> Result<(), LearnerError>
+where
+    W: Write,
+    SK: Hash + Eq + Ord + Display,
+    AK: Hash + Eq + Ord + Display,
+    AS: ActionStatter,
+{
+    writer
+        .write_all(&AgentSnapshot::from_context(context).encode_to_vec())
+        .map_err(|e| LearnerError::Serialization(format!("unable to write protobuf: {e}")))
+}
+
+/// Reads an `AgentContext` from an encoded `AgentSnapshot`, consuming the
+/// whole of `reader`.
+pub fn read_context<R, SK, AK, AS>(mut reader: R) -> Result<AgentContext<SK, AK, AS>, LearnerError>
+where
+    R: Read,
+    SK: Hash + Eq + FromStr,
+    SK::Err: Display,
+    AK: Hash + Eq + FromStr,
+    AK::Err: Display,
+    AS: ActionStatter,
+{
+    let read_err =
+        |e: &dyn Display| LearnerError::Serialization(format!("unable to read protobuf: {e}"));
+    let mut buf = Vec::new();
+    reader.read_to_end(&mut buf).map_err(|e| read_err(&e))?;
+    AgentSnapshot::decode(buf.as_slice())
+        .map_err(|e| read_err(&e))?
+        .into_context()
+}
+
+fn parse<T>(id: &str) -> Result<T, LearnerError>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    id.parse()
+        .map_err(|e| LearnerError::Serialization(format!("unable to parse ID {id:?}: {e}")))
+}
+
+#[cfg(test)]
+#[allow(clippy::panic)]
+mod tests {
+    use crate::agents::bayesian::AgentContext;
+    use crate::errors::LearnerError;
+    use crate::export::protobuf::{self, AgentSnapshot, QEntry};
+    use crate::stats::actionstats::Stats;
+    use maplit::hashmap;
+
+    #[test]
+    fn round_trip() {
+        let context = AgentContext {
+            learning_rate: 0.5,
+            discount_factor: 0.9,
+            priming_threshold: 3,
+            q_values: hashmap! {
+                2_u32 => hashmap! {
+                    7_u8 => Stats {call_count: 1, q_raw: -1.5, q_weighted: 0.5},
+                    3_u8 => Stats {call_count: 2, q_raw: 1.0, q_weighted: 0.75},
+                },
+            },
+            state_visits: hashmap! { 2_u32 => 3 },
+        };
+
+        let mut output = Vec::new();
+        protobuf::write_context(&mut output, &context).unwrap();
+        let snapshot: AgentContext<u32, u8, Stats> =
+            protobuf::read_context(output.as_slice()).unwrap();
+        assert_eq!(context.q_values, snapshot.q_values);
+        assert_eq!(context.state_visits, snapshot.state_visits);
+        assert_eq!(3, snapshot.priming_threshold);
+
+        let entries = AgentSnapshot::from_context(&context).q_values;
+        assert_eq!(
+            vec!["3", "7"],
+            entries
+                .iter()
+                .map(|e| e.action_id.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn read_invalid_ids() {
+        let snapshot = AgentSnapshot {
+            q_values: vec![QEntry {
+                state_id: "not a number".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+        let result = snapshot.into_context::<u32, u8, Stats>();
+        assert!(matches!(result, Err(LearnerError::Serialization(_))));
+        assert!(protobuf::read_context::<_, u32, u8, Stats>(&[0xff_u8][..]).is_err());
+    }
+}
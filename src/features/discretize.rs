@@ -0,0 +1,538 @@
+//! Discretization, which maps continuous observations onto stable state IDs
+//! by dividing each dimension of the observation into bins.
+//!
+//! A `Discretizer` holds one set of `Bins` per dimension. The ID of an
+//! observation is the index of the bin that each of its dimensions falls in,
+//! so observations that fall in the same bins share a state:
+//!
+//! ```
+//! use rlr::features::discretize::{Bins, Discretizer};
+//!
+//! let discretizer = Discretizer::new(vec![
+//!     Bins::Uniform { low: -1.0, high: 1.0, count: 4 },
+//!     Bins::Edges(vec![0.0, 10.0]),
+//! ])
+//! .unwrap();
+//! assert_eq!(vec![3, 1], discretizer.discretize(&[0.9, 5.0]).unwrap());
+//! ```
+//!
+//! Continuous actions can be discretized in the same way. An `ActionRange`
+//! divides a range of values into evenly spaced `ContinuousAction`s, which
+//! tabular agents can choose between, and maps the chosen action's ID back
+//! onto the value to apply:
+//!
+//! ```
+//! use rlr::actions::Actioner;
+//! use rlr::features::discretize::ActionRange;
+//!
+//! let torque = ActionRange::new(-2.0, 2.0, 5).unwrap();
+//! let action = torque.nearest(0.8);
+//! assert_eq!(3, action.id());
+//! assert_eq!(1.0, torque.value(&action.id()).unwrap());
+//! ```
+
+use crate::actions::Actioner;
+use crate::errors::LearnerError;
+use crate::features::floor;
+use crate::internal::math::len_to_f64;
+use crate::states::{FeatureStater, Stater};
+use std::fmt::Debug;
+
+/// Describes how a single dimension of an observation is divided into bins.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Bins {
+    /// `count` bins of equal width between `low` and `high`. Values outside
+    /// of the range fall in the first or last bin.
+    Uniform {
+        /// The lower bound of the first bin.
+        low: f64,
+        /// The upper bound of the last bin.
+        high: f64,
+        /// The number of bins.
+        count: usize,
+    },
+
+    /// Bins separated by the supplied edges, which must be in strictly
+    /// increasing order. `n` edges make `n + 1` bins: values below the first
+    /// edge fall in bin 0, and a value equal to an edge falls in the bin
+    /// above it.
+    Edges(Vec<f64>),
+}
+
+impl Bins {
+    /// Returns bins whose edges are the quantiles of `samples`, so that each
+    /// of the `count` bins holds roughly the same number of samples. Fewer
+    /// bins are returned if the samples have too few distinct values.
+    pub fn quantile(samples: &[f64], count: usize) -> Result<Self, LearnerError> {
+        if count == 0 {
+            return Err(invalid("at least one bin is required"));
+        }
+        if samples.is_empty() {
+            return Err(invalid("at least one sample is required"));
+        }
+        if samples.iter().any(|x| !x.is_finite()) {
+            return Err(LearnerError::NonFinite(
+                "quantile samples must be finite".to_string(),
+            ));
+        }
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let mut edges: Vec<f64> = (1..count)
+            .map(|i| sorted[i * sorted.len() / count])
+            .collect();
+        edges.dedup();
+        if edges.first() == sorted.first() {
+            // An edge at the minimum would leave the first bin empty.
+            edges.remove(0);
+        }
+        Ok(Self::Edges(edges))
+    }
+
+    /// Returns the number of bins.
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Uniform { count, .. } => *count,
+            Self::Edges(edges) => edges.len() + 1,
+        }
+    }
+
+    /// Returns false; there is always at least one bin.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Returns the index of the bin that `x` falls in.
+    pub fn bin(&self, x: f64) -> usize {
+        match self {
+            Self::Uniform { low, high, count } => {
+                let scaled = (x - low) / (high - low) * len_to_f64(*count);
+                floor(scaled.max(0.0)).min(count - 1)
+            }
+            Self::Edges(edges) => edges.partition_point(|edge| *edge <= x),
+        }
+    }
+
+    fn validate(&self) -> Result<(), LearnerError> {
+        match self {
+            Self::Uniform { low, high, count } => {
+                if !low.is_finite() || !high.is_finite() || low >= high {
+                    return Err(invalid(&format!(
+                        "bounds ({low}, {high}) must be finite, with low < high"
+                    )));
+                }
+                if *count == 0 {
+                    return Err(invalid("at least one bin is required"));
+                }
+            }
+            Self::Edges(edges) => {
+                if edges.iter().any(|edge| !edge.is_finite())
+                    || edges.windows(2).any(|pair| pair[0] >= pair[1])
+                {
+                    return Err(invalid(&format!(
+                        "edges {edges:?} must be finite and strictly increasing"
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Maps observations onto state IDs, by finding the bin that each dimension
+/// of the observation falls in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Discretizer {
+    bins: Vec<Bins>,
+}
+
+impl Discretizer {
+    /// Returns a discretizer for observations with one dimension for each
+    /// element of `bins`.
+    pub fn new(bins: Vec<Bins>) -> Result<Self, LearnerError> {
+        if bins.is_empty() {
+            return Err(invalid("at least one dimension is required"));
+        }
+        for dimension in &bins {
+            dimension.validate()?;
+        }
+        Ok(Self { bins })
+    }
+
+    /// Returns the bins of each dimension.
+    pub fn bins(&self) -> &[Bins] {
+        &self.bins
+    }
+
+    /// Returns the number of distinct IDs that the discretizer can produce,
+    /// or `None` if the number overflows a `usize`.
+    pub fn state_count(&self) -> Option<usize> {
+        self.bins
+            .iter()
+            .try_fold(1_usize, |total, bins| total.checked_mul(bins.len()))
+    }
+
+    /// Returns the index of the bin that each dimension of `observation`
+    /// falls in.
+    pub fn discretize(&self, observation: &[f64]) -> Result<Vec<usize>, LearnerError> {
+        if observation.len() != self.bins.len() {
+            return Err(invalid(&format!(
+                "expected an observation with {} dimensions, got {}",
+                self.bins.len(),
+                observation.len()
+            )));
+        }
+        if let Some(x) = observation.iter().find(|x| !x.is_finite()) {
+            return Err(LearnerError::NonFinite(format!(
+                "observations must be finite, got {x}"
+            )));
+        }
+        Ok(self
+            .bins
+            .iter()
+            .zip(observation)
+            .map(|(bins, x)| bins.bin(*x))
+            .collect())
+    }
+
+    /// Returns the index of the bin that each of `state`'s features falls
+    /// in.
+    pub fn discretize_state<'a, S, A>(&self, state: &S) -> Result<Vec<usize>, LearnerError>
+    where
+        S: FeatureStater<'a, A>,
+        A: Actioner<'a>,
+    {
+        self.discretize(&state.features())
+    }
+
+    /// Returns the state of `observation`, in which any of `actions` may be
+    /// taken.
+    pub fn state<'a, A>(
+        &self,
+        observation: &[f64],
+        actions: &'a [A],
+    ) -> Result<DiscreteState<'a, A>, LearnerError> {
+        Ok(DiscreteState {
+            id: self.discretize(observation)?,
+            actions,
+        })
+    }
+}
+
+/// A state identified by the bins of a discretized observation. See
+/// `Discretizer::state`.
+#[derive(Debug, Clone)]
+pub struct DiscreteState<'a, A> {
+    id: Vec<usize>,
+    actions: &'a [A],
+}
+
+impl<'a, A> Stater<'a, A> for DiscreteState<'a, A>
+where
+    A: Actioner<'a>,
+{
+    type Id = Vec<usize>;
+
+    fn possible_actions(&self) -> Vec<&'a A> {
+        self.actions.iter().collect()
+    }
+
+    fn action_is_compatible(&self, action: &'a A) -> bool {
+        self.actions.iter().any(|a| a.id() == action.id())
+    }
+
+    fn get_action(&self, action_id: &A::Id) -> Result<&'a A, LearnerError> {
+        self.actions
+            .iter()
+            .find(|a| &a.id() == action_id)
+            .ok_or_else(|| LearnerError::ActionNotFound {
+                action: format!("{action_id:?}"),
+                reason: format!("state {:?} has no action {action_id:?}", self.id),
+            })
+    }
+
+    fn id(&self) -> Vec<usize> {
+        self.id.clone()
+    }
+
+    fn apply(&self, _: &'a A) -> Result<(), LearnerError> {
+        Ok(())
+    }
+}
+
+/// One of the evenly spaced values of an `ActionRange`. Its ID is its index
+/// within the range, from the lowest value to the highest.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContinuousAction {
+    index: usize,
+    value: f64,
+}
+
+impl ContinuousAction {
+    /// Returns the index of the action within its range.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the value that the action represents.
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+}
+
+impl Actioner<'_> for ContinuousAction {
+    type Id = usize;
+
+    fn id(&self) -> usize {
+        self.index
+    }
+}
+
+/// A continuous range of action values, divided into a fixed number of
+/// evenly spaced `ContinuousAction`s.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionRange {
+    min: f64,
+    max: f64,
+    actions: Vec<ContinuousAction>,
+}
+
+impl ActionRange {
+    /// Returns `bins` actions spread evenly from `min` to `max`, inclusive.
+    /// A single bin represents the middle of the range.
+    pub fn new(min: f64, max: f64, bins: usize) -> Result<Self, LearnerError> {
+        if !min.is_finite() || !max.is_finite() || min >= max {
+            return Err(invalid(&format!(
+                "action range ({min}, {max}) must be finite, with min < max"
+            )));
+        }
+        if bins == 0 {
+            return Err(invalid("at least one action is required"));
+        }
+        let actions = (0..bins)
+            .map(|index| {
+                let value = if bins == 1 {
+                    (max - min).mul_add(0.5, min)
+                } else {
+                    (max - min).mul_add(len_to_f64(index) / len_to_f64(bins - 1), min)
+                };
+                ContinuousAction { index, value }
+            })
+            .collect();
+        Ok(Self { min, max, actions })
+    }
+
+    /// Returns the lowest value of the range.
+    pub fn min(&self) -> f64 {
+        self.min
+    }
+
+    /// Returns the highest value of the range.
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    /// Returns the range's actions, from the lowest value to the highest.
+    pub fn actions(&self) -> &[ContinuousAction] {
+        &self.actions
+    }
+
+    /// Returns the value of the action with the supplied ID, or an error if
+    /// the range has no such action.
+    pub fn value(&self, action_id: &usize) -> Result<f64, LearnerError> {
+        self.actions
+            .get(*action_id)
+            .map(ContinuousAction::value)
+            .ok_or_else(|| LearnerError::ActionNotFound {
+                action: format!("{action_id:?}"),
+                reason: format!("the range has {} actions", self.actions.len()),
+            })
+    }
+
+    /// Returns the action whose value is nearest to `value`. Values outside
+    /// of the range map onto the lowest or highest action.
+    pub fn nearest(&self, value: f64) -> &ContinuousAction {
+        let last = self.actions.len() - 1;
+        if last == 0 {
+            return &self.actions[0];
+        }
+        let scaled = (value - self.min) / (self.max - self.min) * len_to_f64(last);
+        let index = if scaled.is_nan() {
+            0
+        } else {
+            floor(scaled.clamp(0.0, len_to_f64(last)) + 0.5).min(last)
+        };
+        &self.actions[index]
+    }
+}
+
+fn invalid(reason: &str) -> LearnerError {
+    LearnerError::InvalidArgument(format!("invalid discretization: {reason}"))
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::agents::bayesian::Agent;
+    use crate::agents::Agenter;
+    use crate::environments::bandit::{Arm, Bandit};
+    use crate::mocks::*;
+    use crate::stats::actionstats::Stats;
+
+    #[test]
+    fn uniform_bins() {
+        let bins = Bins::Uniform {
+            low: 0.0,
+            high: 1.0,
+            count: 4,
+        };
+        assert_eq!(4, bins.len());
+        assert_eq!(0, bins.bin(-5.0));
+        assert_eq!(0, bins.bin(0.2));
+        assert_eq!(1, bins.bin(0.25));
+        assert_eq!(3, bins.bin(1.0));
+        assert_eq!(3, bins.bin(5.0));
+    }
+
+    #[test]
+    fn edge_bins() {
+        let bins = Bins::Edges(vec![-1.0, 1.0]);
+        assert_eq!(3, bins.len());
+        assert_eq!(0, bins.bin(-2.0));
+        assert_eq!(1, bins.bin(-1.0));
+        assert_eq!(1, bins.bin(0.5));
+        assert_eq!(2, bins.bin(1.0));
+    }
+
+    #[test]
+    fn quantile_bins() {
+        let samples: Vec<f64> = (0..100).rev().map(f64::from).collect();
+        assert_eq!(
+            Bins::Edges(vec![25.0, 50.0, 75.0]),
+            Bins::quantile(&samples, 4).unwrap()
+        );
+
+        // Repeated values produce fewer bins.
+        let samples = [1.0, 1.0, 2.0, 2.0, 3.0];
+        assert_eq!(Bins::Edges(vec![2.0]), Bins::quantile(&samples, 4).unwrap());
+        assert_eq!(Bins::Edges(vec![]), Bins::quantile(&[3.0], 4).unwrap());
+
+        assert!(Bins::quantile(&[], 4).is_err());
+        assert!(Bins::quantile(&[1.0], 0).is_err());
+        assert!(Bins::quantile(&[f64::NAN], 2).is_err());
+    }
+
+    #[test]
+    fn discretize() {
+        let discretizer = Discretizer::new(vec![
+            Bins::Uniform {
+                low: 0.0,
+                high: 1.0,
+                count: 2,
+            },
+            Bins::Edges(vec![0.0]),
+        ])
+        .unwrap();
+        assert_eq!(Some(4), discretizer.state_count());
+        assert_eq!(vec![1, 0], discretizer.discretize(&[0.7, -3.0]).unwrap());
+        assert!(matches!(
+            discretizer.discretize(&[0.7]),
+            Err(LearnerError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            discretizer.discretize(&[0.7, f64::NAN]),
+            Err(LearnerError::NonFinite(_))
+        ));
+
+        assert!(Discretizer::new(vec![]).is_err());
+        assert!(Discretizer::new(vec![Bins::Edges(vec![1.0, 1.0])]).is_err());
+        assert!(Discretizer::new(vec![Bins::Uniform {
+            low: 1.0,
+            high: 0.0,
+            count: 2
+        }])
+        .is_err());
+    }
+
+    #[test]
+    fn discretize_feature_states() {
+        let discretizer = Discretizer::new(vec![Bins::Edges(vec![1.5])]).unwrap();
+        let moves = MockCorridor::moves();
+        let cell = |position| MockCell {
+            position,
+            moves: &moves,
+        };
+        assert_eq!(vec![0], discretizer.discretize_state(&cell(1)).unwrap());
+        assert_eq!(vec![1], discretizer.discretize_state(&cell(2)).unwrap());
+    }
+
+    #[test]
+    fn action_ranges() {
+        let range = ActionRange::new(-1.0, 1.0, 5).unwrap();
+        let values: Vec<f64> = range
+            .actions()
+            .iter()
+            .map(ContinuousAction::value)
+            .collect();
+        assert_eq!(vec![-1.0, -0.5, 0.0, 0.5, 1.0], values);
+        let ids: Vec<usize> = range.actions().iter().map(Actioner::id).collect();
+        assert_eq!(vec![0, 1, 2, 3, 4], ids);
+        assert_eq!(0.5, range.value(&3).unwrap());
+        assert!(matches!(
+            range.value(&5),
+            Err(LearnerError::ActionNotFound { .. })
+        ));
+
+        assert_eq!(2, range.nearest(0.2).index());
+        assert_eq!(3, range.nearest(0.3).index());
+        assert_eq!(0, range.nearest(-7.0).index());
+        assert_eq!(4, range.nearest(f64::INFINITY).index());
+        assert_eq!(0, range.nearest(f64::NAN).index());
+
+        let single = ActionRange::new(0.0, 4.0, 1).unwrap();
+        assert_eq!(2.0, single.value(&0).unwrap());
+        assert_eq!(0, single.nearest(4.0).index());
+
+        assert!(ActionRange::new(1.0, 1.0, 2).is_err());
+        assert!(ActionRange::new(0.0, f64::INFINITY, 2).is_err());
+        assert!(ActionRange::new(0.0, 1.0, 0).is_err());
+    }
+
+    #[test]
+    fn learn_continuous_actions() {
+        let discretizer = Discretizer::new(vec![Bins::Edges(vec![0.0])]).unwrap();
+        let range = ActionRange::new(0.0, 1.0, 3).unwrap();
+        let state = discretizer.state(&[1.0], range.actions()).unwrap();
+
+        let mut agent: Agent<DiscreteState<ContinuousAction>, ContinuousAction, Stats> =
+            Agent::new(0, 1.0, 0.0);
+        for action in range.actions() {
+            // The reward is highest for values close to 0.5.
+            let reward = -(action.value() - 0.5).abs();
+            agent.learn(Some(&state), action, &state, reward).unwrap();
+        }
+        let recommended = agent.recommend_action(&state).unwrap();
+        assert_eq!(0.5, range.value(&recommended.id()).unwrap());
+    }
+
+    #[test]
+    fn learn_from_discrete_states() {
+        let discretizer = Discretizer::new(vec![Bins::Uniform {
+            low: 0.0,
+            high: 1.0,
+            count: 2,
+        }])
+        .unwrap();
+        let arms = Bandit::arms(2);
+        let low = discretizer.state(&[0.1], &arms).unwrap();
+        let also_low = discretizer.state(&[0.3], &arms).unwrap();
+        let high = discretizer.state(&[0.9], &arms).unwrap();
+        assert_eq!(low.id(), also_low.id());
+        assert!(low.get_action(&2).is_err());
+
+        let mut agent: Agent<DiscreteState<Arm>, Arm, Stats> = Agent::new(0, 1.0, 0.0);
+        agent.learn(Some(&low), &arms[1], &high, 1.0).unwrap();
+        assert_eq!(1, agent.recommend_action(&also_low).unwrap().index());
+    }
+}
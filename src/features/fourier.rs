@@ -0,0 +1,262 @@
+//! The Fourier basis, which maps continuous observations onto dense cosine
+//! features for linear value approximation.
+//!
+//! Each observation is first scaled so that each dimension lies in `[0, 1]`.
+//! Feature `i` of a scaled observation `s` is then `cos(pi * c_i · s)`, where
+//! `c_i` is a vector of integer frequencies, one per dimension, no greater
+//! than the order of the basis. Low orders give smooth approximations, and
+//! higher orders add finer detail. See Konidaris, Osentoski and Thomas,
+//! "Value Function Approximation in Reinforcement Learning using the Fourier
+//! Basis" (2011).
+
+use crate::errors::LearnerError;
+use crate::internal::math::len_to_f64;
+use std::convert::TryFrom;
+use std::f64::consts::PI;
+
+/// Computes Fourier basis features for observations within fixed bounds.
+/// Observations outside of the bounds are clamped to them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FourierBasis {
+    lows: Vec<f64>,
+    ranges: Vec<f64>,
+    frequencies: Vec<Vec<usize>>,
+}
+
+impl FourierBasis {
+    /// Returns the full Fourier basis of the specified `order` for
+    /// observations that lie within `bounds`, given as the `(low, high)`
+    /// bounds of each dimension.
+    ///
+    /// The full basis has a feature for every combination of frequencies
+    /// from 0 to `order`, so it has `(order + 1)^d` features for `d`
+    /// dimensions, and captures interactions between dimensions.
+    pub fn new(bounds: &[(f64, f64)], order: usize) -> Result<Self, LearnerError> {
+        let count = u32::try_from(bounds.len())
+            .ok()
+            .and_then(|dimensions| (order + 1).checked_pow(dimensions))
+            .ok_or_else(|| invalid("too many features"))?;
+        let frequencies = (0..count)
+            .map(|mut i| {
+                (0..bounds.len())
+                    .map(|_| {
+                        let frequency = i % (order + 1);
+                        i /= order + 1;
+                        frequency
+                    })
+                    .collect()
+            })
+            .collect();
+        Self::from_frequencies(bounds, frequencies)
+    }
+
+    /// Returns the uncoupled Fourier basis of the specified `order`, which
+    /// varies only one dimension at a time. It has `order * d + 1` features
+    /// for `d` dimensions, so it scales to many more dimensions than the full
+    /// basis, but cannot represent interactions between dimensions. See
+    /// `FourierBasis::new` for a description of the parameters.
+    pub fn uncoupled(bounds: &[(f64, f64)], order: usize) -> Result<Self, LearnerError> {
+        let constant = vec![0; bounds.len()];
+        let frequencies = std::iter::once(constant.clone())
+            .chain((0..bounds.len()).flat_map(|j| {
+                let constant = constant.clone();
+                (1..=order).map(move |frequency| {
+                    let mut frequencies = constant.clone();
+                    frequencies[j] = frequency;
+                    frequencies
+                })
+            }))
+            .collect();
+        Self::from_frequencies(bounds, frequencies)
+    }
+
+    /// Returns a basis with a feature for each of the supplied frequency
+    /// vectors, each of which must have one frequency per dimension. See
+    /// `FourierBasis::new` for a description of `bounds`.
+    pub fn from_frequencies(
+        bounds: &[(f64, f64)],
+        frequencies: Vec<Vec<usize>>,
+    ) -> Result<Self, LearnerError> {
+        if bounds.is_empty() {
+            return Err(invalid("at least one dimension is required"));
+        }
+        if let Some((low, high)) = bounds
+            .iter()
+            .find(|(low, high)| !low.is_finite() || !high.is_finite() || low >= high)
+        {
+            return Err(invalid(&format!(
+                "bounds ({low}, {high}) must be finite, with low < high"
+            )));
+        }
+        if frequencies.is_empty() {
+            return Err(invalid("at least one feature is required"));
+        }
+        if frequencies.iter().any(|c| c.len() != bounds.len()) {
+            return Err(invalid(&format!(
+                "each frequency vector requires one frequency for each of the {} dimensions",
+                bounds.len()
+            )));
+        }
+        Ok(Self {
+            lows: bounds.iter().map(|(low, _)| *low).collect(),
+            ranges: bounds.iter().map(|(low, high)| high - low).collect(),
+            frequencies,
+        })
+    }
+
+    /// Returns the number of dimensions of the observations that the basis
+    /// accepts.
+    pub fn dimensions(&self) -> usize {
+        self.lows.len()
+    }
+
+    /// Returns the number of features, which is the length of the vectors
+    /// returned by `features`.
+    pub fn feature_count(&self) -> usize {
+        self.frequencies.len()
+    }
+
+    /// Returns the frequency vector of each feature.
+    pub fn frequencies(&self) -> &[Vec<usize>] {
+        &self.frequencies
+    }
+
+    /// Returns the features of `observation`, each of which is in the range
+    /// `[-1, 1]`.
+    pub fn features(&self, observation: &[f64]) -> Result<Vec<f64>, LearnerError> {
+        if observation.len() != self.dimensions() {
+            return Err(invalid(&format!(
+                "expected an observation with {} dimensions, got {}",
+                self.dimensions(),
+                observation.len()
+            )));
+        }
+        if let Some(x) = observation.iter().find(|x| !x.is_finite()) {
+            return Err(LearnerError::NonFinite(format!(
+                "observations must be finite, got {x}"
+            )));
+        }
+
+        let scaled: Vec<f64> = observation
+            .iter()
+            .zip(self.lows.iter().zip(&self.ranges))
+            .map(|(x, (low, range))| ((x - low) / range).clamp(0.0, 1.0))
+            .collect();
+        Ok(self
+            .frequencies
+            .iter()
+            .map(|c| {
+                let dot: f64 = c.iter().zip(&scaled).map(|(c, s)| len_to_f64(*c) * s).sum();
+                (PI * dot).cos()
+            })
+            .collect())
+    }
+
+    /// Returns a scale for the learning rate of each feature's weight, of
+    /// `1 / ||c||` for a feature with frequencies `c`, or 1 for the constant
+    /// feature. Scaling a base learning rate this way, as recommended by
+    /// Konidaris et al., keeps high frequency features from learning faster
+    /// than low frequency ones.
+    pub fn learning_rate_scales(&self) -> Vec<f64> {
+        self.frequencies
+            .iter()
+            .map(|c| {
+                let norm = c.iter().map(|c| len_to_f64(*c).powi(2)).sum::<f64>().sqrt();
+                if norm == 0.0 {
+                    1.0
+                } else {
+                    1.0 / norm
+                }
+            })
+            .collect()
+    }
+}
+
+fn invalid(reason: &str) -> LearnerError {
+    LearnerError::InvalidArgument(format!("invalid fourier basis: {reason}"))
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+
+    fn assert_close(expected: &[f64], actual: &[f64]) {
+        assert_eq!(expected.len(), actual.len());
+        for (e, a) in expected.iter().zip(actual) {
+            assert!(
+                (e - a).abs() < 1e-12,
+                "expected {:?}, got {:?}",
+                expected,
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn full_basis() {
+        let basis = FourierBasis::new(&[(0.0, 2.0), (-1.0, 1.0)], 1).unwrap();
+        assert_eq!(2, basis.dimensions());
+        assert_eq!(4, basis.feature_count());
+        assert_eq!(
+            &[vec![0, 0], vec![1, 0], vec![0, 1], vec![1, 1]],
+            basis.frequencies()
+        );
+
+        // Scales to (0.5, 1.0).
+        assert_close(
+            &[1.0, 0.0, -1.0, (1.5 * PI).cos()],
+            &basis.features(&[1.0, 1.0]).unwrap(),
+        );
+        // Clamped to (0.0, 0.0).
+        assert_close(&[1.0; 4], &basis.features(&[-5.0, -5.0]).unwrap());
+    }
+
+    #[test]
+    fn uncoupled_basis() {
+        let basis = FourierBasis::uncoupled(&[(0.0, 1.0); 3], 2).unwrap();
+        assert_eq!(7, basis.feature_count());
+        assert_eq!(
+            &[
+                vec![0, 0, 0],
+                vec![1, 0, 0],
+                vec![2, 0, 0],
+                vec![0, 1, 0],
+                vec![0, 2, 0],
+                vec![0, 0, 1],
+                vec![0, 0, 2],
+            ],
+            basis.frequencies()
+        );
+        assert_close(
+            &[1.0, 0.0, -1.0, 1.0, 1.0, -1.0, 1.0],
+            &basis.features(&[0.5, 0.0, 1.0]).unwrap(),
+        );
+    }
+
+    #[test]
+    fn learning_rate_scales() {
+        let basis =
+            FourierBasis::from_frequencies(&[(0.0, 1.0); 2], vec![vec![0, 0], vec![3, 4]]).unwrap();
+        assert_eq!(vec![1.0, 0.2], basis.learning_rate_scales());
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        assert!(FourierBasis::new(&[], 1).is_err());
+        assert!(FourierBasis::new(&[(1.0, 0.0)], 1).is_err());
+        assert!(FourierBasis::new(&[(0.0, 1.0); 64], 3).is_err());
+        assert!(FourierBasis::from_frequencies(&[(0.0, 1.0)], vec![]).is_err());
+        assert!(FourierBasis::from_frequencies(&[(0.0, 1.0)], vec![vec![1, 1]]).is_err());
+
+        let basis = FourierBasis::new(&[(0.0, 1.0)], 1).unwrap();
+        assert!(matches!(
+            basis.features(&[0.0, 0.0]),
+            Err(LearnerError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            basis.features(&[f64::INFINITY]),
+            Err(LearnerError::NonFinite(_))
+        ));
+    }
+}
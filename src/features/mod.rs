@@ -0,0 +1,22 @@
+//! Feature constructors, which turn continuous observations (such as the
+//! positions and velocities reported by a control task) into features that
+//! agents can learn from.
+//!
+//! Tabular agents, such as `bayesian::Agent`, require each state to have a
+//! discrete ID. The feature constructors in this module produce discrete
+//! features that can be used as such IDs, as well as feature vectors that are
+//! suited to linear value approximation.
+
+pub mod discretize;
+pub mod fourier;
+pub mod tile_coding;
+
+/// Returns `x`, which must be non-negative, rounded down.
+#[allow(
+    clippy::as_conversions,
+    clippy::cast_possible_truncation,
+    clippy::cast_sign_loss
+)]
+fn floor(x: f64) -> usize {
+    x.floor() as usize
+}
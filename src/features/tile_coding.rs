@@ -0,0 +1,245 @@
+//! Tile coding, which maps continuous observations onto sparse binary
+//! features.
+//!
+//! The observation space is covered by several overlapping grids, called
+//! tilings, each of which is offset from the others by a fraction of a tile.
+//! An observation falls in exactly one tile of each tiling, so it activates
+//! one feature per tiling. Nearby observations share most of their active
+//! tiles, which lets an agent generalize between them, while the offsets
+//! between tilings give a finer resolution than any single tiling.
+//! See Sutton and Barto, "Reinforcement Learning: An Introduction", section
+//! 9.5.4.
+//!
+//! The active tiles of an observation can be used directly as the ID of a
+//! state for a tabular agent, or expanded by `TileCoder::features` into a
+//! binary feature vector for linear value approximation.
+
+use crate::errors::LearnerError;
+use crate::features::floor;
+use crate::internal::math::len_to_f64;
+use std::convert::TryFrom;
+
+/// Maps observations within fixed bounds onto tiles.
+///
+/// Each tiling has `tiles + 1` tiles along each dimension, rather than
+/// `tiles`, so that the observation space is still covered once a tiling has
+/// been offset. Observations outside of the bounds are clamped to them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileCoder {
+    lows: Vec<f64>,
+    widths: Vec<f64>,
+    tiles: usize,
+    tiles_per_tiling: usize,
+    offsets: Vec<Vec<f64>>,
+}
+
+impl TileCoder {
+    /// Returns a tile coder for observations that lie within `bounds`, given
+    /// as the `(low, high)` bounds of each dimension. Each dimension of a
+    /// tiling is divided into `tiles` tiles of equal width.
+    ///
+    /// The `tilings` tilings are offset from one another by the asymmetric
+    /// displacements recommended by Sutton and Barto: along dimension `j`,
+    /// tiling `t` is offset by `(t * (2j + 1)) mod tilings` `tilings`ths of a
+    /// tile. Using a power of two that is at least four times the number of
+    /// dimensions for `tilings` gives the best coverage.
+    pub fn new(bounds: &[(f64, f64)], tilings: usize, tiles: usize) -> Result<Self, LearnerError> {
+        let offsets = (0..tilings)
+            .map(|t| {
+                (0..bounds.len())
+                    .map(|j| len_to_f64((t * (2 * j + 1)) % tilings) / len_to_f64(tilings))
+                    .collect()
+            })
+            .collect();
+        Self::from_offsets(bounds, tiles, offsets)
+    }
+
+    /// Returns a tile coder whose tilings have the specified offsets. Each
+    /// element of `offsets` describes one tiling, with one offset per
+    /// dimension, given as a fraction of a tile in the range `[0, 1)`. See
+    /// `TileCoder::new` for a description of the remaining parameters.
+    pub fn from_offsets(
+        bounds: &[(f64, f64)],
+        tiles: usize,
+        offsets: Vec<Vec<f64>>,
+    ) -> Result<Self, LearnerError> {
+        if bounds.is_empty() {
+            return Err(invalid("at least one dimension is required"));
+        }
+        if let Some((low, high)) = bounds
+            .iter()
+            .find(|(low, high)| !low.is_finite() || !high.is_finite() || low >= high)
+        {
+            return Err(invalid(&format!(
+                "bounds ({low}, {high}) must be finite, with low < high"
+            )));
+        }
+        if tiles == 0 {
+            return Err(invalid("at least one tile per dimension is required"));
+        }
+        if offsets.is_empty() {
+            return Err(invalid("at least one tiling is required"));
+        }
+        if offsets.iter().any(|tiling| tiling.len() != bounds.len()) {
+            return Err(invalid(&format!(
+                "each tiling requires one offset for each of the {} dimensions",
+                bounds.len()
+            )));
+        }
+        if offsets.iter().flatten().any(|o| !(0.0..1.0).contains(o)) {
+            return Err(invalid("offsets must be in the range [0, 1)"));
+        }
+        let tiles_per_tiling = u32::try_from(bounds.len())
+            .ok()
+            .and_then(|dimensions| (tiles + 1).checked_pow(dimensions))
+            .filter(|n| n.checked_mul(offsets.len()).is_some())
+            .ok_or_else(|| invalid("too many tiles"))?;
+
+        Ok(Self {
+            lows: bounds.iter().map(|(low, _)| *low).collect(),
+            widths: bounds
+                .iter()
+                .map(|(low, high)| (high - low) / len_to_f64(tiles))
+                .collect(),
+            tiles,
+            tiles_per_tiling,
+            offsets,
+        })
+    }
+
+    /// Returns the number of dimensions of the observations that the coder
+    /// accepts.
+    pub fn dimensions(&self) -> usize {
+        self.lows.len()
+    }
+
+    /// Returns the number of tilings, which is also the number of tiles
+    /// activated by each observation.
+    pub fn tilings(&self) -> usize {
+        self.offsets.len()
+    }
+
+    /// Returns the total number of tiles across all tilings, which is the
+    /// length of the vectors returned by `features`.
+    pub fn feature_count(&self) -> usize {
+        self.tiles_per_tiling * self.tilings()
+    }
+
+    /// Returns the index of the tile that `observation` falls in for each
+    /// tiling, in order of tiling. Indices are unique across tilings, and are
+    /// less than `feature_count`.
+    pub fn active_tiles(&self, observation: &[f64]) -> Result<Vec<usize>, LearnerError> {
+        if observation.len() != self.dimensions() {
+            return Err(invalid(&format!(
+                "expected an observation with {} dimensions, got {}",
+                self.dimensions(),
+                observation.len()
+            )));
+        }
+        if let Some(x) = observation.iter().find(|x| !x.is_finite()) {
+            return Err(LearnerError::NonFinite(format!(
+                "observations must be finite, got {x}"
+            )));
+        }
+
+        let scaled: Vec<f64> = observation
+            .iter()
+            .zip(self.lows.iter().zip(&self.widths))
+            .map(|(x, (low, width))| ((x - low) / width).clamp(0.0, len_to_f64(self.tiles)))
+            .collect();
+        Ok(self
+            .offsets
+            .iter()
+            .enumerate()
+            .map(|(t, offsets)| {
+                let tile = scaled
+                    .iter()
+                    .zip(offsets)
+                    .rev()
+                    .fold(0, |tile, (x, offset)| {
+                        tile * (self.tiles + 1) + floor(x + offset).min(self.tiles)
+                    });
+                t * self.tiles_per_tiling + tile
+            })
+            .collect())
+    }
+
+    /// Returns a binary feature vector of length `feature_count`, in which
+    /// the active tiles of `observation` are 1 and all others are 0.
+    pub fn features(&self, observation: &[f64]) -> Result<Vec<f64>, LearnerError> {
+        let mut features = vec![0.0; self.feature_count()];
+        for tile in self.active_tiles(observation)? {
+            features[tile] = 1.0;
+        }
+        Ok(features)
+    }
+}
+
+fn invalid(reason: &str) -> LearnerError {
+    LearnerError::InvalidArgument(format!("invalid tile coding: {reason}"))
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn active_tiles() {
+        let coder = TileCoder::new(&[(0.0, 1.0)], 2, 4).unwrap();
+        assert_eq!(1, coder.dimensions());
+        assert_eq!(2, coder.tilings());
+        assert_eq!(10, coder.feature_count());
+
+        // The second tiling is offset by half a tile, so nearby observations
+        // share a tile in one tiling, but not the other.
+        assert_eq!(vec![1, 6], coder.active_tiles(&[0.3]).unwrap());
+        assert_eq!(vec![1, 7], coder.active_tiles(&[0.4]).unwrap());
+
+        // Observations are clamped to the bounds.
+        assert_eq!(vec![0, 5], coder.active_tiles(&[-3.0]).unwrap());
+        assert_eq!(vec![4, 9], coder.active_tiles(&[1.0]).unwrap());
+        assert_eq!(vec![4, 9], coder.active_tiles(&[7.0]).unwrap());
+    }
+
+    #[test]
+    fn active_tiles_in_several_dimensions() {
+        let coder =
+            TileCoder::from_offsets(&[(0.0, 1.0), (-1.0, 1.0)], 2, vec![vec![0.0; 2]]).unwrap();
+        assert_eq!(9, coder.feature_count());
+        assert_eq!(vec![1], coder.active_tiles(&[0.75, -0.5]).unwrap());
+        assert_eq!(vec![3], coder.active_tiles(&[0.25, 0.5]).unwrap());
+    }
+
+    #[test]
+    fn features() {
+        let coder = TileCoder::new(&[(0.0, 1.0)], 2, 4).unwrap();
+        let features = coder.features(&[0.3]).unwrap();
+        assert_eq!(
+            vec![0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0],
+            features
+        );
+    }
+
+    #[test]
+    fn invalid_arguments() {
+        assert!(TileCoder::new(&[], 1, 1).is_err());
+        assert!(TileCoder::new(&[(1.0, 1.0)], 1, 1).is_err());
+        assert!(TileCoder::new(&[(0.0, f64::INFINITY)], 1, 1).is_err());
+        assert!(TileCoder::new(&[(0.0, 1.0)], 0, 1).is_err());
+        assert!(TileCoder::new(&[(0.0, 1.0)], 1, 0).is_err());
+        assert!(TileCoder::new(&[(0.0, 1.0); 64], 1, 4).is_err());
+        assert!(TileCoder::from_offsets(&[(0.0, 1.0)], 1, vec![vec![1.0]]).is_err());
+        assert!(TileCoder::from_offsets(&[(0.0, 1.0)], 1, vec![vec![0.0, 0.0]]).is_err());
+
+        let coder = TileCoder::new(&[(0.0, 1.0)], 1, 1).unwrap();
+        assert!(matches!(
+            coder.active_tiles(&[0.0, 0.0]),
+            Err(LearnerError::InvalidArgument(_))
+        ));
+        assert!(matches!(
+            coder.active_tiles(&[f64::NAN]),
+            Err(LearnerError::NonFinite(_))
+        ));
+    }
+}
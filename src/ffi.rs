@@ -0,0 +1,384 @@
+//! A C interface, enabled by the `ffi` feature.
+//!
+//! The interface exposes `bayesian::Agent` as an opaque `RlrAgent`, for use
+//! from C, C++, or any other language that can call C functions. The
+//! declarations are in `include/rlr.h`.
+//!
+//! States and actions are identified by NUL-terminated UTF-8 strings. A
+//! state is described by its ID together with an array of the IDs of the
+//! actions it permits. Functions that can fail return an `RlrStatus`, and a
+//! description of the most recent failure on the calling thread is available
+//! from `rlr_last_error`.
+//!
+//! ```c
+//! RlrAgent *agent = rlr_agent_new(1, 0.5, 0.9);
+//! const char *actions[] = {"left", "right"};
+//! size_t choice;
+//! if (rlr_agent_recommend(agent, "s0", actions, 2, &choice) != RLR_OK) {
+//!     fprintf(stderr, "%s\n", rlr_last_error());
+//! }
+//! rlr_agent_learn(agent, "s0", actions, 2, actions[choice], "s1", actions, 2, 1.0);
+//! rlr_agent_free(agent);
+//! ```
+//!
+//! An `RlrAgent` must only be used by one thread at a time.
+
+use crate::actions::Actioner;
+use crate::agents::bayesian::Agent;
+use crate::agents::Agenter;
+use crate::internal::keyed::{self, KeyedAction, KeyedState};
+use crate::stats::actionstats::Stats;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::{ptr, slice};
+
+/// The result of a call to a function of the C interface.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RlrStatus {
+    /// The call succeeded.
+    Ok = 0,
+
+    /// A required pointer was null.
+    NullPointer = 1,
+
+    /// A string was not valid UTF-8.
+    InvalidUtf8 = 2,
+
+    /// The agent reported an error. See `rlr_last_error`.
+    Error = 3,
+}
+
+/// An agent that learns about states and actions identified by strings.
+/// Instances are created by `rlr_agent_new` or `rlr_agent_restore`, and must
+/// be released with `rlr_agent_free`.
+pub struct RlrAgent {
+    agent: Agent<'static, KeyedState, KeyedAction, Stats>,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Records `message` as the most recent failure on this thread, returning
+/// `status` for convenience.
+fn fail(status: RlrStatus, message: &str) -> RlrStatus {
+    // Messages never contain NUL, except from a caller's invalid string.
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+    status
+}
+
+unsafe fn to_str<'s>(ptr: *const c_char, name: &str) -> Result<&'s str, RlrStatus> {
+    if ptr.is_null() {
+        return Err(fail(RlrStatus::NullPointer, &format!("{name} is null")));
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|e| fail(RlrStatus::InvalidUtf8, &format!("{name} is not UTF-8: {e}")))
+}
+
+unsafe fn to_state(
+    id: *const c_char,
+    action_ids: *const *const c_char,
+    action_count: usize,
+) -> Result<KeyedState, RlrStatus> {
+    let id = to_str(id, "state_id")?;
+    if action_count == 0 {
+        return Ok(KeyedState::new(id.to_string(), Vec::<&str>::new()));
+    }
+    if action_ids.is_null() {
+        return Err(fail(RlrStatus::NullPointer, "action_ids is null"));
+    }
+    let action_ids = slice::from_raw_parts(action_ids, action_count)
+        .iter()
+        .map(|&a| to_str(a, "action_id"))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(KeyedState::new(id.to_string(), action_ids))
+}
+
+unsafe fn agent_mut<'r>(agent: *mut RlrAgent) -> Result<&'r mut RlrAgent, RlrStatus> {
+    agent
+        .as_mut()
+        .ok_or_else(|| fail(RlrStatus::NullPointer, "agent is null"))
+}
+
+/// Returns a new agent. See `bayesian::Agent::new` for a description of the
+/// parameters.
+#[no_mangle]
+pub extern "C" fn rlr_agent_new(
+    priming_threshold: i32,
+    learning_rate: f64,
+    discount_factor: f64,
+) -> *mut RlrAgent {
+    Box::into_raw(Box::new(RlrAgent {
+        agent: Agent::new(priming_threshold, learning_rate, discount_factor),
+    }))
+}
+
+/// Releases an agent. Passing null does nothing.
+///
+/// # Safety
+///
+/// `agent` must be null or a pointer returned by `rlr_agent_new` or
+/// `rlr_agent_restore` that has not already been released.
+#[no_mangle]
+pub unsafe extern "C" fn rlr_agent_free(agent: *mut RlrAgent) {
+    if !agent.is_null() {
+        drop(Box::from_raw(agent));
+    }
+}
+
+/// Recommends one of a state's actions, writing the index of the action
+/// within `action_ids` to `out_index`.
+///
+/// # Safety
+///
+/// `agent` must be a live agent, `state_id` a NUL-terminated string,
+/// `action_ids` an array of `action_count` NUL-terminated strings, and
+/// `out_index` valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rlr_agent_recommend(
+    agent: *mut RlrAgent,
+    state_id: *const c_char,
+    action_ids: *const *const c_char,
+    action_count: usize,
+    out_index: *mut usize,
+) -> RlrStatus {
+    let result = (|| {
+        let agent = agent_mut(agent)?;
+        let state = to_state(state_id, action_ids, action_count)?;
+        if out_index.is_null() {
+            return Err(fail(RlrStatus::NullPointer, "out_index is null"));
+        }
+        let action = agent
+            .agent
+            .recommend_action(&state)
+            .map_err(|e| fail(RlrStatus::Error, &e.to_string()))?;
+        let action_id = action.id();
+        let index = slice::from_raw_parts(action_ids, action_count)
+            .iter()
+            .position(|&a| CStr::from_ptr(a).to_str() == Ok(action_id.as_str()))
+            .ok_or_else(|| {
+                fail(
+                    RlrStatus::Error,
+                    &format!("recommended action {action_id:?} is not one of the state's actions"),
+                )
+            })?;
+        *out_index = index;
+        Ok(())
+    })();
+    result.err().unwrap_or(RlrStatus::Ok)
+}
+
+/// Updates the agent's q-value for taking `action_id` in the previous state,
+/// which led to the current state and earned `reward`.
+///
+/// `previous_state_id` can be null at the start of an episode, in which case the previous
+/// state's actions are ignored.
+///
+/// # Safety
+///
+/// `agent` must be a live agent. `previous_state_id` must be null or a
+/// NUL-terminated string, and `action_id` and `current_state_id` must be
+/// NUL-terminated strings. Each array of action IDs must contain the
+/// specified number of NUL-terminated strings.
+#[no_mangle]
+#[allow(clippy::too_many_arguments)]
+pub unsafe extern "C" fn rlr_agent_learn(
+    agent: *mut RlrAgent,
+    previous_state_id: *const c_char,
+    previous_action_ids: *const *const c_char,
+    previous_action_count: usize,
+    action_id: *const c_char,
+    current_state_id: *const c_char,
+    current_action_ids: *const *const c_char,
+    current_action_count: usize,
+    reward: f64,
+) -> RlrStatus {
+    let result = (|| {
+        let agent = agent_mut(agent)?;
+        let previous_state = if previous_state_id.is_null() {
+            None
+        } else {
+            Some(to_state(
+                previous_state_id,
+                previous_action_ids,
+                previous_action_count,
+            )?)
+        };
+        let action = keyed::intern(to_str(action_id, "action_id")?);
+        let current_state = to_state(current_state_id, current_action_ids, current_action_count)?;
+        agent
+            .agent
+            .learn(previous_state.as_ref(), action, &current_state, reward)
+            .map_err(|e| fail(RlrStatus::Error, &e.to_string()))
+    })();
+    result.err().unwrap_or(RlrStatus::Ok)
+}
+
+/// Writes a binary snapshot of the agent (see `bayesian::Agent::save_to`) to
+/// a new buffer.
+///
+/// The buffer's address is stored in `out_data` and its length in `out_len`.
+/// The buffer must be released with `rlr_bytes_free`.
+///
+/// # Safety
+///
+/// `agent` must be a live agent, and `out_data` and `out_len` must be valid
+/// for writes.
+#[no_mangle]
+pub unsafe extern "C" fn rlr_agent_snapshot(
+    agent: *const RlrAgent,
+    out_data: *mut *mut u8,
+    out_len: *mut usize,
+) -> RlrStatus {
+    let result = (|| {
+        let agent = agent
+            .as_ref()
+            .ok_or_else(|| fail(RlrStatus::NullPointer, "agent is null"))?;
+        if out_data.is_null() || out_len.is_null() {
+            return Err(fail(RlrStatus::NullPointer, "out_data or out_len is null"));
+        }
+        let mut snapshot = Vec::new();
+        agent
+            .agent
+            .save_to(&mut snapshot)
+            .map_err(|e| fail(RlrStatus::Error, &e.to_string()))?;
+        let snapshot = Box::into_raw(snapshot.into_boxed_slice());
+        *out_len = snapshot.len();
+        *out_data = snapshot.cast::<u8>();
+        Ok(())
+    })();
+    result.err().unwrap_or(RlrStatus::Ok)
+}
+
+/// Returns a new agent restored from a snapshot written by
+/// `rlr_agent_snapshot`, or null if the snapshot cannot be read.
+///
+/// # Safety
+///
+/// `data` must be valid for reads of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn rlr_agent_restore(data: *const u8, len: usize) -> *mut RlrAgent {
+    if data.is_null() {
+        fail(RlrStatus::NullPointer, "data is null");
+        return ptr::null_mut();
+    }
+    match Agent::load_from(slice::from_raw_parts(data, len)) {
+        Ok(agent) => Box::into_raw(Box::new(RlrAgent { agent })),
+        Err(e) => {
+            fail(RlrStatus::Error, &e.to_string());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Releases a buffer returned by `rlr_agent_snapshot`. Passing null does
+/// nothing.
+///
+/// # Safety
+///
+/// `data` must be null or a buffer returned by `rlr_agent_snapshot` that has
+/// not already been released, and `len` must be its length.
+#[no_mangle]
+pub unsafe extern "C" fn rlr_bytes_free(data: *mut u8, len: usize) {
+    if !data.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(data, len)));
+    }
+}
+
+/// Returns a description of the most recent failure on the calling thread,
+/// or null if no call has failed. The string remains valid until the next
+/// failure on the same thread.
+#[no_mangle]
+pub extern "C" fn rlr_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |m| m.as_ptr()))
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<CString> {
+        values.iter().map(|v| CString::new(*v).unwrap()).collect()
+    }
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(rlr_last_error()) }
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn recommend_learn_and_snapshot() {
+        let ids = strings(&["s0", "s1", "left", "right"]);
+        let actions = [ids[2].as_ptr(), ids[3].as_ptr()];
+        unsafe {
+            let agent = rlr_agent_new(0, 1.0, 0.0);
+            let status = rlr_agent_learn(
+                agent,
+                ids[0].as_ptr(),
+                actions.as_ptr(),
+                2,
+                ids[3].as_ptr(),
+                ids[1].as_ptr(),
+                actions.as_ptr(),
+                2,
+                1.0,
+            );
+            assert_eq!(RlrStatus::Ok, status);
+
+            let mut index = usize::MAX;
+            let status =
+                rlr_agent_recommend(agent, ids[0].as_ptr(), actions.as_ptr(), 2, &raw mut index);
+            assert_eq!(RlrStatus::Ok, status);
+            assert_eq!(1, index);
+
+            let (mut data, mut len) = (ptr::null_mut(), 0);
+            assert_eq!(
+                RlrStatus::Ok,
+                rlr_agent_snapshot(agent, &raw mut data, &raw mut len)
+            );
+            rlr_agent_free(agent);
+
+            let restored = rlr_agent_restore(data, len);
+            rlr_bytes_free(data, len);
+            assert!(!restored.is_null());
+            let mut index = usize::MAX;
+            let status = rlr_agent_recommend(
+                restored,
+                ids[0].as_ptr(),
+                actions.as_ptr(),
+                2,
+                &raw mut index,
+            );
+            assert_eq!(RlrStatus::Ok, status);
+            assert_eq!(1, index);
+            rlr_agent_free(restored);
+        }
+    }
+
+    #[test]
+    fn failures_are_reported() {
+        let ids = strings(&["s0"]);
+        unsafe {
+            let agent = rlr_agent_new(1, 0.5, 0.9);
+            let mut index = 0;
+            let status =
+                rlr_agent_recommend(agent, ids[0].as_ptr(), ptr::null(), 0, &raw mut index);
+            assert_eq!(RlrStatus::Error, status);
+            assert_eq!("state \"s0\" reports no possible actions", last_error());
+
+            let status = rlr_agent_recommend(agent, ptr::null(), ptr::null(), 0, &raw mut index);
+            assert_eq!(RlrStatus::NullPointer, status);
+            assert_eq!("state_id is null", last_error());
+            rlr_agent_free(agent);
+
+            assert!(rlr_agent_restore([1, 2, 3].as_ptr(), 3).is_null());
+        }
+    }
+}
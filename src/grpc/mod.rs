@@ -0,0 +1,399 @@
+//! A gRPC service that shares a single learning agent between clients,
+//! enabled by the `grpc` feature.
+//!
+//! The service is declared in `proto/agent.proto`, from which clients in
+//! other languages can be generated. It offers three calls:
+//!
+//! - `Recommend` returns the action that the agent recommends for a state.
+//! - `Learn` updates the agent from a `Transition`.
+//! - `Snapshot` returns a snapshot of the agent, as written by
+//!   `bayesian::Agent::save_to`.
+//!
+//! States and actions are identified by strings, and each state is sent
+//! along with the IDs of the actions it permits.
+//!
+//! `AgentService` can be added to a `tonic` server alongside other services.
+//! The `rlr-grpc` binary serves one on its own.
+//!
+//! The agent runs on a dedicated thread, and handles calls one at a time in
+//! the order they arrive, so the agent never sees a partial update.
+
+pub mod proto;
+
+use crate::actions::Actioner;
+use crate::agents::bayesian::Agent;
+use crate::agents::Agenter;
+use crate::errors::LearnerError;
+use crate::internal::keyed::{KeyedAction, KeyedState};
+use crate::states::Stater;
+use crate::stats::actionstats::Stats;
+use proto::{
+    LearnResponse, RecommendRequest, RecommendResponse, SnapshotRequest, SnapshotResponse,
+    Transition,
+};
+use std::convert::Infallible;
+use std::future::Future;
+use std::sync::mpsc;
+use std::task::{Context, Poll};
+use std::thread;
+use tokio::sync::oneshot;
+use tonic::body::BoxBody;
+use tonic::codec::ProstCodec;
+use tonic::codegen::{http, Body, BoxFuture, Service, StdError};
+use tonic::server::{Grpc, NamedService, UnaryService};
+use tonic::{Request, Response, Status};
+
+type KeyedAgent = Agent<'static, KeyedState, KeyedAction, Stats>;
+
+type Reply<T> = oneshot::Sender<Result<T, LearnerError>>;
+
+/// A call for the agent's thread to handle.
+enum Command {
+    Recommend(KeyedState, Reply<String>),
+    Learn(
+        Option<KeyedState>,
+        &'static KeyedAction,
+        KeyedState,
+        f64,
+        Reply<()>,
+    ),
+    Snapshot(Reply<Vec<u8>>),
+}
+
+/// A gRPC service that recommends actions from, and teaches, a single agent.
+///
+/// Clones of the service share the same agent. The agent's thread stops once
+/// every clone has been dropped.
+#[derive(Debug, Clone)]
+pub struct AgentService {
+    commands: mpsc::Sender<Command>,
+}
+
+impl AgentService {
+    /// Returns a new service for a new agent. See `bayesian::Agent::new` for
+    /// a description of the parameters.
+    /// An error is returned if the agent's thread cannot be started.
+    pub fn new(
+        priming_threshold: i32,
+        learning_rate: f64,
+        discount_factor: f64,
+    ) -> Result<Self, LearnerError> {
+        Self::spawn(move || {
+            Ok(Agent::new(
+                priming_threshold,
+                learning_rate,
+                discount_factor,
+            ))
+        })
+    }
+
+    /// Returns a new service for an agent restored from a snapshot, such as
+    /// one returned by the `Snapshot` call.
+    /// An error is returned if the snapshot cannot be read, or if the agent's
+    /// thread cannot be started.
+    pub fn restore(snapshot: Vec<u8>) -> Result<Self, LearnerError> {
+        Self::spawn(move || Agent::load_from(snapshot.as_slice()))
+    }
+
+    /// Starts a thread that owns the agent returned by `make_agent`, and
+    /// handles commands until every sender has been dropped.
+    fn spawn<F>(make_agent: F) -> Result<Self, LearnerError>
+    where
+        F: FnOnce() -> Result<KeyedAgent, LearnerError> + Send + 'static,
+    {
+        let (commands, receiver) = mpsc::channel();
+        let (ready, started) = mpsc::sync_channel(1);
+        thread::Builder::new()
+            .name("rlr-agent".to_string())
+            .spawn(move || {
+                let mut agent = match make_agent() {
+                    Ok(agent) => agent,
+                    Err(err) => {
+                        let _ = ready.send(Err(err));
+                        return;
+                    }
+                };
+                let _ = ready.send(Ok(()));
+                for command in receiver {
+                    handle(&mut agent, command);
+                }
+            })
+            .map_err(|e| LearnerError::Other(format!("unable to start the agent thread: {e}")))?;
+        started
+            .recv()
+            .map_err(|e| LearnerError::Other(format!("the agent thread stopped: {e}")))??;
+        Ok(Self { commands })
+    }
+
+    /// Sends a command to the agent's thread, and waits for its reply.
+    async fn call<T, F>(&self, command: F) -> Result<T, Status>
+    where
+        F: FnOnce(Reply<T>) -> Command,
+    {
+        let (reply, response) = oneshot::channel();
+        self.commands
+            .send(command(reply))
+            .map_err(|_| Status::unavailable("the agent has stopped"))?;
+        response
+            .await
+            .map_err(|_| Status::unavailable("the agent has stopped"))?
+            .map_err(|e| to_status(&e))
+    }
+
+    async fn recommend(
+        self,
+        request: Request<RecommendRequest>,
+    ) -> Result<Response<RecommendResponse>, Status> {
+        let state = request.into_inner().state.ok_or_else(|| missing("state"))?;
+        let state = to_state(state);
+        let action_id = self.call(|reply| Command::Recommend(state, reply)).await?;
+        Ok(Response::new(RecommendResponse { action_id }))
+    }
+
+    async fn learn(self, request: Request<Transition>) -> Result<Response<LearnResponse>, Status> {
+        let transition = request.into_inner();
+        let current_state = transition
+            .current_state
+            .ok_or_else(|| missing("current_state"))?;
+        let current_state = to_state(current_state);
+        let previous_state = transition.previous_state.map(to_state);
+        // The action is looked up among the states' actions rather than
+        // interned, so that clients cannot allocate an action for every ID
+        // they send.
+        let action = previous_state
+            .as_ref()
+            .unwrap_or(&current_state)
+            .get_action(&transition.action_id)
+            .map_err(|e| to_status(&e))?;
+        let reward = transition.reward;
+        self.call(|reply| Command::Learn(previous_state, action, current_state, reward, reply))
+            .await?;
+        Ok(Response::new(LearnResponse {}))
+    }
+
+    async fn snapshot(
+        self,
+        _: Request<SnapshotRequest>,
+    ) -> Result<Response<SnapshotResponse>, Status> {
+        let snapshot = self.call(Command::Snapshot).await?;
+        Ok(Response::new(SnapshotResponse { snapshot }))
+    }
+}
+
+fn handle(agent: &mut KeyedAgent, command: Command) {
+    match command {
+        Command::Recommend(state, reply) => {
+            let _ = reply.send(agent.recommend_action(&state).map(Actioner::id));
+        }
+        Command::Learn(previous_state, action, current_state, reward, reply) => {
+            let _ =
+                reply.send(agent.learn(previous_state.as_ref(), action, &current_state, reward));
+        }
+        Command::Snapshot(reply) => {
+            let mut snapshot = Vec::new();
+            let _ = reply.send(agent.save_to(&mut snapshot).map(|()| snapshot));
+        }
+    }
+}
+
+fn to_state(state: proto::State) -> KeyedState {
+    KeyedState::new(state.id, state.action_ids)
+}
+
+fn missing(field: &str) -> Status {
+    Status::invalid_argument(format!("{field} is required"))
+}
+
+fn to_status(err: &LearnerError) -> Status {
+    let message = err.to_string();
+    match err {
+        LearnerError::ActionNotCompatible { .. }
+        | LearnerError::NoPossibleActions { .. }
+        | LearnerError::ActionNotFound { .. }
+        | LearnerError::NonFinite(_)
+        | LearnerError::InvalidArgument(_) => Status::invalid_argument(message),
+        LearnerError::Lifecycle(_) => Status::failed_precondition(message),
+        _ => Status::internal(message),
+    }
+}
+
+/// Adapts a function to `UnaryService`.
+struct Unary<F>(F);
+
+impl<F, M, R, Fut> UnaryService<M> for Unary<F>
+where
+    F: FnMut(Request<M>) -> Fut,
+    Fut: Future<Output = Result<Response<R>, Status>>,
+{
+    type Response = R;
+    type Future = Fut;
+
+    fn call(&mut self, request: Request<M>) -> Fut {
+        (self.0)(request)
+    }
+}
+
+/// Decodes a request for a unary call, passes it to `handler`, and encodes
+/// the handler's response.
+fn unary<B, M, R, F, Fut>(
+    request: http::Request<B>,
+    handler: F,
+) -> BoxFuture<http::Response<BoxBody>, Infallible>
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+    M: prost::Message + Default + Send + 'static,
+    R: prost::Message + Send + 'static,
+    F: FnMut(Request<M>) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<Response<R>, Status>> + Send + 'static,
+{
+    Box::pin(async move {
+        let mut grpc = Grpc::new(ProstCodec::<R, M>::default());
+        Ok(grpc.unary(Unary(handler), request).await)
+    })
+}
+
+impl<B> Service<http::Request<B>> for AgentService
+where
+    B: Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: http::Request<B>) -> Self::Future {
+        let service = self.clone();
+        match request.uri().path() {
+            "/rlr.v1.Agent/Recommend" => unary(request, move |r| service.clone().recommend(r)),
+            "/rlr.v1.Agent/Learn" => unary(request, move |r| service.clone().learn(r)),
+            "/rlr.v1.Agent/Snapshot" => unary(request, move |r| service.clone().snapshot(r)),
+            path => {
+                let status = Status::unimplemented(format!("unknown method {path}"));
+                Box::pin(async move { Ok(status.into_http()) })
+            }
+        }
+    }
+}
+
+impl NamedService for AgentService {
+    const NAME: &'static str = "rlr.v1.Agent";
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+    use tonic::client;
+    use tonic::codegen::http::uri::PathAndQuery;
+    use tonic::transport::server::TcpIncoming;
+    use tonic::transport::{Channel, Server};
+
+    fn state(id: &str) -> proto::State {
+        proto::State {
+            id: id.to_string(),
+            action_ids: vec!["left".to_string(), "right".to_string()],
+        }
+    }
+
+    async fn call<M, R>(
+        client: &mut client::Grpc<Channel>,
+        path: &'static str,
+        message: M,
+    ) -> Result<R, Status>
+    where
+        M: prost::Message + Send + Sync + 'static,
+        R: prost::Message + Default + Send + Sync + 'static,
+    {
+        client.ready().await.unwrap();
+        let codec = ProstCodec::<M, R>::default();
+        let path = PathAndQuery::from_static(path);
+        let response = client.unary(Request::new(message), path, codec).await?;
+        Ok(response.into_inner())
+    }
+
+    #[test]
+    fn serve() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+            let incoming = TcpIncoming::from_listener(listener, true, None).unwrap();
+            let service = AgentService::new(0, 1.0, 0.0).unwrap();
+            tokio::spawn(
+                Server::builder()
+                    .add_service(service)
+                    .serve_with_incoming(incoming),
+            );
+            let mut client = client::Grpc::new(
+                Channel::from_shared(format!("http://{address}"))
+                    .unwrap()
+                    .connect()
+                    .await
+                    .unwrap(),
+            );
+
+            let transition = Transition {
+                previous_state: Some(state("s0")),
+                action_id: "right".to_string(),
+                current_state: Some(state("s1")),
+                reward: 1.0,
+            };
+            let _: LearnResponse = call(&mut client, "/rlr.v1.Agent/Learn", transition)
+                .await
+                .unwrap();
+
+            let request = RecommendRequest {
+                state: Some(state("s0")),
+            };
+            let response: RecommendResponse = call(&mut client, "/rlr.v1.Agent/Recommend", request)
+                .await
+                .unwrap();
+            assert_eq!("right", response.action_id);
+
+            let err = call::<_, RecommendResponse>(
+                &mut client,
+                "/rlr.v1.Agent/Recommend",
+                RecommendRequest { state: None },
+            )
+            .await
+            .unwrap_err();
+            assert_eq!(tonic::Code::InvalidArgument, err.code());
+
+            let transition = Transition {
+                previous_state: None,
+                action_id: "up".to_string(),
+                current_state: Some(state("s0")),
+                reward: 1.0,
+            };
+            let err = call::<_, LearnResponse>(&mut client, "/rlr.v1.Agent/Learn", transition)
+                .await
+                .unwrap_err();
+            assert_eq!(tonic::Code::InvalidArgument, err.code());
+
+            let response: SnapshotResponse =
+                call(&mut client, "/rlr.v1.Agent/Snapshot", SnapshotRequest {})
+                    .await
+                    .unwrap();
+            drop(client);
+
+            let restored = AgentService::restore(response.snapshot).unwrap();
+            let request = Request::new(RecommendRequest {
+                state: Some(state("s0")),
+            });
+            let response = restored.recommend(request).await.unwrap();
+            assert_eq!("right", response.into_inner().action_id);
+        });
+    }
+
+    #[test]
+    fn restore_invalid_snapshot() {
+        let result = AgentService::restore(vec![1, 2, 3]);
+        assert!(matches!(result, Err(LearnerError::Serialization(_))));
+    }
+}
@@ -0,0 +1,72 @@
+//! The messages of the `rlr.v1` protobuf package, as declared in
+//! `proto/agent.proto`.
+//!
+//! The messages are declared by hand rather than generated, so that building
+//! the crate does not require `protoc`. They must be kept in step with the
+//! `.proto` file.
+
+/// A state, identified by its ID, along with the IDs of the actions it
+/// permits.
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct State {
+    /// The ID of the state.
+    #[prost(string, tag = "1")]
+    pub id: String,
+
+    /// The IDs of the actions that the state permits.
+    #[prost(string, repeated, tag = "2")]
+    pub action_ids: Vec<String>,
+}
+
+/// A request for the action that the agent recommends for a state.
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct RecommendRequest {
+    /// The state to recommend an action for.
+    #[prost(message, optional, tag = "1")]
+    pub state: Option<State>,
+}
+
+/// The action that the agent recommends.
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct RecommendResponse {
+    /// The ID of the recommended action.
+    #[prost(string, tag = "1")]
+    pub action_id: String,
+}
+
+/// The outcome of taking an action in a state.
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Transition {
+    /// The state in which the action was taken, or `None` at the start of an
+    /// episode.
+    #[prost(message, optional, tag = "1")]
+    pub previous_state: Option<State>,
+
+    /// The ID of the action that was taken.
+    #[prost(string, tag = "2")]
+    pub action_id: String,
+
+    /// The state that the action led to.
+    #[prost(message, optional, tag = "3")]
+    pub current_state: Option<State>,
+
+    /// The reward that the action earned.
+    #[prost(double, tag = "4")]
+    pub reward: f64,
+}
+
+/// The response to a `Learn` call.
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct LearnResponse {}
+
+/// A request for a snapshot of the agent.
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct SnapshotRequest {}
+
+/// A snapshot of the agent.
+#[derive(Clone, PartialEq, Eq, prost::Message)]
+pub struct SnapshotResponse {
+    /// The snapshot, as written by `bayesian::Agent::save_to`.
+    #[prost(bytes = "vec", tag = "1")]
+    pub snapshot: Vec<u8>,
+}
@@ -1,53 +1,268 @@
-use crate::actions::Actioner;
-use crate::states::Stater;
+use crate::errors::LearnerError;
 use crate::stats::ActionStatter;
-use std::{collections::HashMap, marker};
+use crate::stores::QStore;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::mem::size_of;
 
+/// An in-memory `QStore`. This is the store that agents use by default.
+///
+/// The map caches the sum of the raw q-values within each state that it has
+/// written to, so that `raw_q_sum` does not need to read every action.
 #[derive(Clone)]
-pub struct QMap<'a, S, A, AS>
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct QMap<SK, AK, AS>
 where
-    A: Actioner<'a>,
-    S: Stater<'a, A>,
+    SK: Hash + Eq,
+    AK: Hash + Eq,
     AS: ActionStatter,
 {
-    #[allow(dead_code)]
-    pub(crate) data: HashMap<&'a str, HashMap<&'a str, Box<AS>>>,
-    _actioner: marker::PhantomData<A>,
-    _stater: marker::PhantomData<S>,
+    pub(crate) data: HashMap<SK, HashMap<AK, AS>>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) visits: HashMap<SK, u64>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    raw_sums: HashMap<SK, f64>,
 }
 
-impl<'a, S, A, AS> QMap<'a, S, A, AS>
+impl<SK, AK, AS> QMap<SK, AK, AS>
 where
-    A: Actioner<'a>,
-    S: Stater<'a, A>,
+    SK: Hash + Eq,
+    AK: Hash + Eq,
     AS: ActionStatter,
 {
-    #[allow(dead_code)]
-    pub(crate) fn new() -> QMap<'a, S, A, AS> {
-        QMap {
+    /// Returns a new, empty `QMap`.
+    pub fn new() -> Self {
+        Self {
             data: HashMap::new(),
-            _actioner: marker::PhantomData {},
-            _stater: marker::PhantomData {},
+            visits: HashMap::new(),
+            raw_sums: HashMap::new(),
         }
     }
 
-    #[allow(dead_code)]
-    pub(crate) fn get_stats(&mut self, state: &'a S, action: &'a A) -> Option<Box<AS>> {
-        let actions = self.get_actions_for_state(state);
-        if let Some(stat) = actions.get(action.id()) {
-            return Some(Box::new(*stat.clone()));
+    /// Returns an iterator over the stats recorded for every state and action,
+    /// borrowing them from the map rather than cloning them. The order of
+    /// iteration is unspecified.
+    pub fn iter(&self) -> impl Iterator<Item = (&SK, &AK, &AS)> {
+        self.data.iter().flat_map(|(state_id, actions)| {
+            actions
+                .iter()
+                .map(move |(action_id, stats)| (state_id, action_id, stats))
+        })
+    }
+
+    /// Returns a reference to the stats recorded for an action within a
+    /// state, or `None` if no stats have been recorded. Unlike
+    /// `QStore::get_stats`, the stats are not cloned.
+    pub fn stats(&self, state_id: &SK, action_id: &AK) -> Option<&AS> {
+        self.data
+            .get(state_id)
+            .and_then(|actions| actions.get(action_id))
+    }
+
+    /// Returns a mutable reference to the stats recorded for an action within
+    /// a state, or `None` if no stats have been recorded. The stats can be
+    /// updated in place, without cloning them or writing them back.
+    pub fn stats_mut(&mut self, state_id: &SK, action_id: &AK) -> Option<&mut AS> {
+        // The caller may change the raw q-value, so the cached sum is
+        // discarded, and recalculated when the state is next written.
+        self.raw_sums.remove(state_id);
+        self.data
+            .get_mut(state_id)
+            .and_then(|actions| actions.get_mut(action_id))
+    }
+
+    /// Returns an estimate of the number of bytes of memory used by the map.
+    ///
+    /// The estimate accounts for the capacity allocated by each of the map's
+    /// tables and for the stats themselves, but not for any memory owned by
+    /// the keys (such as the contents of `String` IDs) or stats.
+    pub fn approx_memory_bytes(&self) -> usize {
+        let state_entry = size_of::<SK>() + size_of::<HashMap<AK, AS>>() + 1;
+        let action_entry = size_of::<AK>() + size_of::<AS>() + 1;
+        let visit_entry = size_of::<SK>() + size_of::<u64>() + 1;
+        let sum_entry = size_of::<SK>() + size_of::<f64>() + 1;
+        self.data.values().fold(
+            size_of::<Self>()
+                + self.data.capacity() * state_entry
+                + self.visits.capacity() * visit_entry
+                + self.raw_sums.capacity() * sum_entry,
+            |total, actions| total + actions.capacity() * action_entry,
+        )
+    }
+}
+
+impl<SK, AK, AS> QMap<SK, AK, AS>
+where
+    SK: Hash + Eq + Clone,
+    AK: Hash + Eq + Clone,
+    AS: ActionStatter,
+{
+    /// Records the stats for an action within a state, replacing the existing
+    /// stats in place if there are any.
+    fn set_stats(&mut self, state_id: &SK, action_id: &AK, stats: AS) {
+        let new = stats.q_value_raw();
+        let recorded = self
+            .data
+            .get_mut(state_id)
+            .and_then(|actions| actions.get_mut(action_id));
+        let old = if let Some(recorded) = recorded {
+            Some(std::mem::replace(recorded, stats).q_value_raw())
+        } else {
+            self.data
+                .entry(state_id.clone())
+                .or_default()
+                .insert(action_id.clone(), stats);
+            None
+        };
+        self.adjust_raw_sum(state_id, old, new);
+    }
+
+    /// Updates the cached raw q-value sum of a state after the raw q-value of
+    /// one of its actions changed from `old` (or was first recorded, if `old`
+    /// is `None`) to `new`. If the sum is not cached, it is calculated from
+    /// the state's current stats.
+    fn adjust_raw_sum(&mut self, state_id: &SK, old: Option<f64>, new: f64) {
+        if let Some(sum) = self.raw_sums.get_mut(state_id) {
+            *sum += new - old.unwrap_or_default();
+        } else if let Some(actions) = self.data.get(state_id) {
+            self.raw_sums.insert(state_id.clone(), sum_raw(actions));
+        }
+    }
+}
+
+/// Returns the sum of the raw q-values of a state's actions.
+fn sum_raw<AK, AS: ActionStatter>(actions: &HashMap<AK, AS>) -> f64 {
+    actions.values().map(ActionStatter::q_value_raw).sum()
+}
+
+impl<SK, AK, AS> Default for QMap<SK, AK, AS>
+where
+    SK: Hash + Eq,
+    AK: Hash + Eq,
+    AS: ActionStatter,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<SK, AK, AS> QStore<SK, AK, AS> for QMap<SK, AK, AS>
+where
+    SK: Hash + Eq + Clone,
+    AK: Hash + Eq + Clone,
+    AS: ActionStatter,
+{
+    fn get_stats(&self, state_id: &SK, action_id: &AK) -> Result<Option<AS>, LearnerError> {
+        Ok(self
+            .data
+            .get(state_id)
+            .and_then(|actions| actions.get(action_id))
+            .cloned())
+    }
+
+    fn update_stats(
+        &mut self,
+        state_id: &SK,
+        action_id: &AK,
+        stats: AS,
+    ) -> Result<(), LearnerError> {
+        self.set_stats(state_id, action_id, stats);
+        Ok(())
+    }
+
+    fn update_stats_with<D, F>(
+        &mut self,
+        state_id: &SK,
+        action_id: &AK,
+        default: D,
+        update: F,
+    ) -> Result<(), LearnerError>
+    where
+        D: FnOnce() -> AS,
+        F: FnOnce(&mut AS),
+    {
+        // Stats that are already recorded are updated in place, rather than
+        // being cloned and written back.
+        let recorded = self
+            .data
+            .get_mut(state_id)
+            .and_then(|actions| actions.get_mut(action_id));
+        if let Some(stats) = recorded {
+            let old = stats.q_value_raw();
+            update(stats);
+            let new = stats.q_value_raw();
+            self.adjust_raw_sum(state_id, Some(old), new);
+            return Ok(());
+        }
+        let mut stats = default();
+        update(&mut stats);
+        self.set_stats(state_id, action_id, stats);
+        Ok(())
+    }
+
+    fn get_actions_for_state(&self, state_id: &SK) -> Result<HashMap<AK, AS>, LearnerError> {
+        Ok(self
+            .data
+            .get(state_id)
+            .map(|actions| {
+                actions
+                    .iter()
+                    .map(|(action_id, stats)| (action_id.clone(), stats.clone()))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    fn visit_actions_for_state<F>(&self, state_id: &SK, mut visit: F) -> Result<(), LearnerError>
+    where
+        F: FnMut(&AK, &AS),
+    {
+        for (action_id, stats) in self.data.get(state_id).into_iter().flatten() {
+            visit(action_id, stats);
         }
-        None
+        Ok(())
+    }
+
+    fn update_actions_for_state(
+        &mut self,
+        state_id: &SK,
+        actions: HashMap<AK, AS>,
+    ) -> Result<(), LearnerError> {
+        for (action_id, stats) in actions {
+            self.set_stats(state_id, &action_id, stats);
+        }
+        Ok(())
+    }
+
+    fn raw_q_sum(&self, state_id: &SK) -> Result<(f64, usize), LearnerError> {
+        let Some(actions) = self.data.get(state_id) else {
+            return Ok((0.0, 0));
+        };
+        let sum = self
+            .raw_sums
+            .get(state_id)
+            .copied()
+            .unwrap_or_else(|| sum_raw(actions));
+        Ok((sum, actions.len()))
+    }
+
+    fn get_visits(&self, state_id: &SK) -> Result<u64, LearnerError> {
+        Ok(self.visits.get(state_id).copied().unwrap_or_default())
     }
 
-    #[allow(dead_code)]
-    pub(crate) fn update_stats(&mut self, state: &'a S, action: &'a A, stats: Box<AS>) {
-        self.get_actions_for_state(state).insert(action.id(), stats);
+    fn set_visits(&mut self, state_id: &SK, visits: u64) -> Result<(), LearnerError> {
+        self.visits.insert(state_id.clone(), visits);
+        Ok(())
     }
 
-    #[allow(dead_code)]
-    pub(crate) fn get_actions_for_state(&mut self, state: &'a S) -> &mut HashMap<&'a str, Box<AS>> {
-        self.data.entry(state.id()).or_insert_with(HashMap::new)
+    fn state_count(&self) -> Result<usize, LearnerError> {
+        Ok(self.data.len())
+    }
+
+    fn entry_count(&self) -> Result<usize, LearnerError> {
+        Ok(self.data.values().map(HashMap::len).sum())
     }
 }
 
@@ -55,56 +270,141 @@ where
 #[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
 mod tests {
     use crate::internal::datastructures::QMap;
-    use crate::mocks::*;
     use crate::stats::actionstats::Stats;
+    use crate::stores::QStore;
 
     #[test]
-    /// If the qmap does not contain any entries for a state, the state
-    /// should be added with an empty hashmap value.
+    /// If the qmap does not contain any entries for a state, an empty map
+    /// should be returned.
     fn get_actions_for_state() {
-        let state: MockStater<MockActioner> = MockStater {
-            return_id: "A",
-            return_possible_actions: vec![],
-            ..Default::default()
-        };
-
-        let mut qmap: QMap<MockStater<MockActioner>, MockActioner, Stats> = QMap::new();
-        let result = qmap.get_actions_for_state(&state);
+        let qmap: QMap<&str, &str, Stats> = QMap::new();
+        let result = qmap.get_actions_for_state(&"A").unwrap();
         assert_eq!(result.len(), 0, "state map must be empty");
     }
 
     #[test]
     fn get_stats_no_data() {
-        let action = MockActioner { return_id: "X" };
-
-        let state: MockStater<MockActioner> = MockStater {
-            return_id: "A",
-            return_possible_actions: vec![&action],
-            ..Default::default()
-        };
-
-        let mut qmap: QMap<MockStater<MockActioner>, MockActioner, Stats> = QMap::new();
-        let result = qmap.get_stats(&state, &action);
+        let qmap: QMap<&str, &str, Stats> = QMap::new();
+        let result = qmap.get_stats(&"A", &"X").unwrap();
 
         assert!(result.is_none(), "result should be None");
     }
 
     #[test]
     fn get_stats_state_has_data() {
-        let action = MockActioner { return_id: "X" };
+        let stats = Stats::default();
+
+        let mut qmap: QMap<&str, &str, Stats> = QMap::new();
+        qmap.update_stats(&"A", &"X", stats).unwrap();
+        let result = qmap.get_stats(&"A", &"X").unwrap();
+
+        assert!(result.is_some(), "result should be Some");
+    }
+
+    #[test]
+    fn update_stats_in_place() {
+        let mut qmap: QMap<&str, &str, Stats> = QMap::new();
+        assert!(qmap.stats(&"A", &"X").is_none());
+        assert!(qmap.stats_mut(&"A", &"X").is_none());
+
+        let default = || Stats {
+            call_count: 2,
+            ..Default::default()
+        };
+        qmap.update_stats_with(&"A", &"X", default, |stats| stats.call_count += 1)
+            .unwrap();
+        assert_eq!(3, qmap.stats(&"A", &"X").unwrap().call_count);
+
+        let before: *const Stats = qmap.stats(&"A", &"X").unwrap();
+        qmap.update_stats_with(&"A", &"X", default, |stats| stats.call_count += 1)
+            .unwrap();
+        qmap.stats_mut(&"A", &"X").unwrap().q_raw = 1.5;
+        qmap.update_stats(&"A", &"X", qmap.get_stats(&"A", &"X").unwrap().unwrap())
+            .unwrap();
+        let after = qmap.stats(&"A", &"X").unwrap();
+        assert_eq!(4, after.call_count);
+        assert_eq!(1.5, after.q_raw);
+        assert!(
+            std::ptr::eq(before, after),
+            "stats must be updated in place"
+        );
+    }
 
-        let state: MockStater<MockActioner> = MockStater {
-            return_id: "A",
-            return_possible_actions: vec![&action],
+    #[test]
+    fn raw_q_sum() {
+        let stats = |q_raw| Stats {
+            q_raw,
             ..Default::default()
         };
+        let mut qmap: QMap<&str, &str, Stats> = QMap::new();
+        assert_eq!((0.0, 0), qmap.raw_q_sum(&"A").unwrap());
 
-        let stats = Box::new(Stats::default());
+        qmap.update_stats(&"A", &"X", stats(1.0)).unwrap();
+        qmap.update_stats(&"A", &"Y", stats(2.0)).unwrap();
+        assert_eq!((3.0, 2), qmap.raw_q_sum(&"A").unwrap());
 
-        let mut qmap: QMap<MockStater<MockActioner>, MockActioner, Stats> = QMap::new();
-        qmap.update_stats(&state, &action, stats);
-        let result = qmap.get_stats(&state, &action);
+        qmap.update_stats(&"A", &"X", stats(4.0)).unwrap();
+        qmap.update_stats_with(&"A", &"Y", Stats::default, |s| s.q_raw -= 1.0)
+            .unwrap();
+        assert_eq!((5.0, 2), qmap.raw_q_sum(&"A").unwrap());
 
-        assert!(result.is_some(), "result should be Some");
+        qmap.stats_mut(&"A", &"X").unwrap().q_raw = 0.5;
+        assert_eq!((1.5, 2), qmap.raw_q_sum(&"A").unwrap());
+
+        let actions = maplit::hashmap! { "Y" => stats(3.0), "Z" => stats(-1.0) };
+        qmap.update_actions_for_state(&"A", actions).unwrap();
+        assert_eq!((2.5, 3), qmap.raw_q_sum(&"A").unwrap());
+        assert_eq!((0.0, 0), qmap.raw_q_sum(&"B").unwrap());
+    }
+
+    #[test]
+    fn update_actions_for_state() {
+        let mut qmap: QMap<&str, &str, Stats> = QMap::new();
+        qmap.update_stats(&"A", &"X", Stats::default()).unwrap();
+
+        let mut actions = qmap.get_actions_for_state(&"A").unwrap();
+        actions.get_mut("X").unwrap().call_count = 3;
+        actions.insert("Y", Stats::default());
+        qmap.update_actions_for_state(&"A", actions).unwrap();
+
+        let result = qmap.get_actions_for_state(&"A").unwrap();
+        assert_eq!(2, result.len());
+        assert_eq!(3, result["X"].call_count);
+    }
+
+    #[test]
+    fn counts_and_memory() {
+        let mut qmap: QMap<u32, u32, Stats> = QMap::new();
+        let empty_bytes = qmap.approx_memory_bytes();
+        assert_eq!(0, qmap.state_count().unwrap());
+        assert_eq!(0, qmap.entry_count().unwrap());
+
+        for state_id in 0..3 {
+            for action_id in 0..4 {
+                qmap.update_stats(&state_id, &action_id, Stats::default())
+                    .unwrap();
+            }
+        }
+
+        assert_eq!(3, qmap.state_count().unwrap());
+        assert_eq!(12, qmap.entry_count().unwrap());
+        assert!(qmap.approx_memory_bytes() > empty_bytes);
+    }
+
+    #[test]
+    fn visits() {
+        let mut qmap: QMap<&str, &str, Stats> = QMap::new();
+        assert_eq!(0, qmap.get_visits(&"A").unwrap());
+        qmap.set_visits(&"A", 3).unwrap();
+        assert_eq!(3, qmap.get_visits(&"A").unwrap());
+    }
+
+    #[test]
+    fn tuple_keys() {
+        let mut qmap: QMap<(u32, u32), u8, Stats> = QMap::new();
+        qmap.update_stats(&(2, 3), &1, Stats::default()).unwrap();
+
+        assert!(qmap.get_stats(&(2, 3), &1).unwrap().is_some());
+        assert!(qmap.get_stats(&(3, 2), &1).unwrap().is_none());
     }
 }
@@ -0,0 +1,109 @@
+//! States and actions identified only by strings.
+//!
+//! Agents hold references to the actions they are given, so bindings that
+//! cannot express Rust lifetimes (such as the Python bindings) describe
+//! states and actions by ID instead. Each distinct action ID is allocated
+//! once and then shared for the life of the process, so that the references
+//! an agent holds are `'static`.
+
+use crate::actions::Actioner;
+use crate::errors::LearnerError;
+use crate::states::Stater;
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+/// An action identified by a string.
+#[derive(Debug, PartialEq, Eq)]
+pub struct KeyedAction {
+    id: String,
+}
+
+impl Actioner<'static> for KeyedAction {
+    type Id = String;
+
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+}
+
+/// Returns the action with the specified ID, allocating it if no action with
+/// that ID has been requested before.
+pub fn intern(id: &str) -> &'static KeyedAction {
+    static ACTIONS: OnceLock<Mutex<HashMap<String, &'static KeyedAction>>> = OnceLock::new();
+    let mut actions = ACTIONS
+        .get_or_init(Mutex::default)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    actions
+        .entry(id.to_string())
+        .or_insert_with(|| Box::leak(Box::new(KeyedAction { id: id.to_string() })))
+}
+
+/// A state identified by a string, which permits a fixed list of actions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyedState {
+    id: String,
+    actions: Vec<&'static KeyedAction>,
+}
+
+impl KeyedState {
+    /// Returns a new state with the specified ID and possible actions.
+    pub fn new<I, T>(id: String, action_ids: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<str>,
+    {
+        Self {
+            id,
+            actions: action_ids.into_iter().map(|a| intern(a.as_ref())).collect(),
+        }
+    }
+}
+
+impl Stater<'static, KeyedAction> for KeyedState {
+    type Id = String;
+
+    fn possible_actions(&self) -> Vec<&'static KeyedAction> {
+        self.actions.clone()
+    }
+
+    fn action_is_compatible(&self, action: &'static KeyedAction) -> bool {
+        self.actions.iter().any(|a| a.id == action.id)
+    }
+
+    fn get_action(&self, action_id: &String) -> Result<&'static KeyedAction, LearnerError> {
+        self.actions
+            .iter()
+            .find(|a| &a.id == action_id)
+            .copied()
+            .ok_or_else(|| LearnerError::ActionNotFound {
+                action: format!("{action_id:?}"),
+                reason: format!("state {:?} has no action {action_id:?}", self.id),
+            })
+    }
+
+    fn id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn apply(&self, _: &'static KeyedAction) -> Result<(), LearnerError> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intern_reuses_actions() {
+        assert!(std::ptr::eq(intern("keyed-a"), intern("keyed-a")));
+        assert!(!std::ptr::eq(intern("keyed-a"), intern("keyed-b")));
+
+        let state = KeyedState::new("s".to_string(), ["keyed-a"]);
+        assert!(state.action_is_compatible(intern("keyed-a")));
+        assert!(!state.action_is_compatible(intern("keyed-b")));
+        assert!(state.get_action(&"keyed-b".to_string()).is_err());
+    }
+}
@@ -1,3 +1,60 @@
+use crate::errors::LearnerError;
+
+/// Determines what a calculation does when it is given, or would produce, a
+/// value that is NaN or infinite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NonFinitePolicy {
+    /// Return a `LearnerError::NonFinite` error, leaving the caller's values
+    /// unchanged.
+    #[default]
+    Error,
+
+    /// Replace each non-finite value with the nearest finite value: infinities
+    /// become `f64::MAX` or `f64::MIN`, and NaN becomes 0.
+    Clamp,
+}
+
+impl NonFinitePolicy {
+    /// Returns `value` if it is finite. Otherwise, returns an error described
+    /// by `describe`, or the clamped value, according to the policy.
+    pub fn resolve<F>(self, value: f64, describe: F) -> Result<f64, LearnerError>
+    where
+        F: FnOnce() -> String,
+    {
+        if value.is_finite() {
+            return Ok(value);
+        }
+        match self {
+            Self::Error => Err(LearnerError::NonFinite(describe())),
+            Self::Clamp => Ok(clamp_finite(value)),
+        }
+    }
+}
+
+/// Converts a count to an `f64`. Counts above 2^53 lose precision, but never
+/// saturate.
+#[allow(clippy::as_conversions, clippy::cast_precision_loss)]
+pub const fn count_to_f64(count: u64) -> f64 {
+    count as f64
+}
+
+/// Converts a length, such as the number of actions in a state, to an `f64`
+/// in the same way as `count_to_f64`.
+#[allow(clippy::as_conversions, clippy::cast_precision_loss)]
+pub const fn len_to_f64(len: usize) -> f64 {
+    len as f64
+}
+
+/// Returns the finite value nearest to `value`. Infinities are clamped to
+/// `f64::MAX` or `f64::MIN`, and NaN, which is not near anything, becomes 0.
+pub fn clamp_finite(value: f64) -> f64 {
+    if value.is_nan() {
+        0.0
+    } else {
+        value.clamp(f64::MIN, f64::MAX)
+    }
+}
+
 /// Bellman applies a Bellman operation to recommend a new q-value for a state
 /// based on the supplied paramters.
 /// See [https://en.wikipedia.org/wiki/Bellman_equation](https://en.wikipedia.org/wiki/Bellman_equation)
@@ -10,11 +67,49 @@ pub fn bellman(
     optimal_future_value: f64,
 ) -> f64 {
     learning_rate.mul_add(
-        discount_factor.mul_add(optimal_future_value, reward) - old_value,
+        td_error(old_value, reward, discount_factor, optimal_future_value),
         old_value,
     )
 }
 
+/// Applies `bellman`, handling non-finite inputs and results according to
+/// `policy`.
+pub fn checked_bellman(
+    policy: NonFinitePolicy,
+    old_value: f64,
+    learning_rate: f64,
+    reward: f64,
+    discount_factor: f64,
+    optimal_future_value: f64,
+) -> Result<f64, LearnerError> {
+    let check = |name: &str, value: f64| {
+        policy.resolve(value, || {
+            format!("the {name} of a q-value update is {value}")
+        })
+    };
+    let new_value = bellman(
+        check("old value", old_value)?,
+        check("learning rate", learning_rate)?,
+        check("reward", reward)?,
+        check("discount factor", discount_factor)?,
+        check("optimal future value", optimal_future_value)?,
+    );
+    policy.resolve(new_value, || {
+        format!("learning from reward {reward} would produce the q-value {new_value}")
+    })
+}
+
+/// Returns the temporal difference error of an update: the difference between
+/// the value that the update targets and the value being updated.
+pub fn td_error(
+    old_value: f64,
+    reward: f64,
+    discount_factor: f64,
+    optimal_future_value: f64,
+) -> f64 {
+    discount_factor.mul_add(optimal_future_value, reward) - old_value
+}
+
 /// Returns a bayesian weighted average where:
 ///   c = A scalar constant, generally set to a value that represents the
 ///       minimum number of observations required before an observed parameter
@@ -32,11 +127,61 @@ pub fn bayesian_average(c: f64, n: f64, m: f64, v: f64) -> f64 {
     safe_divide(c.mul_add(m, n * v), c + n)
 }
 
-/// Returns 0 if the divisor is 0, avoiding div/0 panics.
+/// Applies `bayesian_average`, handling non-finite inputs and results
+/// according to `policy`.
+pub fn checked_bayesian_average(
+    policy: NonFinitePolicy,
+    c: f64,
+    n: f64,
+    m: f64,
+    v: f64,
+) -> Result<f64, LearnerError> {
+    let check = |name: &str, value: f64| {
+        policy.resolve(value, || {
+            format!("the {name} of a weighted q-value is {value}")
+        })
+    };
+    let average = bayesian_average(
+        check("priming threshold", c)?,
+        check("call count", n)?,
+        check("mean", m)?,
+        check("raw q-value", v)?,
+    );
+    policy.resolve(average, || format!("the weighted q-value is {average}"))
+}
+
+/// Returns a count-based exploration bonus of `coefficient / sqrt(visits)`.
+/// Actions that have been visited less often receive a larger bonus. If
+/// `visits` is 0, no bonus is returned.
+#[allow(dead_code)]
+pub fn exploration_bonus(coefficient: f64, visits: f64) -> f64 {
+    safe_divide(coefficient, visits.sqrt())
+}
+
+/// Returns the UCB1 score `value + c * sqrt(ln(parent_visits) / calls)` of
+/// an action that has been called `calls` times from a state that has been
+/// visited `parent_visits` times. Actions that have never been called score
+/// infinity, so that each action is tried at least once.
+/// See [https://en.wikipedia.org/wiki/Multi-armed_bandit#Upper_confidence_bound](https://en.wikipedia.org/wiki/Multi-armed_bandit#Upper_confidence_bound)
+#[allow(dead_code)]
+pub fn ucb(value: f64, calls: f64, parent_visits: f64, c: f64) -> f64 {
+    if calls <= 0.0 {
+        return f64::INFINITY;
+    }
+    c.mul_add((parent_visits.max(1.0).ln() / calls).sqrt(), value)
+}
+
+/// Returns `dividend / divisor`, or 0 if the divisor is 0, rather than an
+/// infinity or NaN. Use `divide_or` to return something other than 0.
 #[allow(dead_code)]
 pub fn safe_divide(dividend: f64, divisor: f64) -> f64 {
+    divide_or(dividend, divisor, 0.0)
+}
+
+/// Returns `dividend / divisor`, or `on_zero` if the divisor is 0.
+pub fn divide_or(dividend: f64, divisor: f64, on_zero: f64) -> f64 {
     if divisor == 0.0 {
-        return 0.0;
+        return on_zero;
     }
     dividend / divisor
 }
@@ -44,7 +189,9 @@ pub fn safe_divide(dividend: f64, divisor: f64) -> f64 {
 #[cfg(test)]
 #[allow(clippy::panic)]
 mod tests {
+    use crate::errors::LearnerError;
     use crate::internal::math;
+    use crate::internal::math::NonFinitePolicy;
 
     #[test]
     fn bellman() {
@@ -64,6 +211,83 @@ mod tests {
         assert_eq!(exp_result, actual_result);
     }
 
+    #[test]
+    fn checked_bellman() {
+        let result = math::checked_bellman(NonFinitePolicy::Error, 0.1, 0.2, 0.3, 0.4, 0.5);
+        assert_eq!(math::bellman(0.1, 0.2, 0.3, 0.4, 0.5), result.unwrap());
+
+        for (inputs, clamped) in [
+            ([f64::NAN, 1.0, 1.0, 0.0, 0.0], 1.0),
+            ([0.0, 1.0, f64::INFINITY, 0.0, 0.0], f64::MAX),
+            ([0.0, 1.0, f64::MAX, 1.0, f64::MAX], f64::MAX),
+            ([0.0, 1.0, f64::MIN, 1.0, f64::MIN], f64::MIN),
+        ] {
+            let [old, rate, reward, discount, future] = inputs;
+            assert!(matches!(
+                math::checked_bellman(NonFinitePolicy::Error, old, rate, reward, discount, future),
+                Err(LearnerError::NonFinite(_))
+            ));
+            let result =
+                math::checked_bellman(NonFinitePolicy::Clamp, old, rate, reward, discount, future);
+            assert_eq!(clamped, result.unwrap());
+        }
+    }
+
+    #[test]
+    fn checked_bayesian_average() {
+        let result = math::checked_bayesian_average(NonFinitePolicy::Error, 1.0, 1.0, 2.0, 4.0);
+        assert_eq!(3.0, result.unwrap());
+        assert!(matches!(
+            math::checked_bayesian_average(NonFinitePolicy::Error, 1.0, 1.0, 2.0, f64::NAN),
+            Err(LearnerError::NonFinite(_))
+        ));
+        let result =
+            math::checked_bayesian_average(NonFinitePolicy::Clamp, 1.0, 1.0, 2.0, f64::NAN);
+        assert_eq!(1.0, result.unwrap());
+    }
+
+    #[test]
+    fn clamp_finite() {
+        assert_eq!(1.5, math::clamp_finite(1.5));
+        assert_eq!(f64::MAX, math::clamp_finite(f64::INFINITY));
+        assert_eq!(f64::MIN, math::clamp_finite(f64::NEG_INFINITY));
+        assert_eq!(0.0, math::clamp_finite(f64::NAN));
+    }
+
+    #[test]
+    fn td_error() {
+        assert_eq!(3.0, math::td_error(1.0, 2.0, 0.5, 4.0));
+        assert_eq!(-1.0, math::td_error(3.0, 2.0, 0.0, 4.0));
+    }
+
+    #[test]
+    fn exploration_bonus() {
+        let test_cases = vec![
+            (2.0, 4.0, 1.0),
+            (2.0, 1.0, 2.0),
+            (2.0, 0.0, 0.0),
+            (0.0, 9.0, 0.0),
+        ];
+        for tc in test_cases {
+            let result = math::exploration_bonus(tc.0, tc.1);
+            assert_eq!(tc.2, result);
+        }
+    }
+
+    #[test]
+    fn ucb() {
+        let test_cases = vec![
+            (0.5, 0.0, 10.0, 2.0, f64::INFINITY),
+            (0.5, 4.0, 1.0, 2.0, 0.5),
+            (0.5, 1.0, std::f64::consts::E, 2.0, 2.5),
+            (0.5, 4.0, std::f64::consts::E.powi(4), 0.0, 0.5),
+        ];
+        for tc in test_cases {
+            let result = math::ucb(tc.0, tc.1, tc.2, tc.3);
+            assert!((tc.4 - result).abs() < 1e-12 || tc.4 == result);
+        }
+    }
+
     #[test]
     fn safe_divide() {
         let test_cases = vec![(10.0, 2.0, 5.0), (0.0, 2.0, 0.0), (10.0, 0.0, 0.0)];
@@ -71,5 +295,7 @@ mod tests {
             let result = math::safe_divide(tc.0, tc.1);
             assert_eq!(tc.2, result);
         }
+        assert_eq!(5.0, math::divide_or(10.0, 2.0, -1.0));
+        assert_eq!(-1.0, math::divide_or(10.0, 0.0, -1.0));
     }
 }
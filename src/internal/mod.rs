@@ -1,2 +1,10 @@
 pub mod datastructures;
+#[cfg(any(
+    feature = "python",
+    feature = "ffi",
+    feature = "grpc",
+    feature = "rest"
+))]
+pub mod keyed;
 pub mod math;
+pub mod rng;
@@ -0,0 +1,15 @@
+//! The random number generator used by agents, trainers, and environments
+//! when no other generator is supplied.
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// Returns a new random number generator seeded from the platform's source of
+/// entropy.
+///
+/// Unlike `rand::thread_rng()`, the generator is owned by its caller rather
+/// than being thread-local. On `wasm32-unknown-unknown`, entropy is read from
+/// the browser via the `wasm` feature.
+pub fn default_rng() -> StdRng {
+    StdRng::from_entropy()
+}
@@ -20,12 +20,31 @@
     clippy::missing_const_for_fn
 )]
 
+// Allows the code generated by `rlr-derive` to refer to this crate as `rlr`,
+// including from within the crate itself.
+extern crate self as rlr;
+
 pub mod actions;
 pub mod agents;
+pub mod environments;
 pub mod errors;
+pub mod export;
+pub mod features;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "grpc")]
+pub mod grpc;
 pub(crate) mod internal;
+pub mod prelude;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod reproducibility;
+#[cfg(feature = "rest")]
+pub mod rest;
 pub mod states;
 pub mod stats;
+pub mod stores;
+pub mod training;
 
 /// Using manually constructed mocks because (at least at this time), none of
 /// the mocking frameworks seem to cope well with generic traits that also have
@@ -1,6 +1,8 @@
 use crate::actions::Actioner;
+use crate::environments::{Environment, Step};
 use crate::errors::LearnerError;
-use crate::states::Stater;
+use crate::internal::math;
+use crate::states::{FeatureStater, Stater};
 use std::cell::RefCell;
 
 pub struct MockStater<'a, A> {
@@ -11,7 +13,7 @@ pub struct MockStater<'a, A> {
     pub(crate) get_action_calls: RefCell<i64>,
 }
 
-impl<'a, A> Default for MockStater<'a, A> {
+impl<A> Default for MockStater<'_, A> {
     fn default() -> Self {
         Self {
             return_id: "",
@@ -27,6 +29,8 @@ impl<'a, A> Stater<'a, A> for MockStater<'a, A>
 where
     A: Actioner<'a>,
 {
+    type Id = String;
+
     fn possible_actions(&self) -> Vec<&'a A> {
         self.return_possible_actions.as_slice().into()
     }
@@ -35,22 +39,21 @@ where
         (self.return_action_is_compatible)(action)
     }
 
-    fn get_action(&self, action_name: &str) -> Result<&'a A, LearnerError> {
+    fn get_action(&self, action_id: &A::Id) -> Result<&'a A, LearnerError> {
         self.get_action_calls.replace_with(|&mut x| x + 1);
         for action in &self.return_possible_actions {
-            if action.id() == action_name {
+            if action.id() == *action_id {
                 return Ok(action);
             }
         }
-        panic!(format!(
-            "Action '{}' not found in MockStater '{}'",
-            action_name,
-            self.id()
-        ))
+        panic!(
+            "Action '{:?}' not found in MockStater '{}'",
+            action_id, self.return_id
+        )
     }
 
-    fn id(&self) -> &str {
-        self.return_id
+    fn id(&self) -> String {
+        self.return_id.to_owned()
     }
 
     fn apply(&self, action: &'a A) -> Result<(), LearnerError> {
@@ -64,7 +67,104 @@ pub struct MockActioner<'a> {
 }
 
 impl<'a> Actioner<'a> for MockActioner<'a> {
-    fn id(&self) -> &'a str {
-        self.return_id
+    type Id = String;
+
+    fn id(&self) -> String {
+        self.return_id.to_owned()
+    }
+}
+
+/// A state within a `MockCorridor`, identified by its position.
+pub struct MockCell<'a> {
+    pub(crate) position: usize,
+    pub(crate) moves: &'a [MockActioner<'a>],
+}
+
+impl<'a> Stater<'a, MockActioner<'a>> for MockCell<'a> {
+    type Id = usize;
+
+    fn possible_actions(&self) -> Vec<&'a MockActioner<'a>> {
+        self.moves.iter().collect()
+    }
+
+    fn action_is_compatible(&self, _: &'a MockActioner<'a>) -> bool {
+        true
+    }
+
+    fn get_action(&self, action_id: &String) -> Result<&'a MockActioner<'a>, LearnerError> {
+        self.moves
+            .iter()
+            .find(|m| m.id() == *action_id)
+            .ok_or_else(|| LearnerError::ActionNotFound {
+                action: format!("{action_id:?}"),
+                reason: format!("unknown move {action_id}"),
+            })
+    }
+
+    fn id(&self) -> usize {
+        self.position
+    }
+
+    fn apply(&self, _: &'a MockActioner<'a>) -> Result<(), LearnerError> {
+        Ok(())
+    }
+}
+
+impl<'a> FeatureStater<'a, MockActioner<'a>> for MockCell<'a> {
+    fn features(&self) -> Vec<f64> {
+        vec![math::len_to_f64(self.position)]
+    }
+}
+
+/// A corridor of `length` cells. Each episode starts in the leftmost cell, and
+/// ends with a reward of 1 once the agent reaches the rightmost cell by
+/// moving "L"eft and "R"ight.
+pub struct MockCorridor<'a> {
+    pub(crate) moves: &'a [MockActioner<'a>],
+    pub(crate) length: usize,
+    pub(crate) position: usize,
+}
+
+impl<'a> MockCorridor<'a> {
+    pub fn moves() -> [MockActioner<'static>; 2] {
+        [
+            MockActioner { return_id: "L" },
+            MockActioner { return_id: "R" },
+        ]
+    }
+
+    pub fn new(moves: &'a [MockActioner<'a>], length: usize) -> Self {
+        Self {
+            moves,
+            length,
+            position: 0,
+        }
+    }
+
+    fn cell(&self) -> MockCell<'a> {
+        MockCell {
+            position: self.position,
+            moves: self.moves,
+        }
+    }
+}
+
+impl<'a> Environment<'a, MockCell<'a>, MockActioner<'a>> for MockCorridor<'a> {
+    fn reset(&mut self) -> Result<MockCell<'a>, LearnerError> {
+        self.position = 0;
+        Ok(self.cell())
+    }
+
+    fn step(&mut self, action: &'a MockActioner<'a>) -> Result<Step<MockCell<'a>>, LearnerError> {
+        self.position = match action.return_id {
+            "R" => (self.position + 1).min(self.length - 1),
+            _ => self.position.saturating_sub(1),
+        };
+        let done = self.position == self.length - 1;
+        Ok(Step {
+            next_state: self.cell(),
+            reward: if done { 1.0 } else { 0.0 },
+            done,
+        })
     }
 }
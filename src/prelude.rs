@@ -0,0 +1,15 @@
+//! Re-exports the traits and types that most users of the crate need, so that
+//! they can be imported with a single `use rlr::prelude::*;`.
+
+pub use crate::actions::Actioner;
+pub use crate::agents::bayesian::{Agent, AgentContext, MergeStrategy, NonFinitePolicy};
+pub use crate::agents::frozen::FrozenPolicy;
+pub use crate::agents::{Agenter, Lifecycle};
+pub use crate::environments::{AsyncEnvironment, Environment, Step};
+pub use crate::errors::LearnerError;
+pub use crate::reproducibility::ReproducibilityConfig;
+pub use crate::states::{FeatureStater, Stater};
+pub use crate::stats::actionstats::Stats;
+pub use crate::stats::ActionStatter;
+pub use crate::stores::{QMap, QStore};
+pub use crate::training::{Schedule, StopReason, Trainer, TrainingReport};
@@ -0,0 +1,352 @@
+//! Python bindings, enabled by the `python` feature.
+//!
+//! The bindings expose `bayesian::Agent` to Python as `rlr.BayesianAgent`.
+//! Python code describes a state as a tuple of its ID and the IDs of the
+//! actions it permits, such as `("s0", ["left", "right"])`, and actions by
+//! their IDs. IDs are strings.
+//!
+//! An agent can be trained against any Python object that provides the
+//! following methods, in the same way as `training::Trainer`:
+//!
+//! - `reset()` starts a new episode and returns the initial state.
+//! - `step(action_id)` applies an action and returns a tuple of the next
+//!   state, the reward earned, and whether the episode has ended.
+//!
+//! ```python
+//! import rlr
+//!
+//! class Corridor:
+//!     def reset(self):
+//!         self.position = 0
+//!         return (str(self.position), ["L", "R"])
+//!
+//!     def step(self, action):
+//!         self.position = max(0, self.position + (1 if action == "R" else -1))
+//!         done = self.position == 3
+//!         return ((str(self.position), ["L", "R"]), 1.0 if done else 0.0, done)
+//!
+//! agent = rlr.BayesianAgent(priming_threshold=1, learning_rate=1.0, discount_factor=0.9)
+//! reports = agent.train(Corridor(), episodes=20, max_steps=50, epsilon=0.1)
+//! assert agent.recommend_action(("0", ["L", "R"])) == "R"
+//! ```
+//!
+//! Errors raised by the agent are raised in Python as `rlr.LearnerError`.
+//! Exceptions raised by an environment's methods propagate unchanged.
+//!
+//! To build an extension module, enable both the `python` feature and
+//! `pyo3/extension-module`, for instance with
+//! `maturin build --features python,pyo3/extension-module`.
+
+// The code generated by `pymethods` converts each method's error into a
+// `PyErr`, even when it already is one.
+#![allow(clippy::useless_conversion)]
+
+use crate::actions::Actioner;
+use crate::agents::bayesian::Agent;
+use crate::agents::Agenter;
+use crate::environments::{Environment, Step};
+use crate::errors;
+use crate::internal::keyed::{self, KeyedAction, KeyedState};
+use crate::stats::actionstats::Stats;
+use crate::training::{self, Schedule, Trainer};
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::collections::HashMap;
+
+create_exception!(
+    rlr,
+    LearnerError,
+    PyException,
+    "Raised when an agent fails to recommend an action or to learn."
+);
+
+/// A state as described by Python code: its ID, and the IDs of the actions
+/// that it permits.
+type PyState = (String, Vec<String>);
+
+fn to_state((id, action_ids): PyState) -> KeyedState {
+    KeyedState::new(id, action_ids)
+}
+
+fn to_py_err(err: &errors::LearnerError) -> PyErr {
+    LearnerError::new_err(err.to_string())
+}
+
+/// A bayesian agent that learns about states and actions identified by
+/// strings.
+#[pyclass(name = "BayesianAgent", module = "rlr", unsendable)]
+pub struct BayesianAgent {
+    agent: Agent<'static, KeyedState, KeyedAction, Stats>,
+}
+
+#[pymethods]
+impl BayesianAgent {
+    /// Returns a new agent. See `bayesian::Agent::new` for a description of
+    /// the parameters. If `seed` is supplied, the agent's random number
+    /// generator is seeded with it, so that ties are broken reproducibly.
+    #[new]
+    #[pyo3(signature = (priming_threshold = 1, learning_rate = 0.5, discount_factor = 0.9, seed = None))]
+    fn new(
+        priming_threshold: i32,
+        learning_rate: f64,
+        discount_factor: f64,
+        seed: Option<u64>,
+    ) -> Self {
+        let mut agent = Agent::new(priming_threshold, learning_rate, discount_factor);
+        if let Some(seed) = seed {
+            agent = agent.with_rng(StdRng::seed_from_u64(seed));
+        }
+        Self { agent }
+    }
+
+    /// Returns the ID of the action that the agent recommends for a state.
+    fn recommend_action(&mut self, state: PyState) -> PyResult<String> {
+        self.agent
+            .recommend_action(&to_state(state))
+            .map(Actioner::id)
+            .map_err(|e| to_py_err(&e))
+    }
+
+    /// Updates the agent's q-value for taking `action` in `previous_state`,
+    /// which led to `current_state` and earned `reward`. `previous_state`
+    /// can be `None` at the start of an episode.
+    #[pyo3(signature = (previous_state, action, current_state, reward))]
+    fn learn(
+        &mut self,
+        previous_state: Option<PyState>,
+        action: &str,
+        current_state: PyState,
+        reward: f64,
+    ) -> PyResult<()> {
+        let previous_state = previous_state.map(to_state);
+        self.agent
+            .learn(
+                previous_state.as_ref(),
+                keyed::intern(action),
+                &to_state(current_state),
+                reward,
+            )
+            .map_err(|e| to_py_err(&e))
+    }
+
+    /// Trains the agent against a Python environment, returning a report for
+    /// each episode. See the module documentation for the methods that the
+    /// environment must provide.
+    #[pyo3(signature = (env, episodes, max_steps, epsilon = 0.0))]
+    fn train(
+        &mut self,
+        env: Bound<'_, PyAny>,
+        episodes: usize,
+        max_steps: usize,
+        epsilon: f64,
+    ) -> PyResult<Vec<EpisodeReport>> {
+        let mut env = PyEnvironment { env, error: None };
+        let result = Trainer::new(episodes, max_steps)
+            .with_epsilon(Schedule::Constant(epsilon))
+            .train(&mut self.agent, &mut env);
+        match (result, env.error) {
+            (_, Some(err)) => Err(err),
+            (Ok(reports), None) => Ok(reports.into_iter().map(EpisodeReport::from).collect()),
+            (Err(err), None) => Err(to_py_err(&err)),
+        }
+    }
+
+    /// Returns the agent's weighted q-values, keyed by state ID and then by
+    /// action ID.
+    fn q_values(&self) -> HashMap<String, HashMap<String, f64>> {
+        let mut q_values: HashMap<String, HashMap<String, f64>> = HashMap::new();
+        for (state_id, action_id, _, q_value) in self.agent.iter_weighted_q_values() {
+            q_values
+                .entry(state_id.clone())
+                .or_default()
+                .insert(action_id.clone(), q_value);
+        }
+        q_values
+    }
+
+    /// Returns the greedy policy learned by the agent, as a map of state IDs
+    /// to action IDs.
+    fn policy(&self) -> HashMap<String, String> {
+        self.agent.extract_policy()
+    }
+
+    /// Stops the agent from learning any further.
+    fn freeze(&mut self) -> PyResult<()> {
+        self.agent.freeze().map_err(|e| to_py_err(&e))
+    }
+
+    /// The number of times the agent has learned from a transition.
+    #[getter]
+    fn step_count(&self) -> u64 {
+        self.agent.step_count()
+    }
+}
+
+/// The outcome of a single training episode.
+#[pyclass(name = "EpisodeReport", module = "rlr", frozen, get_all)]
+#[derive(Debug, Clone)]
+pub struct EpisodeReport {
+    /// The index of the episode.
+    episode: usize,
+
+    /// The number of steps taken during the episode.
+    steps: usize,
+
+    /// The sum of the rewards earned during the episode.
+    total_return: f64,
+
+    /// Whether the environment ended the episode.
+    terminated: bool,
+}
+
+impl From<training::EpisodeReport> for EpisodeReport {
+    fn from(report: training::EpisodeReport) -> Self {
+        Self {
+            episode: report.episode,
+            steps: report.steps,
+            total_return: report.total_return,
+            terminated: report.terminated,
+        }
+    }
+}
+
+/// Adapts a Python object with `reset` and `step` methods to `Environment`.
+/// The first exception raised by the object is kept, so that it can be
+/// raised again once training stops.
+struct PyEnvironment<'py> {
+    env: Bound<'py, PyAny>,
+    error: Option<PyErr>,
+}
+
+impl PyEnvironment<'_> {
+    fn fail(&mut self, err: PyErr) -> errors::LearnerError {
+        let message = err.to_string();
+        self.error.get_or_insert(err);
+        errors::LearnerError::Environment(message)
+    }
+}
+
+impl Environment<'static, KeyedState, KeyedAction> for PyEnvironment<'_> {
+    fn reset(&mut self) -> Result<KeyedState, errors::LearnerError> {
+        match self.env.call_method0("reset").and_then(|s| s.extract()) {
+            Ok(state) => Ok(to_state(state)),
+            Err(err) => Err(self.fail(err)),
+        }
+    }
+
+    fn step(
+        &mut self,
+        action: &'static KeyedAction,
+    ) -> Result<Step<KeyedState>, errors::LearnerError> {
+        let result = self
+            .env
+            .call_method1("step", (action.id(),))
+            .and_then(|r| r.extract::<(PyState, f64, bool)>());
+        match result {
+            Ok((next_state, reward, done)) => Ok(Step {
+                next_state: to_state(next_state),
+                reward,
+                done,
+            }),
+            Err(err) => Err(self.fail(err)),
+        }
+    }
+}
+
+/// The `rlr` Python module.
+#[pymodule]
+fn rlr(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<BayesianAgent>()?;
+    m.add_class::<EpisodeReport>()?;
+    m.add("LearnerError", m.py().get_type_bound::<LearnerError>())?;
+    Ok(())
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use pyo3::exceptions::PyValueError;
+    use pyo3::types::PyDict;
+
+    const CORRIDOR: &str = r#"
+class Corridor:
+    def reset(self):
+        self.position = 0
+        return (str(self.position), ["L", "R"])
+
+    def step(self, action):
+        if action == "X":
+            raise ValueError("unknown action")
+        self.position = max(0, self.position + (1 if action == "R" else -1))
+        done = self.position == 3
+        return ((str(self.position), ["L", "R"]), 1.0 if done else 0.0, done)
+
+class Broken:
+    def reset(self):
+        return ("0", ["X"])
+
+    def step(self, action):
+        raise ValueError("unknown action")
+"#;
+
+    fn with_corridor<F: FnOnce(Python<'_>, &Bound<'_, PyDict>)>(f: F) {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let locals = PyDict::new_bound(py);
+            py.run_bound(CORRIDOR, None, Some(&locals)).unwrap();
+            f(py, &locals);
+        });
+    }
+
+    #[test]
+    fn train_against_python_environment() {
+        with_corridor(|_, locals| {
+            let env = locals
+                .get_item("Corridor")
+                .unwrap()
+                .unwrap()
+                .call0()
+                .unwrap();
+            let mut agent = BayesianAgent::new(1, 1.0, 0.9, Some(7));
+            let reports = agent.train(env, 20, 50, 0.1).unwrap();
+            assert_eq!(20, reports.len());
+            assert!(reports.iter().all(|r| r.terminated));
+
+            let state = ("0".to_string(), vec!["L".to_string(), "R".to_string()]);
+            assert_eq!("R", agent.recommend_action(state).unwrap());
+            assert_eq!(Some(&"R".to_string()), agent.policy().get("0"));
+            assert!(agent.q_values()["0"]["R"] > agent.q_values()["0"]["L"]);
+        });
+    }
+
+    #[test]
+    fn environment_exceptions_propagate() {
+        with_corridor(|py, locals| {
+            let env = locals.get_item("Broken").unwrap().unwrap().call0().unwrap();
+            let mut agent = BayesianAgent::new(1, 1.0, 0.9, None);
+            let err = agent.train(env, 1, 10, 0.0).err().unwrap();
+            assert!(err.is_instance_of::<PyValueError>(py));
+        });
+    }
+
+    #[test]
+    fn learner_errors_are_raised() {
+        with_corridor(|py, _| {
+            let mut agent = BayesianAgent::new(1, 1.0, 0.9, None);
+            let err = agent
+                .recommend_action(("0".to_string(), Vec::new()))
+                .err()
+                .unwrap();
+            assert!(err.is_instance_of::<LearnerError>(py));
+
+            agent.freeze().unwrap();
+            let state = || ("0".to_string(), vec!["L".to_string()]);
+            let err = agent.learn(Some(state()), "L", state(), 1.0).err().unwrap();
+            assert!(err.is_instance_of::<LearnerError>(py));
+        });
+    }
+}
@@ -0,0 +1,203 @@
+//! Seeding every source of randomness in a run from a single seed.
+//!
+//! Agents, trainers, and environments each draw from their own random number
+//! generator, which is seeded from the platform's source of entropy unless
+//! another is supplied. A `ReproducibilityConfig` derives an independent,
+//! seeded generator for each of them from one seed, so that a whole run can
+//! be repeated bit-for-bit:
+//!
+//! ```
+//! use rlr::environments::bandit::{Arm, Bandit, BanditState};
+//! use rlr::prelude::*;
+//! use rlr::reproducibility::ReproducibilityConfig;
+//!
+//! let run = |config: &ReproducibilityConfig| {
+//!     let arms = Bandit::arms(3);
+//!     let mut bandit = Bandit::gaussian_testbed(&arms, config.environment_rng());
+//!     let mut agent: Agent<BanditState, Arm, Stats> =
+//!         Agent::new(1, 0.1, 0.0).with_reproducibility(config);
+//!     Trainer::new(50, 1)
+//!         .with_epsilon(Schedule::Constant(0.1))
+//!         .with_reproducibility(config)
+//!         .train(&mut agent, &mut bandit)
+//!         .unwrap()
+//! };
+//!
+//! let config = ReproducibilityConfig::new(42);
+//! assert_eq!(run(&config), run(&config));
+//! ```
+//!
+//! Environments that accept a seed rather than a generator, such as
+//! `environments::gym::GymEnv`, can be seeded with `environment_seed`.
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+/// The seeds of each source of randomness in a run. See the module
+/// documentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ReproducibilityConfig {
+    seed: u64,
+    agent_seed: u64,
+    environment_seed: u64,
+    trainer_seed: u64,
+    replay_seed: u64,
+}
+
+impl ReproducibilityConfig {
+    /// Returns a configuration whose seeds are all derived from `seed`.
+    /// Each component's seed is distinct, so components do not draw the
+    /// same sequence of numbers.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            agent_seed: derive(seed, 1),
+            environment_seed: derive(seed, 2),
+            trainer_seed: derive(seed, 3),
+            replay_seed: derive(seed, 4),
+        }
+    }
+
+    /// Overrides the seed of the agent's generator, which is used to break
+    /// ties and initialize weights.
+    #[must_use]
+    pub fn with_agent_seed(mut self, seed: u64) -> Self {
+        self.agent_seed = seed;
+        self
+    }
+
+    /// Overrides the seed of the environment's generator.
+    #[must_use]
+    pub fn with_environment_seed(mut self, seed: u64) -> Self {
+        self.environment_seed = seed;
+        self
+    }
+
+    /// Overrides the seed of the trainer's generator, which decides when and
+    /// how to explore.
+    #[must_use]
+    pub fn with_trainer_seed(mut self, seed: u64) -> Self {
+        self.trainer_seed = seed;
+        self
+    }
+
+    /// Overrides the seed of the generator used to sample from replay
+    /// buffers.
+    #[must_use]
+    pub fn with_replay_seed(mut self, seed: u64) -> Self {
+        self.replay_seed = seed;
+        self
+    }
+
+    /// Returns the seed that the configuration was created with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Returns the seed of the agent's generator.
+    pub fn agent_seed(&self) -> u64 {
+        self.agent_seed
+    }
+
+    /// Returns the seed of the environment's generator.
+    pub fn environment_seed(&self) -> u64 {
+        self.environment_seed
+    }
+
+    /// Returns the seed of the trainer's generator.
+    pub fn trainer_seed(&self) -> u64 {
+        self.trainer_seed
+    }
+
+    /// Returns the seed of the replay sampling generator.
+    pub fn replay_seed(&self) -> u64 {
+        self.replay_seed
+    }
+
+    /// Returns a new generator for an agent.
+    pub fn agent_rng(&self) -> StdRng {
+        StdRng::seed_from_u64(self.agent_seed)
+    }
+
+    /// Returns a new generator for an environment.
+    pub fn environment_rng(&self) -> StdRng {
+        StdRng::seed_from_u64(self.environment_seed)
+    }
+
+    /// Returns a new generator for a trainer.
+    pub fn trainer_rng(&self) -> StdRng {
+        StdRng::seed_from_u64(self.trainer_seed)
+    }
+
+    /// Returns a new generator for replay sampling.
+    pub fn replay_rng(&self) -> StdRng {
+        StdRng::seed_from_u64(self.replay_seed)
+    }
+}
+
+/// Derives the seed of stream `stream` from `seed` with the `SplitMix64`
+/// finalizer, which spreads nearby seeds far apart.
+fn derive(seed: u64, stream: u64) -> u64 {
+    let mut z = seed.wrapping_add(stream.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::agents::bayesian::Agent;
+    use crate::environments::bandit::{Arm, Bandit, BanditState};
+    use crate::stats::actionstats::Stats;
+    use crate::training::{EpisodeReport, Schedule, Trainer};
+    use rand::Rng;
+
+    #[test]
+    fn seeds_are_distinct_and_stable() {
+        let config = ReproducibilityConfig::new(7);
+        let seeds = [
+            config.agent_seed(),
+            config.environment_seed(),
+            config.trainer_seed(),
+            config.replay_seed(),
+        ];
+        for (i, a) in seeds.iter().enumerate() {
+            for b in &seeds[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+        assert_eq!(config, ReproducibilityConfig::new(7));
+        assert_ne!(config, ReproducibilityConfig::new(8));
+        assert_eq!(
+            config.agent_rng().gen::<u64>(),
+            config.agent_rng().gen::<u64>()
+        );
+
+        let overridden = config.with_agent_seed(1).with_replay_seed(2);
+        assert_eq!(1, overridden.agent_seed());
+        assert_eq!(2, overridden.replay_seed());
+        assert_eq!(config.trainer_seed(), overridden.trainer_seed());
+        assert_eq!(7, overridden.seed());
+    }
+
+    #[test]
+    fn runs_are_reproducible() {
+        let run = |config: &ReproducibilityConfig| -> Vec<EpisodeReport> {
+            let arms = Bandit::arms(4);
+            let mut bandit = Bandit::gaussian_testbed(&arms, config.environment_rng());
+            let mut agent: Agent<BanditState, Arm, Stats> =
+                Agent::new(1, 0.1, 0.0).with_reproducibility(config);
+            Trainer::new(100, 1)
+                .with_epsilon(Schedule::Constant(0.2))
+                .with_reproducibility(config)
+                .train(&mut agent, &mut bandit)
+                .unwrap()
+        };
+
+        let config = ReproducibilityConfig::new(1);
+        assert_eq!(run(&config), run(&config));
+        assert_ne!(run(&config), run(&ReproducibilityConfig::new(2)));
+    }
+}
@@ -0,0 +1,326 @@
+//! An HTTP/JSON service that recommends actions from, and teaches, a single
+//! agent. The service is enabled by the `rest` feature.
+//!
+//! The service offers three endpoints:
+//!
+//! - `POST /recommend` takes a state descriptor, such as
+//!   `{"id": "s0", "action_ids": ["left", "right"]}`, and responds with the
+//!   recommended action, such as `{"action_id": "right"}`.
+//! - `POST /learn` takes a transition, such as
+//!   `{"previous_state": {...}, "action_id": "right", "current_state": {...},
+//!   "reward": 1.0}`, and responds with `204 No Content`. `previous_state`
+//!   can be omitted or null at the start of an episode. `action_id` must be
+//!   one of `previous_state`'s action IDs, or of `current_state`'s if
+//!   `previous_state` is omitted.
+//! - `GET /policy` responds with the agent's greedy policy, as an object
+//!   mapping state IDs to action IDs.
+//!
+//! The message shapes match those of the `grpc` feature. Errors are reported
+//! with a `4xx` or `5xx` status and a body such as `{"error": "..."}`.
+//! Request bodies larger than `MAX_BODY` bytes are rejected with
+//! `413 Payload Too Large`.
+//!
+//! Requests are handled one at a time, on the thread that calls `serve`.
+
+use crate::actions::Actioner;
+use crate::agents::bayesian::Agent;
+use crate::agents::Agenter;
+use crate::errors::LearnerError;
+use crate::internal::keyed::{KeyedAction, KeyedState};
+use crate::states::Stater;
+use crate::stats::actionstats::Stats;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::io::{self, Read};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// A state, identified by its ID, along with the IDs of the actions it
+/// permits.
+#[derive(Debug, Deserialize)]
+struct StateDescriptor {
+    id: String,
+    action_ids: Vec<String>,
+}
+
+impl From<StateDescriptor> for KeyedState {
+    fn from(state: StateDescriptor) -> Self {
+        Self::new(state.id, state.action_ids)
+    }
+}
+
+/// The outcome of taking an action in a state.
+#[derive(Debug, Deserialize)]
+struct Transition {
+    #[serde(default)]
+    previous_state: Option<StateDescriptor>,
+    action_id: String,
+    current_state: StateDescriptor,
+    reward: f64,
+}
+
+#[derive(Debug, Serialize)]
+struct Recommendation {
+    action_id: String,
+}
+
+/// The largest request body, in bytes, that the server will read.
+pub const MAX_BODY: u64 = 1024 * 1024;
+
+/// A response that has yet to be sent: a status code and a JSON body, if
+/// any.
+type Reply = (u16, Option<String>);
+
+/// Serves a single agent over HTTP.
+pub struct AgentServer {
+    server: Server,
+    agent: Agent<'static, KeyedState, KeyedAction, Stats>,
+}
+
+impl AgentServer {
+    /// Listens on `addr`, serving a new agent. See `bayesian::Agent::new` for
+    /// a description of the remaining parameters.
+    /// An error is returned if the address cannot be bound.
+    pub fn bind<A: ToSocketAddrs>(
+        addr: A,
+        priming_threshold: i32,
+        learning_rate: f64,
+        discount_factor: f64,
+    ) -> Result<Self, LearnerError> {
+        let server = Server::http(addr)
+            .map_err(|e| LearnerError::Other(format!("unable to start server: {e}")))?;
+        Ok(Self {
+            server,
+            agent: Agent::new(priming_threshold, learning_rate, discount_factor),
+        })
+    }
+
+    /// Returns the address that the server is listening on.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    /// Handles requests until the server's listener fails.
+    ///
+    /// An error sending a response, such as a client disconnecting before
+    /// reading it, only affects that client's request, so the server carries
+    /// on handling other requests.
+    pub fn serve(&mut self) -> Result<(), LearnerError> {
+        loop {
+            let request = self.server.recv().map_err(|e| receive_err(&e))?;
+            if let Err(err) = respond(request, &mut self.agent) {
+                report(&err);
+            }
+        }
+    }
+
+    /// Waits up to `timeout` for a request, and handles it. Returns false if
+    /// no request arrived in time. This can be used to serve requests from
+    /// a loop that also does other work.
+    pub fn serve_one(&mut self, timeout: Duration) -> Result<bool, LearnerError> {
+        match self
+            .server
+            .recv_timeout(timeout)
+            .map_err(|e| receive_err(&e))?
+        {
+            Some(request) => respond(request, &mut self.agent).map(|()| true),
+            None => Ok(false),
+        }
+    }
+}
+
+/// Reports an error that occurred while responding to a request, if the
+/// `tracing` feature is enabled.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+fn report(err: &LearnerError) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(error = %err, "unable to respond to request");
+}
+
+fn receive_err(err: &io::Error) -> LearnerError {
+    LearnerError::Other(format!("unable to receive request: {err}"))
+}
+
+fn respond(
+    mut request: Request,
+    agent: &mut Agent<'static, KeyedState, KeyedAction, Stats>,
+) -> Result<(), LearnerError> {
+    let (status, body) = route(&mut request, agent);
+    let mut response = Response::from_string(body.unwrap_or_default()).with_status_code(status);
+    if status != 204 {
+        if let Ok(header) = Header::from_bytes("Content-Type", "application/json") {
+            response.add_header(header);
+        }
+    }
+    request
+        .respond(response)
+        .map_err(|e| LearnerError::Other(format!("unable to send response: {e}")))
+}
+
+fn route(
+    request: &mut Request,
+    agent: &mut Agent<'static, KeyedState, KeyedAction, Stats>,
+) -> Reply {
+    match (request.method(), request.url()) {
+        (Method::Post, "/recommend") => match read_json::<StateDescriptor>(request) {
+            Ok(state) => match agent.recommend_action(&state.into()) {
+                Ok(action) => ok(&Recommendation {
+                    action_id: action.id(),
+                }),
+                Err(err) => error(&err),
+            },
+            Err(reply) => reply,
+        },
+        (Method::Post, "/learn") => match read_json::<Transition>(request) {
+            Ok(transition) => {
+                let previous_state = transition.previous_state.map(KeyedState::from);
+                let current_state = transition.current_state.into();
+                // The action is looked up among the states' actions rather
+                // than interned, so that clients cannot allocate an action
+                // for every ID they send.
+                let action = match previous_state
+                    .as_ref()
+                    .unwrap_or(&current_state)
+                    .get_action(&transition.action_id)
+                {
+                    Ok(action) => action,
+                    Err(err) => return error(&err),
+                };
+                match agent.learn(
+                    previous_state.as_ref(),
+                    action,
+                    &current_state,
+                    transition.reward,
+                ) {
+                    Ok(()) => (204, None),
+                    Err(err) => error(&err),
+                }
+            }
+            Err(reply) => reply,
+        },
+        (Method::Get, "/policy") => ok(&agent.extract_policy()),
+        (_, "/recommend" | "/learn" | "/policy") => failure(405, "method not allowed"),
+        (_, url) => failure(404, &format!("no such endpoint {url}")),
+    }
+}
+
+/// Reads a JSON request body. A `413` reply is returned if the body is
+/// larger than `MAX_BODY`.
+fn read_json<T: for<'de> Deserialize<'de>>(request: &mut Request) -> Result<T, Reply> {
+    let mut body = request.as_reader().take(MAX_BODY);
+    serde_json::from_reader(&mut body).map_err(|e| {
+        if body.limit() == 0 {
+            failure(413, &format!("request body exceeds {MAX_BODY} bytes"))
+        } else {
+            failure(400, &format!("invalid request body: {e}"))
+        }
+    })
+}
+
+fn ok<T: Serialize>(body: &T) -> Reply {
+    match serde_json::to_string(body) {
+        Ok(body) => (200, Some(body)),
+        Err(err) => failure(500, &err.to_string()),
+    }
+}
+
+fn error(err: &LearnerError) -> Reply {
+    let status = match err {
+        LearnerError::ActionNotCompatible { .. }
+        | LearnerError::NoPossibleActions { .. }
+        | LearnerError::ActionNotFound { .. }
+        | LearnerError::NonFinite(_)
+        | LearnerError::InvalidArgument(_) => 400,
+        LearnerError::Lifecycle(_) => 409,
+        _ => 500,
+    };
+    failure(status, &err.to_string())
+}
+
+fn failure(status: u16, message: &str) -> Reply {
+    (status, Some(json!({ "error": message }).to_string()))
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::convert::TryFrom;
+    use std::io::Write;
+    use std::net::TcpStream;
+    use std::sync::mpsc;
+    use std::thread;
+
+    fn send(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{method} {path} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let body = response.split("\r\n\r\n").nth(1).unwrap_or_default();
+        (status, body.to_string())
+    }
+
+    #[test]
+    fn serve() {
+        let (addresses, address) = mpsc::channel();
+        let server = thread::spawn(move || {
+            let mut server = AgentServer::bind("127.0.0.1:0", 0, 1.0, 0.0).unwrap();
+            addresses.send(server.local_addr().unwrap()).unwrap();
+            for _ in 0..8 {
+                assert!(server.serve_one(Duration::from_secs(10)).unwrap());
+            }
+        });
+        let addr = address.recv().unwrap();
+
+        let state = |id| format!(r#"{{"id": "{id}", "action_ids": ["left", "right"]}}"#);
+        let transition = format!(
+            r#"{{"previous_state": {}, "action_id": "right", "current_state": {}, "reward": 1.0}}"#,
+            state("s0"),
+            state("s1")
+        );
+        assert_eq!(
+            (204, String::new()),
+            send(addr, "POST", "/learn", &transition)
+        );
+        assert_eq!(
+            (200, r#"{"action_id":"right"}"#.to_string()),
+            send(addr, "POST", "/recommend", &state("s0"))
+        );
+        let (status, body) = send(addr, "GET", "/policy", "");
+        assert_eq!(200, status);
+        let policy: HashMap<String, String> = serde_json::from_str(&body).unwrap();
+        assert_eq!(Some(&"right".to_string()), policy.get("s0"));
+
+        let (status, body) = send(
+            addr,
+            "POST",
+            "/recommend",
+            r#"{"id": "s2", "action_ids": []}"#,
+        );
+        assert_eq!(400, status);
+        assert!(body.contains("no possible actions"));
+        assert_eq!(400, send(addr, "POST", "/recommend", "not json").0);
+        assert_eq!(405, send(addr, "GET", "/learn", "").0);
+        let unknown_action = format!(
+            r#"{{"action_id": "up", "current_state": {}, "reward": 1.0}}"#,
+            state("s0")
+        );
+        let (status, body) = send(addr, "POST", "/learn", &unknown_action);
+        assert_eq!(400, status);
+        assert!(body.contains("up"));
+        let oversized = format!(
+            r#"{{"id": "{}"}}"#,
+            "s".repeat(usize::try_from(MAX_BODY).unwrap())
+        );
+        assert_eq!(413, send(addr, "POST", "/recommend", &oversized).0);
+        server.join().unwrap();
+    }
+}
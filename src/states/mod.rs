@@ -2,27 +2,49 @@
 
 use crate::actions::Actioner;
 use crate::errors::LearnerError;
+use std::fmt::Debug;
+use std::hash::Hash;
 
 /// Represents the current disposition of the model.
 pub trait Stater<'a, A>
 where
     A: Actioner<'a>,
 {
+    /// The type used to identify states. Any type that can be hashed,
+    /// compared, and cloned can be used, such as strings, integers, or tuples.
+    type Id: Hash + Eq + Ord + Clone + Debug;
+
     /// Provides a slice of Actions that are applicable to this state.
     fn possible_actions(&self) -> Vec<&'a A>;
 
     /// Checks whether or not the supplied action is compatible with this state.
     fn action_is_compatible(&self, actioner: &'a A) -> bool;
 
-    /// Returns an action of the specified name, or an error if no action exists
-    /// of that name fot this state.
-    fn get_action(&self, action_name: &str) -> Result<&'a A, LearnerError>;
+    /// Returns the action with the specified ID, or an error if no action
+    /// exists with that ID fot this state.
+    fn get_action(&self, action_id: &A::Id) -> Result<&'a A, LearnerError>;
 
-    /// Returns a string representation of this state.
+    /// Returns the ID of this state.
     /// Implementors should take care to ensure this is a consistent hash for a
     /// given state.
-    fn id(&self) -> &str;
+    fn id(&self) -> Self::Id;
 
     /// Executes the supplied action.
     fn apply(&self, actioner: &'a A) -> Result<(), LearnerError>;
 }
+
+/// A state that can describe itself as a vector of numeric features, such as
+/// the positions and velocities of a control task.
+///
+/// Agents that approximate values from features, such as
+/// `agents::dqn::DqnAgent`, and the feature constructors in `features` can
+/// consume such states directly, rather than recovering the features from
+/// the state's ID.
+pub trait FeatureStater<'a, A>: Stater<'a, A>
+where
+    A: Actioner<'a>,
+{
+    /// Returns the state's features. Every state that is passed to the same
+    /// agent should return the same number of features, in the same order.
+    fn features(&self) -> Vec<f64>;
+}
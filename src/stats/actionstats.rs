@@ -1,29 +1,34 @@
 //! Statistics about the relationship between an action and a state.
 
 use crate::stats::ActionStatter;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// Contains statistics about an action that has been applied to some state.
 #[derive(PartialEq, Debug, Default, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Stats {
-    pub(crate) call_count: i32,
+    pub(crate) call_count: u64,
 
     /// This is the raw q-value associated with this action.
     pub(crate) q_raw: f64,
 
     /// This is the q-value for this action that has been weighted acroding to
-    /// the agent's weighting rules.
+    /// the agent's weighting rules. Within a store, this is only a cache of
+    /// the value when the stats were last written; see
+    /// `ActionStatter::q_value_weighted`.
     pub(crate) q_weighted: f64,
 }
 
 impl ActionStatter for Stats {
     /// Returns the number of times this action has been called.
-    fn calls(&self) -> i32 {
+    fn calls(&self) -> u64 {
         self.call_count
     }
 
     /// Sets the number of times this action has been called.
-    fn set_calls(&mut self, n: i32) {
-        self.call_count = n
+    fn set_calls(&mut self, n: u64) {
+        self.call_count = n;
     }
 
     /// Returns the raw q-value for this action.
@@ -33,7 +38,7 @@ impl ActionStatter for Stats {
 
     /// Sets the raw q-value for this action.
     fn set_q_value_raw(&mut self, q: f64) {
-        self.q_raw = q
+        self.q_raw = q;
     }
 
     /// Returns the weighted q-value for this action.
@@ -43,6 +48,34 @@ impl ActionStatter for Stats {
 
     /// Sets the weighted q-value for this action.
     fn set_q_value_weighted(&mut self, q: f64) {
-        self.q_weighted = q
+        self.q_weighted = q;
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ucb_score() {
+        let stats = Stats {
+            call_count: 1,
+            q_raw: 0.0,
+            q_weighted: 0.5,
+        };
+        assert!((stats.ucb_score(1, 2.0) - 0.5).abs() < 1e-12);
+        assert!(stats.ucb_score(10, 2.0) > stats.ucb_score(5, 2.0));
+        assert_eq!(f64::INFINITY, Stats::default().ucb_score(10, 2.0));
+    }
+
+    #[test]
+    fn increment_calls_saturates() {
+        let mut stats = Stats::default();
+        stats.increment_calls();
+        assert_eq!(1, stats.calls());
+        stats.set_calls(u64::MAX);
+        stats.increment_calls();
+        assert_eq!(u64::MAX, stats.calls());
     }
 }
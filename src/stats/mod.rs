@@ -1,14 +1,24 @@
 //! Statistics that aid in the learning process.
 
+use crate::internal::math;
+
 pub mod actionstats;
+pub mod rewardstats;
+pub mod stepstats;
 
 /// Represents the stats that can be associated with an action.
 pub trait ActionStatter: Clone + Default {
     /// The number of times this action has been executed.
-    fn calls(&self) -> i32;
+    fn calls(&self) -> u64;
 
     /// Set the number of times this action has been executed.
-    fn set_calls(&mut self, n: i32);
+    fn set_calls(&mut self, n: u64);
+
+    /// Adds one to the number of times this action has been executed. The
+    /// count saturates at `u64::MAX` rather than overflowing.
+    fn increment_calls(&mut self) {
+        self.set_calls(self.calls().saturating_add(1));
+    }
 
     /// The raw Q value for this action.
     fn q_value_raw(&self) -> f64;
@@ -17,8 +27,46 @@ pub trait ActionStatter: Clone + Default {
     fn set_q_value_raw(&mut self, q: f64);
 
     /// The weighted Q value for this action.
+    ///
+    /// Agents calculate weighted q-values from the raw q-values and call
+    /// counts of a state's actions as they are read, such as when
+    /// recommending an action or exporting an `AgentContext`. A weighted
+    /// q-value that has been recorded in a store is only a cache of the value
+    /// when the stats were last written, and is never authoritative: it goes
+    /// out of date as soon as any other action in the state is learned from.
     fn q_value_weighted(&self) -> f64;
 
-    /// Set the weighted Q value for this action.
+    /// Set the weighted Q value for this action. See `q_value_weighted`.
     fn set_q_value_weighted(&mut self, q: f64);
+
+    /// Records a reward that was observed after taking this action. Agents
+    /// call this each time they learn from the action. The default
+    /// implementation discards the reward; implementations such as
+    /// `RewardStats` use it to track the distribution of rewards.
+    fn observe_reward(&mut self, _reward: f64) {}
+
+    /// The agent's step count when this action's stats were last learned
+    /// from, if the implementation records it. The default implementation
+    /// returns `None`.
+    fn last_updated(&self) -> Option<u64> {
+        None
+    }
+
+    /// Records the agent's step count when this action's stats are learned
+    /// from. The default implementation discards the step count.
+    fn set_last_updated(&mut self, _step: u64) {}
+
+    /// Returns the upper-confidence-bound (UCB1) score of this action, given
+    /// the number of times its state has been visited and an exploration
+    /// coefficient `c` (commonly `sqrt(2)`). The score is the action's
+    /// weighted q-value plus a bonus that shrinks as the action is called
+    /// more often, and is infinite for actions that have never been called.
+    fn ucb_score(&self, parent_visits: u64, c: f64) -> f64 {
+        math::ucb(
+            self.q_value_weighted(),
+            math::count_to_f64(self.calls()),
+            math::count_to_f64(parent_visits),
+            c,
+        )
+    }
 }
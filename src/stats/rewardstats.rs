@@ -0,0 +1,244 @@
+//! Statistics that also track the rewards observed for an action.
+
+use crate::internal::math;
+use crate::stats::actionstats::Stats;
+use crate::stats::ActionStatter;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// The running count, mean, and variance of a series of values, computed
+/// incrementally using Welford's algorithm.
+///
+/// See [https://en.wikipedia.org/wiki/Algorithms_for_calculating_variance#Welford's_online_algorithm](https://en.wikipedia.org/wiki/Algorithms_for_calculating_variance#Welford's_online_algorithm)
+#[derive(PartialEq, Debug, Default, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RunningStats {
+    count: u32,
+    mean: f64,
+    m2: f64,
+}
+
+impl RunningStats {
+    /// Adds a value to the series.
+    pub fn push(&mut self, value: f64) {
+        self.count = self.count.saturating_add(1);
+        let delta = value - self.mean;
+        self.mean += delta / f64::from(self.count);
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// Returns the number of values in the series.
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Returns the mean of the series, or 0 if the series is empty.
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Returns the sample variance of the series, or 0 if the series has
+    /// fewer than two values.
+    pub fn variance(&self) -> f64 {
+        if self.count < 2 {
+            return 0.0;
+        }
+        self.m2 / f64::from(self.count - 1)
+    }
+
+    /// Returns the sample standard deviation of the series.
+    pub fn std_dev(&self) -> f64 {
+        self.variance().sqrt()
+    }
+
+    /// Returns the confidence interval of the mean as a `(lower, upper)`
+    /// pair, where `z` is the number of standard errors on either side of the
+    /// mean (for instance, 1.96 for a 95% confidence interval). The interval
+    /// is unbounded if the series has fewer than two values.
+    pub fn confidence_interval(&self, z: f64) -> (f64, f64) {
+        if self.count < 2 {
+            return (f64::NEG_INFINITY, f64::INFINITY);
+        }
+        let margin = z * math::safe_divide(self.variance(), f64::from(self.count)).sqrt();
+        (self.mean - margin, self.mean + margin)
+    }
+}
+
+/// Contains the same statistics as `Stats`, along with the running mean and
+/// variance of the rewards that have been observed for the action.
+#[derive(PartialEq, Debug, Default, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RewardStats {
+    stats: Stats,
+    rewards: RunningStats,
+}
+
+impl RewardStats {
+    /// Returns the running statistics of the rewards observed for the action.
+    pub fn rewards(&self) -> &RunningStats {
+        &self.rewards
+    }
+}
+
+impl ActionStatter for RewardStats {
+    fn calls(&self) -> u64 {
+        self.stats.calls()
+    }
+
+    fn set_calls(&mut self, n: u64) {
+        self.stats.set_calls(n);
+    }
+
+    fn q_value_raw(&self) -> f64 {
+        self.stats.q_value_raw()
+    }
+
+    fn set_q_value_raw(&mut self, q: f64) {
+        self.stats.set_q_value_raw(q);
+    }
+
+    fn q_value_weighted(&self) -> f64 {
+        self.stats.q_value_weighted()
+    }
+
+    fn set_q_value_weighted(&mut self, q: f64) {
+        self.stats.set_q_value_weighted(q);
+    }
+
+    fn observe_reward(&mut self, reward: f64) {
+        self.rewards.push(reward);
+    }
+}
+
+/// Contains the same statistics as `Stats`, along with the last `N` rewards
+/// that have been observed for the action.
+///
+/// Comparing older and newer rewards
+/// in the history can reveal whether the action's rewards are drifting over
+/// time.
+#[derive(PartialEq, Debug, Default, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct RewardHistory<const N: usize> {
+    stats: Stats,
+    rewards: VecDeque<f64>,
+}
+
+impl<const N: usize> RewardHistory<N> {
+    /// Returns up to the last `N` rewards observed for the action, oldest
+    /// first.
+    pub fn recent_rewards(&self) -> &VecDeque<f64> {
+        &self.rewards
+    }
+}
+
+impl<const N: usize> ActionStatter for RewardHistory<N> {
+    fn calls(&self) -> u64 {
+        self.stats.calls()
+    }
+
+    fn set_calls(&mut self, n: u64) {
+        self.stats.set_calls(n);
+    }
+
+    fn q_value_raw(&self) -> f64 {
+        self.stats.q_value_raw()
+    }
+
+    fn set_q_value_raw(&mut self, q: f64) {
+        self.stats.set_q_value_raw(q);
+    }
+
+    fn q_value_weighted(&self) -> f64 {
+        self.stats.q_value_weighted()
+    }
+
+    fn set_q_value_weighted(&mut self, q: f64) {
+        self.stats.set_q_value_weighted(q);
+    }
+
+    fn observe_reward(&mut self, reward: f64) {
+        if N == 0 {
+            return;
+        }
+        if self.rewards.len() == N {
+            self.rewards.pop_front();
+        }
+        self.rewards.push_back(reward);
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::agents::bayesian::Agent;
+    use crate::agents::Agenter;
+    use crate::mocks::*;
+
+    #[test]
+    fn running_stats() {
+        let mut stats = RunningStats::default();
+        assert_eq!(
+            (f64::NEG_INFINITY, f64::INFINITY),
+            stats.confidence_interval(1.96)
+        );
+
+        for value in &[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+            stats.push(*value);
+        }
+
+        assert_eq!(8, stats.count());
+        assert_eq!(5.0, stats.mean());
+        assert!((stats.variance() - 32.0 / 7.0).abs() < 1e-12);
+        let (lower, upper) = stats.confidence_interval(2.0);
+        let margin = 2.0 * (32.0_f64 / 7.0 / 8.0).sqrt();
+        assert!((lower - (5.0 - margin)).abs() < 1e-12);
+        assert!((upper - (5.0 + margin)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn agent_records_rewards() {
+        let action_x = MockActioner { return_id: "X" };
+        let previous_state = MockStater {
+            return_id: "A",
+            return_possible_actions: vec![&action_x],
+            ..Default::default()
+        };
+        let current_state = MockStater {
+            return_id: "B",
+            return_possible_actions: vec![&action_x],
+            ..Default::default()
+        };
+
+        let mut agent: Agent<MockStater<MockActioner>, MockActioner, RewardStats> =
+            Agent::new(1, 0.5, 0.0);
+        for reward in &[1.0, 3.0] {
+            agent
+                .learn(Some(&previous_state), &action_x, &current_state, *reward)
+                .unwrap();
+        }
+
+        let context = agent.get_agent_context();
+        let rewards = context.q_values["A"]["X"].rewards();
+        assert_eq!(2, rewards.count());
+        assert_eq!(2.0, rewards.mean());
+        assert_eq!(2.0, rewards.variance());
+    }
+
+    #[test]
+    fn reward_history() {
+        let mut history = RewardHistory::<3>::default();
+        for reward in 1..=5 {
+            history.observe_reward(f64::from(reward));
+        }
+        assert_eq!(
+            vec![3.0, 4.0, 5.0],
+            history.recent_rewards().iter().copied().collect::<Vec<_>>()
+        );
+
+        let mut empty = RewardHistory::<0>::default();
+        empty.observe_reward(1.0);
+        assert!(empty.recent_rewards().is_empty());
+    }
+}
@@ -0,0 +1,94 @@
+//! Statistics that also record when they were last updated.
+
+use crate::stats::actionstats::Stats;
+use crate::stats::ActionStatter;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Contains the same statistics as `Stats`, along with the agent's step count
+/// (see `Agent::step_count`) when the action was last learned from.
+///
+/// Steps are recorded instead of wall-clock time so that training runs remain
+/// reproducible. Entries whose step is far behind the agent's step count are
+/// no longer changing, and are candidates for pruning.
+#[derive(PartialEq, Debug, Default, Copy, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StepStats {
+    stats: Stats,
+    last_updated: Option<u64>,
+}
+
+impl ActionStatter for StepStats {
+    fn calls(&self) -> u64 {
+        self.stats.calls()
+    }
+
+    fn set_calls(&mut self, n: u64) {
+        self.stats.set_calls(n);
+    }
+
+    fn q_value_raw(&self) -> f64 {
+        self.stats.q_value_raw()
+    }
+
+    fn set_q_value_raw(&mut self, q: f64) {
+        self.stats.set_q_value_raw(q);
+    }
+
+    fn q_value_weighted(&self) -> f64 {
+        self.stats.q_value_weighted()
+    }
+
+    fn set_q_value_weighted(&mut self, q: f64) {
+        self.stats.set_q_value_weighted(q);
+    }
+
+    fn last_updated(&self) -> Option<u64> {
+        self.last_updated
+    }
+
+    fn set_last_updated(&mut self, step: u64) {
+        self.last_updated = Some(step);
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::agents::bayesian::Agent;
+    use crate::agents::Agenter;
+    use crate::mocks::*;
+
+    #[test]
+    fn agent_records_last_updated_step() {
+        let action_x = MockActioner { return_id: "X" };
+        let action_y = MockActioner { return_id: "Y" };
+        let state = |return_id| MockStater {
+            return_id,
+            return_possible_actions: vec![&action_x, &action_y],
+            ..Default::default()
+        };
+        let (a, b) = (state("A"), state("B"));
+
+        let mut agent: Agent<MockStater<MockActioner>, MockActioner, StepStats> =
+            Agent::new(1, 0.5, 0.0);
+        agent.learn(None, &action_x, &a, 1.0).unwrap();
+        assert_eq!(0, agent.step_count());
+
+        agent.learn(Some(&a), &action_x, &b, 1.0).unwrap();
+        agent.learn(Some(&a), &action_y, &b, 1.0).unwrap();
+        agent.learn(Some(&b), &action_x, &a, 1.0).unwrap();
+        assert_eq!(3, agent.step_count());
+
+        let context = agent.get_agent_context();
+        assert_eq!(Some(1), context.q_values["A"]["X"].last_updated());
+        assert_eq!(Some(2), context.q_values["A"]["Y"].last_updated());
+        assert_eq!(Some(3), context.q_values["B"]["X"].last_updated());
+        assert_eq!(None, context.q_values["B"]["Y"].last_updated());
+
+        let restored: Agent<MockStater<MockActioner>, MockActioner, StepStats> =
+            Agent::from_agent_context(context);
+        assert_eq!(3, restored.step_count());
+    }
+}
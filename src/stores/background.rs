@@ -0,0 +1,440 @@
+//! A `QStore` that persists changes to another store on a background thread.
+//!
+//! Persistent stores, such as `SqliteStore` and `RedisStore`, make the agent
+//! wait for every write. A `BackgroundStore` keeps the q-table in memory,
+//! where the agent reads and writes it without waiting, and records which
+//! entries have changed since they were last persisted. Periodically, the
+//! changed entries are handed to a background thread, which writes them to
+//! the persistent store while the agent carries on learning:
+//!
+//! ```ignore
+//! let backend = SqliteStore::open("agent.db")?;
+//! let store = BackgroundStore::new(backend, Duration::from_secs(5));
+//! let mut agent: Agent<_, _, Stats, _> = Agent::new_with_store(store, 1, 0.1, 0.9);
+//! ```
+//!
+//! The in-memory table starts empty, and is never filled from the persistent
+//! store, which is only written to.
+
+use crate::errors::LearnerError;
+use crate::stats::ActionStatter;
+use crate::stores::{QMap, QStore};
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::mem;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Changes handed to the background thread, or a request to report once
+/// every earlier change has been written.
+enum Message<SK, AK, AS> {
+    Write {
+        stats: Vec<(SK, HashMap<AK, AS>)>,
+        visits: Vec<(SK, u64)>,
+    },
+    Flush(Sender<()>),
+}
+
+/// The first error encountered by the background thread, which has not yet
+/// been reported.
+type SharedError = Arc<Mutex<Option<LearnerError>>>;
+
+/// An in-memory `QStore` whose changes are persisted to another store by a
+/// background thread. See the module documentation.
+///
+/// Changed entries are handed to the background thread when a write is made
+/// at least `interval` after the previous hand-off, when `flush` is called,
+/// and when the store is dropped. If the background thread fails to write
+/// to the persistent store, the error is returned by the next call to
+/// `flush`, or by the next write that hands off changes.
+pub struct BackgroundStore<SK, AK, AS>
+where
+    SK: Hash + Eq + Clone + Send + 'static,
+    AK: Hash + Eq + Clone + Send + 'static,
+    AS: ActionStatter + Send + 'static,
+{
+    memory: QMap<SK, AK, AS>,
+    dirty_stats: HashMap<SK, HashSet<AK>>,
+    dirty_visits: HashSet<SK>,
+    interval: Duration,
+    last_hand_off: Instant,
+    error: SharedError,
+    // Dropped before the worker is joined, which ends the worker's loop.
+    sender: Option<Sender<Message<SK, AK, AS>>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl<SK, AK, AS> BackgroundStore<SK, AK, AS>
+where
+    SK: Hash + Eq + Clone + Send + 'static,
+    AK: Hash + Eq + Clone + Send + 'static,
+    AS: ActionStatter + Send + 'static,
+{
+    /// Returns an empty store that hands its changes to a background thread,
+    /// which writes them to `backend`, at most once every `interval`.
+    pub fn new<B>(backend: B, interval: Duration) -> Self
+    where
+        B: QStore<SK, AK, AS> + Send + 'static,
+    {
+        let (sender, receiver) = mpsc::channel();
+        let error = SharedError::default();
+        let worker_error = Arc::clone(&error);
+        let worker = thread::spawn(move || persist(backend, &receiver, &worker_error));
+        Self {
+            memory: QMap::new(),
+            dirty_stats: HashMap::new(),
+            dirty_visits: HashSet::new(),
+            interval,
+            last_hand_off: Instant::now(),
+            error,
+            sender: Some(sender),
+            worker: Some(worker),
+        }
+    }
+
+    /// Returns the number of state-action pairs and state visit counts that
+    /// have changed since they were last handed to the background thread.
+    pub fn dirty_count(&self) -> usize {
+        self.dirty_stats.values().map(HashSet::len).sum::<usize>() + self.dirty_visits.len()
+    }
+
+    /// Hands every changed entry to the background thread, and waits until
+    /// the thread has written them, and every earlier change, to the
+    /// persistent store.
+    pub fn flush(&mut self) -> Result<(), LearnerError> {
+        self.hand_off()?;
+        let (reply, done) = mpsc::channel();
+        self.send(Message::Flush(reply))?;
+        done.recv().map_err(|_| stopped())?;
+        self.take_error()
+    }
+
+    /// Hands changed entries to the background thread if `interval` has
+    /// elapsed since the last hand-off.
+    fn hand_off_if_due(&mut self) -> Result<(), LearnerError> {
+        if self.last_hand_off.elapsed() >= self.interval {
+            self.hand_off()?;
+        }
+        Ok(())
+    }
+
+    /// Hands every changed entry to the background thread, without waiting
+    /// for it to be written.
+    fn hand_off(&mut self) -> Result<(), LearnerError> {
+        self.last_hand_off = Instant::now();
+        self.take_error()?;
+        if self.dirty_stats.is_empty() && self.dirty_visits.is_empty() {
+            return Ok(());
+        }
+        let mut stats = Vec::with_capacity(self.dirty_stats.len());
+        for (state_id, action_ids) in mem::take(&mut self.dirty_stats) {
+            let mut actions = HashMap::with_capacity(action_ids.len());
+            for action_id in action_ids {
+                if let Some(action_stats) = self.memory.get_stats(&state_id, &action_id)? {
+                    actions.insert(action_id, action_stats);
+                }
+            }
+            stats.push((state_id, actions));
+        }
+        let mut visits = Vec::with_capacity(self.dirty_visits.len());
+        for state_id in mem::take(&mut self.dirty_visits) {
+            let count = self.memory.get_visits(&state_id)?;
+            visits.push((state_id, count));
+        }
+        self.send(Message::Write { stats, visits })
+    }
+
+    fn send(&self, message: Message<SK, AK, AS>) -> Result<(), LearnerError> {
+        self.sender
+            .as_ref()
+            .ok_or_else(stopped)?
+            .send(message)
+            .map_err(|_| stopped())
+    }
+
+    fn take_error(&self) -> Result<(), LearnerError> {
+        let error = self
+            .error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take();
+        error.map_or(Ok(()), Err)
+    }
+
+    fn mark_dirty(&mut self, state_id: &SK, action_id: &AK) {
+        self.dirty_stats
+            .entry(state_id.clone())
+            .or_default()
+            .insert(action_id.clone());
+    }
+}
+
+/// Writes each batch of changes received from a `BackgroundStore` to
+/// `backend`, until the store is dropped. The first error is recorded in
+/// `error`, and changes continue to be written after it.
+fn persist<SK, AK, AS, B>(
+    mut backend: B,
+    receiver: &Receiver<Message<SK, AK, AS>>,
+    error: &Mutex<Option<LearnerError>>,
+) where
+    SK: Hash + Eq + Clone,
+    AK: Hash + Eq + Clone,
+    AS: ActionStatter,
+    B: QStore<SK, AK, AS>,
+{
+    for message in receiver {
+        match message {
+            Message::Write { stats, visits } => {
+                let result = stats
+                    .into_iter()
+                    .try_for_each(|(state_id, actions)| {
+                        backend.update_actions_for_state(&state_id, actions)
+                    })
+                    .and_then(|()| {
+                        visits
+                            .iter()
+                            .try_for_each(|(state_id, count)| backend.set_visits(state_id, *count))
+                    });
+                if let Err(e) = result {
+                    error
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .get_or_insert(e);
+                }
+            }
+            Message::Flush(reply) => {
+                // The store may have stopped waiting, in which case there is
+                // nobody to tell.
+                let _ = reply.send(());
+            }
+        }
+    }
+}
+
+fn stopped() -> LearnerError {
+    LearnerError::Storage("background persistence thread has stopped".to_string())
+}
+
+impl<SK, AK, AS> Drop for BackgroundStore<SK, AK, AS>
+where
+    SK: Hash + Eq + Clone + Send + 'static,
+    AK: Hash + Eq + Clone + Send + 'static,
+    AS: ActionStatter + Send + 'static,
+{
+    /// Hands off any remaining changes, and waits for the background thread
+    /// to write them. Errors cannot be reported from `drop`, so `flush`
+    /// should be called first if they matter.
+    fn drop(&mut self) {
+        let _ = self.hand_off();
+        self.sender = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+impl<SK, AK, AS> QStore<SK, AK, AS> for BackgroundStore<SK, AK, AS>
+where
+    SK: Hash + Eq + Clone + Send + 'static,
+    AK: Hash + Eq + Clone + Send + 'static,
+    AS: ActionStatter + Send + 'static,
+{
+    fn get_stats(&self, state_id: &SK, action_id: &AK) -> Result<Option<AS>, LearnerError> {
+        self.memory.get_stats(state_id, action_id)
+    }
+
+    fn update_stats(
+        &mut self,
+        state_id: &SK,
+        action_id: &AK,
+        stats: AS,
+    ) -> Result<(), LearnerError> {
+        self.memory.update_stats(state_id, action_id, stats)?;
+        self.mark_dirty(state_id, action_id);
+        self.hand_off_if_due()
+    }
+
+    fn update_stats_with<D, F>(
+        &mut self,
+        state_id: &SK,
+        action_id: &AK,
+        default: D,
+        update: F,
+    ) -> Result<(), LearnerError>
+    where
+        D: FnOnce() -> AS,
+        F: FnOnce(&mut AS),
+    {
+        self.memory
+            .update_stats_with(state_id, action_id, default, update)?;
+        self.mark_dirty(state_id, action_id);
+        self.hand_off_if_due()
+    }
+
+    fn get_actions_for_state(&self, state_id: &SK) -> Result<HashMap<AK, AS>, LearnerError> {
+        self.memory.get_actions_for_state(state_id)
+    }
+
+    fn visit_actions_for_state<F>(&self, state_id: &SK, visit: F) -> Result<(), LearnerError>
+    where
+        F: FnMut(&AK, &AS),
+    {
+        self.memory.visit_actions_for_state(state_id, visit)
+    }
+
+    fn update_actions_for_state(
+        &mut self,
+        state_id: &SK,
+        actions: HashMap<AK, AS>,
+    ) -> Result<(), LearnerError> {
+        for action_id in actions.keys() {
+            self.mark_dirty(state_id, action_id);
+        }
+        self.memory.update_actions_for_state(state_id, actions)?;
+        self.hand_off_if_due()
+    }
+
+    fn raw_q_sum(&self, state_id: &SK) -> Result<(f64, usize), LearnerError> {
+        self.memory.raw_q_sum(state_id)
+    }
+
+    fn get_visits(&self, state_id: &SK) -> Result<u64, LearnerError> {
+        self.memory.get_visits(state_id)
+    }
+
+    fn set_visits(&mut self, state_id: &SK, visits: u64) -> Result<(), LearnerError> {
+        self.memory.set_visits(state_id, visits)?;
+        self.dirty_visits.insert(state_id.clone());
+        self.hand_off_if_due()
+    }
+
+    fn increment_visits(&mut self, state_id: &SK) -> Result<(), LearnerError> {
+        self.memory.increment_visits(state_id)?;
+        self.dirty_visits.insert(state_id.clone());
+        self.hand_off_if_due()
+    }
+
+    fn state_count(&self) -> Result<usize, LearnerError> {
+        self.memory.state_count()
+    }
+
+    fn entry_count(&self) -> Result<usize, LearnerError> {
+        self.memory.entry_count()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::agents::bayesian::Agent;
+    use crate::mocks::*;
+    use crate::stats::actionstats::Stats;
+    use crate::stores::concurrent::ConcurrentQMap;
+    use crate::training::Trainer;
+
+    type Backend = ConcurrentQMap<&'static str, &'static str, Stats>;
+
+    #[test]
+    fn persists_on_flush_and_drop() {
+        let backend = Backend::new();
+        let mut store = BackgroundStore::new(backend.clone(), Duration::from_hours(1));
+        store.update_stats(&"A", &"X", Stats::default()).unwrap();
+        store
+            .update_stats_with(&"A", &"Y", Stats::default, |_| {})
+            .unwrap();
+        store.set_visits(&"A", 2).unwrap();
+        assert_eq!(3, store.dirty_count());
+        assert_eq!(2, store.entry_count().unwrap());
+        assert_eq!(2, store.get_visits(&"A").unwrap());
+
+        // Nothing is handed off until the interval has elapsed.
+        store.flush().unwrap();
+        assert_eq!(0, store.dirty_count());
+        assert_eq!(2, backend.entry_count().unwrap());
+        assert_eq!(2, backend.get_visits(&"A").unwrap());
+
+        let actions = HashMap::from([("Z", Stats::default())]);
+        store.update_actions_for_state(&"B", actions).unwrap();
+        assert_eq!(1, store.dirty_count());
+        drop(store);
+        assert_eq!(3, backend.entry_count().unwrap());
+        assert_eq!(2, backend.state_count().unwrap());
+    }
+
+    #[test]
+    fn hands_off_periodically() {
+        let backend = Backend::new();
+        let mut store = BackgroundStore::new(backend.clone(), Duration::ZERO);
+        store.update_stats(&"A", &"X", Stats::default()).unwrap();
+        assert_eq!(0, store.dirty_count());
+        store.flush().unwrap();
+        assert_eq!(1, backend.entry_count().unwrap());
+    }
+
+    #[test]
+    fn agent_learns_into_background_store() {
+        let backend: ConcurrentQMap<usize, String, Stats> = ConcurrentQMap::new();
+        let store = BackgroundStore::new(backend.clone(), Duration::from_millis(1));
+        let moves = MockCorridor::moves();
+        let mut env = MockCorridor::new(&moves, 4);
+        let mut agent = Agent::new_with_store(store, 1, 1.0, 0.9);
+        Trainer::new(5, 20).train(&mut agent, &mut env).unwrap();
+
+        agent.qstore.flush().unwrap();
+        assert_eq!(agent.entry_count().unwrap(), backend.entry_count().unwrap());
+        assert_eq!(
+            agent.state_visits(&0).unwrap(),
+            backend.get_visits(&0).unwrap()
+        );
+    }
+
+    /// A store that cannot be written to.
+    struct ReadOnly;
+
+    impl QStore<&'static str, &'static str, Stats> for ReadOnly {
+        fn get_stats(&self, _: &&str, _: &&str) -> Result<Option<Stats>, LearnerError> {
+            Ok(None)
+        }
+
+        fn update_stats(&mut self, _: &&str, _: &&str, _: Stats) -> Result<(), LearnerError> {
+            Err(LearnerError::Storage("read only".to_string()))
+        }
+
+        fn get_actions_for_state(
+            &self,
+            _: &&str,
+        ) -> Result<HashMap<&'static str, Stats>, LearnerError> {
+            Ok(HashMap::new())
+        }
+
+        fn get_visits(&self, _: &&str) -> Result<u64, LearnerError> {
+            Ok(0)
+        }
+
+        fn set_visits(&mut self, _: &&str, _: u64) -> Result<(), LearnerError> {
+            Err(LearnerError::Storage("read only".to_string()))
+        }
+
+        fn state_count(&self) -> Result<usize, LearnerError> {
+            Ok(0)
+        }
+
+        fn entry_count(&self) -> Result<usize, LearnerError> {
+            Ok(0)
+        }
+    }
+
+    #[test]
+    fn reports_backend_errors() {
+        let mut store = BackgroundStore::new(ReadOnly, Duration::from_hours(1));
+        store.update_stats(&"A", &"X", Stats::default()).unwrap();
+        assert!(matches!(store.flush(), Err(LearnerError::Storage(_))));
+
+        // The error is only reported once.
+        store.flush().unwrap();
+        assert_eq!(1, store.entry_count().unwrap());
+    }
+}
@@ -0,0 +1,388 @@
+//! A thread-safe in-memory `QStore`.
+
+use crate::errors::LearnerError;
+use crate::stats::ActionStatter;
+use crate::stores::QStore;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// The number of shards used by `ConcurrentQMap::new`.
+const DEFAULT_SHARD_COUNT: usize = 16;
+
+/// The statistics of each action, and the visit count of each state, within a
+/// single shard. Both are keyed by state ID.
+#[derive(Debug)]
+struct Shard<SK, AK, AS> {
+    actions: HashMap<SK, HashMap<AK, AS>>,
+    visits: HashMap<SK, u64>,
+}
+
+impl<SK, AK, AS> Default for Shard<SK, AK, AS> {
+    fn default() -> Self {
+        Self {
+            actions: HashMap::new(),
+            visits: HashMap::new(),
+        }
+    }
+}
+
+/// An in-memory `QStore` that can be shared between threads.
+///
+/// Cloning a `ConcurrentQMap` returns a new handle to the same underlying
+/// data, so several agents (for instance, one per worker thread) can learn
+/// into a single shared table. The table is split into shards by state ID,
+/// each guarded by its own lock, so agents working on different states
+/// rarely contend with one another.
+///
+/// Each individual read or write is atomic. An agent updates the stats it
+/// learns from with `update_stats_with`, and the visits of the state with
+/// `increment_visits`, each of which holds the shard's lock for the whole
+/// update, so agents learning from the same state do not lose each other's
+/// updates.
+#[derive(Debug)]
+pub struct ConcurrentQMap<SK, AK, AS>
+where
+    SK: Hash + Eq,
+    AK: Hash + Eq,
+    AS: ActionStatter,
+{
+    shards: Arc<Vec<RwLock<Shard<SK, AK, AS>>>>,
+}
+
+impl<SK, AK, AS> ConcurrentQMap<SK, AK, AS>
+where
+    SK: Hash + Eq,
+    AK: Hash + Eq,
+    AS: ActionStatter,
+{
+    /// Returns a new, empty `ConcurrentQMap` with a default number of shards.
+    pub fn new() -> Self {
+        Self::with_shards(DEFAULT_SHARD_COUNT)
+    }
+
+    /// Returns a new, empty `ConcurrentQMap` split into `shard_count` shards.
+    /// At least one shard is always created.
+    pub fn with_shards(shard_count: usize) -> Self {
+        let shards = (0..shard_count.max(1))
+            .map(|_| RwLock::new(Shard::default()))
+            .collect();
+        Self {
+            shards: Arc::new(shards),
+        }
+    }
+
+    fn shard_for(&self, state_id: &SK) -> &RwLock<Shard<SK, AK, AS>> {
+        let mut hasher = DefaultHasher::new();
+        state_id.hash(&mut hasher);
+        let shard_count = u64::try_from(self.shards.len()).unwrap_or(u64::MAX);
+        let index = usize::try_from(hasher.finish() % shard_count).unwrap_or_default();
+        &self.shards[index]
+    }
+
+    fn read(&self, state_id: &SK) -> Result<RwLockReadGuard<'_, Shard<SK, AK, AS>>, LearnerError> {
+        self.shard_for(state_id)
+            .read()
+            .map_err(|_| LearnerError::Storage("concurrent store lock is poisoned".to_string()))
+    }
+
+    fn write(
+        &self,
+        state_id: &SK,
+    ) -> Result<RwLockWriteGuard<'_, Shard<SK, AK, AS>>, LearnerError> {
+        self.shard_for(state_id)
+            .write()
+            .map_err(|_| LearnerError::Storage("concurrent store lock is poisoned".to_string()))
+    }
+
+    /// Sums `count` over every shard, locking one shard at a time.
+    fn fold_shards<F>(&self, count: F) -> Result<usize, LearnerError>
+    where
+        F: Fn(&Shard<SK, AK, AS>) -> usize,
+    {
+        self.shards.iter().try_fold(0, |total, shard| {
+            let shard = shard.read().map_err(|_| {
+                LearnerError::Storage("concurrent store lock is poisoned".to_string())
+            })?;
+            Ok(total + count(&shard))
+        })
+    }
+}
+
+impl<SK, AK, AS> Clone for ConcurrentQMap<SK, AK, AS>
+where
+    SK: Hash + Eq,
+    AK: Hash + Eq,
+    AS: ActionStatter,
+{
+    fn clone(&self) -> Self {
+        Self {
+            shards: Arc::clone(&self.shards),
+        }
+    }
+}
+
+impl<SK, AK, AS> Default for ConcurrentQMap<SK, AK, AS>
+where
+    SK: Hash + Eq,
+    AK: Hash + Eq,
+    AS: ActionStatter,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<SK, AK, AS> QStore<SK, AK, AS> for ConcurrentQMap<SK, AK, AS>
+where
+    SK: Hash + Eq + Clone,
+    AK: Hash + Eq + Clone,
+    AS: ActionStatter,
+{
+    fn get_stats(&self, state_id: &SK, action_id: &AK) -> Result<Option<AS>, LearnerError> {
+        Ok(self
+            .read(state_id)?
+            .actions
+            .get(state_id)
+            .and_then(|actions| actions.get(action_id))
+            .cloned())
+    }
+
+    fn update_stats(
+        &mut self,
+        state_id: &SK,
+        action_id: &AK,
+        stats: AS,
+    ) -> Result<(), LearnerError> {
+        self.write(state_id)?
+            .actions
+            .entry(state_id.clone())
+            .or_default()
+            .insert(action_id.clone(), stats);
+        Ok(())
+    }
+
+    fn update_stats_with<D, F>(
+        &mut self,
+        state_id: &SK,
+        action_id: &AK,
+        default: D,
+        update: F,
+    ) -> Result<(), LearnerError>
+    where
+        D: FnOnce() -> AS,
+        F: FnOnce(&mut AS),
+    {
+        let mut shard = self.write(state_id)?;
+        let actions = shard.actions.entry(state_id.clone()).or_default();
+        if let Some(stats) = actions.get_mut(action_id) {
+            update(stats);
+        } else {
+            let mut stats = default();
+            update(&mut stats);
+            actions.insert(action_id.clone(), stats);
+        }
+        drop(shard);
+        Ok(())
+    }
+
+    fn get_actions_for_state(&self, state_id: &SK) -> Result<HashMap<AK, AS>, LearnerError> {
+        Ok(self
+            .read(state_id)?
+            .actions
+            .get(state_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+
+    fn visit_actions_for_state<F>(&self, state_id: &SK, mut visit: F) -> Result<(), LearnerError>
+    where
+        F: FnMut(&AK, &AS),
+    {
+        let shard = self.read(state_id)?;
+        for (action_id, stats) in shard.actions.get(state_id).into_iter().flatten() {
+            visit(action_id, stats);
+        }
+        drop(shard);
+        Ok(())
+    }
+
+    fn update_actions_for_state(
+        &mut self,
+        state_id: &SK,
+        actions: HashMap<AK, AS>,
+    ) -> Result<(), LearnerError> {
+        if actions.is_empty() {
+            return Ok(());
+        }
+        self.write(state_id)?
+            .actions
+            .entry(state_id.clone())
+            .or_default()
+            .extend(actions);
+        Ok(())
+    }
+
+    fn get_visits(&self, state_id: &SK) -> Result<u64, LearnerError> {
+        Ok(self
+            .read(state_id)?
+            .visits
+            .get(state_id)
+            .copied()
+            .unwrap_or_default())
+    }
+
+    fn set_visits(&mut self, state_id: &SK, visits: u64) -> Result<(), LearnerError> {
+        self.write(state_id)?
+            .visits
+            .insert(state_id.clone(), visits);
+        Ok(())
+    }
+
+    fn increment_visits(&mut self, state_id: &SK) -> Result<(), LearnerError> {
+        let mut shard = self.write(state_id)?;
+        let visits = shard.visits.entry(state_id.clone()).or_default();
+        *visits = visits.saturating_add(1);
+        drop(shard);
+        Ok(())
+    }
+
+    fn state_count(&self) -> Result<usize, LearnerError> {
+        self.fold_shards(|shard| shard.actions.len())
+    }
+
+    fn entry_count(&self) -> Result<usize, LearnerError> {
+        self.fold_shards(|shard| shard.actions.values().map(HashMap::len).sum())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::agents::bayesian::Agent;
+    use crate::agents::Agenter;
+    use crate::mocks::*;
+    use crate::stats::actionstats::Stats;
+    use std::thread;
+
+    #[test]
+    fn clones_share_data() {
+        let mut store: ConcurrentQMap<&str, &str, Stats> = ConcurrentQMap::with_shards(4);
+        let handle = store.clone();
+        store.update_stats(&"A", &"X", Stats::default()).unwrap();
+
+        assert!(handle.get_stats(&"A", &"X").unwrap().is_some());
+        assert!(handle.get_stats(&"A", &"Y").unwrap().is_none());
+        assert_eq!(1, handle.get_actions_for_state(&"A").unwrap().len());
+        assert!(handle.get_actions_for_state(&"B").unwrap().is_empty());
+        assert_eq!(1, handle.state_count().unwrap());
+        store.set_visits(&"A", 2).unwrap();
+        assert_eq!(2, handle.get_visits(&"A").unwrap());
+        assert_eq!(0, handle.get_visits(&"B").unwrap());
+        assert_eq!(1, handle.entry_count().unwrap());
+    }
+
+    #[test]
+    fn update_stats_with_is_atomic() {
+        let store: ConcurrentQMap<&str, &str, Stats> = ConcurrentQMap::with_shards(2);
+
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                let mut store = store.clone();
+                scope.spawn(move || {
+                    for _ in 0..100 {
+                        store
+                            .update_stats_with(&"A", &"X", Stats::default, |stats| {
+                                stats.call_count += 1;
+                            })
+                            .unwrap();
+                    }
+                });
+            }
+        });
+
+        let stats = store.get_stats(&"A", &"X").unwrap().unwrap();
+        assert_eq!(400, stats.call_count);
+    }
+
+    #[test]
+    fn agents_learn_concurrently() {
+        let store: ConcurrentQMap<String, String, Stats> = ConcurrentQMap::new();
+        let state_ids = ["A", "B", "C", "D"];
+
+        thread::scope(|scope| {
+            for state_id in &state_ids {
+                let store = store.clone();
+                scope.spawn(move || {
+                    let action_x = MockActioner { return_id: "X" };
+                    let previous_state = MockStater {
+                        return_id: state_id,
+                        return_possible_actions: vec![&action_x],
+                        ..Default::default()
+                    };
+                    let current_state = MockStater {
+                        return_id: "terminal",
+                        return_possible_actions: vec![],
+                        ..Default::default()
+                    };
+                    let mut agent: Agent<MockStater<MockActioner>, MockActioner, Stats, _> =
+                        Agent::new_with_store(store, 0, 1.0, 0.0);
+                    for _ in 0..50 {
+                        agent
+                            .learn(Some(&previous_state), &action_x, &current_state, 1.0)
+                            .unwrap();
+                    }
+                });
+            }
+        });
+
+        for state_id in &state_ids {
+            let stats = store
+                .get_stats(&state_id.to_string(), &"X".to_string())
+                .unwrap()
+                .unwrap();
+            assert_eq!(50, stats.call_count);
+            assert_eq!(1.0, stats.q_raw);
+        }
+    }
+
+    #[test]
+    fn agents_learn_same_state_concurrently() {
+        let store: ConcurrentQMap<String, String, Stats> = ConcurrentQMap::new();
+        let (threads, iterations) = (8, 200);
+
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                let store = store.clone();
+                scope.spawn(move || {
+                    let action_x = MockActioner { return_id: "X" };
+                    let previous_state = MockStater {
+                        return_id: "A",
+                        return_possible_actions: vec![&action_x],
+                        ..Default::default()
+                    };
+                    let current_state = MockStater {
+                        return_id: "terminal",
+                        return_possible_actions: vec![],
+                        ..Default::default()
+                    };
+                    let mut agent: Agent<MockStater<MockActioner>, MockActioner, Stats, _> =
+                        Agent::new_with_store(store, 0, 1.0, 0.0);
+                    for _ in 0..iterations {
+                        agent
+                            .learn(Some(&previous_state), &action_x, &current_state, 1.0)
+                            .unwrap();
+                    }
+                });
+            }
+        });
+
+        let (a, x) = ("A".to_string(), "X".to_string());
+        let stats = store.get_stats(&a, &x).unwrap().unwrap();
+        assert_eq!(threads * iterations, stats.call_count);
+        assert_eq!(threads * iterations, store.get_visits(&a).unwrap());
+    }
+}
@@ -0,0 +1,143 @@
+//! Stores hold the statistics that an agent records for each state and
+//! action.
+//!
+//! By default, agents keep their statistics in memory using a `QMap`. Other
+//! backends can be supplied to an agent by implementing `QStore`.
+
+pub mod background;
+pub mod concurrent;
+pub mod ordered;
+#[cfg(feature = "redis")]
+pub mod redis;
+pub mod shared;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+pub use crate::internal::datastructures::QMap;
+
+use crate::errors::LearnerError;
+use crate::stats::ActionStatter;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Represents something that can store and retrieve the statistics associated
+/// with each state and action, keyed by state IDs of type `SK` and action IDs
+/// of type `AK`.
+pub trait QStore<SK, AK, AS>
+where
+    SK: Hash + Eq + Clone,
+    AK: Hash + Eq + Clone,
+    AS: ActionStatter,
+{
+    /// Returns the stats recorded for an action within a state, or `None` if
+    /// no stats have been recorded.
+    fn get_stats(&self, state_id: &SK, action_id: &AK) -> Result<Option<AS>, LearnerError>;
+
+    /// Records the stats for an action within a state, replacing any stats
+    /// that were previously recorded.
+    fn update_stats(
+        &mut self,
+        state_id: &SK,
+        action_id: &AK,
+        stats: AS,
+    ) -> Result<(), LearnerError>;
+
+    /// Applies `update` to the stats recorded for an action within a state,
+    /// first recording the stats returned by `default` if none have been
+    /// recorded.
+    ///
+    /// The default implementation reads the stats, updates them, and writes
+    /// them back. In-memory stores override it to update the stats in place,
+    /// without cloning them.
+    fn update_stats_with<D, F>(
+        &mut self,
+        state_id: &SK,
+        action_id: &AK,
+        default: D,
+        update: F,
+    ) -> Result<(), LearnerError>
+    where
+        Self: Sized,
+        D: FnOnce() -> AS,
+        F: FnOnce(&mut AS),
+    {
+        let mut stats = self.get_stats(state_id, action_id)?.unwrap_or_else(default);
+        update(&mut stats);
+        self.update_stats(state_id, action_id, stats)
+    }
+
+    /// Returns the stats recorded for every action within a state, keyed by
+    /// action ID.
+    fn get_actions_for_state(&self, state_id: &SK) -> Result<HashMap<AK, AS>, LearnerError>;
+
+    /// Calls `visit` with the ID and stats of every action recorded within a
+    /// state.
+    ///
+    /// The default implementation reads the stats with
+    /// `get_actions_for_state`. In-memory stores override it to lend out the
+    /// stats they hold, without cloning them.
+    fn visit_actions_for_state<F>(&self, state_id: &SK, mut visit: F) -> Result<(), LearnerError>
+    where
+        Self: Sized,
+        F: FnMut(&AK, &AS),
+    {
+        for (action_id, stats) in &self.get_actions_for_state(state_id)? {
+            visit(action_id, stats);
+        }
+        Ok(())
+    }
+
+    /// Records the stats for several actions within a state at once.
+    /// Implementors may override this to apply the updates more efficiently
+    /// than individual calls to `update_stats`.
+    fn update_actions_for_state(
+        &mut self,
+        state_id: &SK,
+        actions: HashMap<AK, AS>,
+    ) -> Result<(), LearnerError> {
+        for (action_id, stats) in actions {
+            self.update_stats(state_id, &action_id, stats)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the sum of the raw q-values of every action recorded within a
+    /// state, along with the number of actions recorded.
+    ///
+    /// Agents use the sum to find the mean q-value towards which the q-values
+    /// of rarely called actions are weighted. The default implementation
+    /// reads every action within the state, so stores may override it to
+    /// maintain the sum incrementally as stats are updated.
+    fn raw_q_sum(&self, state_id: &SK) -> Result<(f64, usize), LearnerError> {
+        let actions = self.get_actions_for_state(state_id)?;
+        Ok((
+            actions.values().map(ActionStatter::q_value_raw).sum(),
+            actions.len(),
+        ))
+    }
+
+    /// Returns the number of times the state has been visited, or 0 if no
+    /// visits have been recorded.
+    fn get_visits(&self, state_id: &SK) -> Result<u64, LearnerError>;
+
+    /// Records the number of times the state has been visited.
+    fn set_visits(&mut self, state_id: &SK, visits: u64) -> Result<(), LearnerError>;
+
+    /// Adds one to the number of times the state has been visited. The count
+    /// saturates at `u64::MAX` rather than overflowing.
+    ///
+    /// The default implementation reads the count and writes it back. Stores
+    /// that can be shared between agents override it to increment the count
+    /// atomically.
+    fn increment_visits(&mut self, state_id: &SK) -> Result<(), LearnerError> {
+        let visits = self.get_visits(state_id)?;
+        self.set_visits(state_id, visits.saturating_add(1))
+    }
+
+    /// Returns the number of states that have stats recorded in the store.
+    fn state_count(&self) -> Result<usize, LearnerError>;
+
+    /// Returns the total number of state-action pairs that have stats
+    /// recorded in the store.
+    fn entry_count(&self) -> Result<usize, LearnerError>;
+}
@@ -0,0 +1,259 @@
+//! An in-memory `QStore` that keeps its entries sorted by ID.
+//!
+//! A `QMap` is backed by hash maps, so the order in which it yields states
+//! and actions can differ from one run to the next. An `OrderedQMap` is
+//! backed by `BTreeMap`s instead, so `iter` always yields states in ascending
+//! order, and the actions within each state in ascending order. This makes
+//! exports, and any scan over the store, reproducible, at the cost of
+//! logarithmic rather than constant time lookups.
+//!
+//! `QStore::get_actions_for_state` returns a `HashMap`, so the candidates
+//! that an agent considers in `recommend_action` are still sorted by the
+//! agent itself before ties are broken. Agent snapshots written by
+//! `Agent::save_to` are sorted by ID whichever store the agent uses.
+
+use crate::errors::LearnerError;
+use crate::stats::ActionStatter;
+use crate::stores::QStore;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+/// An in-memory `QStore` that iterates over its states and actions in
+/// ascending order of ID.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OrderedQMap<SK, AK, AS>
+where
+    SK: Ord,
+    AK: Ord,
+{
+    data: BTreeMap<SK, BTreeMap<AK, AS>>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    visits: BTreeMap<SK, u64>,
+}
+
+impl<SK, AK, AS> OrderedQMap<SK, AK, AS>
+where
+    SK: Ord,
+    AK: Ord,
+{
+    /// Returns a new, empty `OrderedQMap`.
+    pub const fn new() -> Self {
+        Self {
+            data: BTreeMap::new(),
+            visits: BTreeMap::new(),
+        }
+    }
+
+    /// Returns an iterator over the stats recorded for every state and
+    /// action, ordered by state ID and then by action ID.
+    pub fn iter(&self) -> impl Iterator<Item = (&SK, &AK, &AS)> {
+        self.data.iter().flat_map(|(state_id, actions)| {
+            actions
+                .iter()
+                .map(move |(action_id, stats)| (state_id, action_id, stats))
+        })
+    }
+
+    /// Returns an iterator over the visit count of every state that has been
+    /// visited, ordered by state ID.
+    pub fn iter_visits(&self) -> impl Iterator<Item = (&SK, u64)> {
+        self.visits
+            .iter()
+            .map(|(state_id, visits)| (state_id, *visits))
+    }
+
+    /// Returns a reference to the stats recorded for an action within a
+    /// state, or `None` if no stats have been recorded.
+    pub fn stats(&self, state_id: &SK, action_id: &AK) -> Option<&AS> {
+        self.data
+            .get(state_id)
+            .and_then(|actions| actions.get(action_id))
+    }
+}
+
+impl<SK, AK, AS> Default for OrderedQMap<SK, AK, AS>
+where
+    SK: Ord,
+    AK: Ord,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<SK, AK, AS> QStore<SK, AK, AS> for OrderedQMap<SK, AK, AS>
+where
+    SK: Hash + Eq + Ord + Clone,
+    AK: Hash + Eq + Ord + Clone,
+    AS: ActionStatter,
+{
+    fn get_stats(&self, state_id: &SK, action_id: &AK) -> Result<Option<AS>, LearnerError> {
+        Ok(self.stats(state_id, action_id).cloned())
+    }
+
+    fn update_stats(
+        &mut self,
+        state_id: &SK,
+        action_id: &AK,
+        stats: AS,
+    ) -> Result<(), LearnerError> {
+        self.data
+            .entry(state_id.clone())
+            .or_default()
+            .insert(action_id.clone(), stats);
+        Ok(())
+    }
+
+    fn update_stats_with<D, F>(
+        &mut self,
+        state_id: &SK,
+        action_id: &AK,
+        default: D,
+        update: F,
+    ) -> Result<(), LearnerError>
+    where
+        D: FnOnce() -> AS,
+        F: FnOnce(&mut AS),
+    {
+        let actions = self.data.entry(state_id.clone()).or_default();
+        if let Some(stats) = actions.get_mut(action_id) {
+            update(stats);
+        } else {
+            let mut stats = default();
+            update(&mut stats);
+            actions.insert(action_id.clone(), stats);
+        }
+        Ok(())
+    }
+
+    fn get_actions_for_state(&self, state_id: &SK) -> Result<HashMap<AK, AS>, LearnerError> {
+        Ok(self
+            .data
+            .get(state_id)
+            .map(|actions| {
+                actions
+                    .iter()
+                    .map(|(action_id, stats)| (action_id.clone(), stats.clone()))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    fn visit_actions_for_state<F>(&self, state_id: &SK, mut visit: F) -> Result<(), LearnerError>
+    where
+        F: FnMut(&AK, &AS),
+    {
+        for (action_id, stats) in self.data.get(state_id).into_iter().flatten() {
+            visit(action_id, stats);
+        }
+        Ok(())
+    }
+
+    fn raw_q_sum(&self, state_id: &SK) -> Result<(f64, usize), LearnerError> {
+        Ok(self.data.get(state_id).map_or((0.0, 0), |actions| {
+            (
+                actions.values().map(ActionStatter::q_value_raw).sum(),
+                actions.len(),
+            )
+        }))
+    }
+
+    fn get_visits(&self, state_id: &SK) -> Result<u64, LearnerError> {
+        Ok(self.visits.get(state_id).copied().unwrap_or_default())
+    }
+
+    fn set_visits(&mut self, state_id: &SK, visits: u64) -> Result<(), LearnerError> {
+        self.visits.insert(state_id.clone(), visits);
+        Ok(())
+    }
+
+    fn state_count(&self) -> Result<usize, LearnerError> {
+        Ok(self.data.len())
+    }
+
+    fn entry_count(&self) -> Result<usize, LearnerError> {
+        Ok(self.data.values().map(BTreeMap::len).sum())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::actions::Actioner;
+    use crate::agents::bayesian::Agent;
+    use crate::agents::Agenter;
+    use crate::mocks::*;
+    use crate::stats::actionstats::Stats;
+
+    #[test]
+    fn iterates_in_order() {
+        let mut qmap: OrderedQMap<u32, &str, Stats> = OrderedQMap::new();
+        for state_id in [3, 1, 2] {
+            for action_id in ["Y", "X", "Z"] {
+                qmap.update_stats(&state_id, &action_id, Stats::default())
+                    .unwrap();
+            }
+        }
+        qmap.set_visits(&2, 1).unwrap();
+        qmap.set_visits(&1, 4).unwrap();
+
+        let keys: Vec<_> = qmap.iter().map(|(s, a, _)| (*s, *a)).collect();
+        assert_eq!((1, "X"), keys[0]);
+        assert_eq!((1, "Y"), keys[1]);
+        assert_eq!((3, "Z"), keys[8]);
+        assert_eq!(
+            vec![(&1, 4), (&2, 1)],
+            qmap.iter_visits().collect::<Vec<_>>()
+        );
+        assert_eq!(3, qmap.state_count().unwrap());
+        assert_eq!(9, qmap.entry_count().unwrap());
+    }
+
+    #[test]
+    fn stores_stats() {
+        let stats = |q_raw| Stats {
+            q_raw,
+            ..Default::default()
+        };
+        let mut qmap: OrderedQMap<&str, &str, Stats> = OrderedQMap::default();
+        assert!(qmap.get_stats(&"A", &"X").unwrap().is_none());
+        assert_eq!((0.0, 0), qmap.raw_q_sum(&"A").unwrap());
+        assert_eq!(0, qmap.get_visits(&"A").unwrap());
+
+        qmap.update_stats(&"A", &"X", stats(1.0)).unwrap();
+        qmap.update_stats_with(&"A", &"Y", || stats(2.0), |s| s.call_count += 1)
+            .unwrap();
+        qmap.update_stats_with(&"A", &"X", Stats::default, |s| s.q_raw += 1.0)
+            .unwrap();
+        assert_eq!((4.0, 2), qmap.raw_q_sum(&"A").unwrap());
+        assert_eq!(1, qmap.stats(&"A", &"Y").unwrap().call_count);
+
+        let actions = qmap.get_actions_for_state(&"A").unwrap();
+        assert_eq!(2, actions.len());
+        assert_eq!(2.0, actions["X"].q_raw);
+    }
+
+    #[test]
+    fn agent_learns_into_ordered_store() {
+        let moves = MockCorridor::moves();
+        let cell = |position| MockCell {
+            position,
+            moves: &moves,
+        };
+        let mut agent: Agent<MockCell, MockActioner, Stats, _> =
+            Agent::new_with_store(OrderedQMap::new(), 0, 1.0, 0.0);
+        agent
+            .learn(Some(&cell(1)), &moves[1], &cell(2), 1.0)
+            .unwrap();
+        agent
+            .learn(Some(&cell(0)), &moves[0], &cell(0), -1.0)
+            .unwrap();
+        assert_eq!("R", agent.recommend_action(&cell(1)).unwrap().id());
+        assert_eq!(3, agent.state_count().unwrap());
+        assert_eq!(1, agent.state_visits(&0).unwrap());
+    }
+}
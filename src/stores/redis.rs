@@ -0,0 +1,410 @@
+//! A `QStore` backed by Redis.
+//!
+//! Several agent processes can share a single q-table by pointing their
+//! stores at the same Redis server and key prefix. Each state's stats are
+//! kept in a Redis hash keyed by action ID, so reading a state's actions is a
+//! single round trip.
+
+use crate::errors::LearnerError;
+use crate::stats::ActionStatter;
+use crate::stores::QStore;
+use ::redis::{Client, Commands, Connection, Pipeline};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::str::FromStr;
+
+/// The key prefix used when none is supplied.
+const DEFAULT_PREFIX: &str = "rlr";
+
+/// Writes that have been made to the store, but not yet sent to Redis.
+#[derive(Debug, Default)]
+struct Pending {
+    /// Encoded stats, keyed by state ID and then by action ID.
+    stats: HashMap<String, HashMap<String, String>>,
+    visits: HashMap<String, u64>,
+    len: usize,
+}
+
+/// A `QStore` that keeps statistics in a Redis server, so that they can be
+/// shared between processes.
+///
+/// The store uses three kinds of key, each beginning with the store's
+/// prefix (`rlr` by default):
+///
+/// - `{prefix}:q:{state_id}` is a hash of each action ID to the action's call
+///   count, raw q-value, and weighted q-value, separated by spaces. The
+///   weighted q-value is a cache of its value when the action was last
+///   written, and is not authoritative (see `ActionStatter::q_value_weighted`).
+/// - `{prefix}:states` is a set of the IDs of the states with stats.
+/// - `{prefix}:visits` is a hash of state IDs to visit counts.
+///
+/// Like `SqliteStore`, the store records only the call count and q-values of
+/// each action, and state and action IDs must implement `Display`, with
+/// action IDs also implementing `FromStr`.
+///
+/// Each update is written independently, so when several processes update
+/// the same action at once, the last write wins.
+///
+/// By default every update is sent to Redis immediately. With
+/// `with_write_back`, updates are instead buffered locally and sent in a
+/// single pipeline once enough have accumulated, trading freshness for
+/// fewer round trips. Buffered updates are visible to this store's reads
+/// straight away, but not to other processes until they are flushed.
+pub struct RedisStore {
+    conn: RefCell<Connection>,
+    prefix: String,
+    write_back: Option<usize>,
+    pending: Pending,
+}
+
+impl RedisStore {
+    /// Connects to the Redis server at `url`, such as
+    /// `redis://127.0.0.1:6379/`.
+    pub fn open(url: &str) -> Result<Self, LearnerError> {
+        let client = Client::open(url).map_err(storage_error)?;
+        Ok(Self::from_connection(
+            client.get_connection().map_err(storage_error)?,
+        ))
+    }
+
+    /// Uses an existing connection as a store.
+    pub fn from_connection(conn: Connection) -> Self {
+        Self {
+            conn: RefCell::new(conn),
+            prefix: DEFAULT_PREFIX.to_string(),
+            write_back: None,
+            pending: Pending::default(),
+        }
+    }
+
+    /// Sets the prefix of the keys that the store uses, so that several
+    /// q-tables can share a Redis database. The default is `rlr`.
+    #[must_use]
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// Buffers updates locally, sending them to Redis once `capacity` updates
+    /// have accumulated, when `flush` is called, or when the store is
+    /// dropped.
+    #[must_use]
+    pub fn with_write_back(mut self, capacity: usize) -> Self {
+        self.write_back = Some(capacity.max(1));
+        self
+    }
+
+    /// Sends any buffered updates to Redis.
+    pub fn flush(&mut self) -> Result<(), LearnerError> {
+        if self.pending.len == 0 {
+            return Ok(());
+        }
+        let pending = std::mem::take(&mut self.pending);
+        let mut pipe = ::redis::pipe();
+        for (state_id, actions) in &pending.stats {
+            let actions: Vec<_> = actions.iter().collect();
+            pipe.hset_multiple(self.stats_key(state_id), &actions)
+                .ignore();
+            pipe.sadd(self.states_key(), state_id).ignore();
+        }
+        for (state_id, visits) in &pending.visits {
+            pipe.hset(self.visits_key(), state_id, visits).ignore();
+        }
+        self.query(&pipe)
+    }
+
+    fn stats_key(&self, state_id: &str) -> String {
+        format!("{}:q:{state_id}", self.prefix)
+    }
+
+    fn states_key(&self) -> String {
+        format!("{}:states", self.prefix)
+    }
+
+    fn visits_key(&self) -> String {
+        format!("{}:visits", self.prefix)
+    }
+
+    fn query(&self, pipe: &Pipeline) -> Result<(), LearnerError> {
+        pipe.query(&mut *self.conn.borrow_mut())
+            .map_err(storage_error)
+    }
+
+    /// Records encoded stats for several actions within a state, either by
+    /// buffering them or by sending them to Redis.
+    fn write_stats(
+        &mut self,
+        state_id: String,
+        actions: Vec<(String, String)>,
+    ) -> Result<(), LearnerError> {
+        let Some(capacity) = self.write_back else {
+            let mut pipe = ::redis::pipe();
+            pipe.atomic()
+                .hset_multiple(self.stats_key(&state_id), &actions)
+                .ignore()
+                .sadd(self.states_key(), &state_id)
+                .ignore();
+            return self.query(&pipe);
+        };
+        let buffered = self.pending.stats.entry(state_id).or_default();
+        for (action_id, stats) in actions {
+            if buffered.insert(action_id, stats).is_none() {
+                self.pending.len += 1;
+            }
+        }
+        if self.pending.len >= capacity {
+            self.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for RedisStore {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+impl<SK, AK, AS> QStore<SK, AK, AS> for RedisStore
+where
+    SK: Hash + Eq + Clone + fmt::Display,
+    AK: Hash + Eq + Clone + fmt::Display + FromStr,
+    AK::Err: fmt::Display,
+    AS: ActionStatter,
+{
+    fn get_stats(&self, state_id: &SK, action_id: &AK) -> Result<Option<AS>, LearnerError> {
+        let (state_id, action_id) = (state_id.to_string(), action_id.to_string());
+        if let Some(stats) = self
+            .pending
+            .stats
+            .get(&state_id)
+            .and_then(|actions| actions.get(&action_id))
+        {
+            return decode(stats).map(Some);
+        }
+        let stats: Option<String> = self
+            .conn
+            .borrow_mut()
+            .hget(self.stats_key(&state_id), action_id)
+            .map_err(storage_error)?;
+        stats.as_deref().map(decode).transpose()
+    }
+
+    fn update_stats(
+        &mut self,
+        state_id: &SK,
+        action_id: &AK,
+        stats: AS,
+    ) -> Result<(), LearnerError> {
+        self.write_stats(
+            state_id.to_string(),
+            vec![(action_id.to_string(), encode(&stats))],
+        )
+    }
+
+    fn get_actions_for_state(&self, state_id: &SK) -> Result<HashMap<AK, AS>, LearnerError> {
+        let state_id = state_id.to_string();
+        let mut actions: HashMap<String, String> = self
+            .conn
+            .borrow_mut()
+            .hgetall(self.stats_key(&state_id))
+            .map_err(storage_error)?;
+        if let Some(buffered) = self.pending.stats.get(&state_id) {
+            actions.extend(buffered.iter().map(|(a, s)| (a.clone(), s.clone())));
+        }
+        actions
+            .iter()
+            .map(|(action_id, stats)| {
+                let action_id = action_id.parse().map_err(storage_error)?;
+                Ok((action_id, decode(stats)?))
+            })
+            .collect()
+    }
+
+    fn update_actions_for_state(
+        &mut self,
+        state_id: &SK,
+        actions: HashMap<AK, AS>,
+    ) -> Result<(), LearnerError> {
+        let actions = actions
+            .iter()
+            .map(|(action_id, stats)| (action_id.to_string(), encode(stats)))
+            .collect();
+        self.write_stats(state_id.to_string(), actions)
+    }
+
+    fn get_visits(&self, state_id: &SK) -> Result<u64, LearnerError> {
+        let state_id = state_id.to_string();
+        if let Some(visits) = self.pending.visits.get(&state_id) {
+            return Ok(*visits);
+        }
+        let visits: Option<u64> = self
+            .conn
+            .borrow_mut()
+            .hget(self.visits_key(), state_id)
+            .map_err(storage_error)?;
+        Ok(visits.unwrap_or_default())
+    }
+
+    fn set_visits(&mut self, state_id: &SK, visits: u64) -> Result<(), LearnerError> {
+        let Some(capacity) = self.write_back else {
+            let key = self.visits_key();
+            return self
+                .conn
+                .get_mut()
+                .hset(key, state_id.to_string(), visits)
+                .map_err(storage_error);
+        };
+        if self
+            .pending
+            .visits
+            .insert(state_id.to_string(), visits)
+            .is_none()
+        {
+            self.pending.len += 1;
+        }
+        if self.pending.len >= capacity {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of states with stats in Redis. Updates that are
+    /// still buffered are not counted until they are flushed.
+    fn state_count(&self) -> Result<usize, LearnerError> {
+        self.conn
+            .borrow_mut()
+            .scard(self.states_key())
+            .map_err(storage_error)
+    }
+
+    /// Returns the number of state-action pairs with stats in Redis. Updates
+    /// that are still buffered are not counted until they are flushed.
+    fn entry_count(&self) -> Result<usize, LearnerError> {
+        let mut conn = self.conn.borrow_mut();
+        let states: Vec<String> = conn.smembers(self.states_key()).map_err(storage_error)?;
+        let mut pipe = ::redis::pipe();
+        for state_id in &states {
+            pipe.hlen(self.stats_key(state_id));
+        }
+        let counts: Vec<usize> = pipe.query(&mut *conn).map_err(storage_error)?;
+        Ok(counts.into_iter().sum())
+    }
+}
+
+fn encode<AS: ActionStatter>(stats: &AS) -> String {
+    format!(
+        "{} {} {}",
+        stats.calls(),
+        stats.q_value_raw(),
+        stats.q_value_weighted()
+    )
+}
+
+fn decode<AS: ActionStatter>(encoded: &str) -> Result<AS, LearnerError> {
+    let invalid = || storage_error(format!("invalid stats {encoded:?}"));
+    let mut fields = encoded.split(' ');
+    let mut stats = AS::default();
+    stats.set_calls(
+        fields
+            .next()
+            .ok_or_else(invalid)?
+            .parse()
+            .map_err(|_| invalid())?,
+    );
+    stats.set_q_value_raw(
+        fields
+            .next()
+            .ok_or_else(invalid)?
+            .parse()
+            .map_err(|_| invalid())?,
+    );
+    stats.set_q_value_weighted(
+        fields
+            .next()
+            .ok_or_else(invalid)?
+            .parse()
+            .map_err(|_| invalid())?,
+    );
+    if fields.next().is_some() {
+        return Err(invalid());
+    }
+    Ok(stats)
+}
+
+fn storage_error(e: impl fmt::Display) -> LearnerError {
+    LearnerError::Storage(format!("redis store error: {e}"))
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::stats::actionstats::Stats;
+    use std::env;
+
+    #[test]
+    fn encode_and_decode() {
+        let stats = Stats {
+            call_count: 3,
+            q_raw: 0.1 + 0.2,
+            q_weighted: -1.5,
+        };
+        assert_eq!(stats, decode::<Stats>(&encode(&stats)).unwrap());
+        assert!(matches!(
+            decode::<Stats>("3 0.5"),
+            Err(LearnerError::Storage(_))
+        ));
+        assert!(decode::<Stats>("3 0.5 x").is_err());
+        assert!(decode::<Stats>("3 0.5 1 2").is_err());
+    }
+
+    /// Runs against the Redis server at `RLR_REDIS_URL`, using a key prefix
+    /// unique to the test run.
+    #[test]
+    #[ignore = "requires a Redis server at RLR_REDIS_URL"]
+    fn shared_between_stores() {
+        let url = env::var("RLR_REDIS_URL").unwrap();
+        let prefix = format!("rlr-test-{}", std::process::id());
+        let (a, x, y) = ("A".to_string(), "X".to_string(), "Y".to_string());
+        let stats = Stats {
+            call_count: 2,
+            q_raw: 1.5,
+            q_weighted: 0.5,
+        };
+
+        let mut writer = RedisStore::open(&url)
+            .unwrap()
+            .with_prefix(&prefix)
+            .with_write_back(10);
+        let reader = RedisStore::open(&url).unwrap().with_prefix(&prefix);
+        writer.update_stats(&a, &x, stats).unwrap();
+        QStore::<String, String, Stats>::set_visits(&mut writer, &a, 4).unwrap();
+        assert_eq!(Some(stats), writer.get_stats(&a, &x).unwrap());
+        let missing: Option<Stats> = reader.get_stats(&a, &x).unwrap();
+        assert!(missing.is_none(), "buffered updates are not yet shared");
+
+        writer.flush().unwrap();
+        assert_eq!(Some(stats), reader.get_stats(&a, &x).unwrap());
+        assert_eq!(
+            Ok(4),
+            QStore::<String, String, Stats>::get_visits(&reader, &a)
+        );
+
+        let mut direct = RedisStore::open(&url).unwrap().with_prefix(&prefix);
+        direct.update_stats(&a, &y, Stats::default()).unwrap();
+        let actions: HashMap<String, Stats> = reader.get_actions_for_state(&a).unwrap();
+        assert_eq!(2, actions.len());
+        assert_eq!(Ok(1), QStore::<String, String, Stats>::state_count(&reader));
+        assert_eq!(Ok(2), QStore::<String, String, Stats>::entry_count(&reader));
+
+        let mut conn = Client::open(url.as_str())
+            .unwrap()
+            .get_connection()
+            .unwrap();
+        let keys: Vec<String> = conn.keys(format!("{prefix}:*")).unwrap();
+        let _: () = conn.del(keys).unwrap();
+    }
+}
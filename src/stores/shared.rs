@@ -0,0 +1,163 @@
+//! A `QStore` that several agents on one thread can learn into.
+
+use crate::errors::LearnerError;
+use crate::stats::ActionStatter;
+use crate::stores::QStore;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::rc::Rc;
+
+/// A handle to a store that is shared with other handles.
+///
+/// Cloning a `SharedQStore` returns a new handle to the same underlying
+/// store, so agents that are interchangeable (for instance, the members of a
+/// `agents::multi::MultiAgentManager` that play identical roles) can keep a
+/// single table between them, rather than one table each. Any store can be
+/// shared. Handles cannot be sent between threads; use a
+/// `concurrent::ConcurrentQMap` to share a table across threads.
+#[derive(Debug, Default)]
+pub struct SharedQStore<QS> {
+    inner: Rc<RefCell<QS>>,
+}
+
+impl<QS> SharedQStore<QS> {
+    /// Returns a handle to `store`, which becomes shared with every clone of
+    /// the handle.
+    pub fn new(store: QS) -> Self {
+        Self {
+            inner: Rc::new(RefCell::new(store)),
+        }
+    }
+
+    /// Returns true if this handle and `other` share the same store.
+    pub fn shares_with(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    /// Returns the number of handles to the store, including this one.
+    pub fn handle_count(&self) -> usize {
+        Rc::strong_count(&self.inner)
+    }
+}
+
+impl<QS> Clone for SharedQStore<QS> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: Rc::clone(&self.inner),
+        }
+    }
+}
+
+impl<SK, AK, AS, QS> QStore<SK, AK, AS> for SharedQStore<QS>
+where
+    SK: Hash + Eq + Clone,
+    AK: Hash + Eq + Clone,
+    AS: ActionStatter,
+    QS: QStore<SK, AK, AS>,
+{
+    fn get_stats(&self, state_id: &SK, action_id: &AK) -> Result<Option<AS>, LearnerError> {
+        self.inner.borrow().get_stats(state_id, action_id)
+    }
+
+    fn update_stats(
+        &mut self,
+        state_id: &SK,
+        action_id: &AK,
+        stats: AS,
+    ) -> Result<(), LearnerError> {
+        self.inner
+            .borrow_mut()
+            .update_stats(state_id, action_id, stats)
+    }
+
+    fn update_stats_with<D, F>(
+        &mut self,
+        state_id: &SK,
+        action_id: &AK,
+        default: D,
+        update: F,
+    ) -> Result<(), LearnerError>
+    where
+        D: FnOnce() -> AS,
+        F: FnOnce(&mut AS),
+    {
+        self.inner
+            .borrow_mut()
+            .update_stats_with(state_id, action_id, default, update)
+    }
+
+    fn get_actions_for_state(&self, state_id: &SK) -> Result<HashMap<AK, AS>, LearnerError> {
+        self.inner.borrow().get_actions_for_state(state_id)
+    }
+
+    fn visit_actions_for_state<F>(&self, state_id: &SK, visit: F) -> Result<(), LearnerError>
+    where
+        F: FnMut(&AK, &AS),
+    {
+        self.inner.borrow().visit_actions_for_state(state_id, visit)
+    }
+
+    fn update_actions_for_state(
+        &mut self,
+        state_id: &SK,
+        actions: HashMap<AK, AS>,
+    ) -> Result<(), LearnerError> {
+        self.inner
+            .borrow_mut()
+            .update_actions_for_state(state_id, actions)
+    }
+
+    fn raw_q_sum(&self, state_id: &SK) -> Result<(f64, usize), LearnerError> {
+        self.inner.borrow().raw_q_sum(state_id)
+    }
+
+    fn get_visits(&self, state_id: &SK) -> Result<u64, LearnerError> {
+        self.inner.borrow().get_visits(state_id)
+    }
+
+    fn set_visits(&mut self, state_id: &SK, visits: u64) -> Result<(), LearnerError> {
+        self.inner.borrow_mut().set_visits(state_id, visits)
+    }
+
+    fn increment_visits(&mut self, state_id: &SK) -> Result<(), LearnerError> {
+        self.inner.borrow_mut().increment_visits(state_id)
+    }
+
+    fn state_count(&self) -> Result<usize, LearnerError> {
+        self.inner.borrow().state_count()
+    }
+
+    fn entry_count(&self) -> Result<usize, LearnerError> {
+        self.inner.borrow().entry_count()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::stats::actionstats::Stats;
+    use crate::stores::QMap;
+
+    #[test]
+    fn clones_share_data() {
+        let mut store: SharedQStore<QMap<&str, &str, Stats>> = SharedQStore::default();
+        let handle = store.clone();
+        assert!(handle.shares_with(&store));
+        assert!(!handle.shares_with(&SharedQStore::default()));
+        assert_eq!(2, handle.handle_count());
+
+        store.update_stats(&"A", &"X", Stats::default()).unwrap();
+        store
+            .update_stats_with(&"A", &"Y", Stats::default, |_| {})
+            .unwrap();
+        assert!(handle.get_stats(&"A", &"X").unwrap().is_some());
+        assert_eq!(2, handle.get_actions_for_state(&"A").unwrap().len());
+        assert_eq!((0.0, 2), handle.raw_q_sum(&"A").unwrap());
+        assert_eq!(1, handle.state_count().unwrap());
+        assert_eq!(2, handle.entry_count().unwrap());
+        store.set_visits(&"A", 3).unwrap();
+        assert_eq!(3, handle.get_visits(&"A").unwrap());
+    }
+}
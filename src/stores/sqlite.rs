@@ -0,0 +1,331 @@
+//! A `QStore` backed by a SQLite database.
+//!
+//! Statistics written to the store survive restarts of the process, and the
+//! size of the store is limited by disk rather than memory.
+
+use crate::errors::LearnerError;
+use crate::stats::ActionStatter;
+use crate::stores::QStore;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::hash::Hash;
+use std::path::Path;
+use std::str::FromStr;
+
+/// The statements used to prepare a database for use as a store.
+const SCHEMA: &str = "CREATE TABLE IF NOT EXISTS q_values (
+    state_id TEXT NOT NULL,
+    action_id TEXT NOT NULL,
+    calls INTEGER NOT NULL,
+    q_raw REAL NOT NULL,
+    q_weighted REAL NOT NULL,
+    PRIMARY KEY (state_id, action_id)
+);
+CREATE TABLE IF NOT EXISTS state_visits (
+    state_id TEXT NOT NULL PRIMARY KEY,
+    visits INTEGER NOT NULL
+);";
+
+/// The statement used to insert or replace the stats for a state and action.
+const UPSERT: &str = "INSERT INTO q_values (state_id, action_id, calls, q_raw, q_weighted)
+    VALUES (?1, ?2, ?3, ?4, ?5)
+    ON CONFLICT (state_id, action_id) DO UPDATE SET
+        calls = excluded.calls,
+        q_raw = excluded.q_raw,
+        q_weighted = excluded.q_weighted";
+
+/// A `QStore` that persists statistics to a SQLite database.
+///
+/// The store records the call count and the raw and weighted q-values of each
+/// state and action. Any other data held by an `ActionStatter` implementation
+/// is not persisted.
+///
+/// The `q_weighted` column holds the weighted q-value as it was when the row
+/// was last written, and is not updated when other actions of the state
+/// change, so it should not be read as the action's current weighted
+/// q-value. Agents recalculate weighted q-values from `calls` and `q_raw`,
+/// and `Agent::get_agent_context` returns up-to-date values.
+///
+/// State and action IDs are stored as text, so they must implement `Display`,
+/// and action IDs must also parse back from that text via `FromStr`.
+pub struct SqliteStore {
+    conn: Connection,
+}
+
+impl SqliteStore {
+    /// Opens (or creates) the SQLite database at `path` for use as a store.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, LearnerError> {
+        Self::from_connection(Connection::open(path).map_err(storage_error)?)
+    }
+
+    /// Opens a store backed by a transient in-memory SQLite database.
+    pub fn open_in_memory() -> Result<Self, LearnerError> {
+        Self::from_connection(Connection::open_in_memory().map_err(storage_error)?)
+    }
+
+    /// Prepares an existing connection for use as a store, creating the
+    /// store's table if it does not already exist.
+    pub fn from_connection(conn: Connection) -> Result<Self, LearnerError> {
+        conn.execute_batch(SCHEMA).map_err(storage_error)?;
+        Ok(Self { conn })
+    }
+
+    fn count(&self, query: &str) -> Result<usize, LearnerError> {
+        self.conn
+            .query_row(query, [], |row| row.get(0))
+            .map_err(storage_error)
+    }
+}
+
+impl<SK, AK, AS> QStore<SK, AK, AS> for SqliteStore
+where
+    SK: Hash + Eq + Clone + fmt::Display,
+    AK: Hash + Eq + Clone + fmt::Display + FromStr,
+    AK::Err: fmt::Display,
+    AS: ActionStatter,
+{
+    fn get_stats(&self, state_id: &SK, action_id: &AK) -> Result<Option<AS>, LearnerError> {
+        self.conn
+            .query_row(
+                "SELECT calls, q_raw, q_weighted FROM q_values
+                    WHERE state_id = ?1 AND action_id = ?2",
+                params![state_id.to_string(), action_id.to_string()],
+                |row| Ok(to_stats(row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()
+            .map_err(storage_error)
+    }
+
+    fn update_stats(
+        &mut self,
+        state_id: &SK,
+        action_id: &AK,
+        stats: AS,
+    ) -> Result<(), LearnerError> {
+        self.conn
+            .prepare_cached(UPSERT)
+            .and_then(|mut stmt| {
+                stmt.execute(params![
+                    state_id.to_string(),
+                    action_id.to_string(),
+                    to_sql_count(stats.calls()),
+                    stats.q_value_raw(),
+                    stats.q_value_weighted()
+                ])
+            })
+            .map(|_| ())
+            .map_err(storage_error)
+    }
+
+    fn get_actions_for_state(&self, state_id: &SK) -> Result<HashMap<AK, AS>, LearnerError> {
+        let mut stmt = self
+            .conn
+            .prepare_cached(
+                "SELECT action_id, calls, q_raw, q_weighted FROM q_values WHERE state_id = ?1",
+            )
+            .map_err(storage_error)?;
+        let rows = stmt
+            .query_map(params![state_id.to_string()], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    to_stats(row.get(1)?, row.get(2)?, row.get(3)?),
+                ))
+            })
+            .map_err(storage_error)?;
+        rows.map(|row| {
+            let (action_id, stats) = row.map_err(storage_error)?;
+            let action_id = action_id.parse().map_err(storage_error)?;
+            Ok((action_id, stats))
+        })
+        .collect()
+    }
+
+    fn update_actions_for_state(
+        &mut self,
+        state_id: &SK,
+        actions: HashMap<AK, AS>,
+    ) -> Result<(), LearnerError> {
+        let tx = self.conn.transaction().map_err(storage_error)?;
+        {
+            let state_id = state_id.to_string();
+            let mut stmt = tx.prepare_cached(UPSERT).map_err(storage_error)?;
+            for (action_id, stats) in actions {
+                stmt.execute(params![
+                    state_id,
+                    action_id.to_string(),
+                    to_sql_count(stats.calls()),
+                    stats.q_value_raw(),
+                    stats.q_value_weighted()
+                ])
+                .map_err(storage_error)?;
+            }
+        }
+        tx.commit().map_err(storage_error)
+    }
+
+    fn get_visits(&self, state_id: &SK) -> Result<u64, LearnerError> {
+        let visits: Option<i64> = self
+            .conn
+            .query_row(
+                "SELECT visits FROM state_visits WHERE state_id = ?1",
+                params![state_id.to_string()],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(storage_error)?;
+        Ok(visits.map_or(0, from_sql_count))
+    }
+
+    fn set_visits(&mut self, state_id: &SK, visits: u64) -> Result<(), LearnerError> {
+        self.conn
+            .execute(
+                "INSERT INTO state_visits (state_id, visits) VALUES (?1, ?2)
+                    ON CONFLICT (state_id) DO UPDATE SET visits = excluded.visits",
+                params![state_id.to_string(), to_sql_count(visits)],
+            )
+            .map(|_| ())
+            .map_err(storage_error)
+    }
+
+    fn state_count(&self) -> Result<usize, LearnerError> {
+        self.count("SELECT COUNT(DISTINCT state_id) FROM q_values")
+    }
+
+    fn entry_count(&self) -> Result<usize, LearnerError> {
+        self.count("SELECT COUNT(*) FROM q_values")
+    }
+}
+
+/// Converts a count to an SQLite integer, which is signed, saturating at
+/// `i64::MAX`.
+fn to_sql_count(count: u64) -> i64 {
+    i64::try_from(count).unwrap_or(i64::MAX)
+}
+
+/// Converts an SQLite integer to a count, treating negative values as 0.
+fn from_sql_count(count: i64) -> u64 {
+    u64::try_from(count).unwrap_or_default()
+}
+
+fn to_stats<AS: ActionStatter>(calls: i64, q_raw: f64, q_weighted: f64) -> AS {
+    let mut stats = AS::default();
+    stats.set_calls(from_sql_count(calls));
+    stats.set_q_value_raw(q_raw);
+    stats.set_q_value_weighted(q_weighted);
+    stats
+}
+
+fn storage_error(e: impl fmt::Display) -> LearnerError {
+    LearnerError::Storage(format!("sqlite store error: {e}"))
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::agents::bayesian::Agent;
+    use crate::agents::Agenter;
+    use crate::mocks::*;
+    use crate::stats::actionstats::Stats;
+
+    #[test]
+    fn get_and_update_stats() {
+        let a = "A".to_string();
+        let x = "X".to_string();
+        let mut store = SqliteStore::open_in_memory().unwrap();
+        let missing: Option<Stats> = store.get_stats(&"A".to_string(), &"X".to_string()).unwrap();
+        assert!(missing.is_none());
+
+        let stats = Stats {
+            call_count: 2,
+            q_raw: 1.5,
+            q_weighted: 0.5,
+        };
+        store.update_stats(&a, &x, stats).unwrap();
+        store
+            .update_stats(&a, &"Y".to_string(), Stats::default())
+            .unwrap();
+        assert_eq!(Some(stats), store.get_stats(&a, &x).unwrap());
+
+        let updated = Stats {
+            call_count: 3,
+            ..stats
+        };
+        store.update_stats(&a, &x, updated).unwrap();
+        let actions: HashMap<String, Stats> = store.get_actions_for_state(&a).unwrap();
+        assert_eq!(2, actions.len());
+        assert_eq!(updated, actions["X"]);
+        assert_eq!(Ok(1), QStore::<String, String, Stats>::state_count(&store));
+        assert_eq!(Ok(2), QStore::<String, String, Stats>::entry_count(&store));
+    }
+
+    #[test]
+    fn agent_with_sqlite_store_matches_qmap() {
+        let action_x = MockActioner { return_id: "X" };
+        let action_y = MockActioner { return_id: "Y" };
+        let previous_state = MockStater {
+            return_id: "A",
+            return_possible_actions: vec![&action_x, &action_y],
+            ..Default::default()
+        };
+        let current_state = MockStater {
+            return_id: "B",
+            return_possible_actions: vec![&action_x, &action_y],
+            ..Default::default()
+        };
+
+        let mut in_memory: Agent<MockStater<MockActioner>, MockActioner, Stats> =
+            Agent::new(2, 0.5, 0.9);
+        let mut sqlite: Agent<MockStater<MockActioner>, MockActioner, Stats, SqliteStore> =
+            Agent::new_with_store(SqliteStore::open_in_memory().unwrap(), 2, 0.5, 0.9);
+        for _ in 0..3 {
+            in_memory
+                .learn(Some(&previous_state), &action_x, &current_state, 1.0)
+                .unwrap();
+            sqlite
+                .learn(Some(&previous_state), &action_x, &current_state, 1.0)
+                .unwrap();
+        }
+
+        let context = in_memory.get_agent_context();
+        for (state_id, actions) in &context.q_values {
+            for (action_id, stats) in actions {
+                let stored: Option<Stats> = QStore::<String, String, Stats>::get_stats(
+                    sqlite.qstore.as_ref(),
+                    state_id,
+                    action_id,
+                )
+                .unwrap();
+                // The stored weighted q-value is only a cache, which is not
+                // kept up to date, so it is not compared.
+                assert_eq!(
+                    Some((stats.call_count, stats.q_raw)),
+                    stored.map(|stored| (stored.call_count, stored.q_raw))
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn persists_across_connections() {
+        let path = std::env::temp_dir().join(format!("rlr-sqlite-store-{}.db", std::process::id()));
+        let stats = Stats {
+            call_count: 1,
+            q_raw: 2.0,
+            q_weighted: 3.0,
+        };
+        {
+            let mut store = SqliteStore::open(&path).unwrap();
+            store.update_stats(&"A", &1_u32, stats).unwrap();
+            QStore::<&str, u32, Stats>::set_visits(&mut store, &"A", 4).unwrap();
+        }
+        let store = SqliteStore::open(&path).unwrap();
+        let result: Option<Stats> = store.get_stats(&"A", &1_u32).unwrap();
+        let visits = QStore::<&str, u32, Stats>::get_visits(&store, &"A").unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(Some(stats), result);
+        assert_eq!(4, visits);
+    }
+}
@@ -0,0 +1,287 @@
+//! Periodic snapshots of an agent during training.
+//!
+//! A `Checkpointer` is passed to `Trainer::run_with_checkpoints`, which
+//! offers it the agent at the end of each episode. The checkpointer saves the
+//! agent every N episodes, whenever the trainer's moving average return
+//! reaches a new best, or both, and deletes all but the most recent
+//! checkpoints if a retention limit is set.
+//!
+//! How an agent is written is up to the caller, so any agent can be
+//! checkpointed. For instance, a `bayesian::Agent` can be checkpointed with
+//! its `save_to` method (which requires the `bincode` feature):
+//!
+//! ```ignore
+//! let mut checkpointer =
+//!     Checkpointer::to_path("checkpoints/agent-{episode}.bin", |agent: &Agent<_, _, _>, w| {
+//!         agent.save_to(w)
+//!     })
+//!     .every(100)
+//!     .keep_last(3);
+//! trainer.run_with_checkpoints(&mut agent, &mut env, &mut checkpointer)?;
+//! ```
+
+use crate::errors::LearnerError;
+use crate::training::EpisodeStats;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// A function that writes a snapshot of an agent.
+pub type SaveFn<'c, G> = Box<dyn FnMut(&G, &mut dyn Write) -> Result<(), LearnerError> + 'c>;
+
+/// A function that opens a writer for the checkpoint with the supplied ID.
+pub type OpenFn<'c> = Box<dyn FnMut(usize) -> io::Result<Box<dyn Write + 'c>> + 'c>;
+
+/// A function that deletes the checkpoint with the supplied ID.
+pub type RemoveFn<'c> = Box<dyn FnMut(usize) -> io::Result<()> + 'c>;
+
+/// Decides when to snapshot an agent during training, and where to write the
+/// snapshots. See the module documentation.
+///
+/// Each checkpoint is identified by the number of episodes that the trainer
+/// had completed when it was written.
+pub struct Checkpointer<'c, G: ?Sized> {
+    save: SaveFn<'c, G>,
+    open: OpenFn<'c>,
+    remove: RemoveFn<'c>,
+    every: Option<usize>,
+    on_best: bool,
+    keep: Option<usize>,
+    best_return: Option<f64>,
+    saved: VecDeque<usize>,
+}
+
+impl<'c, G: ?Sized> Checkpointer<'c, G> {
+    /// Returns a checkpointer that writes each checkpoint to a file, whose
+    /// path is `template` with every `{episode}` replaced by the checkpoint's
+    /// ID. `save` writes the agent to the file.
+    pub fn to_path<F>(template: &str, save: F) -> Self
+    where
+        F: FnMut(&G, &mut dyn Write) -> Result<(), LearnerError> + 'c,
+    {
+        let template = template.to_string();
+        let path = move |id: usize| template.replace("{episode}", &id.to_string());
+        let open_path = path.clone();
+        Self::to_writers(
+            move |id| Ok(BufWriter::new(File::create(open_path(id))?)),
+            move |id| std::fs::remove_file(path(id)),
+            save,
+        )
+    }
+
+    /// Returns a checkpointer that writes each checkpoint to a writer
+    /// returned by `open`, given the checkpoint's ID, and deletes checkpoints
+    /// that are no longer retained with `remove`. `save` writes the agent to
+    /// the writer.
+    pub fn to_writers<O, W, R, F>(mut open: O, remove: R, save: F) -> Self
+    where
+        O: FnMut(usize) -> io::Result<W> + 'c,
+        W: Write + 'c,
+        R: FnMut(usize) -> io::Result<()> + 'c,
+        F: FnMut(&G, &mut dyn Write) -> Result<(), LearnerError> + 'c,
+    {
+        Self {
+            save: Box::new(save),
+            open: Box::new(move |id| -> io::Result<Box<dyn Write + 'c>> {
+                Ok(Box::new(open(id)?))
+            }),
+            remove: Box::new(remove),
+            every: None,
+            on_best: false,
+            keep: None,
+            best_return: None,
+            saved: VecDeque::new(),
+        }
+    }
+
+    /// Writes a checkpoint every `episodes` episodes. An interval of 0
+    /// disables periodic checkpoints.
+    #[must_use]
+    pub fn every(mut self, episodes: usize) -> Self {
+        self.every = (episodes > 0).then_some(episodes);
+        self
+    }
+
+    /// Writes a checkpoint whenever the trainer's moving average return is
+    /// higher than it has been at any previous checkpoint offered to this
+    /// checkpointer.
+    #[must_use]
+    pub fn on_best(mut self) -> Self {
+        self.on_best = true;
+        self
+    }
+
+    /// Keeps only the `count` most recent checkpoints, deleting older ones
+    /// as new ones are written. By default every checkpoint is kept.
+    #[must_use]
+    pub fn keep_last(mut self, count: usize) -> Self {
+        self.keep = Some(count);
+        self
+    }
+
+    /// Returns the IDs of the checkpoints that have been written and not
+    /// deleted, from oldest to newest.
+    pub fn saved(&self) -> Vec<usize> {
+        self.saved.iter().copied().collect()
+    }
+
+    /// Writes a checkpoint of `agent` if one is due, given the stats of the
+    /// episodes the trainer has completed.
+    pub(crate) fn after_episode(
+        &mut self,
+        agent: &G,
+        stats: &EpisodeStats,
+    ) -> Result<(), LearnerError> {
+        let id = stats.episodes();
+        let periodic = self.every.is_some_and(|every| id.is_multiple_of(every));
+        let mut best = false;
+        if self.on_best {
+            if let Some(average) = stats.moving_average_return() {
+                best = self.best_return.is_none_or(|previous| average > previous);
+                if best {
+                    self.best_return = Some(average);
+                }
+            }
+        }
+        if !periodic && !best {
+            return Ok(());
+        }
+
+        let storage_err =
+            |e: io::Error| LearnerError::Storage(format!("unable to write checkpoint {id}: {e}"));
+        let mut writer = (self.open)(id).map_err(storage_err)?;
+        (self.save)(agent, &mut writer)?;
+        writer.flush().map_err(storage_err)?;
+        drop(writer);
+        self.saved.push_back(id);
+
+        while self.keep.is_some_and(|keep| self.saved.len() > keep) {
+            if let Some(old) = self.saved.pop_front() {
+                (self.remove)(old).map_err(|e| {
+                    LearnerError::Storage(format!("unable to delete checkpoint {old}: {e}"))
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::agents::bayesian::Agent;
+    use crate::mocks::*;
+    use crate::stats::actionstats::Stats;
+    use crate::training::Trainer;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::cell::RefCell;
+    use std::collections::BTreeMap;
+    use std::rc::Rc;
+
+    type CorridorAgent<'a> = Agent<'a, MockCell<'a>, MockActioner<'a>, Stats>;
+
+    /// A writer that stores what is written to it in a shared map.
+    struct Entry {
+        id: usize,
+        files: Rc<RefCell<BTreeMap<usize, Vec<u8>>>>,
+    }
+
+    impl Write for Entry {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.files
+                .borrow_mut()
+                .entry(self.id)
+                .or_default()
+                .extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn save_step_count(agent: &CorridorAgent, writer: &mut dyn Write) -> Result<(), LearnerError> {
+        write!(writer, "{}", agent.step_count())
+            .map_err(|e| LearnerError::Serialization(e.to_string()))
+    }
+
+    #[test]
+    fn periodic_checkpoints_with_retention() {
+        let moves = MockCorridor::moves();
+        let mut env = MockCorridor::new(&moves, 3);
+        let mut agent: CorridorAgent = Agent::new(1, 1.0, 0.9);
+        let files = Rc::new(RefCell::new(BTreeMap::new()));
+        let (opened, removed) = (Rc::clone(&files), Rc::clone(&files));
+        let mut checkpointer = Checkpointer::to_writers(
+            move |id| {
+                Ok(Entry {
+                    id,
+                    files: Rc::clone(&opened),
+                })
+            },
+            move |id| {
+                removed.borrow_mut().remove(&id);
+                Ok(())
+            },
+            save_step_count,
+        )
+        .every(2)
+        .keep_last(2);
+
+        Trainer::new(7, 10)
+            .run_with_checkpoints(&mut agent, &mut env, &mut checkpointer)
+            .unwrap();
+
+        assert_eq!(vec![4, 6], checkpointer.saved());
+        let files = files.borrow();
+        assert_eq!(vec![&4, &6], files.keys().collect::<Vec<_>>());
+        let steps: u64 = String::from_utf8(files[&6].clone())
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(steps >= 12);
+    }
+
+    #[test]
+    fn best_checkpoints_to_path() {
+        let moves = MockCorridor::moves();
+        let mut env = MockCorridor::new(&moves, 3);
+        let mut agent: CorridorAgent = Agent::new(1, 1.0, 0.9).with_rng(StdRng::seed_from_u64(1));
+        let dir = std::env::temp_dir().join(format!("rlr-checkpoints-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let template = dir.join("agent-{episode}.txt");
+        let mut checkpointer = Checkpointer::to_path(template.to_str().unwrap(), save_step_count)
+            .on_best()
+            .keep_last(1);
+
+        // Every episode returns 1, so only the first sets a new best.
+        Trainer::new(3, 100)
+            .run_with_checkpoints(&mut agent, &mut env, &mut checkpointer)
+            .unwrap();
+
+        assert_eq!(vec![1], checkpointer.saved());
+        let written = std::fs::read_to_string(dir.join("agent-1.txt")).unwrap();
+        assert!(written.parse::<u64>().unwrap() >= 2);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn write_errors() {
+        let moves = MockCorridor::moves();
+        let mut env = MockCorridor::new(&moves, 3);
+        let mut agent: CorridorAgent = Agent::new(1, 1.0, 0.9);
+        let mut checkpointer = Checkpointer::to_writers(
+            |_| -> io::Result<Vec<u8>> { Err(io::Error::other("disk full")) },
+            |_| Ok(()),
+            save_step_count,
+        )
+        .every(1);
+
+        let result =
+            Trainer::new(3, 10).run_with_checkpoints(&mut agent, &mut env, &mut checkpointer);
+        assert!(matches!(result, Err(LearnerError::Storage(_))));
+    }
+}
@@ -0,0 +1,86 @@
+//! Loggers that record scalar metrics, such as episode returns, over the
+//! course of training so that runs can be plotted and compared.
+
+use crate::errors::LearnerError;
+use crate::export::csv::escape;
+use std::fmt;
+use std::io::Write;
+
+/// Records scalar metrics. Each metric is identified by a tag, and each value
+/// is recorded against a step (such as an episode index).
+pub trait MetricsLogger {
+    /// Records `value` as the value of the metric `tag` at `step`.
+    fn log_scalar(&mut self, tag: &str, step: u64, value: f64) -> Result<(), LearnerError>;
+}
+
+/// Writes metrics as a CSV stream with the columns `step`, `tag`, and
+/// `value`, one row per value, in the order the values are logged.
+///
+/// This is the same layout that `TensorBoard` uses when exporting scalars, so
+/// the output can be loaded by most plotting tools (for instance, by pivoting
+/// on `tag` in pandas).
+#[derive(Debug)]
+pub struct CsvMetrics<W> {
+    writer: W,
+    wrote_header: bool,
+}
+
+impl<W: Write> CsvMetrics<W> {
+    /// Returns a logger that writes to `writer`. The header row is written
+    /// along with the first value.
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            wrote_header: false,
+        }
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn into_inner(mut self) -> Result<W, LearnerError> {
+        self.writer.flush().map_err(write_err)?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> MetricsLogger for CsvMetrics<W> {
+    fn log_scalar(&mut self, tag: &str, step: u64, value: f64) -> Result<(), LearnerError> {
+        if !self.wrote_header {
+            writeln!(self.writer, "step,tag,value").map_err(write_err)?;
+            self.wrote_header = true;
+        }
+        writeln!(self.writer, "{step},{},{value}", escape(tag)).map_err(write_err)
+    }
+}
+
+impl<L: MetricsLogger + ?Sized> MetricsLogger for &mut L {
+    fn log_scalar(&mut self, tag: &str, step: u64, value: f64) -> Result<(), LearnerError> {
+        (**self).log_scalar(tag, step, value)
+    }
+}
+
+fn write_err(e: impl fmt::Display) -> LearnerError {
+    LearnerError::Serialization(format!("unable to write metrics: {e}"))
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_metrics() {
+        let mut metrics = CsvMetrics::new(Vec::new());
+        metrics.log_scalar("episode_return", 0, 1.5).unwrap();
+        metrics.log_scalar("q, mean", 0, -0.25).unwrap();
+        metrics.log_scalar("episode_return", 1, 2.0).unwrap();
+
+        let output = String::from_utf8(metrics.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            "step,tag,value\n\
+             0,episode_return,1.5\n\
+             0,\"q, mean\",-0.25\n\
+             1,episode_return,2\n",
+            output
+        );
+    }
+}
@@ -0,0 +1,943 @@
+//! Utilities for training agents against environments.
+//!
+//! A `Trainer` runs the recommend/step/learn loop that is otherwise written
+//! by hand for every model: for each episode it resets the environment, asks
+//! the agent for actions, applies them to the environment, and has the agent
+//! learn from the rewards that result.
+//!
+//! Training runs for a fixed number of episodes unless the trainer is given
+//! early stopping criteria, such as a target average return or a time budget.
+//! `Trainer::run` returns a `TrainingReport` recording why training stopped.
+//!
+//! `Trainer::run_with_checkpoints` also snapshots the agent as it trains,
+//! using a `checkpoint::Checkpointer`.
+//!
+//! `Trainer::run_async` trains against an `AsyncEnvironment`, awaiting each
+//! reset and step rather than blocking on it. It can be driven by any
+//! executor, such as `tokio`'s current-thread runtime.
+//!
+//! The trainer can also log metrics about each episode to a
+//! `metrics::MetricsLogger`, such as `metrics::CsvMetrics`.
+//!
+//! Experience gathered by other threads or processes can be learned from with
+//! `stream::learn_from`, which drains a channel of transitions into an agent.
+//!
+//! When the `rayon` feature is enabled, `parallel::ParallelTrainer` trains a
+//! single agent against several copies of an environment at once.
+
+pub mod checkpoint;
+pub mod metrics;
+#[cfg(feature = "rayon")]
+pub mod parallel;
+pub mod stream;
+
+use crate::actions::Actioner;
+use crate::agents::Agenter;
+use crate::environments::{AsyncEnvironment, Environment, Step};
+use crate::errors::LearnerError;
+use crate::internal::{math, rng};
+use crate::reproducibility::ReproducibilityConfig;
+use crate::states::Stater;
+use checkpoint::Checkpointer;
+use metrics::MetricsLogger;
+use rand::{Rng, RngCore};
+use std::convert::TryFrom;
+use std::future::{self, Future};
+use std::pin::pin;
+use std::task::{Context, Poll, Waker};
+use std::time::{Duration, Instant};
+
+/// A value that changes over the course of training, such as the exploration
+/// rate. Schedules are evaluated once per episode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Schedule {
+    /// The value never changes.
+    Constant(f64),
+
+    /// The value moves in a straight line from `start` to `end` over the
+    /// first `episodes` episodes, and remains at `end` thereafter.
+    Linear {
+        /// The value for the first episode.
+        start: f64,
+        /// The value once `episodes` episodes have elapsed.
+        end: f64,
+        /// The number of episodes over which the value changes.
+        episodes: u32,
+    },
+
+    /// The value starts at `start` and is multiplied by `decay` after each
+    /// episode, but never falls below `min`.
+    Exponential {
+        /// The value for the first episode.
+        start: f64,
+        /// The factor applied to the value after each episode.
+        decay: f64,
+        /// The smallest value the schedule will return.
+        min: f64,
+    },
+}
+
+impl Schedule {
+    /// Returns the value of the schedule for the given (zero-based) episode.
+    pub fn value(&self, episode: usize) -> f64 {
+        match *self {
+            Self::Constant(value) => value,
+            Self::Linear {
+                start,
+                end,
+                episodes,
+            } => {
+                let elapsed = u32::try_from(episode).unwrap_or(u32::MAX).min(episodes);
+                let progress = if episodes == 0 {
+                    1.0
+                } else {
+                    f64::from(elapsed) / f64::from(episodes)
+                };
+                (end - start).mul_add(progress, start)
+            }
+            Self::Exponential { start, decay, min } => {
+                let exponent = i32::try_from(episode).unwrap_or(i32::MAX);
+                (start * decay.powi(exponent)).max(min)
+            }
+        }
+    }
+}
+
+/// A summary of a single training episode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpisodeReport {
+    /// The zero-based index of the episode.
+    pub episode: usize,
+
+    /// The number of steps taken during the episode.
+    pub steps: usize,
+
+    /// The sum of the rewards earned during the episode.
+    pub total_return: f64,
+
+    /// Whether the environment ended the episode. This is `false` if the
+    /// episode was cut short because it reached the trainer's step limit.
+    pub terminated: bool,
+}
+
+/// The reason that a call to `Trainer::run` stopped training.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    /// Every episode was run.
+    EpisodesCompleted,
+
+    /// The moving average return reached the target set by
+    /// `Trainer::with_target_return`.
+    TargetReturn,
+
+    /// The moving average return stopped changing, as configured by
+    /// `Trainer::with_convergence`.
+    Converged,
+
+    /// The time budget set by `Trainer::with_time_budget` ran out.
+    TimeBudget,
+
+    /// The step budget set by `Trainer::with_max_total_steps` ran out.
+    StepBudget,
+}
+
+/// A summary of a call to `Trainer::run`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrainingReport {
+    /// The report of each episode that was run, in order.
+    pub episodes: Vec<EpisodeReport>,
+
+    /// Why training stopped.
+    pub stop_reason: StopReason,
+
+    /// The total number of steps taken across all episodes.
+    pub total_steps: usize,
+
+    /// The time spent training.
+    pub elapsed: Duration,
+}
+
+/// Accumulates the returns and lengths of training episodes, along with their
+/// moving averages, to show whether an agent's learning is improving.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EpisodeStats {
+    window: usize,
+    returns: Vec<f64>,
+    lengths: Vec<usize>,
+    terminated: usize,
+}
+
+impl Default for EpisodeStats {
+    /// Returns an empty collector whose moving averages cover the last 100
+    /// episodes.
+    fn default() -> Self {
+        Self::new(100)
+    }
+}
+
+impl EpisodeStats {
+    /// Returns an empty collector whose moving averages cover the last
+    /// `window` episodes. A window of 0 is treated as a window of 1.
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            returns: Vec::new(),
+            lengths: Vec::new(),
+            terminated: 0,
+        }
+    }
+
+    /// Records the results of an episode.
+    pub fn push(&mut self, report: &EpisodeReport) {
+        self.returns.push(report.total_return);
+        self.lengths.push(report.steps);
+        if report.terminated {
+            self.terminated += 1;
+        }
+    }
+
+    /// Returns the number of episodes covered by the moving averages.
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Returns the number of episodes that have been recorded.
+    pub fn episodes(&self) -> usize {
+        self.returns.len()
+    }
+
+    /// Returns the number of recorded episodes that were ended by the
+    /// environment, rather than by reaching a step limit.
+    pub fn terminated(&self) -> usize {
+        self.terminated
+    }
+
+    /// Returns the total return of each recorded episode, in order.
+    pub fn returns(&self) -> &[f64] {
+        &self.returns
+    }
+
+    /// Returns the length of each recorded episode, in order.
+    pub fn lengths(&self) -> &[usize] {
+        &self.lengths
+    }
+
+    /// Returns the highest total return of any recorded episode, or `None`
+    /// if no episodes have been recorded.
+    pub fn best_return(&self) -> Option<f64> {
+        self.returns.iter().copied().reduce(f64::max)
+    }
+
+    /// Returns the mean return of the most recent episodes (up to the
+    /// window size), or `None` if no episodes have been recorded.
+    pub fn moving_average_return(&self) -> Option<f64> {
+        mean(self.returns.iter().rev().take(self.window).copied())
+    }
+
+    /// Returns the mean length of the most recent episodes (up to the
+    /// window size), or `None` if no episodes have been recorded.
+    pub fn moving_average_length(&self) -> Option<f64> {
+        mean(
+            self.lengths
+                .iter()
+                .rev()
+                .take(self.window)
+                .map(|&l| math::len_to_f64(l)),
+        )
+    }
+
+    /// Returns the moving average return as of each recorded episode, which
+    /// is suitable for plotting a learning curve.
+    pub fn moving_average_returns(&self) -> Vec<f64> {
+        (0..self.returns.len())
+            .filter_map(|i| {
+                let start = (i + 1).saturating_sub(self.window);
+                mean(self.returns[start..=i].iter().copied())
+            })
+            .collect()
+    }
+}
+
+impl Extend<EpisodeReport> for EpisodeStats {
+    fn extend<I: IntoIterator<Item = EpisodeReport>>(&mut self, reports: I) {
+        for report in reports {
+            self.push(&report);
+        }
+    }
+}
+
+/// Returns the mean of the values, or `None` if there are none.
+fn mean<I: Iterator<Item = f64>>(values: I) -> Option<f64> {
+    let (count, total) = values.fold((0, 0.0), |(count, total), value| (count + 1, total + value));
+    if count == 0 {
+        return None;
+    }
+    Some(total / math::len_to_f64(count))
+}
+
+/// Runs an agent through a number of episodes of an environment, having the
+/// agent learn from each step.
+///
+/// By default the trainer always takes the action recommended by the agent.
+/// An exploration rate can be supplied via `with_epsilon`, in which case the
+/// trainer takes a random action (instead of the agent's recommendation) with
+/// the given probability.
+///
+/// The trainer records the results of every episode it runs, across all
+/// calls to `train`, in an `EpisodeStats` available via `stats`.
+///
+/// Training can be stopped before every episode has run by the criteria set
+/// with `with_target_return`, `with_convergence`, `with_time_budget`, and
+/// `with_max_total_steps`. All but the step budget are checked at the end of
+/// each episode.
+pub struct Trainer<'t> {
+    episodes: usize,
+    max_steps: usize,
+    target_return: Option<f64>,
+    convergence: Option<(f64, usize)>,
+    time_budget: Option<Duration>,
+    max_total_steps: Option<usize>,
+    epsilon: Schedule,
+    rng: Box<dyn RngCore + 't>,
+    on_episode: Box<dyn FnMut(&EpisodeReport) + 't>,
+    stats: EpisodeStats,
+    metrics: Option<Box<dyn MetricsLogger + 't>>,
+}
+
+impl<'t> Trainer<'t> {
+    /// Returns a new trainer that runs `episodes` episodes, each of which is
+    /// cut short after `max_steps` steps if the environment has not ended it.
+    pub fn new(episodes: usize, max_steps: usize) -> Self {
+        Self {
+            episodes,
+            max_steps,
+            target_return: None,
+            convergence: None,
+            time_budget: None,
+            max_total_steps: None,
+            epsilon: Schedule::Constant(0.0),
+            rng: Box::new(rng::default_rng()),
+            on_episode: Box::new(|_| {}),
+            stats: EpisodeStats::default(),
+            metrics: None,
+        }
+    }
+
+    /// Sets a logger to which the trainer writes the metrics
+    /// `episode_return`, `episode_length`, and `epsilon` at the end of each
+    /// episode, using the episode's index as the step. Metrics about the
+    /// agent itself, such as its q-value changes, can be logged to the same
+    /// logger from the agent's `on_learn` hook.
+    #[must_use]
+    pub fn with_metrics<L: MetricsLogger + 't>(mut self, metrics: L) -> Self {
+        self.metrics = Some(Box::new(metrics));
+        self
+    }
+
+    /// Stops training once the moving average return of the trainer's
+    /// `EpisodeStats` reaches `target`. The average is only checked once the
+    /// stats hold a full window of episodes.
+    #[must_use]
+    pub fn with_target_return(mut self, target: f64) -> Self {
+        self.target_return = Some(target);
+        self
+    }
+
+    /// Stops training once the moving average return has changed by no more
+    /// than `tolerance` after each of `patience` consecutive episodes.
+    #[must_use]
+    pub fn with_convergence(mut self, tolerance: f64, patience: usize) -> Self {
+        self.convergence = Some((tolerance, patience));
+        self
+    }
+
+    /// Stops training at the end of the first episode that ends after
+    /// `budget` has elapsed since training started.
+    #[must_use]
+    pub fn with_time_budget(mut self, budget: Duration) -> Self {
+        self.time_budget = Some(budget);
+        self
+    }
+
+    /// Stops training once `max_total_steps` steps have been taken across
+    /// all episodes, cutting the last episode short if necessary.
+    #[must_use]
+    pub fn with_max_total_steps(mut self, max_total_steps: usize) -> Self {
+        self.max_total_steps = Some(max_total_steps);
+        self
+    }
+
+    /// Sets the number of episodes covered by the moving averages in the
+    /// trainer's `EpisodeStats`. The default is 100.
+    #[must_use]
+    pub fn with_stats_window(mut self, window: usize) -> Self {
+        self.stats = EpisodeStats::new(window);
+        self
+    }
+
+    /// Returns the statistics of the episodes the trainer has run.
+    pub fn stats(&self) -> &EpisodeStats {
+        &self.stats
+    }
+
+    /// Sets the schedule for the probability that the trainer takes a random
+    /// action rather than the action recommended by the agent. The default is
+    /// a constant 0, meaning that the agent's recommendation is always taken.
+    #[must_use]
+    pub fn with_epsilon(mut self, epsilon: Schedule) -> Self {
+        self.epsilon = epsilon;
+        self
+    }
+
+    /// Sets the random number generator that the trainer uses to decide when
+    /// to explore, and which random action to take. By default, the trainer
+    /// uses a `rand::rngs::StdRng` seeded from the platform's source of
+    /// entropy.
+    #[must_use]
+    pub fn with_rng<R: RngCore + 't>(mut self, rng: R) -> Self {
+        self.rng = Box::new(rng);
+        self
+    }
+
+    /// Seeds the trainer's random number generator from `config`. See
+    /// `reproducibility::ReproducibilityConfig`.
+    #[must_use]
+    pub fn with_reproducibility(self, config: &ReproducibilityConfig) -> Self {
+        self.with_rng(config.trainer_rng())
+    }
+
+    /// Sets a function that is called with the report of each episode as soon
+    /// as the episode ends. This can be used to monitor training progress.
+    #[must_use]
+    pub fn on_episode<F: FnMut(&EpisodeReport) + 't>(mut self, on_episode: F) -> Self {
+        self.on_episode = Box::new(on_episode);
+        self
+    }
+
+    /// Trains `agent` against `env`, returning a report for each episode.
+    /// See `run` for a report of why training stopped.
+    pub fn train<'a, S, A, G, E>(
+        &mut self,
+        agent: &mut G,
+        env: &mut E,
+    ) -> Result<Vec<EpisodeReport>, LearnerError>
+    where
+        S: Stater<'a, A>,
+        A: Actioner<'a> + 'a,
+        G: Agenter<'a, S, A> + ?Sized,
+        E: Environment<'a, S, A>,
+    {
+        Ok(self.run(agent, env)?.episodes)
+    }
+
+    /// Trains `agent` against `env` until every episode has run or an early
+    /// stopping criterion is met, returning a report of the training.
+    pub fn run<'a, S, A, G, E>(
+        &mut self,
+        agent: &mut G,
+        env: &mut E,
+    ) -> Result<TrainingReport, LearnerError>
+    where
+        S: Stater<'a, A>,
+        A: Actioner<'a> + 'a,
+        G: Agenter<'a, S, A> + ?Sized,
+        E: Environment<'a, S, A>,
+    {
+        self.run_inner(agent, env, |_, _| Ok(()))
+    }
+
+    /// Trains `agent` against `env` as `run` does, offering the agent to
+    /// `checkpointer` at the end of each episode so that it can write any
+    /// checkpoint that is due. Training stops with an error if a checkpoint
+    /// cannot be written.
+    pub fn run_with_checkpoints<'a, S, A, G, E>(
+        &mut self,
+        agent: &mut G,
+        env: &mut E,
+        checkpointer: &mut Checkpointer<'_, G>,
+    ) -> Result<TrainingReport, LearnerError>
+    where
+        S: Stater<'a, A>,
+        A: Actioner<'a> + 'a,
+        G: Agenter<'a, S, A> + ?Sized,
+        E: Environment<'a, S, A>,
+    {
+        self.run_inner(agent, env, |agent, stats| {
+            checkpointer.after_episode(agent, stats)
+        })
+    }
+
+    /// Trains `agent` against an asynchronous environment, returning a
+    /// report for each episode. See `run_async`.
+    // The future borrows the trainer, whose generator and callbacks are not
+    // required to be `Send`.
+    #[allow(clippy::future_not_send)]
+    pub async fn train_async<'a, S, A, G, E>(
+        &mut self,
+        agent: &mut G,
+        env: &mut E,
+    ) -> Result<Vec<EpisodeReport>, LearnerError>
+    where
+        S: Stater<'a, A>,
+        A: Actioner<'a> + 'a,
+        G: Agenter<'a, S, A> + ?Sized,
+        E: AsyncEnvironment<'a, S, A>,
+    {
+        Ok(self.run_async(agent, env).await?.episodes)
+    }
+
+    /// Trains `agent` against an asynchronous environment as `run` does,
+    /// awaiting each reset and step of the environment. The agent itself
+    /// recommends and learns synchronously between steps.
+    ///
+    /// The returned future is not `Send`, so on a multi-threaded runtime it
+    /// must be driven with `block_on` or spawned as a local task.
+    #[allow(clippy::future_not_send)]
+    pub async fn run_async<'a, S, A, G, E>(
+        &mut self,
+        agent: &mut G,
+        env: &mut E,
+    ) -> Result<TrainingReport, LearnerError>
+    where
+        S: Stater<'a, A>,
+        A: Actioner<'a> + 'a,
+        G: Agenter<'a, S, A> + ?Sized,
+        E: AsyncEnvironment<'a, S, A>,
+    {
+        self.run_loop(agent, env, |_, _| Ok(())).await
+    }
+
+    /// Runs the training loop against a synchronous environment, calling
+    /// `after_episode` with the agent and the updated stats at the end of
+    /// each episode.
+    fn run_inner<'a, S, A, G, E, F>(
+        &mut self,
+        agent: &mut G,
+        env: &mut E,
+        after_episode: F,
+    ) -> Result<TrainingReport, LearnerError>
+    where
+        S: Stater<'a, A>,
+        A: Actioner<'a> + 'a,
+        G: Agenter<'a, S, A> + ?Sized,
+        E: Environment<'a, S, A>,
+        F: FnMut(&G, &EpisodeStats) -> Result<(), LearnerError>,
+    {
+        // The environment's futures are always ready, so the loop runs to
+        // completion on the first poll.
+        complete(self.run_loop(agent, &mut Ready(env), after_episode))
+    }
+
+    /// Runs the training loop, calling `after_episode` with the agent and
+    /// the updated stats at the end of each episode.
+    #[allow(clippy::future_not_send)]
+    async fn run_loop<'a, S, A, G, E, F>(
+        &mut self,
+        agent: &mut G,
+        env: &mut E,
+        mut after_episode: F,
+    ) -> Result<TrainingReport, LearnerError>
+    where
+        S: Stater<'a, A>,
+        A: Actioner<'a> + 'a,
+        G: Agenter<'a, S, A> + ?Sized,
+        E: AsyncEnvironment<'a, S, A>,
+        F: FnMut(&G, &EpisodeStats) -> Result<(), LearnerError>,
+    {
+        let started = Instant::now();
+        let mut reports = Vec::with_capacity(self.episodes);
+        let mut total_steps = 0_usize;
+        let mut stable_episodes = 0;
+        let mut stop_reason = StopReason::EpisodesCompleted;
+        for episode in 0..self.episodes {
+            let max_steps = self.max_total_steps.map_or(self.max_steps, |max_total| {
+                self.max_steps.min(max_total.saturating_sub(total_steps))
+            });
+            let epsilon = self.epsilon.value(episode);
+            let mut state = env.reset().await?;
+            let mut report = EpisodeReport {
+                episode,
+                steps: 0,
+                total_return: 0.0,
+                terminated: false,
+            };
+            while report.steps < max_steps {
+                let action = if self.rng.gen::<f64>() < epsilon {
+                    self.random_action(&state)?
+                } else {
+                    agent.recommend_action(&state)?
+                };
+                let step = env.step(action).await?;
+                agent.learn(Some(&state), action, &step.next_state, step.reward)?;
+                report.steps += 1;
+                report.total_return += step.reward;
+                state = step.next_state;
+                if step.done {
+                    report.terminated = true;
+                    break;
+                }
+            }
+            (self.on_episode)(&report);
+            if let Some(metrics) = self.metrics.as_mut() {
+                let step = u64::try_from(episode).unwrap_or(u64::MAX);
+                metrics.log_scalar("episode_return", step, report.total_return)?;
+                metrics.log_scalar("episode_length", step, math::len_to_f64(report.steps))?;
+                metrics.log_scalar("epsilon", step, epsilon)?;
+            }
+            let previous_average = self.stats.moving_average_return();
+            self.stats.push(&report);
+            total_steps += report.steps;
+            reports.push(report);
+            after_episode(agent, &self.stats)?;
+
+            let average = self.stats.moving_average_return();
+            if let (Some((tolerance, _)), Some(previous), Some(average)) =
+                (self.convergence, previous_average, average)
+            {
+                if (average - previous).abs() <= tolerance {
+                    stable_episodes += 1;
+                } else {
+                    stable_episodes = 0;
+                }
+            }
+            let window_full = self.stats.episodes() >= self.stats.window();
+            if let Some(reason) = [
+                (
+                    window_full
+                        && self
+                            .target_return
+                            .zip(average)
+                            .is_some_and(|(target, average)| average >= target),
+                    StopReason::TargetReturn,
+                ),
+                (
+                    self.convergence
+                        .is_some_and(|(_, patience)| stable_episodes >= patience),
+                    StopReason::Converged,
+                ),
+                (
+                    self.time_budget
+                        .is_some_and(|budget| started.elapsed() >= budget),
+                    StopReason::TimeBudget,
+                ),
+                (
+                    self.max_total_steps
+                        .is_some_and(|max_total| total_steps >= max_total),
+                    StopReason::StepBudget,
+                ),
+            ]
+            .iter()
+            .find_map(|&(stop, reason)| stop.then_some(reason))
+            {
+                stop_reason = reason;
+                break;
+            }
+        }
+        Ok(TrainingReport {
+            episodes: reports,
+            stop_reason,
+            total_steps,
+            elapsed: started.elapsed(),
+        })
+    }
+
+    #[allow(clippy::use_debug)]
+    fn random_action<'a, S, A>(&mut self, state: &S) -> Result<&'a A, LearnerError>
+    where
+        S: Stater<'a, A>,
+        A: Actioner<'a> + 'a,
+    {
+        let actions = state.possible_actions();
+        if actions.is_empty() {
+            return Err(LearnerError::NoPossibleActions {
+                state: format!("{:?}", state.id()),
+            });
+        }
+        Ok(actions[self.rng.gen_range(0, actions.len())])
+    }
+}
+
+/// Presents a synchronous environment as an asynchronous one, whose futures
+/// are always ready.
+struct Ready<'e, E>(&'e mut E);
+
+impl<'a, S, A, E> AsyncEnvironment<'a, S, A> for Ready<'_, E>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a>,
+    E: Environment<'a, S, A>,
+{
+    fn reset(&mut self) -> impl Future<Output = Result<S, LearnerError>> {
+        future::ready(self.0.reset())
+    }
+
+    fn step(&mut self, action: &'a A) -> impl Future<Output = Result<Step<S>, LearnerError>> {
+        future::ready(self.0.step(action))
+    }
+}
+
+/// Polls `future` until it completes. The future is never woken, so this is
+/// only suitable for futures that do not wait on anything external.
+fn complete<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let mut context = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = future.as_mut().poll(&mut context) {
+            return output;
+        }
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::agents::bayesian::Agent;
+    use crate::mocks::*;
+    use crate::stats::actionstats::Stats;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use std::cell::RefCell;
+
+    #[test]
+    fn schedule_value() {
+        let linear = Schedule::Linear {
+            start: 1.0,
+            end: 0.0,
+            episodes: 4,
+        };
+        assert_eq!(1.0, linear.value(0));
+        assert_eq!(0.25, linear.value(3));
+        assert_eq!(0.0, linear.value(10));
+
+        let exponential = Schedule::Exponential {
+            start: 1.0,
+            decay: 0.5,
+            min: 0.2,
+        };
+        assert_eq!(1.0, exponential.value(0));
+        assert_eq!(0.25, exponential.value(2));
+        assert_eq!(0.2, exponential.value(3));
+
+        assert_eq!(0.3, Schedule::Constant(0.3).value(100));
+    }
+
+    #[test]
+    fn train() {
+        let moves = MockCorridor::moves();
+        let mut env = MockCorridor::new(&moves, 5);
+        let mut agent: Agent<MockCell, MockActioner, Stats> =
+            Agent::new(1, 1.0, 0.9).with_rng(StdRng::seed_from_u64(1));
+        let reported = RefCell::new(0);
+        let mut trainer = Trainer::new(30, 50)
+            .with_epsilon(Schedule::Linear {
+                start: 0.5,
+                end: 0.0,
+                episodes: 20,
+            })
+            .with_rng(StdRng::seed_from_u64(2))
+            .on_episode(|_| *reported.borrow_mut() += 1);
+
+        let reports = trainer.train(&mut agent, &mut env).unwrap();
+
+        assert_eq!(30, reports.len());
+        assert_eq!(30, *reported.borrow());
+        let last = reports.last().unwrap();
+        assert!(last.terminated);
+        assert_eq!(4, last.steps);
+        assert_eq!(1.0, last.total_return);
+        assert_eq!(30, trainer.stats().episodes());
+        assert_eq!(Some(1.0), trainer.stats().best_return());
+    }
+
+    #[test]
+    fn episode_stats() {
+        let report = |episode, steps, total_return| EpisodeReport {
+            episode,
+            steps,
+            total_return,
+            terminated: steps < 10,
+        };
+        let mut stats = EpisodeStats::new(2);
+        assert_eq!(None, stats.moving_average_return());
+
+        stats.extend(vec![
+            report(0, 10, 1.0),
+            report(1, 4, 3.0),
+            report(2, 6, 2.0),
+        ]);
+
+        assert_eq!(3, stats.episodes());
+        assert_eq!(2, stats.terminated());
+        assert_eq!(&[1.0, 3.0, 2.0], stats.returns());
+        assert_eq!(&[10, 4, 6], stats.lengths());
+        assert_eq!(Some(3.0), stats.best_return());
+        assert_eq!(Some(2.5), stats.moving_average_return());
+        assert_eq!(Some(5.0), stats.moving_average_length());
+        assert_eq!(vec![1.0, 2.0, 2.5], stats.moving_average_returns());
+    }
+
+    #[test]
+    fn train_with_metrics() {
+        let moves = MockCorridor::moves();
+        let mut env = MockCorridor::new(&moves, 2);
+        let mut agent: Agent<MockCell, MockActioner, Stats> = Agent::new(1, 1.0, 0.9);
+        let mut metrics = metrics::CsvMetrics::new(Vec::new());
+
+        Trainer::new(2, 5)
+            .with_epsilon(Schedule::Constant(0.0))
+            .with_metrics(&mut metrics)
+            .train(&mut agent, &mut env)
+            .unwrap();
+
+        let output = String::from_utf8(metrics.into_inner().unwrap()).unwrap();
+        let rows: Vec<&str> = output.lines().collect();
+        assert_eq!(7, rows.len());
+        assert_eq!("1,episode_return,1", rows[4]);
+        assert_eq!("1,epsilon,0", rows[6]);
+    }
+
+    /// A corridor whose resets and steps each wait once before completing,
+    /// as a request to a remote service would.
+    struct RemoteCorridor<'a> {
+        corridor: MockCorridor<'a>,
+        requests: usize,
+    }
+
+    /// Returns `output` after being polled once without completing.
+    async fn respond<T>(output: T) -> T {
+        let mut waited = false;
+        future::poll_fn(|cx| {
+            if waited {
+                Poll::Ready(())
+            } else {
+                waited = true;
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+        })
+        .await;
+        output
+    }
+
+    impl<'a> AsyncEnvironment<'a, MockCell<'a>, MockActioner<'a>> for RemoteCorridor<'a> {
+        async fn reset(&mut self) -> Result<MockCell<'a>, LearnerError> {
+            self.requests += 1;
+            respond(self.corridor.reset()).await
+        }
+
+        async fn step(
+            &mut self,
+            action: &'a MockActioner<'a>,
+        ) -> Result<Step<MockCell<'a>>, LearnerError> {
+            self.requests += 1;
+            respond(self.corridor.step(action)).await
+        }
+    }
+
+    #[test]
+    fn train_async() {
+        let moves = MockCorridor::moves();
+        let mut env = RemoteCorridor {
+            corridor: MockCorridor::new(&moves, 3),
+            requests: 0,
+        };
+        let mut agent: Agent<MockCell, MockActioner, Stats> =
+            Agent::new(1, 1.0, 0.9).with_rng(StdRng::seed_from_u64(1));
+        let mut trainer = Trainer::new(5, 20);
+
+        let reports = complete(trainer.train_async(&mut agent, &mut env)).unwrap();
+        assert_eq!(5, reports.len());
+        assert!(reports.iter().all(|report| report.terminated));
+        let steps: usize = reports.iter().map(|report| report.steps).sum();
+        assert_eq!(5 + steps, env.requests);
+        assert_eq!(5, trainer.stats().episodes());
+
+        let report = complete(
+            Trainer::new(100, 20)
+                .with_max_total_steps(3)
+                .run_async(&mut agent, &mut env),
+        )
+        .unwrap();
+        assert_eq!(StopReason::StepBudget, report.stop_reason);
+    }
+
+    #[test]
+    fn run_stops_at_target_return() {
+        let moves = MockCorridor::moves();
+        let mut env = MockCorridor::new(&moves, 3);
+        let mut agent: Agent<MockCell, MockActioner, Stats> = Agent::new(1, 1.0, 0.9);
+
+        // Every episode that reaches the end of the corridor returns 1.
+        let report = Trainer::new(100, 50)
+            .with_stats_window(5)
+            .with_target_return(1.0)
+            .run(&mut agent, &mut env)
+            .unwrap();
+        assert_eq!(StopReason::TargetReturn, report.stop_reason);
+        assert_eq!(5, report.episodes.len());
+        assert_eq!(
+            report.episodes.iter().map(|r| r.steps).sum::<usize>(),
+            report.total_steps
+        );
+    }
+
+    #[test]
+    fn run_stops_when_converged() {
+        let moves = MockCorridor::moves();
+        let mut env = MockCorridor::new(&moves, 3);
+        let mut agent: Agent<MockCell, MockActioner, Stats> = Agent::new(1, 1.0, 0.9);
+
+        // The average of a constant return never changes after the first
+        // episode.
+        let report = Trainer::new(100, 50)
+            .with_convergence(0.0, 3)
+            .run(&mut agent, &mut env)
+            .unwrap();
+        assert_eq!(StopReason::Converged, report.stop_reason);
+        assert_eq!(4, report.episodes.len());
+    }
+
+    #[test]
+    fn run_stops_at_budgets() {
+        let moves = MockCorridor::moves();
+        let mut env = MockCorridor::new(&moves, 100);
+        let mut agent: Agent<MockCell, MockActioner, Stats> = Agent::new(1, 1.0, 0.9);
+
+        let report = Trainer::new(10, 4)
+            .with_max_total_steps(10)
+            .run(&mut agent, &mut env)
+            .unwrap();
+        assert_eq!(StopReason::StepBudget, report.stop_reason);
+        assert_eq!(10, report.total_steps);
+        assert_eq!(
+            vec![4, 4, 2],
+            report.episodes.iter().map(|r| r.steps).collect::<Vec<_>>()
+        );
+
+        let report = Trainer::new(10, 4)
+            .with_time_budget(Duration::ZERO)
+            .run(&mut agent, &mut env)
+            .unwrap();
+        assert_eq!(StopReason::TimeBudget, report.stop_reason);
+        assert_eq!(1, report.episodes.len());
+
+        let report = Trainer::new(3, 4).run(&mut agent, &mut env).unwrap();
+        assert_eq!(StopReason::EpisodesCompleted, report.stop_reason);
+        assert_eq!(3, report.episodes.len());
+    }
+
+    #[test]
+    fn train_stops_at_max_steps() {
+        let moves = MockCorridor::moves();
+        let mut env = MockCorridor::new(&moves, 100);
+        let mut agent: Agent<MockCell, MockActioner, Stats> = Agent::new(1, 1.0, 0.9);
+
+        let reports = Trainer::new(2, 3).train(&mut agent, &mut env).unwrap();
+
+        for report in reports {
+            assert_eq!(3, report.steps);
+            assert!(!report.terminated);
+        }
+    }
+}
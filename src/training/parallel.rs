@@ -0,0 +1,217 @@
+//! Trains a single agent against several copies of an environment at once.
+
+use crate::actions::Actioner;
+use crate::agents::bayesian::{Agent, AgentContext, MergeStrategy};
+use crate::environments::Environment;
+use crate::errors::LearnerError;
+use crate::states::Stater;
+use crate::stats::ActionStatter;
+use crate::training::{EpisodeReport, Schedule, Trainer};
+use rayon::prelude::*;
+
+/// The context of an agent that learns about states `S` and actions `A`.
+type Context<'a, S, A, AS> = AgentContext<<S as Stater<'a, A>>::Id, <A as Actioner<'a>>::Id, AS>;
+
+/// Trains an agent against several copies of an environment in parallel,
+/// using rayon's global thread pool.
+///
+/// Training proceeds in rounds. At the start of each round, every environment
+/// is given a worker agent that starts from a copy of the shared agent's
+/// q-values. Each worker trains against its own environment for a number of
+/// episodes, and at the end of the round the workers' q-values are merged
+/// back into the shared agent, weighting each worker's q-values by the
+/// number of times it called each action. Stats inherited from the shared
+/// agent are only counted once, however many workers inherited them.
+///
+/// Worker agents are created with `Agent::from_agent_context` by default,
+/// so they share the shared agent's learning rate, discount factor, and
+/// priming threshold, but none of its other settings. Use `train_with` to
+/// configure the workers differently.
+pub struct ParallelTrainer {
+    rounds: usize,
+    episodes_per_round: usize,
+    max_steps: usize,
+    epsilon: Schedule,
+}
+
+impl ParallelTrainer {
+    /// Returns a new trainer that runs `rounds` rounds, in each of which
+    /// every environment runs `episodes_per_round` episodes. Each episode is
+    /// cut short after `max_steps` steps if the environment has not ended it.
+    pub fn new(rounds: usize, episodes_per_round: usize, max_steps: usize) -> Self {
+        Self {
+            rounds,
+            episodes_per_round,
+            max_steps,
+            epsilon: Schedule::Constant(0.0),
+        }
+    }
+
+    /// Sets the schedule for the probability that a worker takes a random
+    /// action rather than the action recommended by its agent. Unlike
+    /// `Trainer::with_epsilon`, the schedule is evaluated once per round.
+    /// The default is a constant 0.
+    #[must_use]
+    pub fn with_epsilon(mut self, epsilon: Schedule) -> Self {
+        self.epsilon = epsilon;
+        self
+    }
+
+    /// Trains `agent` against every environment in `envs`, returning a report
+    /// for each episode. Reports are ordered by round, and then by
+    /// environment, and are numbered in that order.
+    /// An error is returned if any worker fails to train, or if the shared
+    /// agent cannot learn (for instance, if it has been frozen).
+    pub fn train<'a, S, A, AS, E>(
+        &self,
+        agent: &mut Agent<'a, S, A, AS>,
+        envs: &mut [E],
+    ) -> Result<Vec<EpisodeReport>, LearnerError>
+    where
+        S: Stater<'a, A>,
+        S::Id: Send + Sync,
+        A: Actioner<'a> + Sync + 'a,
+        A::Id: Send + Sync,
+        AS: ActionStatter + Send + Sync,
+        E: Environment<'a, S, A> + Send,
+    {
+        self.train_with(agent, envs, Agent::from_agent_context)
+    }
+
+    /// Trains `agent` in the same way as `train`, but creates each worker
+    /// agent by calling `make_worker` with a copy of the shared agent's
+    /// context. This can be used to give workers settings, such as an
+    /// exploration bonus, that are not part of an `AgentContext`.
+    pub fn train_with<'a, S, A, AS, E, F>(
+        &self,
+        agent: &mut Agent<'a, S, A, AS>,
+        envs: &mut [E],
+        make_worker: F,
+    ) -> Result<Vec<EpisodeReport>, LearnerError>
+    where
+        S: Stater<'a, A>,
+        S::Id: Send + Sync,
+        A: Actioner<'a> + Sync + 'a,
+        A::Id: Send + Sync,
+        AS: ActionStatter + Send + Sync,
+        E: Environment<'a, S, A> + Send,
+        F: Fn(AgentContext<S::Id, A::Id, AS>) -> Agent<'a, S, A, AS> + Sync,
+    {
+        let mut reports = Vec::with_capacity(self.rounds * self.episodes_per_round * envs.len());
+        for round in 0..self.rounds {
+            let base = agent.get_agent_context();
+            let epsilon = Schedule::Constant(self.epsilon.value(round));
+            let results = envs
+                .par_iter_mut()
+                .map(|env| {
+                    let mut worker = make_worker(base.clone());
+                    let mut trainer =
+                        Trainer::new(self.episodes_per_round, self.max_steps).with_epsilon(epsilon);
+                    let reports = trainer.train(&mut worker, env)?;
+                    Ok((worker.get_agent_context(), reports))
+                })
+                .collect::<Result<Vec<_>, LearnerError>>()?;
+
+            let mut contexts = Vec::with_capacity(results.len());
+            for (context, worker_reports) in results {
+                contexts.push(context);
+                reports.extend(worker_reports);
+            }
+            if let Some(merged) = merge_workers::<S, A, AS>(&base, contexts)? {
+                agent.merge_from(&merged, MergeStrategy::Replace)?;
+            }
+        }
+        for (episode, report) in reports.iter_mut().enumerate() {
+            report.episode = episode;
+        }
+        Ok(reports)
+    }
+}
+
+/// Merges the contexts of the workers of a round, each of which started from
+/// `base`. The stats and visits that each worker after the first inherited
+/// from `base` are subtracted before merging, so that they are only counted
+/// once. Returns `None` if there are no workers.
+fn merge_workers<'a, S, A, AS>(
+    base: &Context<'a, S, A, AS>,
+    contexts: Vec<Context<'a, S, A, AS>>,
+) -> Result<Option<Context<'a, S, A, AS>>, LearnerError>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a> + 'a,
+    AS: ActionStatter,
+{
+    let mut contexts = contexts.into_iter();
+    let Some(first) = contexts.next() else {
+        return Ok(None);
+    };
+    let mut merged: Agent<'a, S, A, AS> = Agent::from_agent_context(first);
+    for mut context in contexts {
+        for (state_id, actions) in &mut context.q_values {
+            for (action_id, stats) in actions {
+                let inherited = base
+                    .q_values
+                    .get(state_id)
+                    .and_then(|actions| actions.get(action_id))
+                    .map_or(0, ActionStatter::calls);
+                stats.set_calls(stats.calls().saturating_sub(inherited));
+            }
+        }
+        for (state_id, visits) in &mut context.state_visits {
+            let inherited = base.state_visits.get(state_id).copied().unwrap_or_default();
+            *visits = visits.saturating_sub(inherited);
+        }
+        merged.merge_from(&context, MergeStrategy::WeightedAverage)?;
+    }
+    Ok(Some(merged.get_agent_context()))
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::agents::Agenter;
+    use crate::mocks::*;
+    use crate::stats::actionstats::Stats;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn train() {
+        let moves = MockCorridor::moves();
+        let mut envs: Vec<MockCorridor> = (0..4).map(|_| MockCorridor::new(&moves, 4)).collect();
+        let mut agent: Agent<MockCell, MockActioner, Stats> = Agent::new(1, 1.0, 0.9);
+
+        let reports = ParallelTrainer::new(3, 5, 50)
+            .with_epsilon(Schedule::Constant(0.1))
+            .train(&mut agent, &mut envs)
+            .unwrap();
+        assert_eq!(3 * 5 * 4, reports.len());
+        assert!(reports.iter().enumerate().all(|(i, r)| r.episode == i));
+
+        let total_steps: usize = reports.iter().map(|r| r.steps).sum();
+        assert_eq!(
+            u64::try_from(total_steps).unwrap(),
+            agent.step_count(),
+            "every step must be counted exactly once"
+        );
+
+        let mut env = MockCorridor::new(&moves, 4);
+        let mut state = env.reset().unwrap();
+        for _ in 0..3 {
+            let action = agent.recommend_action(&state).unwrap();
+            assert_eq!("R", action.return_id);
+            state = env.step(action).unwrap().next_state;
+        }
+    }
+
+    #[test]
+    fn train_when_frozen() {
+        let moves = MockCorridor::moves();
+        let mut envs = vec![MockCorridor::new(&moves, 3)];
+        let mut agent: Agent<MockCell, MockActioner, Stats> = Agent::new(1, 1.0, 0.9);
+        agent.freeze().unwrap();
+
+        let result = ParallelTrainer::new(1, 1, 10).train(&mut agent, &mut envs);
+        assert!(matches!(result, Err(LearnerError::Lifecycle(_))));
+    }
+}
@@ -0,0 +1,189 @@
+//! Learning from a stream of transitions produced elsewhere.
+//!
+//! A `Trainer` steps through an environment and learns in the same loop. To
+//! gather experience from many environments at once, each environment can
+//! instead be run by its own worker, which sends every `Transition` it
+//! observes down a channel, while a single learner drains the channel with
+//! `learn_from`.
+//!
+//! `learn_from` accepts anything that yields transitions, so it works with
+//! `std::sync::mpsc` and `crossbeam` receivers alike. Passing a receiver
+//! blocks until every sender has been dropped, while passing
+//! `Receiver::try_iter` learns from the transitions that are waiting and then
+//! returns, so that the learner can do other work between batches.
+//!
+//! ```
+//! use rlr::training::stream::{learn_from, Transition};
+//! # use rlr::prelude::*;
+//! # struct Cell;
+//! # struct Move;
+//! # impl<'a> Actioner<'a> for Move {
+//! #     type Id = ();
+//! #     fn id(&self) {}
+//! # }
+//! # impl<'a> Stater<'a, Move> for Cell {
+//! #     type Id = ();
+//! #     fn possible_actions(&self) -> Vec<&'a Move> { vec![&Move] }
+//! #     fn action_is_compatible(&self, _: &'a Move) -> bool { true }
+//! #     fn get_action(&self, _: &()) -> Result<&'a Move, LearnerError> { Ok(&Move) }
+//! #     fn id(&self) {}
+//! #     fn apply(&self, _: &'a Move) -> Result<(), LearnerError> { Ok(()) }
+//! # }
+//! use rlr::agents::bayesian::Agent;
+//! use rlr::stats::actionstats::Stats;
+//! use std::sync::mpsc;
+//! use std::thread;
+//!
+//! let (sender, receiver) = mpsc::channel();
+//! thread::scope(|scope| {
+//!     for _ in 0..4 {
+//!         let sender = sender.clone();
+//!         scope.spawn(move || {
+//!             // Step through an environment, sending each transition.
+//!             let transition = Transition::new(Some(Cell), &Move, Cell, 1.0);
+//!             sender.send(transition).unwrap();
+//!         });
+//!     }
+//! });
+//! drop(sender);
+//!
+//! let mut agent: Agent<Cell, Move, Stats> = Agent::new(1, 1.0, 0.9);
+//! assert_eq!(4, learn_from(&mut agent, receiver).unwrap());
+//! ```
+
+use crate::actions::Actioner;
+use crate::agents::Agenter;
+use crate::errors::LearnerError;
+use crate::states::Stater;
+
+/// A single step of experience: the state an action was taken from, the
+/// action, the state that resulted, and the reward that was earned.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transition<'a, S, A> {
+    /// The state the action was taken from, if any.
+    pub previous_state: Option<S>,
+
+    /// The action that was taken.
+    pub action: &'a A,
+
+    /// The state that resulted from the action.
+    pub current_state: S,
+
+    /// The reward earned by the action.
+    pub reward: f64,
+}
+
+impl<'a, S, A> Transition<'a, S, A> {
+    /// Returns a new transition.
+    pub const fn new(
+        previous_state: Option<S>,
+        action: &'a A,
+        current_state: S,
+        reward: f64,
+    ) -> Self {
+        Self {
+            previous_state,
+            action,
+            current_state,
+            reward,
+        }
+    }
+}
+
+/// Has `agent` learn from each of `transitions` in turn, returning the number
+/// of transitions it learned from.
+///
+/// Learning stops at the first error, which is returned. Transitions that
+/// have not yet been yielded are left where they are, so a receiver can be
+/// drained again once the error has been dealt with.
+pub fn learn_from<'a, S, A, G, I>(agent: &mut G, transitions: I) -> Result<usize, LearnerError>
+where
+    S: Stater<'a, A>,
+    A: Actioner<'a> + 'a,
+    G: Agenter<'a, S, A> + ?Sized,
+    I: IntoIterator<Item = Transition<'a, S, A>>,
+{
+    let mut learned = 0;
+    for transition in transitions {
+        agent.learn(
+            transition.previous_state.as_ref(),
+            transition.action,
+            &transition.current_state,
+            transition.reward,
+        )?;
+        learned += 1;
+    }
+    Ok(learned)
+}
+
+#[cfg(test)]
+#[allow(clippy::wildcard_imports, clippy::default_trait_access, clippy::panic)]
+mod tests {
+    use super::*;
+    use crate::agents::bayesian::Agent;
+    use crate::environments::Environment;
+    use crate::mocks::*;
+    use crate::stats::actionstats::Stats;
+    use std::sync::mpsc;
+    use std::thread;
+
+    #[test]
+    fn learns_from_many_workers() {
+        let moves = MockCorridor::moves();
+        let (sender, receiver) = mpsc::channel();
+        thread::scope(|scope| {
+            for _ in 0..3 {
+                let sender = sender.clone();
+                let moves = &moves;
+                scope.spawn(move || {
+                    let mut env = MockCorridor::new(moves, 4);
+                    let mut state = env.reset().unwrap();
+                    loop {
+                        let step = env.step(&moves[1]).unwrap();
+                        let done = step.done;
+                        let next_state = MockCell {
+                            position: step.next_state.position,
+                            moves,
+                        };
+                        let transition =
+                            Transition::new(Some(state), &moves[1], step.next_state, step.reward);
+                        sender.send(transition).unwrap();
+                        if done {
+                            break;
+                        }
+                        state = next_state;
+                    }
+                });
+            }
+        });
+
+        let mut agent: Agent<MockCell, MockActioner, Stats> = Agent::new(1, 1.0, 0.9);
+        assert_eq!(9, learn_from(&mut agent, receiver.try_iter()).unwrap());
+        assert_eq!(9, agent.step_count());
+        assert_eq!(3, agent.state_visits(&0).unwrap());
+        assert_eq!(4, agent.state_count().unwrap());
+
+        drop(sender);
+        assert_eq!(0, learn_from(&mut agent, receiver).unwrap());
+    }
+
+    #[test]
+    fn stops_at_the_first_error() {
+        let moves = MockCorridor::moves();
+        let cell = |position| MockCell {
+            position,
+            moves: &moves,
+        };
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..2 {
+            sender
+                .send(Transition::new(Some(cell(0)), &moves[1], cell(1), 0.0))
+                .unwrap();
+        }
+
+        let mut agent: Agent<MockCell, MockActioner, Stats> = Agent::new(1, 1.0, 0.9);
+        agent.freeze().unwrap();
+        assert!(learn_from(&mut agent, receiver.try_iter()).is_err());
+        assert_eq!(1, receiver.try_iter().count());
+    }
+}